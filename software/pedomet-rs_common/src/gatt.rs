@@ -0,0 +1,93 @@
+//! UUIDs of the GATT services and characteristics of the pedometer.
+//!
+//! They are only listed in [`gatt_uuids`](crate::gatt_uuids), so that the firmware and the GUI
+//! cannot drift apart when characteristics are added.
+
+/// Base UUID of the Bluetooth SIG, which 16 bit UUIDs are part of.
+const BLUETOOTH_BASE_UUID: u128 = 0x00000000_0000_1000_8000_00805F9B34FB;
+
+/// Invoke the given macro with the UUIDs of all GATT services and characteristics as
+/// `NAME = "uuid"` pairs.
+///
+/// The UUIDs are passed as string literals because the attributes of `nrf_softdevice` cannot
+/// evaluate constants. New characteristics have to be appended.
+#[macro_export]
+macro_rules! gatt_uuids {
+    ($callback:ident) => {
+        $callback! {
+            BATTERY_SERVICE = "180f",
+            BATTERY_LEVEL = "2a19",
+            PEDOMETER_SERVICE = "1c2a0000-abf2-4b98-ba1c-25d5ea728525",
+            REQUEST_EVENTS = "1c2a0001-abf2-4b98-ba1c-25d5ea728525",
+            RESPONSE_EVENTS = "1c2a0002-abf2-4b98-ba1c-25d5ea728525",
+            DELETE_EVENTS = "1c2a0003-abf2-4b98-ba1c-25d5ea728525",
+            EPOCH_MS = "1c2a0004-abf2-4b98-ba1c-25d5ea728525",
+            BOOT_ID = "1c2a0005-abf2-4b98-ba1c-25d5ea728525",
+            MAX_EVENT_ID = "1c2a0006-abf2-4b98-ba1c-25d5ea728525",
+            CONFIG = "1c2a0007-abf2-4b98-ba1c-25d5ea728525",
+            DIAGNOSTICS = "1c2a0008-abf2-4b98-ba1c-25d5ea728525",
+            STORAGE_FILL_PERCENT = "1c2a0009-abf2-4b98-ba1c-25d5ea728525",
+            TIME_SYNC = "1c2a000a-abf2-4b98-ba1c-25d5ea728525",
+            DELETE_BOOT = "1c2a000b-abf2-4b98-ba1c-25d5ea728525",
+            REQUEST_EVENTS_SINCE = "1c2a000c-abf2-4b98-ba1c-25d5ea728525",
+            DAILY_STEPS = "1c2a000d-abf2-4b98-ba1c-25d5ea728525",
+            MARKER = "1c2a000e-abf2-4b98-ba1c-25d5ea728525",
+            SELF_TEST = "1c2a000f-abf2-4b98-ba1c-25d5ea728525",
+            CONFIG_CHANGED = "1c2a0010-abf2-4b98-ba1c-25d5ea728525",
+            REQUEST_EVENTS_QUERY = "1c2a0011-abf2-4b98-ba1c-25d5ea728525",
+            WRITE_ERROR = "1c2a0012-abf2-4b98-ba1c-25d5ea728525",
+            ACK_EVENTS = "1c2a0013-abf2-4b98-ba1c-25d5ea728525",
+            FIRMWARE_VERSION = "1c2a0014-abf2-4b98-ba1c-25d5ea728525",
+            ACCELERATION = "1c2a0015-abf2-4b98-ba1c-25d5ea728525",
+            BOOT_SUMMARIES = "1c2a0016-abf2-4b98-ba1c-25d5ea728525",
+            TEMPERATURE = "1c2a0017-abf2-4b98-ba1c-25d5ea728525",
+            STORAGE_USAGE = "1c2a0018-abf2-4b98-ba1c-25d5ea728525",
+            DELETE_RESULT = "1c2a0019-abf2-4b98-ba1c-25d5ea728525",
+            REQUEST_EVENTS_TRANSFER = "1c2a001a-abf2-4b98-ba1c-25d5ea728525",
+            RSC_SERVICE = "1814",
+            RSC_MEASUREMENT = "2a53",
+            RSC_FEATURE = "2a54",
+        }
+    };
+}
+
+macro_rules! define_uuid_consts {
+    ($($name:ident = $uuid:literal,)*) => {
+        $(pub const $name: u128 = parse_uuid($uuid);)*
+    };
+}
+
+gatt_uuids!(define_uuid_consts);
+
+/// The part of a UUID that differs between the characteristics, i.e. the 16 bit UUID of the
+/// Bluetooth SIG or the second group of the UUIDs of the pedometer service.
+pub const fn short_uuid(uuid: u128) -> u16 {
+    (uuid >> 96) as u16
+}
+
+/// Parse a 16 bit UUID like `"2a19"` or a 128 bit UUID like
+/// `"1c2a0000-abf2-4b98-ba1c-25d5ea728525"`.
+pub const fn parse_uuid(uuid: &str) -> u128 {
+    let bytes = uuid.as_bytes();
+    let mut value: u128 = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        let digit = match bytes[i] {
+            b'0'..=b'9' => bytes[i] - b'0',
+            b'a'..=b'f' => bytes[i] - b'a' + 10,
+            b'A'..=b'F' => bytes[i] - b'A' + 10,
+            b'-' => {
+                i += 1;
+                continue;
+            }
+            _ => panic!("Invalid character in UUID"),
+        };
+        value = (value << 4) | digit as u128;
+        i += 1;
+    }
+    match bytes.len() {
+        4 => BLUETOOTH_BASE_UUID | (value << 96),
+        36 => value,
+        _ => panic!("Invalid length of UUID"),
+    }
+}
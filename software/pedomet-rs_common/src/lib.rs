@@ -6,6 +6,9 @@ use serde::{Deserialize, Serialize};
 #[cfg(feature = "std")]
 extern crate std;
 
+pub mod gatt;
+pub mod protocol;
+
 #[derive(Debug, Copy, Clone, Serialize, Deserialize, MaxSize)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct PedometerEvent {
@@ -21,8 +24,149 @@ pub enum PedometerEventType {
     HostEpochMs(u64),
     Steps(u16),
     Boot,
+    /// Total steps of the local day that ended at the event timestamp
+    DailySummary(u32),
+    Error(PedometerError),
+    /// Boot of the device with the content of the RESETREAS register. Replaces [`Self::Boot`]
+    /// which is only kept for events stored by older firmware.
+    BootWithResetReason(u32),
+    /// Marker set by the user
+    Marker(PedometerMarker),
+    /// Result of the power-on self test
+    SelfTest(PedometerSelfTest),
+    /// Result of the daily maintenance of the event storage
+    Maintenance(PedometerMaintenance),
+    /// Reading of a sensor on an add-on board
+    SensorReading(PedometerSensorReading),
+    /// The activity of the user changed at the event timestamp
+    Activity(PedometerActivity),
+    /// Temperature of the IMU in 0.01 °C, e.g. to explain the battery voltage
+    Temperature(i16),
+}
+
+/// Sensor of an add-on board, see [`PedometerSensorReading`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum PedometerSensor {
+    /// Heart rate in beats per minute
+    HeartRate = 0,
+    /// Oxygen saturation of the blood in percent
+    SpO2 = 1,
+}
+
+impl PedometerSensor {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::HeartRate),
+            1 => Some(Self::SpO2),
+            _ => None,
+        }
+    }
+}
+
+/// Reading of a sensor on an add-on board.
+///
+/// The sensor is only stored as number, so that hosts keep the readings of sensors that were
+/// added after them. New sensors only have to be appended to [`PedometerSensor`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, MaxSize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PedometerSensorReading {
+    /// See [`PedometerSensor`]
+    pub sensor: u8,
+    /// Value in the unit of the sensor
+    pub value: i32,
 }
 
+impl PedometerSensorReading {
+    pub fn new(sensor: PedometerSensor, value: i32) -> Self {
+        Self {
+            sensor: sensor as u8,
+            value,
+        }
+    }
+
+    /// Returns `None` if the sensor is unknown.
+    pub fn sensor(&self) -> Option<PedometerSensor> {
+        PedometerSensor::from_u8(self.sensor)
+    }
+}
+
+/// Marker that is set by the user to annotate the events, e.g. to record a session.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, MaxSize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum PedometerMarker {
+    SessionStart = 0,
+    SessionEnd = 1,
+}
+
+impl PedometerMarker {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::SessionStart),
+            1 => Some(Self::SessionEnd),
+            _ => None,
+        }
+    }
+}
+
+/// Activity of the user that the device classifies by the cadence.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize, MaxSize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum PedometerActivity {
+    #[default]
+    Idle = 0,
+    Walking = 1,
+    Running = 2,
+}
+
+impl PedometerActivity {
+    /// Minimum cadence of walking in steps per minute. Single steps, e.g. in the kitchen, are
+    /// not counted as walking.
+    pub const WALKING_MIN_CADENCE: u32 = 40;
+    /// Minimum cadence of running in steps per minute
+    pub const RUNNING_MIN_CADENCE: u32 = 140;
+
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::Idle),
+            1 => Some(Self::Walking),
+            2 => Some(Self::Running),
+            _ => None,
+        }
+    }
+
+    /// Classify the cadence in steps per minute.
+    pub fn from_cadence(steps_per_minute: u32) -> Self {
+        if steps_per_minute >= Self::RUNNING_MIN_CADENCE {
+            Self::Running
+        } else if steps_per_minute >= Self::WALKING_MIN_CADENCE {
+            Self::Walking
+        } else {
+            Self::Idle
+        }
+    }
+}
+
+/// Error that occurred on the device and is stored as event for later diagnosis.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, MaxSize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PedometerError {
+    /// A connection was closed with the given HCI status code
+    Disconnected(u8),
+    /// A softdevice call failed with the given raw error code
+    Softdevice(u32),
+    /// The IMU did not respond and was power cycled
+    ImuPowerCycled,
+}
+
+/// The device reserves the event indexes in blocks of this size, so that it does not persist its
+/// counters for every event. The rest of a block is skipped after a reboot, so there may be a gap
+/// of less than this many indexes before the first event of a boot.
+pub const EVENT_INDEX_BLOCK: u32 = 256;
+
 impl PedometerEvent {
     pub fn serialize(
         &self,
@@ -30,18 +174,34 @@ impl PedometerEvent {
         Ok(postcard::to_vec(&self)?)
     }
 
+    /// Serialize the event into a COBS frame. The serialized event is followed by its CRC16
+    /// within the frame, so that the host detects corrupted notifications.
     pub fn serialize_for_transport<'a>(
         &self,
         buf: &'a mut [u8],
     ) -> PedometerCommonResult<&'a [u8]> {
-        Ok(postcard::to_slice_cobs(&self, buf)?)
+        let crc = crc16(&self.serialize()?).to_le_bytes();
+        Ok(postcard::to_slice_cobs(&(self, crc), buf)?)
     }
 
     pub fn deserialize(buf: &[u8]) -> PedometerCommonResult<(Self, &[u8])> {
         Ok(postcard::take_from_bytes(buf)?)
     }
 
+    /// Deserialize a frame of [`Self::serialize_for_transport`]. Fails with
+    /// [`PedometerCommonError::Crc`] if the event was corrupted.
     pub fn deserialize_from_transport(buf: &mut [u8]) -> PedometerCommonResult<(Self, &mut [u8])> {
+        let ((event, crc), rest): ((Self, [u8; 2]), _) = postcard::take_from_bytes_cobs(buf)?;
+        if crc16(&event.serialize()?) != u16::from_le_bytes(crc) {
+            return Err(PedometerCommonError::Crc);
+        }
+        Ok((event, rest))
+    }
+
+    /// Deserialize a frame of older firmware, which did not append the CRC.
+    pub fn deserialize_from_transport_without_crc(
+        buf: &mut [u8],
+    ) -> PedometerCommonResult<(Self, &mut [u8])> {
         Ok(postcard::take_from_bytes_cobs(buf)?)
     }
 
@@ -50,19 +210,995 @@ impl PedometerEvent {
     }
 
     pub const fn get_max_serialized_transport_size() -> usize {
-        let serialized_size = Self::get_max_serialized_size();
+        // The CRC is appended to the event
+        let serialized_size = Self::get_max_serialized_size() + 2;
 
         // https://en.wikipedia.org/wiki/Consistent_Overhead_Byte_Stuffing
         serialized_size + 1 + (254.0 / serialized_size as f32 + 1.0) as usize
     }
 }
 
+/// CRC-16/CCITT-FALSE of the data.
+pub(crate) fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0xFFFF_u16;
+    for byte in data {
+        crc ^= u16::from(*byte) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// Size of the config characteristic. Shorter values are padded with zeros.
+pub const CONFIG_CHARACTERISTIC_SIZE: usize = 32;
+
+/// Complete configuration of the device as it can be read from the config characteristic.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, MaxSize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PedometerConfig {
+    /// Offset of the user's local time to UTC at the next local midnight
+    pub utc_offset_minutes: i16,
+    /// Steps events within this window are merged into a single event. 0 disables coalescing.
+    pub step_coalescing_window_secs: u16,
+    /// Fill level of the event storage in percent at which the host is notified
+    pub storage_warning_percent: u8,
+    /// Second fill level of the event storage in percent at which the host is notified again
+    pub storage_critical_percent: u8,
+    /// The device alerts the user if no steps were counted for this duration during the active
+    /// hours. 0 disables the alert.
+    pub idle_alert_minutes: u16,
+    /// First local hour in which the idle alert is active
+    pub active_hours_start: u8,
+    /// Local hour at which the idle alert becomes inactive, may be before the start to span
+    /// midnight
+    pub active_hours_end: u8,
+    /// Interval in which the battery voltage is measured. The device shortens it while steps are
+    /// counted and extends it after a long idle time.
+    pub battery_sample_interval_secs: u16,
+    /// Interval in which the battery voltage is measured while it is below
+    /// `battery_low_mv`
+    pub battery_low_sample_interval_secs: u16,
+    /// Battery voltage below which the LED blinks on every measurement
+    pub battery_low_mv: u16,
+    /// Minimum acceleration of a step in 16 mg, at most 31. Higher values count less false steps,
+    /// e.g. while cycling.
+    pub step_threshold: u8,
+    /// Steps that have to be detected in a row before they are counted, at most 7
+    pub step_debounce_steps: u8,
+    /// If no steps were counted for this duration, the accelerometer sleeps in its low-power
+    /// mode until a significant motion. 0 keeps it awake.
+    pub motion_sleep_minutes: u16,
+}
+
+impl Default for PedometerConfig {
+    fn default() -> Self {
+        Self {
+            utc_offset_minutes: 0,
+            step_coalescing_window_secs: 5 * 60,
+            storage_warning_percent: 80,
+            storage_critical_percent: 95,
+            idle_alert_minutes: 0,
+            active_hours_start: 8,
+            active_hours_end: 20,
+            battery_sample_interval_secs: 5 * 60,
+            battery_low_sample_interval_secs: 30,
+            battery_low_mv: 3550,
+            // Defaults of the IMU
+            step_threshold: 16,
+            step_debounce_steps: 6,
+            motion_sleep_minutes: 15,
+        }
+    }
+}
+
+/// A single configuration value as it is written to the config characteristic.
+///
+/// New variants must only be appended because the variant index is used as storage key.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, MaxSize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PedometerConfigValue {
+    UtcOffsetMinutes(i16),
+    StepCoalescingWindowSecs(u16),
+    StorageWarningPercent(u8),
+    StorageCriticalPercent(u8),
+    IdleAlertMinutes(u16),
+    ActiveHoursStart(u8),
+    ActiveHoursEnd(u8),
+    BatterySampleIntervalSecs(u16),
+    BatteryLowSampleIntervalSecs(u16),
+    BatteryLowMv(u16),
+    StepThreshold(u8),
+    StepDebounceSteps(u8),
+    MotionSleepMinutes(u16),
+}
+
+const _: () = assert!(PedometerConfig::POSTCARD_MAX_SIZE <= CONFIG_CHARACTERISTIC_SIZE);
+const _: () = assert!(PedometerConfigValue::POSTCARD_MAX_SIZE <= CONFIG_CHARACTERISTIC_SIZE);
+
+impl PedometerConfig {
+    pub fn apply(&mut self, value: PedometerConfigValue) {
+        match value {
+            PedometerConfigValue::UtcOffsetMinutes(utc_offset_minutes) => {
+                self.utc_offset_minutes = utc_offset_minutes
+            }
+            PedometerConfigValue::StepCoalescingWindowSecs(step_coalescing_window_secs) => {
+                self.step_coalescing_window_secs = step_coalescing_window_secs
+            }
+            PedometerConfigValue::StorageWarningPercent(storage_warning_percent) => {
+                self.storage_warning_percent = storage_warning_percent
+            }
+            PedometerConfigValue::StorageCriticalPercent(storage_critical_percent) => {
+                self.storage_critical_percent = storage_critical_percent
+            }
+            PedometerConfigValue::IdleAlertMinutes(idle_alert_minutes) => {
+                self.idle_alert_minutes = idle_alert_minutes
+            }
+            PedometerConfigValue::ActiveHoursStart(active_hours_start) => {
+                self.active_hours_start = active_hours_start
+            }
+            PedometerConfigValue::ActiveHoursEnd(active_hours_end) => {
+                self.active_hours_end = active_hours_end
+            }
+            PedometerConfigValue::BatterySampleIntervalSecs(battery_sample_interval_secs) => {
+                self.battery_sample_interval_secs = battery_sample_interval_secs
+            }
+            PedometerConfigValue::BatteryLowSampleIntervalSecs(
+                battery_low_sample_interval_secs,
+            ) => self.battery_low_sample_interval_secs = battery_low_sample_interval_secs,
+            PedometerConfigValue::BatteryLowMv(battery_low_mv) => {
+                self.battery_low_mv = battery_low_mv
+            }
+            PedometerConfigValue::StepThreshold(step_threshold) => {
+                self.step_threshold = step_threshold
+            }
+            PedometerConfigValue::StepDebounceSteps(step_debounce_steps) => {
+                self.step_debounce_steps = step_debounce_steps
+            }
+            PedometerConfigValue::MotionSleepMinutes(motion_sleep_minutes) => {
+                self.motion_sleep_minutes = motion_sleep_minutes
+            }
+        }
+    }
+
+    /// Whether the idle alert is active in the given local hour.
+    pub fn is_active_hour(&self, local_hour: u8) -> bool {
+        if self.active_hours_start <= self.active_hours_end {
+            (self.active_hours_start..self.active_hours_end).contains(&local_hour)
+        } else {
+            local_hour >= self.active_hours_start || local_hour < self.active_hours_end
+        }
+    }
+
+    /// Number of storage thresholds that are reached by the given fill level.
+    pub fn storage_warning_level(&self, fill_percent: u8) -> u8 {
+        [self.storage_warning_percent, self.storage_critical_percent]
+            .iter()
+            .filter(|threshold| fill_percent >= **threshold)
+            .count() as u8
+    }
+
+    pub fn serialize_for_characteristic(
+        &self,
+    ) -> PedometerCommonResult<[u8; CONFIG_CHARACTERISTIC_SIZE]> {
+        let mut buf = [0; CONFIG_CHARACTERISTIC_SIZE];
+        postcard::to_slice(self, &mut buf)?;
+        Ok(buf)
+    }
+
+    pub fn deserialize(buf: &[u8]) -> PedometerCommonResult<Self> {
+        Ok(postcard::take_from_bytes(buf)?.0)
+    }
+}
+
+impl PedometerConfigValue {
+    /// Number of different config values, i.e. the number of variants.
+    pub const NUM_KEYS: u8 = 13;
+
+    pub fn key(&self) -> u8 {
+        match self {
+            PedometerConfigValue::UtcOffsetMinutes(_) => 0,
+            PedometerConfigValue::StepCoalescingWindowSecs(_) => 1,
+            PedometerConfigValue::StorageWarningPercent(_) => 2,
+            PedometerConfigValue::StorageCriticalPercent(_) => 3,
+            PedometerConfigValue::IdleAlertMinutes(_) => 4,
+            PedometerConfigValue::ActiveHoursStart(_) => 5,
+            PedometerConfigValue::ActiveHoursEnd(_) => 6,
+            PedometerConfigValue::BatterySampleIntervalSecs(_) => 7,
+            PedometerConfigValue::BatteryLowSampleIntervalSecs(_) => 8,
+            PedometerConfigValue::BatteryLowMv(_) => 9,
+            PedometerConfigValue::StepThreshold(_) => 10,
+            PedometerConfigValue::StepDebounceSteps(_) => 11,
+            PedometerConfigValue::MotionSleepMinutes(_) => 12,
+        }
+    }
+
+    pub fn serialize(
+        &self,
+    ) -> PedometerCommonResult<heapless::Vec<u8, { <Self as MaxSize>::POSTCARD_MAX_SIZE }>> {
+        Ok(postcard::to_vec(&self)?)
+    }
+
+    pub fn serialize_for_characteristic(
+        &self,
+    ) -> PedometerCommonResult<[u8; CONFIG_CHARACTERISTIC_SIZE]> {
+        let mut buf = [0; CONFIG_CHARACTERISTIC_SIZE];
+        postcard::to_slice(self, &mut buf)?;
+        Ok(buf)
+    }
+
+    pub fn deserialize(buf: &[u8]) -> PedometerCommonResult<Self> {
+        Ok(postcard::take_from_bytes(buf)?.0)
+    }
+}
+
+/// Request for all events from the given position on. Events are sent if they belong to a later
+/// boot or to the given boot with an index of at least `min_event_index`. This way the events of
+/// previous boots that the host already has are skipped even if the indexes were reset.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PedometerEventRequest {
+    pub boot_id: u32,
+    pub min_event_index: u32,
+}
+
+impl PedometerEventRequest {
+    pub const SIZE: usize = 8;
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut buf = [0; Self::SIZE];
+        buf[..4].copy_from_slice(&self.boot_id.to_le_bytes());
+        buf[4..].copy_from_slice(&self.min_event_index.to_le_bytes());
+        buf
+    }
+
+    pub fn from_bytes(buf: &[u8; Self::SIZE]) -> Self {
+        Self {
+            boot_id: u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]),
+            min_event_index: u32::from_le_bytes([buf[4], buf[5], buf[6], buf[7]]),
+        }
+    }
+
+    pub fn matches(&self, event: &PedometerEvent) -> bool {
+        (event.boot_id, event.index) >= (self.boot_id, self.min_event_index)
+    }
+}
+
+/// Number of hosts whose [`PedometerSyncCursor`] the device keeps.
+pub const MAX_SYNC_HOSTS: usize = 4;
+
+/// Acknowledgment of a host that it synced all events before the given boot and index.
+///
+/// The device keeps the cursor of every host and only deletes events that all of them
+/// acknowledged, so that deleting the events after a sync does not take them from another host.
+/// If more than [`MAX_SYNC_HOSTS`] hosts sync, the one that is furthest behind is forgotten.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PedometerSyncCursor {
+    /// Chosen by the host, e.g. randomly on its first start
+    pub host_id: u32,
+    pub boot_id: u32,
+    pub min_event_index: u32,
+}
+
+impl PedometerSyncCursor {
+    pub const SIZE: usize = 12;
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut buf = [0; Self::SIZE];
+        buf[..4].copy_from_slice(&self.host_id.to_le_bytes());
+        buf[4..8].copy_from_slice(&self.boot_id.to_le_bytes());
+        buf[8..].copy_from_slice(&self.min_event_index.to_le_bytes());
+        buf
+    }
+
+    pub fn from_bytes(buf: &[u8; Self::SIZE]) -> Self {
+        Self {
+            host_id: u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]),
+            boot_id: u32::from_le_bytes([buf[4], buf[5], buf[6], buf[7]]),
+            min_event_index: u32::from_le_bytes([buf[8], buf[9], buf[10], buf[11]]),
+        }
+    }
+
+    /// The host acknowledged the event.
+    pub fn is_synced(&self, event: &PedometerEvent) -> bool {
+        (event.boot_id, event.index) < (self.boot_id, self.min_event_index)
+    }
+}
+
+/// Types of events that can be requested with a [`PedometerEventQuery`].
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum PedometerEventFilter {
+    #[default]
+    All = 0,
+    /// Host epoch and boot events, which the host needs to convert the timestamps of the other
+    /// events
+    TimeReferences = 1,
+}
+
+impl PedometerEventFilter {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::All),
+            1 => Some(Self::TimeReferences),
+            _ => None,
+        }
+    }
+
+    pub fn matches(&self, event_type: &PedometerEventType) -> bool {
+        match self {
+            Self::All => true,
+            Self::TimeReferences => matches!(
+                event_type,
+                PedometerEventType::HostEpochMs(_)
+                    | PedometerEventType::Boot
+                    | PedometerEventType::BootWithResetReason(_)
+            ),
+        }
+    }
+}
+
+/// [`PedometerEventRequest`] that only returns the events of the given types. This way the host
+/// can get the time references of all boots before the bulk of the steps events.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PedometerEventQuery {
+    pub since: PedometerEventRequest,
+    pub filter: PedometerEventFilter,
+}
+
+impl PedometerEventQuery {
+    pub const SIZE: usize = PedometerEventRequest::SIZE + 1;
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut buf = [0; Self::SIZE];
+        buf[..PedometerEventRequest::SIZE].copy_from_slice(&self.since.to_bytes());
+        buf[PedometerEventRequest::SIZE] = self.filter as u8;
+        buf
+    }
+
+    /// Returns `None` if the filter is unknown.
+    pub fn from_bytes(buf: &[u8; Self::SIZE]) -> Option<Self> {
+        let (since, filter) = buf.split_at(PedometerEventRequest::SIZE);
+        Some(Self {
+            since: PedometerEventRequest::from_bytes(since.try_into().ok()?),
+            filter: PedometerEventFilter::from_u8(filter[0])?,
+        })
+    }
+
+    pub fn matches(&self, event: &PedometerEvent) -> bool {
+        self.since.matches(event) && self.filter.matches(&event.event_type)
+    }
+}
+
+/// Header of every notification of a chunked event transfer, which the host starts by writing a
+/// [`PedometerEventQuery`] to the request events transfer characteristic. The device then
+/// notifies the events in as many chunks as needed instead of one response per request. The
+/// events follow the header like in a single response.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PedometerEventChunkHeader {
+    /// Starts with 0 for every transfer, so the host notices a lost chunk
+    pub sequence: u16,
+    /// No more chunks follow
+    pub last: bool,
+    /// Only set together with `last` if the device stopped before all events were sent, e.g.
+    /// because the transfer got too long. The host requests the rest after its last event.
+    pub incomplete: bool,
+}
+
+impl PedometerEventChunkHeader {
+    pub const SIZE: usize = 3;
+    const LAST: u8 = 1 << 0;
+    const INCOMPLETE: u8 = 1 << 1;
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut buf = [0; Self::SIZE];
+        buf[..2].copy_from_slice(&self.sequence.to_le_bytes());
+        if self.last {
+            buf[2] |= Self::LAST;
+        }
+        if self.incomplete {
+            buf[2] |= Self::INCOMPLETE;
+        }
+        buf
+    }
+
+    pub fn from_bytes(buf: &[u8; Self::SIZE]) -> Self {
+        Self {
+            sequence: u16::from_le_bytes([buf[0], buf[1]]),
+            last: buf[2] & Self::LAST != 0,
+            incomplete: buf[2] & Self::INCOMPLETE != 0,
+        }
+    }
+}
+
+pub const TIME_SYNC_CHARACTERISTIC_SIZE: usize = 32;
+
+/// Messages of the round trip time synchronization via the time sync characteristic.
+///
+/// The host sends a [`Self::Request`] with its current time `t1`, the device answers with a
+/// [`Self::Response`] containing its uptime at reception and the host receives it at `t4`. The
+/// device time then corresponds to the host time `t1 + (t4 - t1) / 2`, which is sent back in a
+/// [`Self::Result`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, MaxSize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PedometerTimeSync {
+    Request { host_t1_ms: u64 },
+    Response { host_t1_ms: u64, device_ms: u64 },
+    Result { device_ms: u64, host_epoch_ms: u64 },
+}
+
+const _: () = assert!(PedometerTimeSync::POSTCARD_MAX_SIZE <= TIME_SYNC_CHARACTERISTIC_SIZE);
+
+impl PedometerTimeSync {
+    pub fn serialize_for_characteristic(
+        &self,
+    ) -> PedometerCommonResult<[u8; TIME_SYNC_CHARACTERISTIC_SIZE]> {
+        let mut buf = [0; TIME_SYNC_CHARACTERISTIC_SIZE];
+        postcard::to_slice(self, &mut buf)?;
+        Ok(buf)
+    }
+
+    pub fn deserialize(buf: &[u8]) -> PedometerCommonResult<Self> {
+        Ok(postcard::take_from_bytes(buf)?.0)
+    }
+}
+
+/// Start of the advertised name, followed by a suffix to tell multiple devices apart.
+pub const DEVICE_NAME_PREFIX: &str = "pedomet-rs";
+/// Length of the advertised name, e.g. `pedomet-rs-3F2A`
+pub const DEVICE_NAME_LEN: usize = DEVICE_NAME_PREFIX.len() + 5;
+
+/// Advertised name with the lower 16 bits of the device address as hexadecimal suffix.
+pub fn device_name(device_address: u32) -> [u8; DEVICE_NAME_LEN] {
+    const HEX_DIGITS: &[u8; 16] = b"0123456789ABCDEF";
+    let mut name = [b'-'; DEVICE_NAME_LEN];
+    name[..DEVICE_NAME_PREFIX.len()].copy_from_slice(DEVICE_NAME_PREFIX.as_bytes());
+    for (i, digit) in name[DEVICE_NAME_PREFIX.len() + 1..].iter_mut().enumerate() {
+        *digit = HEX_DIGITS[(device_address >> (12 - 4 * i) & 0xF) as usize];
+    }
+    name
+}
+
+/// Company identifier of the manufacturer specific advertising data. 0xFFFF is reserved for
+/// testing and not assigned to any company.
+pub const ADVERTISING_COMPANY_ID: u16 = 0xFFFF;
+
+/// Live data that is broadcast in the manufacturer specific advertising data, so that it can be
+/// shown without a connection.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PedometerAdvertisingData {
+    /// Steps since the last local midnight
+    pub daily_steps: u32,
+    pub soc: Option<u8>,
+    /// So many events were stored since the last sync that the device asks the host to sync
+    pub data_waiting: bool,
+}
+
+impl PedometerAdvertisingData {
+    /// Size without the company identifier
+    pub const SIZE: usize = 6;
+    /// Size of the data of older firmware without the flags
+    const MIN_SIZE: usize = 5;
+    const SOC_UNKNOWN: u8 = 0xFF;
+    const DATA_WAITING: u8 = 1 << 0;
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut buf = [0; Self::SIZE];
+        buf[..4].copy_from_slice(&self.daily_steps.to_le_bytes());
+        buf[4] = self.soc.unwrap_or(Self::SOC_UNKNOWN);
+        if self.data_waiting {
+            buf[5] |= Self::DATA_WAITING;
+        }
+        buf
+    }
+
+    /// Parse the manufacturer data without the company identifier.
+    pub fn from_bytes(buf: &[u8]) -> Option<Self> {
+        let flags = buf.get(Self::MIN_SIZE).copied().unwrap_or_default();
+        let buf: &[u8; Self::MIN_SIZE] = buf.get(..Self::MIN_SIZE)?.try_into().ok()?;
+        Some(Self {
+            daily_steps: u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]),
+            soc: Some(buf[4]).filter(|soc| *soc != Self::SOC_UNKNOWN),
+            data_waiting: flags & Self::DATA_WAITING != 0,
+        })
+    }
+}
+
+/// Size of the diagnostics characteristic. Shorter values are padded with zeros.
+pub const DIAGNOSTICS_CHARACTERISTIC_SIZE: usize = 48;
+
+/// Runtime counters of the device since the last boot.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize, MaxSize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PedometerDiagnostics {
+    /// Wake-ups because the IMU FIFO threshold was reached
+    pub fifo_interrupts: u32,
+    /// Wake-ups by the fallback timer because the FIFO threshold was not reached in time
+    pub timer_fallbacks: u32,
+    /// FIFO reads in which the IMU reported an overrun, i.e. lost samples
+    pub fifo_overruns: u32,
+    /// Power cycles of the IMU because it did not respond
+    pub imu_power_cycles: u32,
+    /// Commands that were dropped because the flash task could not keep up
+    pub dropped_commands: u32,
+    /// Steps that the detector of the IMU recognized, i.e. the entries of the FIFO
+    pub detected_steps: u32,
+    /// Steps that the step counter of the IMU reported after the debounce
+    pub counted_steps: u32,
+}
+
+const _: () = assert!(PedometerDiagnostics::POSTCARD_MAX_SIZE <= DIAGNOSTICS_CHARACTERISTIC_SIZE);
+
+impl PedometerDiagnostics {
+    pub fn serialize_for_characteristic(
+        &self,
+    ) -> PedometerCommonResult<[u8; DIAGNOSTICS_CHARACTERISTIC_SIZE]> {
+        let mut buf = [0; DIAGNOSTICS_CHARACTERISTIC_SIZE];
+        postcard::to_slice(self, &mut buf)?;
+        Ok(buf)
+    }
+
+    /// Older firmware sends fewer counters, which are read as 0 from the padding.
+    pub fn deserialize(buf: &[u8]) -> PedometerCommonResult<Self> {
+        Ok(postcard::take_from_bytes(buf)?.0)
+    }
+
+    /// Detected steps that were not counted, e.g. because fewer steps than the debounce steps
+    /// were taken in a row.
+    pub fn filtered_steps(&self) -> u32 {
+        self.detected_steps.saturating_sub(self.counted_steps)
+    }
+}
+
+/// Size of the write error characteristic. Shorter values are padded with zeros.
+pub const WRITE_ERROR_CHARACTERISTIC_SIZE: usize = 8;
+
+/// Reason why the device rejected a value that the host wrote to a characteristic.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, MaxSize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PedometerWriteError {
+    /// The value had the given length, which does not match the characteristic
+    InvalidLength(u8),
+    /// The value had the right length but could not be interpreted
+    InvalidValue,
+}
+
+/// Notified by the device whenever it rejected a write of the host.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, MaxSize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PedometerWriteStatus {
+    /// Short UUID of the written characteristic, see [`gatt::short_uuid`]
+    pub characteristic: u16,
+    pub error: PedometerWriteError,
+}
+
+const _: () = assert!(PedometerWriteStatus::POSTCARD_MAX_SIZE <= WRITE_ERROR_CHARACTERISTIC_SIZE);
+
+impl PedometerWriteStatus {
+    pub fn serialize_for_characteristic(
+        &self,
+    ) -> PedometerCommonResult<[u8; WRITE_ERROR_CHARACTERISTIC_SIZE]> {
+        let mut buf = [0; WRITE_ERROR_CHARACTERISTIC_SIZE];
+        postcard::to_slice(self, &mut buf)?;
+        Ok(buf)
+    }
+
+    pub fn deserialize(buf: &[u8]) -> PedometerCommonResult<Self> {
+        Ok(postcard::take_from_bytes(buf)?.0)
+    }
+}
+
+/// Result of the power-on self test as bitfield of the failed checks.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize, MaxSize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PedometerSelfTest(pub u8);
+
+impl PedometerSelfTest {
+    /// The IMU did not answer with the expected WHO_AM_I value
+    pub const IMU_NOT_RESPONDING: u8 = 1 << 0;
+    /// The event queue could not be read and was cleared
+    pub const STORAGE_UNREADABLE: u8 = 1 << 1;
+    /// The SAADC returned a battery voltage that is physically impossible
+    pub const SAADC_IMPLAUSIBLE: u8 = 1 << 2;
+    /// The battery voltage was below the cutoff
+    pub const BATTERY_LOW: u8 = 1 << 3;
+
+    pub fn passed(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn has_failed(&self, check: u8) -> bool {
+        self.0 & check != 0
+    }
+
+    pub fn set_failed(&mut self, check: u8) {
+        self.0 |= check;
+    }
+}
+
+/// Result of the maintenance of the event storage.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize, MaxSize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PedometerMaintenance {
+    /// Readable events in the storage
+    pub events: u32,
+    /// Entries that could not be read and were removed
+    pub removed_entries: u16,
+    /// Events whose index is not larger than the one of the previous event of the same boot
+    pub out_of_order_events: u16,
+    /// Fill level of the storage in percent after the maintenance
+    pub fill_percent: u8,
+}
+
+/// Notified by the device once it deleted events, so that the host knows which events it alone
+/// keeps from then on.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PedometerDeleteResult {
+    /// Entries that were actually deleted
+    pub deleted: u32,
+    /// Boot id and index of the oldest event that is still stored, `None` if the storage is empty
+    pub oldest: Option<(u32, u32)>,
+}
+
+impl PedometerDeleteResult {
+    pub const SIZE: usize = 13;
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut buf = [0; Self::SIZE];
+        buf[..4].copy_from_slice(&self.deleted.to_le_bytes());
+        if let Some((boot_id, index)) = self.oldest {
+            buf[4] = 1;
+            buf[5..9].copy_from_slice(&boot_id.to_le_bytes());
+            buf[9..].copy_from_slice(&index.to_le_bytes());
+        }
+        buf
+    }
+
+    pub fn from_bytes(buf: &[u8; Self::SIZE]) -> Self {
+        let u32_at = |i: usize| u32::from_le_bytes(buf[i..i + 4].try_into().unwrap());
+        Self {
+            deleted: u32_at(0),
+            oldest: (buf[4] != 0).then(|| (u32_at(5), u32_at(9))),
+        }
+    }
+}
+
+/// Usage of the event storage, so that the host can warn before old events are overwritten.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PedometerStorageUsage {
+    /// Bytes of the storage that are used by entries or cannot be reused, yet
+    pub used_bytes: u32,
+    pub capacity_bytes: u32,
+    /// Stored entries, i.e. events including the ones that were synced but not deleted, yet
+    pub entries: u32,
+}
+
+impl PedometerStorageUsage {
+    pub const SIZE: usize = 12;
+
+    pub fn free_bytes(&self) -> u32 {
+        self.capacity_bytes.saturating_sub(self.used_bytes)
+    }
+
+    pub fn fill_percent(&self) -> u8 {
+        if self.capacity_bytes == 0 {
+            return 100;
+        }
+        (self.used_bytes.min(self.capacity_bytes) as u64 * 100 / self.capacity_bytes as u64) as u8
+    }
+
+    /// Estimate how many more entries fit into the free bytes based on the average size of the
+    /// stored entries. `None` if there are no entries to estimate from.
+    pub fn entries_left(&self) -> Option<u32> {
+        if self.entries == 0 || self.used_bytes == 0 {
+            return None;
+        }
+        Some((self.free_bytes() as u64 * self.entries as u64 / self.used_bytes as u64) as u32)
+    }
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut buf = [0; Self::SIZE];
+        buf[..4].copy_from_slice(&self.used_bytes.to_le_bytes());
+        buf[4..8].copy_from_slice(&self.capacity_bytes.to_le_bytes());
+        buf[8..].copy_from_slice(&self.entries.to_le_bytes());
+        buf
+    }
+
+    pub fn from_bytes(buf: &[u8; Self::SIZE]) -> Self {
+        let u32_at = |i: usize| u32::from_le_bytes(buf[i..i + 4].try_into().unwrap());
+        Self {
+            used_bytes: u32_at(0),
+            capacity_bytes: u32_at(4),
+            entries: u32_at(8),
+        }
+    }
+}
+
+/// Version of the firmware, so that the host can tell which features the device supports and
+/// whether a newer firmware is available.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PedometerFirmwareVersion {
+    pub major: u8,
+    pub minor: u8,
+    pub patch: u8,
+}
+
+impl PedometerFirmwareVersion {
+    pub const SIZE: usize = 3;
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        [self.major, self.minor, self.patch]
+    }
+
+    pub fn from_bytes(buf: &[u8; Self::SIZE]) -> Self {
+        Self {
+            major: buf[0],
+            minor: buf[1],
+            patch: buf[2],
+        }
+    }
+}
+
+impl core::fmt::Display for PedometerFirmwareVersion {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// Stored events of one boot, so that the host knows which boots are on the device without
+/// requesting all events.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PedometerBootSummary {
+    pub boot_id: u32,
+    pub first_index: u32,
+    pub last_index: u32,
+    /// Device timestamps of the first and the last event
+    pub first_timestamp_ms: u64,
+    pub last_timestamp_ms: u64,
+    /// Steps of the stored step events. The steps before the first stored step event are only
+    /// included if the boot event is still stored, as the counter starts with the boot.
+    pub steps: u32,
+    last_step_counter: Option<u16>,
+}
+
+impl PedometerBootSummary {
+    pub const SIZE: usize = 32;
+
+    fn new(event: &PedometerEvent) -> Self {
+        let mut summary = Self {
+            boot_id: event.boot_id,
+            first_index: event.index,
+            first_timestamp_ms: event.timestamp_ms,
+            ..Default::default()
+        };
+        if matches!(
+            event.event_type,
+            PedometerEventType::Boot | PedometerEventType::BootWithResetReason(_)
+        ) {
+            summary.last_step_counter = Some(0);
+        }
+        summary.add(event);
+        summary
+    }
+
+    fn add(&mut self, event: &PedometerEvent) {
+        self.last_index = event.index;
+        self.last_timestamp_ms = event.timestamp_ms;
+        if let PedometerEventType::Steps(step_counter) = event.event_type {
+            if let Some(last_step_counter) = self.last_step_counter {
+                self.steps += step_counter.wrapping_sub(last_step_counter) as u32;
+            }
+            self.last_step_counter = Some(step_counter);
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut buf = [0; Self::SIZE];
+        buf[..4].copy_from_slice(&self.boot_id.to_le_bytes());
+        buf[4..8].copy_from_slice(&self.first_index.to_le_bytes());
+        buf[8..12].copy_from_slice(&self.last_index.to_le_bytes());
+        buf[12..20].copy_from_slice(&self.first_timestamp_ms.to_le_bytes());
+        buf[20..28].copy_from_slice(&self.last_timestamp_ms.to_le_bytes());
+        buf[28..].copy_from_slice(&self.steps.to_le_bytes());
+        buf
+    }
+
+    pub fn from_bytes(buf: &[u8; Self::SIZE]) -> Self {
+        let u32_at = |i: usize| u32::from_le_bytes(buf[i..i + 4].try_into().unwrap());
+        let u64_at = |i: usize| u64::from_le_bytes(buf[i..i + 8].try_into().unwrap());
+        Self {
+            boot_id: u32_at(0),
+            first_index: u32_at(4),
+            last_index: u32_at(8),
+            first_timestamp_ms: u64_at(12),
+            last_timestamp_ms: u64_at(20),
+            steps: u32_at(28),
+            last_step_counter: None,
+        }
+    }
+}
+
+/// Summaries of the boots starting with the requested one in ascending order. If there are more
+/// boots than fit into one response, the host requests the next ones after the last boot.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PedometerBootSummaries {
+    pub summaries: heapless::Vec<PedometerBootSummary, { Self::MAX_SUMMARIES }>,
+    pub more: bool,
+}
+
+impl PedometerBootSummaries {
+    pub const MAX_SUMMARIES: usize = 7;
+    pub const SIZE: usize = 2 + Self::MAX_SUMMARIES * PedometerBootSummary::SIZE;
+
+    /// Add an event of the queue, whose boots are stored in ascending order.
+    ///
+    /// Returns `false` if the event belongs to a boot that does not fit into the response
+    /// anymore, so the remaining events do not have to be read.
+    pub fn add(&mut self, event: &PedometerEvent) -> bool {
+        match self.summaries.last_mut() {
+            Some(summary) if summary.boot_id == event.boot_id => summary.add(event),
+            _ => {
+                if self
+                    .summaries
+                    .push(PedometerBootSummary::new(event))
+                    .is_err()
+                {
+                    self.more = true;
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut buf = [0; Self::SIZE];
+        buf[0] = self.summaries.len() as u8;
+        buf[1] = self.more as u8;
+        for (chunk, summary) in buf[2..]
+            .chunks_exact_mut(PedometerBootSummary::SIZE)
+            .zip(&self.summaries)
+        {
+            chunk.copy_from_slice(&summary.to_bytes());
+        }
+        buf
+    }
+
+    pub fn from_bytes(buf: &[u8; Self::SIZE]) -> Option<Self> {
+        let count = buf[0] as usize;
+        if count > Self::MAX_SUMMARIES {
+            return None;
+        }
+        Some(Self {
+            summaries: buf[2..]
+                .chunks_exact(PedometerBootSummary::SIZE)
+                .take(count)
+                // The chunks have exactly the size of a summary
+                .map(|chunk| PedometerBootSummary::from_bytes(chunk.try_into().unwrap()))
+                .collect(),
+            more: buf[1] != 0,
+        })
+    }
+}
+
+/// Raw sample of the accelerometer for evaluating the step detection. One digit is 0.061 mg.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PedometerAcceleration {
+    pub x: i16,
+    pub y: i16,
+    pub z: i16,
+}
+
+impl PedometerAcceleration {
+    pub const SIZE: usize = 6;
+    pub const MILLI_G_PER_DIGIT: f64 = 0.061;
+    /// The accelerometer runs at 26 Hz for the pedometer
+    pub const SAMPLE_INTERVAL_MS: u32 = 38;
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut buf = [0; Self::SIZE];
+        buf[..2].copy_from_slice(&self.x.to_le_bytes());
+        buf[2..4].copy_from_slice(&self.y.to_le_bytes());
+        buf[4..].copy_from_slice(&self.z.to_le_bytes());
+        buf
+    }
+
+    pub fn from_bytes(buf: &[u8; Self::SIZE]) -> Self {
+        Self {
+            x: i16::from_le_bytes([buf[0], buf[1]]),
+            y: i16::from_le_bytes([buf[2], buf[3]]),
+            z: i16::from_le_bytes([buf[4], buf[5]]),
+        }
+    }
+}
+
+/// Consecutive accelerometer samples that are notified together to reduce the number of
+/// notifications.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PedometerAccelerationSamples {
+    /// Device uptime of the first sample
+    pub uptime_ms: u32,
+    pub samples: [PedometerAcceleration; Self::SAMPLES],
+}
+
+impl PedometerAccelerationSamples {
+    pub const SAMPLES: usize = 8;
+    pub const SIZE: usize = 4 + Self::SAMPLES * PedometerAcceleration::SIZE;
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut buf = [0; Self::SIZE];
+        buf[..4].copy_from_slice(&self.uptime_ms.to_le_bytes());
+        for (chunk, sample) in buf[4..]
+            .chunks_exact_mut(PedometerAcceleration::SIZE)
+            .zip(&self.samples)
+        {
+            chunk.copy_from_slice(&sample.to_bytes());
+        }
+        buf
+    }
+
+    pub fn from_bytes(buf: &[u8; Self::SIZE]) -> Self {
+        let mut samples = [PedometerAcceleration::default(); Self::SAMPLES];
+        for (sample, chunk) in samples
+            .iter_mut()
+            .zip(buf[4..].chunks_exact(PedometerAcceleration::SIZE))
+        {
+            // The chunks have exactly the size of a sample
+            *sample = PedometerAcceleration::from_bytes(chunk.try_into().unwrap());
+        }
+        Self {
+            uptime_ms: u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]),
+            samples,
+        }
+    }
+
+    /// Device uptime of every sample in milliseconds
+    pub fn iter(&self) -> impl Iterator<Item = (u32, PedometerAcceleration)> + '_ {
+        self.samples.iter().enumerate().map(|(i, sample)| {
+            (
+                self.uptime_ms + i as u32 * PedometerAcceleration::SAMPLE_INTERVAL_MS,
+                *sample,
+            )
+        })
+    }
+}
+
 #[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum PedometerCommonError {
     Postcard,
+    /// A received event does not match its CRC
+    Crc,
 }
 
+#[cfg(feature = "std")]
+impl std::fmt::Display for PedometerCommonError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PedometerCommonError::Postcard => write!(f, "Postcard (de)serialization failed"),
+            PedometerCommonError::Crc => write!(f, "CRC mismatch"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for PedometerCommonError {}
+
 impl From<postcard::Error> for PedometerCommonError {
     fn from(_value: postcard::Error) -> Self {
         PedometerCommonError::Postcard
@@ -70,3 +1206,182 @@ impl From<postcard::Error> for PedometerCommonError {
 }
 
 pub type PedometerCommonResult<T> = Result<T, PedometerCommonError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(boot_id: u32, index: u32, event_type: PedometerEventType) -> PedometerEvent {
+        PedometerEvent {
+            index,
+            boot_id,
+            timestamp_ms: 0,
+            event_type,
+        }
+    }
+
+    #[test]
+    fn acceleration_samples() {
+        let samples = PedometerAccelerationSamples {
+            uptime_ms: 1_000,
+            samples: [PedometerAcceleration {
+                x: -1,
+                y: 2,
+                z: 16_393,
+            }; PedometerAccelerationSamples::SAMPLES],
+        };
+        assert_eq!(
+            PedometerAccelerationSamples::from_bytes(&samples.to_bytes()),
+            samples
+        );
+        assert_eq!(samples.iter().nth(2).unwrap().0, 1_076);
+    }
+
+    #[test]
+    fn boot_summaries() {
+        let mut summaries = PedometerBootSummaries::default();
+        // The boot event of the first boot was already deleted
+        for event in [
+            event(1, 10, PedometerEventType::Steps(100)),
+            event(1, 11, PedometerEventType::Steps(150)),
+            event(2, 12, PedometerEventType::BootWithResetReason(0)),
+            event(2, 13, PedometerEventType::Steps(u16::MAX)),
+            event(2, 14, PedometerEventType::Steps(4)),
+        ] {
+            assert!(summaries.add(&event));
+        }
+        let summaries = PedometerBootSummaries::from_bytes(&summaries.to_bytes()).unwrap();
+        assert!(!summaries.more);
+        let boots: heapless::Vec<_, { PedometerBootSummaries::MAX_SUMMARIES }> = summaries
+            .summaries
+            .iter()
+            .map(|summary| {
+                (
+                    summary.boot_id,
+                    summary.first_index,
+                    summary.last_index,
+                    summary.steps,
+                )
+            })
+            .collect();
+        assert_eq!(boots[..], [(1, 10, 11, 50), (2, 12, 14, 0x10004)]);
+
+        let mut summaries = PedometerBootSummaries::default();
+        for boot_id in 0..PedometerBootSummaries::MAX_SUMMARIES as u32 {
+            assert!(summaries.add(&event(boot_id, boot_id, PedometerEventType::Boot)));
+        }
+        assert!(!summaries.add(&event(100, 100, PedometerEventType::Boot)));
+        assert!(summaries.more);
+    }
+
+    #[test]
+    fn storage_usage() {
+        let usage = PedometerStorageUsage {
+            used_bytes: 1_000,
+            capacity_bytes: 4_000,
+            entries: 50,
+        };
+        assert_eq!(PedometerStorageUsage::from_bytes(&usage.to_bytes()), usage);
+        assert_eq!(usage.free_bytes(), 3_000);
+        assert_eq!(usage.fill_percent(), 25);
+        assert_eq!(usage.entries_left(), Some(150));
+        assert_eq!(PedometerStorageUsage::default().entries_left(), None);
+        assert_eq!(PedometerStorageUsage::default().fill_percent(), 100);
+    }
+
+    #[test]
+    fn delete_result() {
+        for result in [
+            PedometerDeleteResult {
+                deleted: 12,
+                oldest: Some((3, 40)),
+            },
+            PedometerDeleteResult {
+                deleted: 7,
+                oldest: None,
+            },
+        ] {
+            assert_eq!(
+                PedometerDeleteResult::from_bytes(&result.to_bytes()),
+                result
+            );
+        }
+    }
+
+    #[test]
+    fn event_chunk_header() {
+        for header in [
+            PedometerEventChunkHeader {
+                sequence: 513,
+                last: false,
+                incomplete: false,
+            },
+            PedometerEventChunkHeader {
+                sequence: 0,
+                last: true,
+                incomplete: true,
+            },
+        ] {
+            assert_eq!(
+                PedometerEventChunkHeader::from_bytes(&header.to_bytes()),
+                header
+            );
+        }
+    }
+
+    #[test]
+    fn advertising_data() {
+        let data = PedometerAdvertisingData {
+            daily_steps: 12_345,
+            soc: Some(80),
+            data_waiting: true,
+        };
+        assert_eq!(
+            PedometerAdvertisingData::from_bytes(&data.to_bytes()),
+            Some(data)
+        );
+        // Older firmware does not send the flags
+        assert_eq!(
+            PedometerAdvertisingData::from_bytes(&data.to_bytes()[..5]),
+            Some(PedometerAdvertisingData {
+                data_waiting: false,
+                ..data
+            })
+        );
+        assert_eq!(PedometerAdvertisingData::from_bytes(&[0; 4]), None);
+    }
+
+    #[test]
+    fn crc16_check_value() {
+        assert_eq!(crc16(b"123456789"), 0x29B1);
+    }
+
+    #[test]
+    fn event_transport_crc() {
+        let event = event(3, 7, PedometerEventType::Steps(1234));
+        let mut buf = [0; 64];
+        let len = event.serialize_for_transport(&mut buf).unwrap().len();
+        let mut frame = buf;
+        let (received, rest) = PedometerEvent::deserialize_from_transport(&mut frame).unwrap();
+        assert_eq!((received.boot_id, received.index), (3, 7));
+        assert_eq!(rest.len(), buf.len() - len);
+
+        // Corrupt the steps, which keeps the frame decodable
+        let mut frame = buf;
+        frame[len - 4] ^= 0x01;
+        assert!(matches!(
+            PedometerEvent::deserialize_from_transport(&mut frame),
+            Err(PedometerCommonError::Crc)
+        ));
+    }
+
+    #[test]
+    fn event_transport_without_crc() {
+        let event = event(3, 7, PedometerEventType::Steps(1234));
+        let mut buf = [0; 64];
+        postcard::to_slice_cobs(&event, &mut buf).unwrap();
+        let (received, _) =
+            PedometerEvent::deserialize_from_transport_without_crc(&mut buf).unwrap();
+        assert_eq!((received.boot_id, received.index), (3, 7));
+    }
+}
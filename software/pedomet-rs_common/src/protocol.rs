@@ -0,0 +1,367 @@
+//! Interpretation of the values that the host writes to the characteristics of the pedometer
+//! service.
+//!
+//! This does not depend on the BLE stack, so the firmware only has to pass the written values
+//! and carry out the resulting [`PedometerCommand`]s, while the protocol can be tested on the
+//! host.
+
+use crate::{
+    gatt, PedometerConfigValue, PedometerEvent, PedometerEventQuery, PedometerEventRequest,
+    PedometerMarker, PedometerSyncCursor, PedometerTimeSync, PedometerWriteError,
+};
+
+/// Events that the host requested to be notified via the response events characteristic.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PedometerEventSelection {
+    /// Events with at least the given index regardless of the boot
+    MinIndex(u32),
+    Since(PedometerEventRequest),
+    Query(PedometerEventQuery),
+}
+
+impl PedometerEventSelection {
+    pub fn matches(&self, event: &PedometerEvent) -> bool {
+        match self {
+            Self::MinIndex(min_event_index) => event.index >= *min_event_index,
+            Self::Since(request) => request.matches(event),
+            Self::Query(query) => query.matches(event),
+        }
+    }
+}
+
+/// What the device has to do after a valid write of the host.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PedometerCommand {
+    GetEvents(PedometerEventSelection),
+    /// Notify all matching events in chunks, see [`crate::PedometerEventChunkHeader`]
+    TransferEvents(PedometerEventQuery),
+    /// Delete the events before the given index that all hosts acknowledged
+    DeleteEvents(u32),
+    DeleteBoot(u32),
+    /// Update the cursor of a host and delete the events that all hosts acknowledged
+    AckEvents(PedometerSyncCursor),
+    /// Store the current host time. The device answers with its uptime, so the host can
+    /// correlate both.
+    SetEpochMs(u64),
+    /// Store the host time of the given device uptime, which is the result of a time sync
+    SetEpochMsAt {
+        host_epoch_ms: u64,
+        device_ms: u64,
+    },
+    StoreConfig(PedometerConfigValue),
+    /// Notify the answer to a time sync request
+    RespondTimeSync(PedometerTimeSync),
+    PushMarker(PedometerMarker),
+    /// Start or stop notifying the raw accelerometer samples
+    StreamAcceleration(bool),
+    /// Notify the summaries of the boots starting with the given boot id
+    GetBootSummaries(u32),
+}
+
+/// Check that a written value has exactly the size of the expected type.
+fn fixed_write_value<const N: usize>(data: &[u8]) -> Result<[u8; N], PedometerWriteError> {
+    data.try_into()
+        .map_err(|_| PedometerWriteError::InvalidLength(data.len() as u8))
+}
+
+/// Interpret a value that the host wrote to the given characteristic at the given device uptime.
+///
+/// Returns `Ok(None)` for characteristics that do not trigger a command. On error the write has
+/// to be rejected with the error, see [`crate::PedometerWriteStatus`].
+pub fn handle_write(
+    characteristic: u128,
+    data: &[u8],
+    device_ms: u64,
+) -> Result<Option<PedometerCommand>, PedometerWriteError> {
+    let command = match characteristic {
+        gatt::REQUEST_EVENTS => PedometerCommand::GetEvents(PedometerEventSelection::MinIndex(
+            u32::from_le_bytes(fixed_write_value(data)?),
+        )),
+        gatt::REQUEST_EVENTS_SINCE => PedometerCommand::GetEvents(PedometerEventSelection::Since(
+            PedometerEventRequest::from_bytes(&fixed_write_value(data)?),
+        )),
+        gatt::REQUEST_EVENTS_QUERY => PedometerCommand::GetEvents(PedometerEventSelection::Query(
+            PedometerEventQuery::from_bytes(&fixed_write_value(data)?)
+                .ok_or(PedometerWriteError::InvalidValue)?,
+        )),
+        gatt::DELETE_EVENTS => {
+            PedometerCommand::DeleteEvents(u32::from_le_bytes(fixed_write_value(data)?))
+        }
+        gatt::DELETE_BOOT => {
+            PedometerCommand::DeleteBoot(u32::from_le_bytes(fixed_write_value(data)?))
+        }
+        gatt::ACK_EVENTS => {
+            PedometerCommand::AckEvents(PedometerSyncCursor::from_bytes(&fixed_write_value(data)?))
+        }
+        gatt::EPOCH_MS => {
+            PedometerCommand::SetEpochMs(u64::from_le_bytes(fixed_write_value(data)?))
+        }
+        gatt::CONFIG => PedometerCommand::StoreConfig(
+            PedometerConfigValue::deserialize(data)
+                .map_err(|_| PedometerWriteError::InvalidValue)?,
+        ),
+        gatt::TIME_SYNC => {
+            match PedometerTimeSync::deserialize(data)
+                .map_err(|_| PedometerWriteError::InvalidValue)?
+            {
+                PedometerTimeSync::Request { host_t1_ms } => {
+                    PedometerCommand::RespondTimeSync(PedometerTimeSync::Response {
+                        host_t1_ms,
+                        device_ms,
+                    })
+                }
+                PedometerTimeSync::Result {
+                    device_ms,
+                    host_epoch_ms,
+                } => PedometerCommand::SetEpochMsAt {
+                    host_epoch_ms,
+                    device_ms,
+                },
+                // Only the device sends responses
+                PedometerTimeSync::Response { .. } => {
+                    return Err(PedometerWriteError::InvalidValue)
+                }
+            }
+        }
+        gatt::MARKER => {
+            let [value] = fixed_write_value(data)?;
+            PedometerCommand::PushMarker(
+                PedometerMarker::from_u8(value).ok_or(PedometerWriteError::InvalidValue)?,
+            )
+        }
+        gatt::REQUEST_EVENTS_TRANSFER => PedometerCommand::TransferEvents(
+            PedometerEventQuery::from_bytes(&fixed_write_value(data)?)
+                .ok_or(PedometerWriteError::InvalidValue)?,
+        ),
+        gatt::BOOT_SUMMARIES => {
+            PedometerCommand::GetBootSummaries(u32::from_le_bytes(fixed_write_value(data)?))
+        }
+        gatt::ACCELERATION => match fixed_write_value(data)? {
+            [0] => PedometerCommand::StreamAcceleration(false),
+            [1] => PedometerCommand::StreamAcceleration(true),
+            _ => return Err(PedometerWriteError::InvalidValue),
+        },
+        _ => return Ok(None),
+    };
+    Ok(Some(command))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{PedometerEventFilter, PedometerEventType, TIME_SYNC_CHARACTERISTIC_SIZE};
+
+    use super::*;
+
+    fn event(boot_id: u32, index: u32, event_type: PedometerEventType) -> PedometerEvent {
+        PedometerEvent {
+            index,
+            boot_id,
+            timestamp_ms: 0,
+            event_type,
+        }
+    }
+
+    fn time_sync(message: PedometerTimeSync) -> [u8; TIME_SYNC_CHARACTERISTIC_SIZE] {
+        message.serialize_for_characteristic().unwrap()
+    }
+
+    #[test]
+    fn request_events() {
+        assert_eq!(
+            handle_write(gatt::REQUEST_EVENTS, &42u32.to_le_bytes(), 0),
+            Ok(Some(PedometerCommand::GetEvents(
+                PedometerEventSelection::MinIndex(42)
+            )))
+        );
+        let request = PedometerEventRequest {
+            boot_id: 3,
+            min_event_index: 7,
+        };
+        assert_eq!(
+            handle_write(gatt::REQUEST_EVENTS_SINCE, &request.to_bytes(), 0),
+            Ok(Some(PedometerCommand::GetEvents(
+                PedometerEventSelection::Since(request)
+            )))
+        );
+        let query = PedometerEventQuery {
+            since: request,
+            filter: PedometerEventFilter::TimeReferences,
+        };
+        assert_eq!(
+            handle_write(gatt::REQUEST_EVENTS_QUERY, &query.to_bytes(), 0),
+            Ok(Some(PedometerCommand::GetEvents(
+                PedometerEventSelection::Query(query)
+            )))
+        );
+        assert_eq!(
+            handle_write(gatt::REQUEST_EVENTS_TRANSFER, &query.to_bytes(), 0),
+            Ok(Some(PedometerCommand::TransferEvents(query)))
+        );
+    }
+
+    #[test]
+    fn reject_unknown_event_filter() {
+        let mut data = PedometerEventQuery::default().to_bytes();
+        data[PedometerEventRequest::SIZE] = 0xff;
+        assert_eq!(
+            handle_write(gatt::REQUEST_EVENTS_QUERY, &data, 0),
+            Err(PedometerWriteError::InvalidValue)
+        );
+    }
+
+    #[test]
+    fn reject_invalid_length() {
+        assert_eq!(
+            handle_write(gatt::DELETE_EVENTS, &[1, 2, 3], 0),
+            Err(PedometerWriteError::InvalidLength(3))
+        );
+        assert_eq!(
+            handle_write(gatt::EPOCH_MS, &42u32.to_le_bytes(), 0),
+            Err(PedometerWriteError::InvalidLength(4))
+        );
+        assert_eq!(
+            handle_write(gatt::ACK_EVENTS, &[], 0),
+            Err(PedometerWriteError::InvalidLength(0))
+        );
+    }
+
+    #[test]
+    fn delete_and_ack_events() {
+        assert_eq!(
+            handle_write(gatt::DELETE_EVENTS, &10u32.to_le_bytes(), 0),
+            Ok(Some(PedometerCommand::DeleteEvents(10)))
+        );
+        assert_eq!(
+            handle_write(gatt::DELETE_BOOT, &2u32.to_le_bytes(), 0),
+            Ok(Some(PedometerCommand::DeleteBoot(2)))
+        );
+        let cursor = PedometerSyncCursor {
+            host_id: 0xdead_beef,
+            boot_id: 2,
+            min_event_index: 100,
+        };
+        assert_eq!(
+            handle_write(gatt::ACK_EVENTS, &cursor.to_bytes(), 0),
+            Ok(Some(PedometerCommand::AckEvents(cursor)))
+        );
+    }
+
+    #[test]
+    fn time_sync_round_trip() {
+        assert_eq!(
+            handle_write(
+                gatt::TIME_SYNC,
+                &time_sync(PedometerTimeSync::Request { host_t1_ms: 1_000 }),
+                500
+            ),
+            Ok(Some(PedometerCommand::RespondTimeSync(
+                PedometerTimeSync::Response {
+                    host_t1_ms: 1_000,
+                    device_ms: 500
+                }
+            )))
+        );
+        assert_eq!(
+            handle_write(
+                gatt::TIME_SYNC,
+                &time_sync(PedometerTimeSync::Result {
+                    device_ms: 500,
+                    host_epoch_ms: 1_020
+                }),
+                600
+            ),
+            Ok(Some(PedometerCommand::SetEpochMsAt {
+                host_epoch_ms: 1_020,
+                device_ms: 500
+            }))
+        );
+        assert_eq!(
+            handle_write(
+                gatt::TIME_SYNC,
+                &time_sync(PedometerTimeSync::Response {
+                    host_t1_ms: 1_000,
+                    device_ms: 500
+                }),
+                600
+            ),
+            Err(PedometerWriteError::InvalidValue)
+        );
+        assert_eq!(
+            handle_write(gatt::TIME_SYNC, &[0xff], 600),
+            Err(PedometerWriteError::InvalidValue)
+        );
+    }
+
+    #[test]
+    fn epoch_ms() {
+        assert_eq!(
+            handle_write(gatt::EPOCH_MS, &1_700_000_000_000u64.to_le_bytes(), 0),
+            Ok(Some(PedometerCommand::SetEpochMs(1_700_000_000_000)))
+        );
+    }
+
+    #[test]
+    fn marker() {
+        assert_eq!(
+            handle_write(gatt::MARKER, &[0], 0),
+            Ok(Some(PedometerCommand::PushMarker(
+                PedometerMarker::SessionStart
+            )))
+        );
+        assert_eq!(
+            handle_write(gatt::MARKER, &[0xff], 0),
+            Err(PedometerWriteError::InvalidValue)
+        );
+    }
+
+    #[test]
+    fn stream_acceleration() {
+        assert_eq!(
+            handle_write(gatt::ACCELERATION, &[1], 0),
+            Ok(Some(PedometerCommand::StreamAcceleration(true)))
+        );
+        assert_eq!(
+            handle_write(gatt::ACCELERATION, &[0], 0),
+            Ok(Some(PedometerCommand::StreamAcceleration(false)))
+        );
+        assert_eq!(
+            handle_write(gatt::ACCELERATION, &[2], 0),
+            Err(PedometerWriteError::InvalidValue)
+        );
+    }
+
+    #[test]
+    fn boot_summaries() {
+        assert_eq!(
+            handle_write(gatt::BOOT_SUMMARIES, &3u32.to_le_bytes(), 0),
+            Ok(Some(PedometerCommand::GetBootSummaries(3)))
+        );
+    }
+
+    #[test]
+    fn ignore_read_only_characteristics() {
+        assert_eq!(handle_write(gatt::BOOT_ID, &[1, 2, 3, 4], 0), Ok(None));
+    }
+
+    #[test]
+    fn event_selection() {
+        let steps = PedometerEventType::Steps(10);
+        let boot = PedometerEventType::Boot;
+        let since = PedometerEventRequest {
+            boot_id: 2,
+            min_event_index: 5,
+        };
+        assert!(PedometerEventSelection::MinIndex(5).matches(&event(1, 5, steps)));
+        assert!(!PedometerEventSelection::MinIndex(5).matches(&event(3, 4, steps)));
+        assert!(PedometerEventSelection::Since(since).matches(&event(3, 0, steps)));
+        assert!(!PedometerEventSelection::Since(since).matches(&event(2, 4, steps)));
+        let query = PedometerEventSelection::Query(PedometerEventQuery {
+            since,
+            filter: PedometerEventFilter::TimeReferences,
+        });
+        assert!(query.matches(&event(2, 5, boot)));
+        assert!(!query.matches(&event(2, 6, steps)));
+    }
+}
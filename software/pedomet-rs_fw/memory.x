@@ -2,6 +2,9 @@ MEMORY
 {
   /* NOTE 1 K = 1 KiBi = 1024 bytes */
   /* You must fill in these values for your application */
-  FLASH : ORIGIN = 0x00000000 + 156K, LENGTH = 1024K - 156K - 512K
+  /* The top 512K are used for the event queue, the 8K below for the config, the 8K below that
+     for the sync cursors, the 8K below that for the bonds and the 8K below that for the counters
+     of the event queue */
+  FLASH : ORIGIN = 0x00000000 + 156K, LENGTH = 1024K - 156K - 512K - 8K - 8K - 8K - 8K
   RAM : ORIGIN = 0x20000000 + 12K, LENGTH = 256K - 12K
 }
@@ -0,0 +1,102 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use embassy_sync::{
+    blocking_mutex::raw::CriticalSectionRawMutex, channel::Channel, signal::Signal,
+};
+use embassy_time::{Duration, Instant, Timer};
+use heapless::Vec;
+use nrf_softdevice::ble::Connection;
+use pedomet_rs_common::{PedometerAcceleration, PedometerAccelerationSamples};
+use pedomet_rs_imu::Acceleration;
+
+use crate::fmt::{debug, info, unwrap};
+use crate::{Server, WriteValue};
+
+const SAMPLE_INTERVAL: Duration =
+    Duration::from_millis(PedometerAcceleration::SAMPLE_INTERVAL_MS as u64);
+/// Samples further apart were not read consecutively, e.g. because the stream was paused
+const MAX_SAMPLE_GAP: Duration =
+    Duration::from_millis(2 * PedometerAcceleration::SAMPLE_INTERVAL_MS as u64);
+const SAMPLE_CHANNEL_SIZE: usize = 2 * PedometerAccelerationSamples::SAMPLES;
+
+/// Whether the host enabled the stream by writing the acceleration characteristic
+static STREAMING: AtomicBool = AtomicBool::new(false);
+/// Wakes the IMU task when the stream was enabled
+static STREAMING_SIGNAL: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+static SAMPLE_CHANNEL: Channel<
+    CriticalSectionRawMutex,
+    (Instant, PedometerAcceleration),
+    SAMPLE_CHANNEL_SIZE,
+> = Channel::new();
+
+/// Start or stop reading the raw accelerometer samples. The stream is stopped whenever a host
+/// connects or disconnects, so it does not drain the battery unnoticed.
+pub fn set_enabled(enabled: bool) {
+    if STREAMING.swap(enabled, Ordering::Relaxed) != enabled {
+        info!("Acceleration stream enabled: {}", enabled);
+    }
+    if enabled {
+        STREAMING_SIGNAL.signal(());
+    }
+}
+
+pub fn is_enabled() -> bool {
+    STREAMING.load(Ordering::Relaxed)
+}
+
+/// Wait until the next sample has to be read by the IMU task. Never returns while the stream is
+/// disabled.
+pub async fn wait_for_sample() {
+    while !STREAMING.load(Ordering::Relaxed) {
+        STREAMING_SIGNAL.wait().await;
+    }
+    Timer::after(SAMPLE_INTERVAL).await;
+}
+
+/// Pass a sample to the connection. It is dropped if the notifications fall behind.
+pub fn push_sample(acceleration: Acceleration) {
+    let sample = PedometerAcceleration {
+        x: acceleration.x,
+        y: acceleration.y,
+        z: acceleration.z,
+    };
+    if SAMPLE_CHANNEL.try_send((Instant::now(), sample)).is_err() {
+        debug!("Acceleration sample dropped");
+    }
+}
+
+/// Notify the samples in batches of consecutive ones.
+pub async fn notify_acceleration(server: &Server, connection: &Connection) -> ! {
+    let mut samples: Vec<
+        (Instant, PedometerAcceleration),
+        { PedometerAccelerationSamples::SAMPLES },
+    > = Vec::new();
+    loop {
+        let sample = SAMPLE_CHANNEL.receive().await;
+        if samples
+            .last()
+            .is_some_and(|(last, _)| sample.0.saturating_duration_since(*last) > MAX_SAMPLE_GAP)
+        {
+            samples.clear();
+        }
+        let _ = samples.push(sample);
+        if !samples.is_full() {
+            continue;
+        }
+
+        let batch = PedometerAccelerationSamples {
+            uptime_ms: samples[0].0.as_millis() as u32,
+            samples: core::array::from_fn(|i| samples[i].1),
+        };
+        samples.clear();
+        if !STREAMING.load(Ordering::Relaxed) {
+            continue;
+        }
+        if let Err(e) = server.pedometer.acceleration_notify(
+            connection,
+            &unwrap!(WriteValue::from_slice(&batch.to_bytes())),
+        ) {
+            debug!("Could not send acceleration samples! {:?}", e);
+        }
+    }
+}
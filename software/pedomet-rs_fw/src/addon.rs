@@ -0,0 +1,33 @@
+//! Extension point for add-on boards with further sensors, e.g. for the heart rate.
+//!
+//! The driver of an add-on sends its readings to [`ADDON_READING_CHANNEL`]. They are stored in
+//! the event queue next to the steps and synced by the hosts like all other events, so an add-on
+//! needs no changes to the protocol.
+
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, channel::Channel};
+use pedomet_rs_common::{PedometerEventType, PedometerSensorReading};
+
+use crate::fmt::debug;
+use crate::{FlashCommand, FlashCommandSender};
+
+/// Readings that may queue up while the flash task is busy, e.g. during a sync
+const ADDON_READING_CHANNEL_SIZE: usize = 8;
+
+pub static ADDON_READING_CHANNEL: Channel<
+    CriticalSectionRawMutex,
+    PedometerSensorReading,
+    ADDON_READING_CHANNEL_SIZE,
+> = Channel::new();
+
+/// Store the readings of the add-on sensors as events.
+#[embassy_executor::task]
+pub(crate) async fn addon_task(flash_command_sender: FlashCommandSender) -> ! {
+    loop {
+        let reading = ADDON_READING_CHANNEL.receive().await;
+        debug!("Add-on sensor reading: {:?}", reading);
+        flash_command_sender.send_or_drop(FlashCommand::PushEvent((
+            PedometerEventType::SensorReading(reading),
+            None,
+        )));
+    }
+}
@@ -0,0 +1,214 @@
+use core::cell::RefCell;
+use core::ops::Range;
+
+use embassy_sync::blocking_mutex::{raw::CriticalSectionRawMutex, Mutex};
+use embassy_time::{Duration, Instant};
+use embedded_storage_async::nor_flash::NorFlash;
+use nrf_softdevice::ble::{
+    gatt_server,
+    security::{IoCapabilities, SecurityHandler},
+    Connection, EncryptionInfo, IdentityKey, MasterId,
+};
+use nrf_softdevice::raw;
+use sequential_storage::{cache::NoCache, map};
+
+use crate::{
+    error::PedometerResult,
+    fmt::{info, warn},
+    storage_event_queue::PAGE_SIZE,
+    sync_cursors::SYNC_CURSORS_FLASH_RANGE,
+    FlashCommand, FlashCommandSender,
+};
+
+const BONDS_FLASH_SIZE: u32 = 2 * PAGE_SIZE;
+/// The bonds are stored directly in front of the sync cursors
+pub(crate) const BONDS_FLASH_RANGE: Range<u32> =
+    (SYNC_CURSORS_FLASH_RANGE.start - BONDS_FLASH_SIZE)..SYNC_CURSORS_FLASH_RANGE.start;
+/// All bonds are stored as one item
+const BONDS_KEY: u8 = 0;
+/// Hosts that can be bonded at the same time. If another one bonds, the oldest bond is dropped.
+const MAX_BONDS: usize = 4;
+const BONDS_DATA_SIZE: usize = MAX_BONDS * Bond::SIZE;
+/// New hosts can only pair this long after boot, unless no host is bonded, yet. Otherwise
+/// anybody nearby could pair and read the events, as the device cannot show a passkey. Pairing
+/// without bonding is refused as well, because it would still encrypt the link.
+const BONDING_WINDOW: Duration = Duration::from_secs(5 * 60);
+
+/// Bonds of the hosts, unknown until they were loaded from the flash
+static BONDS: Mutex<CriticalSectionRawMutex, RefCell<Option<heapless::Vec<Bond, MAX_BONDS>>>> =
+    Mutex::new(RefCell::new(None));
+
+#[derive(Copy, Clone)]
+struct Bond {
+    master_id: MasterId,
+    key: EncryptionInfo,
+    peer_id: IdentityKey,
+}
+
+impl Bond {
+    const SIZE: usize = 50;
+
+    fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut buf = [0; Self::SIZE];
+        buf[..2].copy_from_slice(&self.master_id.ediv.to_le_bytes());
+        buf[2..10].copy_from_slice(&self.master_id.rand);
+        buf[10..26].copy_from_slice(&self.key.ltk);
+        buf[26] = self.key.flags;
+        let peer_id = self.peer_id.as_raw();
+        buf[27..43].copy_from_slice(&peer_id.id_info.irk);
+        buf[43] = peer_id.id_addr_info.addr_type();
+        buf[44..].copy_from_slice(&peer_id.id_addr_info.addr);
+        buf
+    }
+
+    fn from_bytes(buf: &[u8; Self::SIZE]) -> Self {
+        let mut rand = [0; 8];
+        rand.copy_from_slice(&buf[2..10]);
+        let mut ltk = [0; 16];
+        ltk.copy_from_slice(&buf[10..26]);
+        let mut irk = [0; 16];
+        irk.copy_from_slice(&buf[27..43]);
+        let mut addr = [0; 6];
+        addr.copy_from_slice(&buf[44..]);
+        Self {
+            master_id: MasterId {
+                ediv: u16::from_le_bytes([buf[0], buf[1]]),
+                rand,
+            },
+            key: EncryptionInfo {
+                ltk,
+                flags: buf[26],
+            },
+            peer_id: IdentityKey::from_raw(raw::ble_gap_id_key_t {
+                id_info: raw::ble_gap_irk_t { irk },
+                id_addr_info: raw::ble_gap_addr_t {
+                    _bitfield_1: raw::ble_gap_addr_t::new_bitfield_1(0, buf[43]),
+                    addr,
+                },
+            }),
+        }
+    }
+}
+
+/// Load the stored bonds. If they cannot be read, the hosts have to bond again.
+pub async fn load_bonds<S: NorFlash>(flash: &mut S) {
+    let mut bonds = heapless::Vec::new();
+    let mut buf = [0_u8; BONDS_DATA_SIZE + 16];
+    let item: Result<Option<&[u8]>, _> = map::fetch_item(
+        flash,
+        BONDS_FLASH_RANGE,
+        &mut NoCache::new(),
+        &mut buf,
+        &BONDS_KEY,
+    )
+    .await;
+    match item {
+        Ok(Some(data)) => {
+            for chunk in data.chunks_exact(Bond::SIZE) {
+                // The chunks have the right size and there are not more than can be stored
+                if let Ok(chunk) = chunk.try_into() {
+                    let _ = bonds.push(Bond::from_bytes(chunk));
+                }
+            }
+        }
+        Ok(None) => {}
+        Err(_) => warn!("Could not read bonds"),
+    }
+    info!("Loaded {} bonds", bonds.len());
+    BONDS.lock(|cell| *cell.borrow_mut() = Some(bonds));
+}
+
+/// Persist the current bonds.
+pub async fn store_bonds<S: NorFlash>(flash: &mut S) -> PedometerResult<()> {
+    let mut data = [0_u8; BONDS_DATA_SIZE];
+    let len = BONDS.lock(|cell| {
+        let bonds = cell.borrow();
+        let bonds = bonds.as_deref().unwrap_or_default();
+        for (bond, chunk) in bonds.iter().zip(data.chunks_exact_mut(Bond::SIZE)) {
+            chunk.copy_from_slice(&bond.to_bytes());
+        }
+        bonds.len()
+    });
+    let mut buf = [0_u8; BONDS_DATA_SIZE + 16];
+    map::store_item(
+        flash,
+        BONDS_FLASH_RANGE,
+        &mut NoCache::new(),
+        &mut buf,
+        &BONDS_KEY,
+        &&data[..len * Bond::SIZE],
+    )
+    .await?;
+    info!("Stored {} bonds", len);
+    Ok(())
+}
+
+/// Bonds with hosts via Just Works, as the device has neither a display nor buttons, and keeps
+/// the bonds in the flash.
+pub struct Bonder {
+    pub flash_command_sender: FlashCommandSender,
+}
+
+impl SecurityHandler for Bonder {
+    fn io_capabilities(&self) -> IoCapabilities {
+        IoCapabilities::None
+    }
+
+    /// Asked for every pairing. Bonded hosts encrypt the link with their stored key instead, so
+    /// an unknown host is disconnected if it cannot bond.
+    fn can_bond(&self, conn: &Connection) -> bool {
+        let can_bond = BONDS.lock(|cell| match cell.borrow().as_ref() {
+            Some(bonds) => bonds.is_empty() || Instant::now() < Instant::MIN + BONDING_WINDOW,
+            None => false,
+        });
+        if !can_bond {
+            warn!("Refuse pairing outside of the bonding window");
+            // Fails only if the host already disconnected
+            let _ = conn.disconnect();
+        }
+        can_bond
+    }
+
+    fn on_bonded(
+        &self,
+        _conn: &Connection,
+        master_id: MasterId,
+        key: EncryptionInfo,
+        peer_id: IdentityKey,
+    ) {
+        info!("Bonded with new host");
+        BONDS.lock(|cell| {
+            if let Some(bonds) = cell.borrow_mut().as_mut() {
+                // A host that bonds again replaces its previous bond
+                bonds.retain(|bond| !bond.peer_id.is_match(peer_id.addr));
+                if bonds.is_full() {
+                    bonds.remove(0);
+                }
+                let _ = bonds.push(Bond {
+                    master_id,
+                    key,
+                    peer_id,
+                });
+            }
+        });
+        self.flash_command_sender
+            .send_or_drop(FlashCommand::StoreBonds);
+    }
+
+    fn get_key(&self, _conn: &Connection, master_id: MasterId) -> Option<EncryptionInfo> {
+        BONDS.lock(|cell| {
+            cell.borrow()
+                .as_ref()?
+                .iter()
+                .find(|bond| bond.master_id == master_id)
+                .map(|bond| bond.key)
+        })
+    }
+
+    fn load_sys_attrs(&self, conn: &Connection) {
+        // The subscriptions are not stored, the host subscribes again on every connection
+        if let Err(e) = gatt_server::set_sys_attrs(conn, None) {
+            warn!("Could not set system attributes! {:?}", e);
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use embassy_futures::select::{select3, Either3};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, watch::Watch};
+use embassy_time::{Instant, Timer};
+
+use crate::config::CONFIG_WATCH;
+use crate::fmt::{debug, info, unwrap};
+
+const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+
+/// Difference between the unix epoch and the device uptime in ms. Updated on every time sync.
+pub static EPOCH_OFFSET_MS_WATCH: Watch<CriticalSectionRawMutex, u64, 4> = Watch::new();
+
+pub fn set_host_epoch_ms(epoch_ms: u64) {
+    set_host_epoch_ms_at(epoch_ms, Instant::now().as_millis());
+}
+
+/// Set the host time that corresponds to the given device uptime.
+pub fn set_host_epoch_ms_at(epoch_ms: u64, device_ms: u64) {
+    let epoch_offset_ms = epoch_ms.saturating_sub(device_ms);
+    info!("Set epoch offset to {}ms", epoch_offset_ms);
+    EPOCH_OFFSET_MS_WATCH.sender().send(epoch_offset_ms);
+}
+
+/// Current local hour, unknown if the time was not synchronized by a host, yet.
+pub fn local_hour() -> Option<u8> {
+    let epoch_offset_ms = EPOCH_OFFSET_MS_WATCH.try_get()?;
+    let utc_offset_ms = CONFIG_WATCH
+        .try_get()
+        .map_or(0, |config| config.utc_offset_minutes as i64 * 60 * 1000);
+    let local_now_ms = (Instant::now().as_millis() + epoch_offset_ms) as i64 + utc_offset_ms;
+    Some((local_now_ms.rem_euclid(DAY_MS) / (60 * 60 * 1000)) as u8)
+}
+
+/// Wait until the next local midnight.
+///
+/// This never returns if the time was not synchronized by a host, yet. Time syncs and changes of
+/// the configured UTC offset while waiting are taken into account.
+pub async fn wait_for_local_midnight() {
+    let mut epoch_offset_rx = unwrap!(EPOCH_OFFSET_MS_WATCH.receiver());
+    let mut config_rx = unwrap!(CONFIG_WATCH.receiver());
+    loop {
+        let epoch_offset_ms = epoch_offset_rx.get().await;
+        let utc_offset_ms = config_rx
+            .try_get()
+            .map_or(0, |config| config.utc_offset_minutes as i64 * 60 * 1000);
+
+        let local_now_ms = (Instant::now().as_millis() + epoch_offset_ms) as i64 + utc_offset_ms;
+        let ms_until_midnight = DAY_MS - local_now_ms.rem_euclid(DAY_MS);
+        debug!("Next local midnight in {}ms", ms_until_midnight);
+
+        match select3(
+            Timer::after_millis(ms_until_midnight as u64),
+            epoch_offset_rx.changed(),
+            config_rx.changed(),
+        )
+        .await
+        {
+            Either3::First(_) => return,
+            _ => debug!("Time was synchronized, recalculate local midnight"),
+        }
+    }
+}
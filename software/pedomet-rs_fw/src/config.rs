@@ -0,0 +1,71 @@
+use core::ops::Range;
+
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, watch::Watch};
+use embedded_storage_async::nor_flash::NorFlash;
+use pedomet_rs_common::{PedometerConfig, PedometerConfigValue};
+use sequential_storage::{cache::NoCache, map};
+
+use crate::{
+    error::PedometerResult,
+    fmt::{info, warn},
+    storage_event_queue::{PAGE_SIZE, QUEUE_FLASH_RANGE},
+};
+
+const CONFIG_FLASH_SIZE: u32 = 2 * PAGE_SIZE;
+/// The config is stored directly in front of the event queue
+pub(crate) const CONFIG_FLASH_RANGE: Range<u32> =
+    (QUEUE_FLASH_RANGE.start - CONFIG_FLASH_SIZE)..QUEUE_FLASH_RANGE.start;
+
+pub static CONFIG_WATCH: Watch<CriticalSectionRawMutex, PedometerConfig, 5> = Watch::new();
+
+/// Load all stored config values and publish the resulting config.
+///
+/// Values that cannot be read are logged and left at their defaults.
+pub async fn load_config<S: NorFlash>(flash: &mut S) -> PedometerConfig {
+    let mut config = PedometerConfig::default();
+    let mut buf = [0_u8; 64];
+    for key in 0..PedometerConfigValue::NUM_KEYS {
+        let item: Result<Option<&[u8]>, _> = map::fetch_item(
+            flash,
+            CONFIG_FLASH_RANGE,
+            &mut NoCache::new(),
+            &mut buf,
+            &key,
+        )
+        .await;
+        match item {
+            Ok(Some(data)) => match PedometerConfigValue::deserialize(data) {
+                Ok(value) => config.apply(value),
+                Err(e) => warn!("Could not deserialize config value {}: {:?}", key, e),
+            },
+            Ok(None) => {}
+            Err(_) => warn!("Could not read config value {}", key),
+        }
+    }
+    info!("Loaded config: {:?}", config);
+    CONFIG_WATCH.sender().send(config);
+    config
+}
+
+/// Persist a single config value and publish the updated config.
+pub async fn store_config_value<S: NorFlash>(
+    flash: &mut S,
+    config: &mut PedometerConfig,
+    value: PedometerConfigValue,
+) -> PedometerResult<()> {
+    let data = value.serialize()?;
+    let mut buf = [0_u8; 64];
+    map::store_item(
+        flash,
+        CONFIG_FLASH_RANGE,
+        &mut NoCache::new(),
+        &mut buf,
+        &value.key(),
+        &&data[..],
+    )
+    .await?;
+    config.apply(value);
+    info!("Stored config value {:?} => {:?}", value, config);
+    CONFIG_WATCH.sender().send(*config);
+    Ok(())
+}
@@ -27,9 +27,12 @@ impl From<pedomet_rs_common::PedometerCommonError> for PedometerFwError {
     }
 }
 
-impl From<embedded_hal_async::i2c::ErrorKind> for PedometerFwError {
-    fn from(_value: embedded_hal_async::i2c::ErrorKind) -> Self {
-        PedometerFwError::Imu
+impl<E> From<pedomet_rs_imu::Error<E>> for PedometerFwError {
+    fn from(value: pedomet_rs_imu::Error<E>) -> Self {
+        match value {
+            pedomet_rs_imu::Error::I2c(_) => PedometerFwError::Imu,
+            pedomet_rs_imu::Error::InvalidFifoThreshold => PedometerFwError::Misc,
+        }
     }
 }
 
@@ -0,0 +1,27 @@
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, signal::Signal};
+
+use crate::fmt::{info, warn};
+use crate::{BAT_SOC_WATCH, LED_BLINK_SIGNAL};
+
+/// State of charge in percent that is shown by one LED blink
+const SOC_PERCENT_PER_BLINK: u8 = 20;
+
+/// Signaled by the IMU task whenever the user tapped the device twice
+pub static DOUBLE_TAP_SIGNAL: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+/// Show the state of charge with the LED after a double tap, so the battery can be checked
+/// without a host. The LED blinks once per started 20%.
+#[embassy_executor::task]
+pub async fn gesture_task() -> ! {
+    loop {
+        DOUBLE_TAP_SIGNAL.wait().await;
+        match BAT_SOC_WATCH.try_get() {
+            Some(soc) => {
+                let blinks = soc.div_ceil(SOC_PERCENT_PER_BLINK).max(1);
+                info!("Double tap, show soc of {}% with {} blinks", soc, blinks);
+                LED_BLINK_SIGNAL.signal(blinks);
+            }
+            None => warn!("Double tap, but the battery was not measured, yet"),
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use embassy_futures::select::{select3, Either3};
+use embassy_time::{Duration, Instant, Timer};
+
+use crate::config::CONFIG_WATCH;
+use crate::fmt::{debug, info, unwrap};
+use crate::{clock, DAILY_STEPS_WATCH, LED_BLINK_SIGNAL};
+
+/// Number of LED blinks of the idle alert
+const IDLE_ALERT_BLINKS: u8 = 5;
+
+/// Alert the user if no steps were counted for the configured duration during the active hours.
+///
+/// The alert is repeated after the same duration if the user stays idle. It is never raised if
+/// the time was not synchronized by a host, yet, because the active hours are unknown.
+#[embassy_executor::task]
+pub async fn idle_alert_task() -> ! {
+    let mut config_rx = unwrap!(CONFIG_WATCH.receiver());
+    let mut daily_steps_rx = unwrap!(DAILY_STEPS_WATCH.receiver());
+    let mut last_activity = Instant::now();
+    loop {
+        let config = config_rx.get().await;
+        if config.idle_alert_minutes == 0 {
+            config_rx.changed().await;
+            continue;
+        }
+        let alert_at = last_activity + Duration::from_secs(config.idle_alert_minutes as u64 * 60);
+
+        match select3(
+            Timer::at(alert_at),
+            daily_steps_rx.changed(),
+            config_rx.changed(),
+        )
+        .await
+        {
+            Either3::First(_) => {
+                match clock::local_hour() {
+                    Some(local_hour) if config.is_active_hour(local_hour) => {
+                        info!("No steps for {}min, alert user", config.idle_alert_minutes);
+                        LED_BLINK_SIGNAL.signal(IDLE_ALERT_BLINKS);
+                    }
+                    local_hour => debug!("Skip idle alert at local hour {:?}", local_hour),
+                }
+                last_activity = Instant::now();
+            }
+            Either3::Second(_) => last_activity = Instant::now(),
+            Either3::Third(_) => debug!("Config changed, reschedule idle alert"),
+        }
+    }
+}
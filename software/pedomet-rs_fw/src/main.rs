@@ -1,10 +1,21 @@
 #![no_std]
 #![no_main]
 
+mod accel_stream;
+#[cfg(feature = "addon-sensors")]
+mod addon;
+mod bonds;
+mod clock;
+mod config;
 mod error;
 mod fmt;
-mod imu;
+mod gesture;
+mod idle_alert;
+mod rsc;
+mod self_test;
 mod storage_event_queue;
+mod sync_cursors;
+mod watchdog;
 
 #[cfg(not(feature = "defmt"))]
 use panic_reset as _;
@@ -12,10 +23,13 @@ use panic_reset as _;
 #[cfg(feature = "defmt")]
 use {defmt_rtt as _, panic_probe as _};
 
-use crate::fmt::{info, unwrap, warn};
+use crate::error::{PedometerFwError, PedometerResult};
+use crate::fmt::{debug, error, info, unwrap, warn};
+use core::future::Future;
 use core::mem;
+use core::sync::atomic::{AtomicU32, Ordering};
 use embassy_executor::Spawner;
-use embassy_futures::select::{select, select3, Either, Either3};
+use embassy_futures::select::{select, select3, select4, Either, Either4};
 use embassy_nrf::{
     bind_interrupts,
     gpio::{Input, Level, Output, OutputDrive, Pull},
@@ -26,186 +40,1062 @@ use embassy_nrf::{
 };
 use embassy_sync::{
     blocking_mutex::raw::CriticalSectionRawMutex,
-    channel::{Channel, Receiver, Sender, TrySendError},
+    channel::{Channel, Receiver, Sender},
+    signal::Signal,
     watch::Watch,
 };
-use embassy_time::{Duration, Instant, Timer};
-use imu::Imu;
-use nrf_softdevice::ble::{gatt_server, peripheral, Connection};
+use embassy_time::{with_timeout, Duration, Instant, Timer};
+use nrf_softdevice::ble::{
+    gatt_server::{self, NotifyValueError, SetValueError},
+    peripheral::{self, AdvertiseError},
+    Connection,
+};
 use nrf_softdevice::{
     ble::advertisement_builder::{
-        Flag, LegacyAdvertisementBuilder, LegacyAdvertisementPayload, ServiceList, ServiceUuid16,
+        AdvertisementDataType, Flag, LegacyAdvertisementBuilder, LegacyAdvertisementPayload,
+        ServiceList, ServiceUuid16,
     },
     Flash,
 };
-use nrf_softdevice::{raw, Softdevice};
-use pedomet_rs_common::PedometerEventType;
+use nrf_softdevice::{raw, RawError, Softdevice};
+use pedomet_rs_common::{
+    gatt,
+    protocol::{self, PedometerCommand, PedometerEventSelection},
+    PedometerAccelerationSamples, PedometerActivity, PedometerAdvertisingData,
+    PedometerBootSummaries, PedometerConfig, PedometerConfigValue, PedometerDeleteResult,
+    PedometerDiagnostics, PedometerError, PedometerEvent, PedometerEventChunkHeader,
+    PedometerEventFilter, PedometerEventQuery, PedometerEventRequest, PedometerEventType,
+    PedometerFirmwareVersion, PedometerSelfTest, PedometerStorageUsage, PedometerSyncCursor,
+    PedometerWriteError, PedometerWriteStatus, ADVERTISING_COMPANY_ID, CONFIG_CHARACTERISTIC_SIZE,
+    DEVICE_NAME_LEN, DIAGNOSTICS_CHARACTERISTIC_SIZE, TIME_SYNC_CHARACTERISTIC_SIZE,
+    WRITE_ERROR_CHARACTERISTIC_SIZE,
+};
+use pedomet_rs_imu::{
+    FifoEnabled, Lsm6ds3, MotionWake, PedometerRunning, PedometerSensitivity, Timestamp,
+    Unconfigured,
+};
 use static_cell::StaticCell;
 use storage_event_queue::{BreakIteration, HandleEntry, PopEntry, StorageEventQueue};
+use sync_cursors::SyncCursors;
+use watchdog::{WatchedTask, BLE_PING_SIGNAL};
 
 #[embassy_executor::task]
-async fn softdevice_task(sd: &'static Softdevice) -> ! {
-    sd.run().await
+async fn softdevice_task(sd: &'static Softdevice, flash_command_sender: FlashCommandSender) -> ! {
+    sd.run_with_callback(|ble_evt| {
+        // SAFETY: The event is valid for the duration of the callback
+        let ble_evt = unsafe { &*ble_evt };
+        if ble_evt.header.evt_id as u32 == raw::BLE_GAP_EVTS_BLE_GAP_EVT_DISCONNECTED {
+            // SAFETY: The event id was checked above
+            let reason = unsafe { ble_evt.evt.gap_evt.params.disconnected.reason };
+            info!("Disconnected with reason 0x{:02x}", reason);
+            push_error(&flash_command_sender, PedometerError::Disconnected(reason));
+        } else if ble_evt.header.evt_id as u32 == raw::BLE_GATTS_EVTS_BLE_GATTS_EVT_HVN_TX_COMPLETE
+        {
+            NOTIFICATION_TX_COMPLETE_SIGNAL.signal(());
+        }
+    })
+    .await
+}
+
+fn push_error(flash_command_sender: &FlashCommandSender, error: PedometerError) {
+    flash_command_sender.send_or_drop(FlashCommand::PushEvent((
+        PedometerEventType::Error(error),
+        None,
+    )));
 }
 
-#[nrf_softdevice::gatt_service(uuid = "180f")]
-struct BatteryService {
-    #[characteristic(uuid = "2a19", read, notify)]
-    battery_level: u8,
+/// Setting a characteristic value only fails if the softdevice is in an unexpected state. This is
+/// recorded instead of resetting the device.
+fn set_value(flash_command_sender: &FlashCommandSender, result: Result<(), SetValueError>) {
+    if let Err(e) = result {
+        warn!("Could not set characteristic value! {:?}", e);
+        if let SetValueError::Raw(e) = e {
+            push_error(flash_command_sender, PedometerError::Softdevice(e as u32));
+        }
+    }
+}
+
+/// The commands are dropped by all tasks, so their count is only added to the diagnostics of the
+/// IMU task when the characteristic is set. These change with every FIFO read anyway.
+fn diagnostics_characteristic(
+    diagnostics: PedometerDiagnostics,
+) -> [u8; DIAGNOSTICS_CHARACTERISTIC_SIZE] {
+    let diagnostics = PedometerDiagnostics {
+        dropped_commands: DROPPED_COMMANDS.load(Ordering::Relaxed),
+        ..diagnostics
+    };
+    unwrap!(diagnostics.serialize_for_characteristic())
+}
+
+/// Carry out a value that the host wrote to the given characteristic, see
+/// [`protocol::handle_write`].
+fn handle_write(
+    server: &Server,
+    connection: &Connection,
+    flash_command_sender: &FlashCommandSender,
+    characteristic: u128,
+    data: &[u8],
+) {
+    let command = match protocol::handle_write(characteristic, data, Instant::now().as_millis()) {
+        Ok(Some(command)) => command,
+        Ok(None) => return,
+        Err(e) => {
+            reject_write(server, connection, characteristic, e);
+            return;
+        }
+    };
+    info!("pedometer command: {:?}", command);
+    match command {
+        PedometerCommand::GetEvents(selection) => {
+            flash_command_sender.send_or_drop(FlashCommand::GetEvents(selection));
+        }
+        PedometerCommand::TransferEvents(query) => {
+            flash_command_sender.send_or_drop(FlashCommand::TransferEvents(query));
+        }
+        PedometerCommand::DeleteEvents(min_event_index) => {
+            flash_command_sender.send_or_drop(FlashCommand::DeleteEvents(min_event_index));
+        }
+        PedometerCommand::DeleteBoot(boot_id) => {
+            flash_command_sender.send_or_drop(FlashCommand::DeleteBoot(boot_id));
+        }
+        PedometerCommand::AckEvents(cursor) => {
+            flash_command_sender.send_or_drop(FlashCommand::AckEvents(cursor));
+        }
+        PedometerCommand::SetEpochMs(epoch_ms) => {
+            clock::set_host_epoch_ms(epoch_ms);
+            if flash_command_sender.send_or_drop(FlashCommand::PushEvent((
+                PedometerEventType::HostEpochMs(epoch_ms),
+                None,
+            ))) {
+                let device_ms = Instant::now().as_millis().to_le_bytes();
+                if let Err(e) = server
+                    .pedometer
+                    .epoch_ms_notify(connection, &unwrap!(WriteValue::from_slice(&device_ms)))
+                {
+                    info!("send notification error: {:?}", e);
+                }
+            }
+        }
+        PedometerCommand::SetEpochMsAt {
+            host_epoch_ms,
+            device_ms,
+        } => {
+            clock::set_host_epoch_ms_at(host_epoch_ms, device_ms);
+            flash_command_sender.send_or_drop(FlashCommand::PushEvent((
+                PedometerEventType::HostEpochMs(host_epoch_ms),
+                Some(Instant::from_millis(device_ms)),
+            )));
+        }
+        PedometerCommand::StoreConfig(value) => {
+            flash_command_sender.send_or_drop(FlashCommand::StoreConfig(value));
+        }
+        PedometerCommand::RespondTimeSync(response) => {
+            match response.serialize_for_characteristic() {
+                Ok(data) => {
+                    if let Err(e) = server
+                        .pedometer
+                        .time_sync_notify(connection, &unwrap!(WriteValue::from_slice(&data)))
+                    {
+                        info!("send notification error: {:?}", e);
+                    }
+                }
+                Err(e) => warn!("Could not serialize time sync response: {:?}", e),
+            }
+        }
+        PedometerCommand::PushMarker(marker) => {
+            flash_command_sender.send_or_drop(FlashCommand::PushEvent((
+                PedometerEventType::Marker(marker),
+                None,
+            )));
+        }
+        PedometerCommand::StreamAcceleration(enabled) => accel_stream::set_enabled(enabled),
+        PedometerCommand::GetBootSummaries(min_boot_id) => {
+            flash_command_sender.send_or_drop(FlashCommand::GetBootSummaries(min_boot_id));
+        }
+    }
+}
+
+/// Tell the host that its write to the given characteristic was ignored.
+fn reject_write(
+    server: &Server,
+    connection: &Connection,
+    characteristic: u128,
+    error: PedometerWriteError,
+) {
+    let status = PedometerWriteStatus {
+        characteristic: gatt::short_uuid(characteristic),
+        error,
+    };
+    warn!("Reject write: {:?}", status);
+    if let Err(e) = server
+        .pedometer
+        .write_error_notify(connection, &unwrap!(status.serialize_for_characteristic()))
+    {
+        info!("send notification error: {:?}", e);
+    }
+}
+
+/// Version of the firmware for the host, a package version that does not fit fails the build
+const FIRMWARE_VERSION: PedometerFirmwareVersion = PedometerFirmwareVersion {
+    major: firmware_version_part(env!("CARGO_PKG_VERSION_MAJOR")),
+    minor: firmware_version_part(env!("CARGO_PKG_VERSION_MINOR")),
+    patch: firmware_version_part(env!("CARGO_PKG_VERSION_PATCH")),
+};
+
+const fn firmware_version_part(part: &str) -> u8 {
+    match u8::from_str_radix(part, 10) {
+        Ok(part) => part,
+        Err(_) => panic!("Firmware version part does not fit into a byte"),
+    }
 }
 
 const EVENT_RESPONSE_SIZE: usize = 250;
+const FLASH_COMMAND_CHANNEL_SIZE: usize = 4;
+/// Steps, errors and markers may be pushed while a slow command is handled
+const PUSH_EVENT_CHANNEL_SIZE: usize = 16;
+/// Number of queue entries read for a response before pending events are persisted in between
+const GET_EVENTS_CHUNK_SIZE: usize = 256;
+/// A transfer ends after this many chunks, so that the other commands are not delayed for too
+/// long. The host requests the remaining events with a new transfer.
+const MAX_TRANSFER_CHUNKS: u16 = 128;
+/// The host disconnected if the notification task does not take a response or chunk for this long
+const TRANSFER_CHUNK_TIMEOUT: Duration = Duration::from_secs(5);
+/// Maximum number of attempts to notify an event response while the TX buffers are exhausted
+const EVENT_RESPONSE_MAX_ATTEMPTS: u32 = 10;
+/// Advertising is restarted in this interval to update the live data
+const ADVERTISING_UPDATE_INTERVAL: Duration = Duration::from_secs(30);
+/// The advertising data asks the host to sync once so many events were not read, so that a
+/// listening host only connects when it is worth it, see PedometerAdvertisingData::data_waiting
+const DATA_WAITING_EVENTS: u32 = 1_000;
+/// Interval in which the event storage is checked
+const STORAGE_MAINTENANCE_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+/// The activity is classified by the cadence within this window
+const ACTIVITY_WINDOW: Duration = Duration::from_secs(60);
+/// Interval in which the temperature of the IMU is stored
+const TEMPERATURE_INTERVAL: Duration = Duration::from_secs(30 * 60);
+/// Timeout of a single operation of the IMU
+const IMU_OPERATION_TIMEOUT: Duration = Duration::from_secs(1);
+/// Delay before the IMU is power cycled after it failed. It is doubled after every failed
+/// recovery up to the maximum, so that a broken IMU does not fill the storage with error events.
+const IMU_MIN_RECOVERY_DELAY: Duration = Duration::from_secs(1);
+const IMU_MAX_RECOVERY_DELAY: Duration = Duration::from_secs(60 * 60);
+/// The user counts as active if steps were counted within this duration. The battery drains
+/// faster then, so it is measured more often.
+const BATTERY_ACTIVE_WINDOW: Duration = Duration::from_secs(2 * 60);
+/// The user counts as idle if no steps were counted for this duration. The battery is measured
+/// less often then.
+const BATTERY_IDLE_AFTER: Duration = Duration::from_secs(30 * 60);
+/// Factor by which the configured battery sample interval is shortened during activity and
+/// extended while idle
+const BATTERY_ACTIVITY_FACTOR: u64 = 4;
+
+type Imu<'a, S> = Lsm6ds3<&'a mut Twim<'static, TWISPI0>, S>;
+/// FIFO threshold in words at which INT1 is raised, every steps entry takes 3 words
+const IMU_FIFO_THRESHOLD: u16 = 3 * 10 / 2;
+/// Value of a characteristic that is written by the host. Fixed size values would make the
+/// generated server panic on a write with another length, so the handlers check the length.
+type WriteValue<const N: usize> = heapless::Vec<u8, N>;
+
+/// Define the GATT services with the UUIDs from [`pedomet_rs_common::gatt_uuids`].
+macro_rules! define_gatt_services {
+    (
+        BATTERY_SERVICE = $battery_service:tt,
+        BATTERY_LEVEL = $battery_level:tt,
+        PEDOMETER_SERVICE = $pedometer_service:tt,
+        REQUEST_EVENTS = $request_events:tt,
+        RESPONSE_EVENTS = $response_events:tt,
+        DELETE_EVENTS = $delete_events:tt,
+        EPOCH_MS = $epoch_ms:tt,
+        BOOT_ID = $boot_id:tt,
+        MAX_EVENT_ID = $max_event_id:tt,
+        CONFIG = $config:tt,
+        DIAGNOSTICS = $diagnostics:tt,
+        STORAGE_FILL_PERCENT = $storage_fill_percent:tt,
+        TIME_SYNC = $time_sync:tt,
+        DELETE_BOOT = $delete_boot:tt,
+        REQUEST_EVENTS_SINCE = $request_events_since:tt,
+        DAILY_STEPS = $daily_steps:tt,
+        MARKER = $marker:tt,
+        SELF_TEST = $self_test:tt,
+        CONFIG_CHANGED = $config_changed:tt,
+        REQUEST_EVENTS_QUERY = $request_events_query:tt,
+        WRITE_ERROR = $write_error:tt,
+        ACK_EVENTS = $ack_events:tt,
+        FIRMWARE_VERSION = $firmware_version:tt,
+        ACCELERATION = $acceleration:tt,
+        BOOT_SUMMARIES = $boot_summaries:tt,
+        TEMPERATURE = $temperature:tt,
+        STORAGE_USAGE = $storage_usage:tt,
+        DELETE_RESULT = $delete_result:tt,
+        REQUEST_EVENTS_TRANSFER = $request_events_transfer:tt,
+        RSC_SERVICE = $rsc_service:tt,
+        RSC_MEASUREMENT = $rsc_measurement:tt,
+        RSC_FEATURE = $rsc_feature:tt,
+    ) => {
+        #[nrf_softdevice::gatt_service(uuid = $battery_service)]
+        struct BatteryService {
+            #[characteristic(uuid = $battery_level, read, notify)]
+            battery_level: u8,
+        }
 
-#[nrf_softdevice::gatt_service(uuid = "1c2a0000-abf2-4b98-ba1c-25d5ea728525")]
-struct PedometerService {
-    #[characteristic(uuid = "1c2a0001-abf2-4b98-ba1c-25d5ea728525", write)]
-    request_events: u32,
-    #[characteristic(uuid = "1c2a0002-abf2-4b98-ba1c-25d5ea728525", notify)]
-    response_events: [u8; EVENT_RESPONSE_SIZE],
-    #[characteristic(uuid = "1c2a0003-abf2-4b98-ba1c-25d5ea728525", write)]
-    delete_events: u32,
-    #[characteristic(uuid = "1c2a0004-abf2-4b98-ba1c-25d5ea728525", notify, write)]
-    epoch_ms: u64,
-    #[characteristic(uuid = "1c2a0005-abf2-4b98-ba1c-25d5ea728525", read)]
-    boot_id: u32,
-    #[characteristic(uuid = "1c2a0006-abf2-4b98-ba1c-25d5ea728525", read, notify)]
-    max_event_id: u32,
+        // The characteristics that give access to the events or delete them require an encrypted
+        // link. Only bonded hosts get one, as unknown hosts cannot pair outside of the bonding
+        // window, see bonds::Bonder
+        #[nrf_softdevice::gatt_service(uuid = $pedometer_service)]
+        struct PedometerService {
+            #[characteristic(uuid = $request_events, security = "justworks", write)]
+            request_events: WriteValue<4>,
+            #[characteristic(uuid = $response_events, security = "justworks", notify)]
+            response_events: [u8; EVENT_RESPONSE_SIZE],
+            #[characteristic(uuid = $delete_events, security = "justworks", write)]
+            delete_events: WriteValue<4>,
+            #[characteristic(uuid = $epoch_ms, security = "justworks", notify, write)]
+            epoch_ms: WriteValue<8>,
+            #[characteristic(uuid = $boot_id, read)]
+            boot_id: u32,
+            #[characteristic(uuid = $max_event_id, read, notify)]
+            max_event_id: u32,
+            #[characteristic(uuid = $config, read, write)]
+            config: WriteValue<CONFIG_CHARACTERISTIC_SIZE>,
+            #[characteristic(uuid = $diagnostics, read)]
+            diagnostics: [u8; DIAGNOSTICS_CHARACTERISTIC_SIZE],
+            // Fill level of the event storage in percent. It is notified whenever a threshold is
+            // reached.
+            #[characteristic(uuid = $storage_fill_percent, read, notify)]
+            storage_fill_percent: u8,
+            // Round trip time synchronization, see PedometerTimeSync
+            #[characteristic(uuid = $time_sync, write, notify)]
+            time_sync: WriteValue<TIME_SYNC_CHARACTERISTIC_SIZE>,
+            // Delete all events of the given boot. The events of the current boot cannot be
+            // deleted.
+            #[characteristic(uuid = $delete_boot, security = "justworks", write)]
+            delete_boot: WriteValue<4>,
+            // Request events by boot and index, see PedometerEventRequest
+            #[characteristic(uuid = $request_events_since, security = "justworks", write)]
+            request_events_since: WriteValue<{ PedometerEventRequest::SIZE }>,
+            // Steps since the last local midnight. It is notified whenever steps were counted.
+            #[characteristic(uuid = $daily_steps, read, notify)]
+            daily_steps: u32,
+            // Store a marker event, see PedometerMarker
+            #[characteristic(uuid = $marker, write)]
+            marker: WriteValue<1>,
+            // Result of the power-on self test, see PedometerSelfTest
+            #[characteristic(uuid = $self_test, read)]
+            self_test: u8,
+            // The complete config, notified whenever it was changed by any host
+            #[characteristic(uuid = $config_changed, notify)]
+            config_changed: [u8; CONFIG_CHARACTERISTIC_SIZE],
+            // Request events by boot, index and type, see PedometerEventQuery
+            #[characteristic(uuid = $request_events_query, security = "justworks", write)]
+            request_events_query: WriteValue<{ PedometerEventQuery::SIZE }>,
+            // Notified whenever a write of the host was rejected, see PedometerWriteStatus
+            #[characteristic(uuid = $write_error, notify)]
+            write_error: [u8; WRITE_ERROR_CHARACTERISTIC_SIZE],
+            // Acknowledge the synced events of a host, see PedometerSyncCursor
+            #[characteristic(uuid = $ack_events, security = "justworks", write)]
+            ack_events: WriteValue<{ PedometerSyncCursor::SIZE }>,
+            // Version of the firmware, see PedometerFirmwareVersion
+            #[characteristic(uuid = $firmware_version, read)]
+            firmware_version: [u8; PedometerFirmwareVersion::SIZE],
+            // Write 1 to stream the raw accelerometer samples for debugging and 0 to stop, see
+            // PedometerAccelerationSamples
+            #[characteristic(uuid = $acceleration, write, notify)]
+            acceleration: WriteValue<{ PedometerAccelerationSamples::SIZE }>,
+            // Write a boot id to get the summaries of the stored boots starting with it, see
+            // PedometerBootSummaries
+            #[characteristic(uuid = $boot_summaries, security = "justworks", write, notify)]
+            boot_summaries: WriteValue<{ PedometerBootSummaries::SIZE }>,
+            // Temperature of the IMU in 0.01 °C. It is notified whenever it was measured.
+            #[characteristic(uuid = $temperature, read, notify)]
+            temperature: i16,
+            // Used bytes, capacity and entries of the event storage, see PedometerStorageUsage.
+            // It is notified whenever the fill level changed by a percent.
+            #[characteristic(uuid = $storage_usage, read, notify)]
+            storage_usage: [u8; PedometerStorageUsage::SIZE],
+            // Notified once deleted events were removed from the storage, see
+            // PedometerDeleteResult
+            #[characteristic(uuid = $delete_result, security = "justworks", notify)]
+            delete_result: [u8; PedometerDeleteResult::SIZE],
+            // Request all events by boot, index and type in one transfer of several
+            // notifications of the response events characteristic, see
+            // PedometerEventChunkHeader
+            #[characteristic(uuid = $request_events_transfer, security = "justworks", write)]
+            request_events_transfer: WriteValue<{ PedometerEventQuery::SIZE }>,
+        }
+
+        // Running Speed and Cadence service of the Bluetooth SIG, so that generic fitness apps
+        // can show the cadence without the custom protocol
+        #[nrf_softdevice::gatt_service(uuid = $rsc_service)]
+        struct RscService {
+            #[characteristic(uuid = $rsc_measurement, notify)]
+            measurement: [u8; rsc::RSC_MEASUREMENT_SIZE],
+            #[characteristic(uuid = $rsc_feature, read)]
+            feature: u16,
+        }
+    };
 }
 
+pedomet_rs_common::gatt_uuids!(define_gatt_services);
+
 #[nrf_softdevice::gatt_server]
 struct Server {
     bas: BatteryService,
     pedometer: PedometerService,
+    rsc: RscService,
 }
 
+type PushEvent = (PedometerEventType, Option<Instant>);
+
 #[derive(Debug, Copy, Clone)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 enum FlashCommand {
-    PushEvent((PedometerEventType, Option<Instant>)),
-    GetEvents(u32),
+    PushEvent(PushEvent),
+    GetEvents(PedometerEventSelection),
+    TransferEvents(PedometerEventQuery),
+    /// Delete the events before the given index that all hosts acknowledged
     DeleteEvents(u32),
+    DeleteBoot(u32),
+    /// Update the cursor of a host and delete the events that all hosts acknowledged
+    AckEvents(PedometerSyncCursor),
+    StoreConfig(PedometerConfigValue),
+    /// Persist the bonds after a host bonded
+    StoreBonds,
+    /// Check the event storage and store the result as event
+    Maintain,
+    /// Summarize the stored boots starting with the given boot id
+    GetBootSummaries(u32),
+    /// Sent by the watchdog task to check that the commands are still handled
+    FeedWatchdog,
+}
+
+static FLASH_COMMAND_CHANNEL: StaticCell<
+    Channel<CriticalSectionRawMutex, FlashCommand, FLASH_COMMAND_CHANNEL_SIZE>,
+> = StaticCell::new();
+static PUSH_EVENT_CHANNEL: StaticCell<
+    Channel<CriticalSectionRawMutex, PushEvent, PUSH_EVENT_CHANNEL_SIZE>,
+> = StaticCell::new();
+/// Sends the commands to the flash task. Events are sent via their own deeper channel, which the
+/// flash task empties before it handles the next command. This way no events are dropped while a
+/// slow command like [`FlashCommand::GetEvents`] is handled during a sync.
+#[derive(Copy, Clone)]
+struct FlashCommandSender {
+    events: Sender<'static, CriticalSectionRawMutex, PushEvent, PUSH_EVENT_CHANNEL_SIZE>,
+    commands: Sender<'static, CriticalSectionRawMutex, FlashCommand, FLASH_COMMAND_CHANNEL_SIZE>,
+}
+
+impl FlashCommandSender {
+    /// Send the command without waiting. It is dropped and counted if the channel is full.
+    ///
+    /// Returns whether the command was sent.
+    fn send_or_drop(&self, command: FlashCommand) -> bool {
+        let sent = match command {
+            FlashCommand::PushEvent(event) => self.events.try_send(event).is_ok(),
+            command => self.commands.try_send(command).is_ok(),
+        };
+        if !sent {
+            let dropped = DROPPED_COMMANDS.fetch_add(1, Ordering::Relaxed) + 1;
+            warn!("Could not send command, {} dropped since boot.", dropped);
+        }
+        sent
+    }
+
+    async fn send(&self, command: FlashCommand) {
+        match command {
+            FlashCommand::PushEvent(event) => self.events.send(event).await,
+            command => self.commands.send(command).await,
+        }
+    }
 }
 
-static FLASH_COMMAND_CHANNEL: StaticCell<Channel<CriticalSectionRawMutex, FlashCommand, 4>> =
-    StaticCell::new();
 static READ_EVENT_CHANNEL: StaticCell<
     Channel<CriticalSectionRawMutex, [u8; EVENT_RESPONSE_SIZE], 2>,
 > = StaticCell::new();
 
-static BAT_SOC_WATCH: Watch<CriticalSectionRawMutex, u8, 2> = Watch::new();
+/// Advertised name with the suffix of this device, see [`pedomet_rs_common::device_name`]
+static DEVICE_NAME: StaticCell<[u8; DEVICE_NAME_LEN]> = StaticCell::new();
+
+pub static BAT_SOC_WATCH: Watch<CriticalSectionRawMutex, u8, 2> = Watch::new();
+/// Measure the battery right away instead of waiting for the sample interval
+static BAT_SAMPLE_SIGNAL: Signal<CriticalSectionRawMutex, ()> = Signal::new();
 pub static BOOT_ID_WATCH: Watch<CriticalSectionRawMutex, u32, 2> = Watch::new();
 pub static MAX_EVENT_ID_WATCH: Watch<CriticalSectionRawMutex, u32, 2> = Watch::new();
+/// Steps since the last local midnight (or since boot if no midnight has passed, yet)
+pub static DAILY_STEPS: AtomicU32 = AtomicU32::new(0);
+/// Updated with the value of [`DAILY_STEPS`] whenever it changes
+pub static DAILY_STEPS_WATCH: Watch<CriticalSectionRawMutex, u32, 3> = Watch::new();
+static DIAGNOSTICS_WATCH: Watch<CriticalSectionRawMutex, PedometerDiagnostics, 2> = Watch::new();
+/// Commands for the flash task that were dropped because its channel was full
+static DROPPED_COMMANDS: AtomicU32 = AtomicU32::new(0);
+/// Events that were stored since a host read all events the last time, see
+/// [`count_unsynced_events`] for the ones from before the boot
+static UNSYNCED_EVENTS: AtomicU32 = AtomicU32::new(0);
+static STORAGE_USAGE_WATCH: Watch<CriticalSectionRawMutex, PedometerStorageUsage, 2> = Watch::new();
+/// Last temperature of the IMU in 0.01 °C
+static TEMPERATURE_WATCH: Watch<CriticalSectionRawMutex, i16, 2> = Watch::new();
+/// Blink the LED the given number of times
+pub static LED_BLINK_SIGNAL: Signal<CriticalSectionRawMutex, u8> = Signal::new();
+/// Result of [`FlashCommand::GetBootSummaries`] for the connection
+static BOOT_SUMMARIES_SIGNAL: Signal<CriticalSectionRawMutex, PedometerBootSummaries> =
+    Signal::new();
+/// Result of the commands that delete events for the connection
+static DELETE_RESULT_SIGNAL: Signal<CriticalSectionRawMutex, PedometerDeleteResult> = Signal::new();
+/// Signaled whenever the softdevice transmitted notifications and TX buffers are free again
+static NOTIFICATION_TX_COMPLETE_SIGNAL: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+async fn store_event(event_queue: &mut StorageEventQueue<Flash>, (event_type, instant): PushEvent) {
+    if let Err(e) = event_queue
+        .push_event(event_type, instant.map(|i| i.as_millis()))
+        .await
+    {
+        warn!("Could not push event! {:?}", e);
+    } else {
+        UNSYNCED_EVENTS.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// A host read the events up to the end of the queue, so none of them wait for a sync anymore.
+fn all_events_read(selection: PedometerEventSelection) {
+    // The time references are read before the other events
+    let filtered = matches!(
+        selection,
+        PedometerEventSelection::Query(query) if query.filter != PedometerEventFilter::All
+    );
+    if !filtered {
+        UNSYNCED_EVENTS.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Initialise [`UNSYNCED_EVENTS`] with the stored events that not every known host acknowledged.
+/// Without any cursor no host acknowledged them yet.
+async fn count_unsynced_events(
+    event_queue: &mut StorageEventQueue<Flash>,
+    sync_cursors: &SyncCursors,
+) {
+    let mut unsynced = 0;
+    if let Err(e) = event_queue
+        .for_each(|event| {
+            if sync_cursors.is_empty() || !sync_cursors.all_synced(&event) {
+                unsynced += 1;
+            }
+            // The counter is only compared to the threshold, so the rest is not read
+            Ok(HandleEntry {
+                pop: PopEntry::Keep,
+                br: if unsynced < DATA_WAITING_EVENTS {
+                    BreakIteration::Continue
+                } else {
+                    BreakIteration::Break
+                },
+            })
+        })
+        .await
+    {
+        warn!("Could not count unsynced events! {:?}", e);
+    }
+    UNSYNCED_EVENTS.store(unsynced, Ordering::Relaxed);
+}
+
+/// Events read for a response, see [`read_events`]
+struct ReadEvents {
+    /// Position after the last handled entry, the next chunk of a transfer continues there
+    next: PedometerEventRequest,
+    events: usize,
+    /// All matching events fit into the buffer
+    complete: bool,
+}
+
+/// Serialize the events that match the selection into the buffer, starting at the position `from`
+/// where the previous chunk stopped. The position stays valid if the oldest events are popped or
+/// overwritten in between, as the entries are compared by boot and index.
+///
+/// Reading pauses regularly to persist the events that arrived in the meantime, so that no steps
+/// are dropped during a long sync.
+async fn read_events(
+    event_queue: &mut StorageEventQueue<Flash>,
+    push_event_receiver: &Receiver<
+        'static,
+        CriticalSectionRawMutex,
+        PushEvent,
+        PUSH_EVENT_CHANNEL_SIZE,
+    >,
+    selection: PedometerEventSelection,
+    from: PedometerEventRequest,
+    buf: &mut [u8],
+) -> PedometerResult<ReadEvents> {
+    let mut offset = 0;
+    let mut read = ReadEvents {
+        next: from,
+        events: 0,
+        complete: false,
+    };
+    loop {
+        let mut entries_handled = 0_usize;
+        let mut full = false;
+        let mut paused = false;
+        event_queue
+            .for_each(|event| {
+                if !read.next.matches(&event) {
+                    return Ok(HandleEntry {
+                        pop: PopEntry::Keep,
+                        br: BreakIteration::Continue,
+                    });
+                }
+                entries_handled += 1;
+                let after_event = PedometerEventRequest {
+                    boot_id: event.boot_id,
+                    min_event_index: event.index.saturating_add(1),
+                };
+                let br = if selection.matches(&event) {
+                    match event
+                        .serialize_for_transport(&mut buf[offset..])
+                        .map(|buf| buf.len())
+                    {
+                        Ok(length) => {
+                            offset += length;
+                            read.events += 1;
+                            read.next = after_event;
+                            full = offset >= buf.len();
+                            if full {
+                                BreakIteration::Break
+                            } else {
+                                BreakIteration::Continue
+                            }
+                        }
+                        Err(_e) => {
+                            // Zero out the non-used bytes, the event is sent with the next chunk
+                            buf[offset..].fill(0);
+                            full = true;
+                            BreakIteration::Break
+                        }
+                    }
+                } else {
+                    read.next = after_event;
+                    BreakIteration::Continue
+                };
+                let br = if br == BreakIteration::Continue
+                    && entries_handled >= GET_EVENTS_CHUNK_SIZE
+                    && !push_event_receiver.is_empty()
+                {
+                    paused = true;
+                    BreakIteration::Break
+                } else {
+                    br
+                };
+                Ok(HandleEntry {
+                    pop: PopEntry::Keep,
+                    br,
+                })
+            })
+            .await?;
+        if !paused {
+            read.complete = !full;
+            return Ok(read);
+        }
+        // Persist the steps that arrived in the meantime before continuing
+        debug!("Pause getting events at {:?}", read.next);
+        while let Ok(event) = push_event_receiver.try_receive() {
+            store_event(event_queue, event).await;
+        }
+    }
+}
+
+/// Send the events that match the query to the notification task in chunks, see
+/// [`PedometerEventChunkHeader`]. Each chunk continues after the last handled event, like the
+/// request of the host does after an incomplete transfer.
+async fn transfer_events(
+    event_queue: &mut StorageEventQueue<Flash>,
+    push_event_receiver: &Receiver<
+        'static,
+        CriticalSectionRawMutex,
+        PushEvent,
+        PUSH_EVENT_CHANNEL_SIZE,
+    >,
+    event_sender: &Sender<'static, CriticalSectionRawMutex, [u8; EVENT_RESPONSE_SIZE], 2>,
+    query: PedometerEventQuery,
+) {
+    let mut header = PedometerEventChunkHeader::default();
+    let mut since = query.since;
+    loop {
+        let mut buf = [0u8; EVENT_RESPONSE_SIZE];
+        let (header_buf, events_buf) = buf.split_at_mut(PedometerEventChunkHeader::SIZE);
+        match read_events(
+            event_queue,
+            push_event_receiver,
+            PedometerEventSelection::Query(query),
+            since,
+            events_buf,
+        )
+        .await
+        {
+            Ok(read) => {
+                since = read.next;
+                header.last = read.complete;
+            }
+            Err(e) => {
+                // The host requests the rest after the events that were read before
+                warn!("Could not get events! {:?}", e);
+                header.last = true;
+                header.incomplete = true;
+            }
+        }
+        if !header.last && header.sequence + 1 >= MAX_TRANSFER_CHUNKS {
+            header.last = true;
+            header.incomplete = true;
+        }
+        header_buf.copy_from_slice(&header.to_bytes());
+        if with_timeout(TRANSFER_CHUNK_TIMEOUT, event_sender.send(buf))
+            .await
+            .is_err()
+        {
+            warn!("Abort event transfer after {} chunks", header.sequence);
+            // End the transfer in place of the chunk that was not sent, so that the host
+            // requests the rest right away if it is still connected. Otherwise it notices the
+            // abort by its own timeout.
+            let header = PedometerEventChunkHeader {
+                last: true,
+                incomplete: true,
+                ..header
+            };
+            let mut buf = [0u8; EVENT_RESPONSE_SIZE];
+            buf[..PedometerEventChunkHeader::SIZE].copy_from_slice(&header.to_bytes());
+            if event_sender.try_send(buf).is_err() {
+                warn!("Could not send end of aborted event transfer");
+            }
+            return;
+        }
+        watchdog::feed(WatchedTask::Flash);
+        if header.last {
+            info!("Transferred events in {} chunks", header.sequence + 1);
+            if !header.incomplete {
+                all_events_read(PedometerEventSelection::Query(query));
+            }
+            return;
+        }
+        header.sequence += 1;
+    }
+}
+
+/// Pop the events from the front of the queue as long as they fulfill the predicate.
+async fn delete_events_while(
+    event_queue: &mut StorageEventQueue<Flash>,
+    mut predicate: impl FnMut(&PedometerEvent) -> bool,
+) {
+    let mut result = PedometerDeleteResult::default();
+    if let Err(e) = event_queue
+        .for_each(|event| {
+            // The first event that is kept must not be popped
+            Ok(if predicate(&event) {
+                result.deleted += 1;
+                HandleEntry {
+                    pop: PopEntry::Pop,
+                    br: BreakIteration::Continue,
+                }
+            } else {
+                result.oldest = Some((event.boot_id, event.index));
+                HandleEntry {
+                    pop: PopEntry::Keep,
+                    br: BreakIteration::Break,
+                }
+            })
+        })
+        .await
+    {
+        warn!("Could not delete events! {:?}", e);
+        return;
+    }
+    info!("Deleted events: {:?}", result);
+    DELETE_RESULT_SIGNAL.signal(result);
+}
 
 #[embassy_executor::task]
 async fn flash_task(
     sd: &'static Softdevice,
-    command_receiver: Receiver<'static, CriticalSectionRawMutex, FlashCommand, 4>,
+    reset_reason: u32,
+    mut self_test: PedometerSelfTest,
+    push_event_receiver: Receiver<
+        'static,
+        CriticalSectionRawMutex,
+        PushEvent,
+        PUSH_EVENT_CHANNEL_SIZE,
+    >,
+    command_receiver: Receiver<
+        'static,
+        CriticalSectionRawMutex,
+        FlashCommand,
+        FLASH_COMMAND_CHANNEL_SIZE,
+    >,
     event_sender: Sender<'static, CriticalSectionRawMutex, [u8; EVENT_RESPONSE_SIZE], 2>,
 ) {
-    let flash = Flash::take(sd);
-    let mut event_queue = unwrap!(StorageEventQueue::new(flash, false).await);
+    let mut event_queue = StorageEventQueue::new(Flash::take(sd));
+    // Hosts cannot bond before the existing bonds are known
+    bonds::load_bonds(event_queue.flash()).await;
+    if let Err(e) = event_queue.init(false, reset_reason).await {
+        // Without a readable queue no event could ever be stored again, so the events are
+        // dropped instead of ending up in a reset loop
+        warn!("Could not read event queue, clear it! {:?}", e);
+        self_test.set_failed(PedometerSelfTest::STORAGE_UNREADABLE);
+        if let Err(e) = event_queue.init(true, reset_reason).await {
+            // Keep handling the commands, steps are still counted and shown while connected
+            error!("Could not clear event queue! {:?}", e);
+        }
+    }
+    if let Err(e) = event_queue
+        .push_event(PedometerEventType::SelfTest(self_test), None)
+        .await
+    {
+        warn!("Could not push self test event! {:?}", e);
+    }
+    self_test::SELF_TEST_WATCH.sender().send(self_test);
+    let mut config = config::load_config(event_queue.flash()).await;
+    let mut sync_cursors = SyncCursors::load(event_queue.flash()).await;
+    // The counter is not stored, so the events from before the boot are counted again
+    count_unsynced_events(&mut event_queue, &sync_cursors).await;
+    let storage_usage_sender = STORAGE_USAGE_WATCH.sender();
 
     loop {
-        let command = command_receiver.receive().await;
+        match event_queue.usage().await {
+            Ok(usage) => storage_usage_sender.send_if_modified(|current| {
+                let modified = *current != Some(usage);
+                *current = Some(usage);
+                modified
+            }),
+            Err(e) => warn!("Could not determine storage usage! {:?}", e),
+        }
+
+        // select polls the events first, so they are never delayed by other commands
+        let command = match select(push_event_receiver.receive(), command_receiver.receive()).await
+        {
+            Either::First(event) => FlashCommand::PushEvent(event),
+            Either::Second(command) => command,
+        };
         info!("Received command: {:?}", command);
         match command {
-            FlashCommand::PushEvent((event_type, instant)) => {
-                if let Err(e) = event_queue
-                    .push_event(event_type, instant.map(|i| i.as_millis()))
-                    .await
+            FlashCommand::PushEvent(event) => store_event(&mut event_queue, event).await,
+            FlashCommand::GetEvents(selection) => {
+                let mut buf = [0u8; EVENT_RESPONSE_SIZE];
+                match read_events(
+                    &mut event_queue,
+                    &push_event_receiver,
+                    selection,
+                    PedometerEventRequest::default(),
+                    &mut buf,
+                )
+                .await
                 {
-                    warn!("Could not push event! {:?}", e);
+                    Ok(read) => {
+                        info!("Send {} events to notification task", read.events);
+                        // The flash task must not wait for a host that stopped reading
+                        if with_timeout(TRANSFER_CHUNK_TIMEOUT, event_sender.send(buf))
+                            .await
+                            .is_err()
+                        {
+                            warn!("Drop event response, the notification task does not take it");
+                        } else if read.complete {
+                            all_events_read(selection);
+                        }
+                    }
+                    Err(e) => warn!("Could not get events! {:?}", e),
                 }
             }
-            FlashCommand::GetEvents(min_event_index) => {
-                let mut buf = [0u8; EVENT_RESPONSE_SIZE];
-                let mut offset = 0;
-                let mut num_events = 0;
-
-                if let Err(e) = event_queue
+            FlashCommand::TransferEvents(query) => {
+                transfer_events(&mut event_queue, &push_event_receiver, &event_sender, query).await
+            }
+            FlashCommand::DeleteEvents(min_event_index) => {
+                delete_events_while(&mut event_queue, |event| {
+                    event.index < min_event_index && sync_cursors.all_synced(event)
+                })
+                .await;
+            }
+            FlashCommand::AckEvents(cursor) => {
+                if let Err(e) = sync_cursors.update(event_queue.flash(), cursor).await {
+                    warn!("Could not store sync cursor! {:?}", e);
+                }
+                delete_events_while(&mut event_queue, |event| sync_cursors.all_synced(event)).await;
+            }
+            FlashCommand::DeleteBoot(boot_id) => {
+                let mut result = PedometerDeleteResult::default();
+                if BOOT_ID_WATCH.try_get() == Some(boot_id) {
+                    warn!("Events of the current boot cannot be deleted");
+                } else if let Err(e) = event_queue
                     .for_each(|event| {
-                        let br = if event.index >= min_event_index {
-                            match event
-                                .serialize_for_transport(&mut buf[offset..])
-                                .map(|buf| buf.len())
-                            {
-                                Ok(length) => {
-                                    offset += length;
-                                    num_events += 1;
-                                    if offset >= buf.len() {
-                                        BreakIteration::Break
-                                    } else {
-                                        BreakIteration::Continue
-                                    }
-                                }
-                                Err(_e) => {
-                                    // Zero out the non-used bytes
-                                    buf[offset..].fill(0);
-                                    BreakIteration::Break
-                                }
-                            }
-                        } else {
-                            BreakIteration::Continue
-                        };
                         Ok(HandleEntry {
-                            pop: PopEntry::Keep,
-                            br,
+                            // Events that another host did not sync, yet, are kept
+                            pop: if event.boot_id == boot_id && sync_cursors.all_synced(&event) {
+                                result.deleted += 1;
+                                PopEntry::Pop
+                            } else {
+                                // Iterated from the oldest event
+                                result.oldest.get_or_insert((event.boot_id, event.index));
+                                PopEntry::Keep
+                            },
+                            // Boots are stored in ascending order
+                            br: if event.boot_id > boot_id {
+                                BreakIteration::Break
+                            } else {
+                                BreakIteration::Continue
+                            },
                         })
                     })
                     .await
                 {
-                    warn!("Could not push event! {:?}", e);
+                    warn!("Could not delete events of boot! {:?}", e);
                 } else {
-                    info!("Send {} events to notification task", num_events);
-                    event_sender.send(buf).await;
+                    info!("Deleted events of boot {}: {:?}", boot_id, result);
+                    DELETE_RESULT_SIGNAL.signal(result);
                 }
             }
-            FlashCommand::DeleteEvents(min_event_index) => {
+            FlashCommand::StoreConfig(value) => {
+                if let Err(e) =
+                    config::store_config_value(event_queue.flash(), &mut config, value).await
+                {
+                    warn!("Could not store config value! {:?}", e);
+                }
+            }
+            FlashCommand::StoreBonds => {
+                if let Err(e) = bonds::store_bonds(event_queue.flash()).await {
+                    warn!("Could not store bonds! {:?}", e);
+                }
+            }
+            FlashCommand::Maintain => match event_queue.maintain().await {
+                Ok(maintenance) => {
+                    info!("Storage maintenance finished: {:?}", maintenance);
+                    if let Err(e) = event_queue
+                        .push_event(PedometerEventType::Maintenance(maintenance), None)
+                        .await
+                    {
+                        warn!("Could not push maintenance event! {:?}", e);
+                    }
+                }
+                Err(e) => warn!("Storage maintenance failed! {:?}", e),
+            },
+            FlashCommand::GetBootSummaries(min_boot_id) => {
+                let mut summaries = PedometerBootSummaries::default();
                 if let Err(e) = event_queue
                     .for_each(|event| {
+                        // Boots are stored in ascending order
+                        let br = if event.boot_id < min_boot_id || summaries.add(&event) {
+                            BreakIteration::Continue
+                        } else {
+                            BreakIteration::Break
+                        };
                         Ok(HandleEntry {
-                            pop: PopEntry::Pop,
-                            br: if event.index < min_event_index {
-                                BreakIteration::Continue
-                            } else {
-                                BreakIteration::Break
-                            },
+                            pop: PopEntry::Keep,
+                            br,
                         })
                     })
                     .await
                 {
-                    warn!("Could not delete events! {:?}", e);
+                    warn!("Could not summarize boots! {:?}", e);
+                } else {
+                    info!(
+                        "Summarized {} boots, more: {}",
+                        summaries.summaries.len(),
+                        summaries.more
+                    );
+                    BOOT_SUMMARIES_SIGNAL.signal(summaries);
                 }
             }
+            FlashCommand::FeedWatchdog => watchdog::feed(WatchedTask::Flash),
         }
     }
 }
 
+/// Notify the responses and chunks read by the flash task. A chunk that cannot be sent is
+/// dropped, the host notices it by the sequence number of the next one or by its timeout if it
+/// was the last one.
 async fn notify_response_events(
     server: &Server,
     connection: &Connection,
     events_receiver: Receiver<'_, CriticalSectionRawMutex, [u8; EVENT_RESPONSE_SIZE], 2>,
+    flash_command_sender: FlashCommandSender,
 ) -> ! {
     loop {
-        let response = events_receiver.receive().await;
-        if let Err(e) = server
-            .pedometer
-            .response_events_notify(connection, &response)
-        {
-            warn!("Could not send event response! {:?}", e);
+        let response = match select(events_receiver.receive(), BLE_PING_SIGNAL.wait()).await {
+            Either::First(response) => response,
+            Either::Second(()) => {
+                watchdog::feed(WatchedTask::Ble);
+                continue;
+            }
+        };
+        // Retry at the latest after one connection interval if no TX complete event is received
+        let retry_interval =
+            Duration::from_micros(connection.conn_params().max_conn_interval as u64 * 1250);
+        let mut attempt = 1;
+        loop {
+            NOTIFICATION_TX_COMPLETE_SIGNAL.reset();
+            match server
+                .pedometer
+                .response_events_notify(connection, &response)
+            {
+                Ok(()) => break,
+                Err(NotifyValueError::Raw(RawError::Resources))
+                    if attempt < EVENT_RESPONSE_MAX_ATTEMPTS =>
+                {
+                    debug!("TX buffers exhausted, wait for transmission");
+                    select(
+                        NOTIFICATION_TX_COMPLETE_SIGNAL.wait(),
+                        Timer::after(retry_interval),
+                    )
+                    .await;
+                    attempt += 1;
+                }
+                Err(e) => {
+                    warn!("Could not send event response! {:?}", e);
+                    if let NotifyValueError::Raw(e) = e {
+                        push_error(&flash_command_sender, PedometerError::Softdevice(e as u32));
+                    }
+                    break;
+                }
+            }
         }
+        // The response was sent or dropped, either way the notification is not stuck
+        watchdog::feed(WatchedTask::Ble);
+    }
+}
+
+#[embassy_executor::task]
+async fn storage_maintenance_task(flash_command_sender: FlashCommandSender) -> ! {
+    loop {
+        Timer::after(STORAGE_MAINTENANCE_INTERVAL).await;
+        flash_command_sender.send(FlashCommand::Maintain).await;
+    }
+}
+
+fn battery_voltage_mv(sample: i16) -> u32 {
+    // 0.6V internal reference, gain 1/3, voltage divider 1/3
+    sample.max(0) as u32 * 1800 / 2_u32.pow(12) * 3
+}
+
+/// Time until the next battery sample depending on the last reading and on the time since steps
+/// were counted.
+fn battery_sample_interval(
+    config: &PedometerConfig,
+    voltage_mv: u32,
+    since_activity: Duration,
+) -> Duration {
+    if voltage_mv < config.battery_low_mv as u32 {
+        return Duration::from_secs(config.battery_low_sample_interval_secs.max(1) as u64);
     }
+    let interval_secs = config.battery_sample_interval_secs.max(1) as u64;
+    let interval_secs = if since_activity < BATTERY_ACTIVE_WINDOW {
+        interval_secs / BATTERY_ACTIVITY_FACTOR
+    } else if since_activity >= BATTERY_IDLE_AFTER {
+        interval_secs * BATTERY_ACTIVITY_FACTOR
+    } else {
+        interval_secs
+    };
+    Duration::from_secs(interval_secs.max(1))
 }
 
 #[embassy_executor::task]
-async fn read_battery_task(mut saadc: Saadc<'static, 1>, mut bat_led: Output<'static>) -> ! {
+async fn read_battery_task(mut saadc: Saadc<'static, 1>) -> ! {
     let soc_sender = BAT_SOC_WATCH.sender();
+    let mut config_rx = unwrap!(config::CONFIG_WATCH.receiver());
+    let mut daily_steps_rx = unwrap!(DAILY_STEPS_WATCH.receiver());
+    let mut last_activity = Instant::now();
     loop {
         let mut buf = [0; 1];
         saadc.sample(&mut buf).await;
-
-        // 0.6V internal reference, gain 1/3, voltage divider 1/3
-        let voltage_mv = buf[0] as u32 * 1800 / 2_u32.pow(12) * 3;
+        let voltage_mv = battery_voltage_mv(buf[0]);
 
         // Highly incorrect SOC based on linear interpolation between 3.5V and 4.1V
         let soc = ((voltage_mv as i32 - 3500) / 6).clamp(0, 100);
@@ -215,94 +1105,816 @@ async fn read_battery_task(mut saadc: Saadc<'static, 1>, mut bat_led: Output<'st
         );
         soc_sender.send(soc as u8);
 
-        let wait_time = if voltage_mv < 3550 {
-            bat_led.set_low();
-            Timer::after_millis(200).await;
-            bat_led.set_high();
-            Duration::from_secs(30)
-        } else {
-            Duration::from_secs(300)
-        };
+        if voltage_mv < config_rx.try_get().unwrap_or_default().battery_low_mv as u32 {
+            LED_BLINK_SIGNAL.signal(1);
+        }
 
-        Timer::after(wait_time).await;
+        let sampled_at = Instant::now();
+        loop {
+            let config = config_rx.try_get().unwrap_or_default();
+            let since_activity = Instant::now().saturating_duration_since(last_activity);
+            let sample_at =
+                sampled_at + battery_sample_interval(&config, voltage_mv, since_activity);
+            // Measure again right away if the config changed, so that new thresholds apply.
+            // Counted steps only reschedule the next sample.
+            match select4(
+                Timer::at(sample_at),
+                config_rx.changed(),
+                BAT_SAMPLE_SIGNAL.wait(),
+                daily_steps_rx.changed(),
+            )
+            .await
+            {
+                Either4::Fourth(_) => last_activity = Instant::now(),
+                _ => break,
+            }
+        }
+    }
+}
+
+/// The LED is shared by the battery warning and the idle alert, so it is only driven here.
+#[embassy_executor::task]
+async fn led_task(mut led: Output<'static>) -> ! {
+    loop {
+        let blinks = LED_BLINK_SIGNAL.wait().await;
+        for _ in 0..blinks {
+            // The LED is active low
+            led.set_low();
+            Timer::after_millis(200).await;
+            led.set_high();
+            Timer::after_millis(200).await;
+        }
     }
 }
 
-async fn handle_signals(server: &Server, connection: &Connection) -> ! {
+async fn handle_signals(
+    server: &Server,
+    connection: &Connection,
+    flash_command_sender: FlashCommandSender,
+) -> ! {
     let mut soc_rx = unwrap!(BAT_SOC_WATCH.receiver());
     let mut max_event_id_rx = unwrap!(MAX_EVENT_ID_WATCH.receiver());
+    let mut config_rx = unwrap!(config::CONFIG_WATCH.receiver());
+    let mut diagnostics_rx = unwrap!(DIAGNOSTICS_WATCH.receiver());
+    let mut storage_usage_rx = unwrap!(STORAGE_USAGE_WATCH.receiver());
+    let mut daily_steps_rx = unwrap!(DAILY_STEPS_WATCH.receiver());
+    let mut temperature_rx = unwrap!(TEMPERATURE_WATCH.receiver());
+    let mut storage_fill_percent = storage_usage_rx.try_get().map(|usage| usage.fill_percent());
+    let mut storage_warning_level = storage_warning_level(storage_fill_percent);
     loop {
-        match select(soc_rx.changed(), max_event_id_rx.changed()).await {
-            Either::First(soc) => {
+        let signal = match select4(
+            select4(
+                soc_rx.changed(),
+                max_event_id_rx.changed(),
+                config_rx.changed(),
+                diagnostics_rx.changed(),
+            ),
+            storage_usage_rx.changed(),
+            daily_steps_rx.changed(),
+            temperature_rx.changed(),
+        )
+        .await
+        {
+            Either4::First(signal) => signal,
+            Either4::Fourth(temperature) => {
+                if let Err(e) = server
+                    .pedometer
+                    .temperature_notify(connection, &temperature)
+                {
+                    debug!("Could not send temperature notification! {:?}", e);
+                    set_value(
+                        &flash_command_sender,
+                        server.pedometer.temperature_set(&temperature),
+                    );
+                }
+                continue;
+            }
+            Either4::Third(daily_steps) => {
+                if let Err(e) = server
+                    .pedometer
+                    .daily_steps_notify(connection, &daily_steps)
+                {
+                    debug!("Could not send daily_steps notification! {:?}", e);
+                    set_value(
+                        &flash_command_sender,
+                        server.pedometer.daily_steps_set(&daily_steps),
+                    );
+                }
+                continue;
+            }
+            Either4::Second(usage) => {
+                let usage_value = usage.to_bytes();
+                let fill_percent = usage.fill_percent();
+                if storage_fill_percent == Some(fill_percent) {
+                    set_value(
+                        &flash_command_sender,
+                        server.pedometer.storage_usage_set(&usage_value),
+                    );
+                    continue;
+                }
+                storage_fill_percent = Some(fill_percent);
+                if let Err(e) = server
+                    .pedometer
+                    .storage_usage_notify(connection, &usage_value)
+                {
+                    debug!("Could not send storage usage notification! {:?}", e);
+                    set_value(
+                        &flash_command_sender,
+                        server.pedometer.storage_usage_set(&usage_value),
+                    );
+                }
+                let level = storage_warning_level(Some(fill_percent));
+                if level > storage_warning_level {
+                    warn!("Storage is {}% full", fill_percent);
+                    if let Err(e) = server
+                        .pedometer
+                        .storage_fill_percent_notify(connection, &fill_percent)
+                    {
+                        warn!("Could not send storage fill notification! {:?}", e);
+                        set_value(
+                            &flash_command_sender,
+                            server.pedometer.storage_fill_percent_set(&fill_percent),
+                        );
+                    }
+                } else {
+                    set_value(
+                        &flash_command_sender,
+                        server.pedometer.storage_fill_percent_set(&fill_percent),
+                    );
+                }
+                storage_warning_level = level;
+                continue;
+            }
+        };
+        match signal {
+            Either4::First(soc) => {
                 if let Err(e) = server.bas.battery_level_notify(connection, &soc) {
                     warn!("Could not send soc notification! {:?}", e);
-                    unwrap!(server.bas.battery_level_set(&soc));
+                    set_value(&flash_command_sender, server.bas.battery_level_set(&soc));
                 } else {
                     info!("Sent battery notification");
                 }
             }
-            Either::Second(max_event_id) => {
+            Either4::Second(max_event_id) => {
                 if let Err(e) = server
                     .pedometer
                     .max_event_id_notify(connection, &max_event_id)
                 {
                     warn!("Could not set max_event_id notification! {:?}", e);
-                    unwrap!(server.pedometer.max_event_id_set(&max_event_id));
+                    set_value(
+                        &flash_command_sender,
+                        server.pedometer.max_event_id_set(&max_event_id),
+                    );
                 } else {
                     info!("Sent max_event_id notification");
                 }
             }
+            Either4::Third(config) => {
+                let config = unwrap!(config.serialize_for_characteristic());
+                set_value(
+                    &flash_command_sender,
+                    server
+                        .pedometer
+                        .config_set(&unwrap!(WriteValue::from_slice(&config))),
+                );
+                if let Err(e) = server.pedometer.config_changed_notify(connection, &config) {
+                    debug!("Could not send config changed notification! {:?}", e);
+                }
+            }
+            Either4::Fourth(diagnostics) => {
+                set_value(
+                    &flash_command_sender,
+                    server
+                        .pedometer
+                        .diagnostics_set(&diagnostics_characteristic(diagnostics)),
+                );
+            }
         }
     }
 }
 
-#[embassy_executor::task]
-async fn imu_task(
-    mut imu: Imu<Twim<'static, TWISPI0>>,
-    mut imu_int: Input<'static>,
-    flash_command_sender: Sender<'static, CriticalSectionRawMutex, FlashCommand, 4>,
-) {
-    unwrap!(imu.dump_all_registers().await);
+/// Notify the results of the flash commands that the host waits for.
+async fn notify_flash_results(server: &Server, connection: &Connection) -> ! {
+    loop {
+        match select(BOOT_SUMMARIES_SIGNAL.wait(), DELETE_RESULT_SIGNAL.wait()).await {
+            Either::First(summaries) => {
+                if let Err(e) = server.pedometer.boot_summaries_notify(
+                    connection,
+                    &unwrap!(WriteValue::from_slice(&summaries.to_bytes())),
+                ) {
+                    warn!("Could not send boot summaries! {:?}", e);
+                }
+            }
+            Either::Second(result) => {
+                if let Err(e) = server
+                    .pedometer
+                    .delete_result_notify(connection, &result.to_bytes())
+                {
+                    warn!("Could not send delete result! {:?}", e);
+                }
+            }
+        }
+    }
+}
 
-    unwrap!(imu.init().await);
-    unwrap!(imu.enable_pedometer(false).await);
-    unwrap!(imu.enable_fifo_for_pedometer(Some(3 * 10 / 2)).await); // Threshold is in words
-    unwrap!(imu.dump_all_registers().await);
+/// Steps sample that was not pushed to flash, yet, because later samples of the same coalescing
+/// window may still replace it
+#[derive(Debug, Copy, Clone)]
+struct PendingSteps {
+    steps: u16,
+    timestamp: Instant,
+    window_start: Instant,
+}
+
+impl PendingSteps {
+    fn new(steps: u16, timestamp: Instant) -> Self {
+        Self {
+            steps,
+            timestamp,
+            window_start: timestamp,
+        }
+    }
+
+    fn window_end(&self, window: Duration) -> Instant {
+        self.window_start + window
+    }
+}
+
+fn step_coalescing_window() -> Duration {
+    Duration::from_secs(
+        config::CONFIG_WATCH
+            .try_get()
+            .map_or(0, |config| config.step_coalescing_window_secs) as u64,
+    )
+}
+
+/// It is always assumed that the timestamp is before imu_now and there was at most one timer
+/// overflow between.
+fn imu_timestamp_to_instant(timestamp: Timestamp, mcu_now: Instant, imu_now: Timestamp) -> Instant {
+    mcu_now - Duration::from_micros(timestamp.elapsed_until(imu_now).as_micros() as u64)
+}
+
+async fn push_steps(flash_command_sender: &FlashCommandSender, pending: PendingSteps) {
+    info!("Send steps to flash");
+    flash_command_sender
+        .send(FlashCommand::PushEvent((
+            PedometerEventType::Steps(pending.steps),
+            Some(pending.timestamp),
+        )))
+        .await;
+}
+
+fn storage_warning_level(fill_percent: Option<u8>) -> u8 {
+    match (fill_percent, config::CONFIG_WATCH.try_get()) {
+        (Some(fill_percent), Some(config)) => config.storage_warning_level(fill_percent),
+        _ => 0,
+    }
+}
+
+/// Power the IMU off and on again. This also releases the bus if the IMU holds it.
+async fn power_cycle_imu(imu_pwr: &mut Output<'static>) {
+    imu_pwr.set_low();
+    Timer::after_millis(20).await;
+    imu_pwr.set_high();
+    Timer::after_millis(20).await;
+}
+
+/// Run an operation of the IMU with a timeout, so that a wedged bus cannot block forever.
+async fn imu_op<T, E>(
+    operation: impl Future<Output = pedomet_rs_imu::Result<T, E>>,
+) -> PedometerResult<T> {
+    Ok(with_timeout(IMU_OPERATION_TIMEOUT, operation)
+        .await
+        .map_err(|_| PedometerFwError::Imu)??)
+}
+
+/// Processing state of the steps that is kept when the IMU is power cycled
+#[derive(Default)]
+struct StepState {
+    /// Step counter as stored in the steps events. It is continued after a power cycle although
+    /// the IMU starts counting from zero again.
+    last_steps: u16,
+    /// Step counter when the IMU was configured, which its own counter continues. The IMU keeps
+    /// counting while it sleeps.
+    counter_offset: u16,
+    /// Steps events carry the absolute step counter, so only the last sample of a window is kept
+    pending_steps: Option<PendingSteps>,
+    diagnostics: PedometerDiagnostics,
+    activity: PedometerActivity,
+    /// Start and step counter at the start of the window in which the activity is classified
+    activity_window: Option<(Instant, u16)>,
+    /// When the temperature of the IMU was measured last
+    last_temperature: Option<Instant>,
+}
+
+impl StepState {
+    /// Classify the activity by the cadence in the window that ends at the given time and start
+    /// the next window. Returns the activity and the start of the window if it changed.
+    fn classify_activity(&mut self, now: Instant) -> Option<(PedometerActivity, Instant)> {
+        let Some((start, start_steps)) = self.activity_window else {
+            self.activity_window = Some((now, self.last_steps));
+            return None;
+        };
+        if now < start + ACTIVITY_WINDOW {
+            return None;
+        }
+        self.activity_window = Some((now, self.last_steps));
+        let steps = self.last_steps.wrapping_sub(start_steps) as u64;
+        let cadence = steps * 60_000 / (now - start).as_millis().max(1);
+        let activity = PedometerActivity::from_cadence(cadence.min(u32::MAX as u64) as u32);
+        if activity == self.activity {
+            return None;
+        }
+        self.activity = activity;
+        // The activity changed within the window, so it is assumed to have started with it
+        Some((activity, start))
+    }
+
+    /// Time at which the IMU goes to sleep if no steps are counted until then. It stays awake
+    /// while steps or an activity are still to be stored and while the samples are streamed.
+    fn motion_sleep_at(&self, last_motion: Instant) -> Option<Instant> {
+        let minutes = config::CONFIG_WATCH
+            .try_get()
+            .map_or(0, |config| config.motion_sleep_minutes);
+        if minutes == 0
+            || self.pending_steps.is_some()
+            || self.activity != PedometerActivity::Idle
+            || accel_stream::is_enabled()
+        {
+            return None;
+        }
+        Some(last_motion + Duration::from_secs(minutes as u64 * 60))
+    }
+
+    /// Time at which the temperature of the IMU has to be measured next
+    fn temperature_due_at(&self) -> Instant {
+        self.last_temperature
+            .map_or(Instant::from_ticks(0), |last| last + TEMPERATURE_INTERVAL)
+    }
+
+    /// Take over the step counter of the IMU.
+    async fn count_steps(
+        &mut self,
+        flash_command_sender: &FlashCommandSender,
+        imu_steps: u16,
+        timestamp: Instant,
+    ) {
+        let step_counter = self.counter_offset.wrapping_add(imu_steps);
+        let counted_steps = step_counter.wrapping_sub(self.last_steps) as u32;
+        self.diagnostics.counted_steps += counted_steps;
+        DAILY_STEPS.fetch_add(counted_steps, Ordering::Relaxed);
+        self.last_steps = step_counter;
+        DAILY_STEPS_WATCH
+            .sender()
+            .send(DAILY_STEPS.load(Ordering::Relaxed));
+
+        let window = step_coalescing_window();
+        match self.pending_steps.as_mut() {
+            Some(pending) if timestamp < pending.window_end(window) => {
+                pending.steps = step_counter;
+                pending.timestamp = timestamp;
+            }
+            _ => {
+                if let Some(pending) = self
+                    .pending_steps
+                    .replace(PendingSteps::new(step_counter, timestamp))
+                {
+                    push_steps(flash_command_sender, pending).await;
+                }
+            }
+        }
+    }
+}
+
+/// Why the steps are not processed anymore although the IMU works
+enum StepsExit<'a> {
+    /// The IMU has to be configured with another sensitivity
+    Reconfigure,
+    /// No steps were counted for the configured duration, so the IMU may sleep
+    Sleep(Imu<'a, FifoEnabled>),
+}
+
+/// State of the configured IMU
+enum ImuMode<'a> {
+    Counting(Imu<'a, FifoEnabled>),
+    /// Waiting in the low-power mode for a significant motion
+    Sleeping(Imu<'a, MotionWake>),
+}
+
+fn pedometer_sensitivity(config: &PedometerConfig) -> PedometerSensitivity {
+    PedometerSensitivity {
+        threshold: config
+            .step_threshold
+            .min(PedometerSensitivity::MAX_THRESHOLD),
+        debounce_steps: config
+            .step_debounce_steps
+            .min(PedometerSensitivity::MAX_DEBOUNCE_STEPS),
+    }
+}
+
+async fn configure_imu(
+    mut imu: Imu<'_, Unconfigured>,
+    sensitivity: PedometerSensitivity,
+) -> PedometerResult<Imu<'_, FifoEnabled>> {
+    imu_op(imu.dump_all_registers()).await?;
+
+    let mut imu = imu_op(imu.init()).await?;
+    imu_op(imu.set_pedometer_sensitivity(sensitivity)).await?;
+    imu_op(imu.enable_double_tap()).await?;
+    let imu = imu_op(imu.enable_pedometer(false)).await?;
+    let mut imu = imu_op(imu.enable_fifo_for_pedometer(Some(IMU_FIFO_THRESHOLD))).await?;
+    imu_op(imu.dump_all_registers()).await?;
+    Ok(imu)
+}
+
+/// Read the steps from the FIFO whenever it is filled up to its threshold. Returns if an
+/// operation of the IMU failed, the IMU has to be configured with another sensitivity or no
+/// steps were counted for the motion sleep duration.
+async fn process_steps<'a>(
+    mut imu: Imu<'a, FifoEnabled>,
+    imu_int: &mut Input<'static>,
+    flash_command_sender: &FlashCommandSender,
+    state: &mut StepState,
+    sensitivity: PedometerSensitivity,
+) -> PedometerResult<StepsExit<'a>> {
+    let diagnostics_sender = DIAGNOSTICS_WATCH.sender();
+    let mut config_rx = unwrap!(config::CONFIG_WATCH.receiver());
+    let mut last_motion = Instant::now();
 
     imu_int.wait_for_low().await;
     loop {
-        select(Timer::after_secs(10 * 60), imu_int.wait_for_rising_edge()).await;
-        info!("Imu interrupt or timer elapsed");
+        watchdog::feed(WatchedTask::Imu);
+        let fallback_timeout = Instant::now() + Duration::from_secs(10 * 60);
+        let mut timeout = fallback_timeout;
+        if let Some(pending) = &state.pending_steps {
+            timeout = timeout.min(pending.window_end(step_coalescing_window()));
+        }
+        // No steps are read while the user is idle, so the end of an activity needs a timer
+        if let (Some((start, _)), false) = (
+            state.activity_window,
+            state.activity == PedometerActivity::Idle,
+        ) {
+            timeout = timeout.min(start + ACTIVITY_WINDOW);
+        }
+        if let Some(sleep_at) = state.motion_sleep_at(last_motion) {
+            timeout = timeout.min(sleep_at);
+        }
+        timeout = timeout.min(state.temperature_due_at());
+
+        let mut reconfigure = false;
+        let midnight = match select4(
+            Timer::at(timeout),
+            imu_int.wait_for_rising_edge(),
+            clock::wait_for_local_midnight(),
+            select(config_rx.changed(), accel_stream::wait_for_sample()),
+        )
+        .await
+        {
+            Either4::First(_) => {
+                info!("Timer elapsed");
+                // Timeouts of the coalescing window are expected and do not hint at the threshold
+                if timeout == fallback_timeout {
+                    state.diagnostics.timer_fallbacks += 1;
+                }
+                false
+            }
+            Either4::Second(_) => {
+                if imu_op(imu.read_double_tap()).await? {
+                    gesture::DOUBLE_TAP_SIGNAL.signal(());
+                    // INT1 is shared with the FIFO threshold, which may be reached as well
+                    if imu_int.is_low() {
+                        continue;
+                    }
+                }
+                info!("Imu interrupt");
+                state.diagnostics.fifo_interrupts += 1;
+                false
+            }
+            Either4::Third(_) => {
+                info!("Local midnight");
+                true
+            }
+            Either4::Fourth(Either::First(config)) => {
+                reconfigure = pedometer_sensitivity(&config) != sensitivity;
+                false
+            }
+            Either4::Fourth(Either::Second(_)) => {
+                accel_stream::push_sample(imu_op(imu.read_acceleration()).await?);
+                // Only read the FIFO if it reached its threshold in the meantime
+                if imu_int.is_low() {
+                    continue;
+                }
+                false
+            }
+        };
 
         let mcu_now = Instant::now();
-        let imu_now = unwrap!(imu.read_timestamp().await);
+        let imu_now = imu_op(imu.read_timestamp()).await?;
+
+        if mcu_now >= state.temperature_due_at() {
+            measure_temperature(&mut imu, state, flash_command_sender).await?;
+        }
+
+        if imu_op(imu.read_fifo_overrun()).await? {
+            warn!("IMU FIFO overrun, steps samples were lost");
+            state.diagnostics.fifo_overruns += 1;
+        }
 
-        while let Some(steps) = unwrap!(imu.read_steps_from_fifo().await) {
+        while let Some(steps) = imu_op(imu.read_steps_from_fifo()).await? {
+            let timestamp = imu_timestamp_to_instant(steps.timestamp, mcu_now, imu_now);
             info!(
                 "From FIFO: {:?}@{}ms ({}:{})",
                 steps,
                 steps.timestamp.as_duration().as_millis(),
-                steps.timestamp.to_instant(mcu_now, imu_now).as_millis(),
+                timestamp.as_millis(),
                 mcu_now.as_millis(),
             );
-            info!("Send steps to flash");
+            // Every detected step is stored in the FIFO, but the counter only increases after
+            // the debounce
+            state.diagnostics.detected_steps += 1;
+            state
+                .count_steps(flash_command_sender, steps.steps, timestamp)
+                .await;
+            last_motion = mcu_now;
+        }
+
+        diagnostics_sender.send(state.diagnostics);
+
+        if let Some((activity, start)) = state.classify_activity(mcu_now) {
+            info!("Activity changed to {:?}", activity);
             flash_command_sender
                 .send(FlashCommand::PushEvent((
-                    PedometerEventType::Steps(steps.steps),
-                    Some(steps.timestamp.to_instant(mcu_now, imu_now)),
+                    PedometerEventType::Activity(activity),
+                    Some(start),
                 )))
                 .await;
         }
 
+        // The steps before midnight have to be stored before the summary of their day
+        if let Some(pending) = state.pending_steps {
+            if midnight || mcu_now >= pending.window_end(step_coalescing_window()) {
+                push_steps(flash_command_sender, pending).await;
+                state.pending_steps = None;
+            }
+        }
+
+        if midnight {
+            // All steps until now were read from the FIFO above, so they belong to the last day
+            push_daily_summary(flash_command_sender).await;
+        }
+
+        // The steps in the FIFO were read above, so none are lost by the power cycle
+        if reconfigure {
+            info!("Pedometer sensitivity changed, reconfigure IMU");
+            return Ok(StepsExit::Reconfigure);
+        }
+
+        if state
+            .motion_sleep_at(last_motion)
+            .is_some_and(|sleep_at| mcu_now >= sleep_at)
+        {
+            return Ok(StepsExit::Sleep(imu));
+        }
+
         imu_int.wait_for_low().await;
     }
 }
 
+/// Store the temperature of the IMU as event and pass it to the connection.
+async fn measure_temperature<S: PedometerRunning>(
+    imu: &mut Imu<'_, S>,
+    state: &mut StepState,
+    flash_command_sender: &FlashCommandSender,
+) -> PedometerResult<()> {
+    let temperature = imu_op(imu.read_temperature()).await?.as_centi_celsius();
+    state.last_temperature = Some(Instant::now());
+    info!("IMU temperature: {} c°C", temperature);
+    TEMPERATURE_WATCH.sender().send(temperature);
+    flash_command_sender
+        .send(FlashCommand::PushEvent((
+            PedometerEventType::Temperature(temperature),
+            None,
+        )))
+        .await;
+    Ok(())
+}
+
+async fn push_daily_summary(flash_command_sender: &FlashCommandSender) {
+    let daily_steps = DAILY_STEPS.swap(0, Ordering::Relaxed);
+    DAILY_STEPS_WATCH.sender().send(0);
+    info!("Send daily summary with {} steps to flash", daily_steps);
+    flash_command_sender
+        .send(FlashCommand::PushEvent((
+            PedometerEventType::DailySummary(daily_steps),
+            None,
+        )))
+        .await;
+}
+
+/// Wait in the low-power mode of the IMU until it has to count the steps again, i.e. after a
+/// significant motion, a config change that concerns the step detection or when the samples are
+/// streamed. The few steps until a significant motion is detected are only read after the wake
+/// up, so the daily summary is stored right away at midnight. The temperature is still measured
+/// in the meantime.
+async fn wait_for_wake(
+    imu: &mut Imu<'_, MotionWake>,
+    imu_int: &mut Input<'static>,
+    flash_command_sender: &FlashCommandSender,
+    state: &mut StepState,
+    sensitivity: PedometerSensitivity,
+) -> PedometerResult<()> {
+    let mut config_rx = unwrap!(config::CONFIG_WATCH.receiver());
+
+    imu_int.wait_for_low().await;
+    loop {
+        watchdog::feed(WatchedTask::Imu);
+        match select4(
+            imu_int.wait_for_rising_edge(),
+            select(
+                clock::wait_for_local_midnight(),
+                Timer::at(state.temperature_due_at()),
+            ),
+            config_rx.changed(),
+            accel_stream::wait_for_sample(),
+        )
+        .await
+        {
+            Either4::First(_) => {
+                // The double tap shares INT1 with the significant motion
+                if imu_op(imu.read_double_tap()).await? {
+                    gesture::DOUBLE_TAP_SIGNAL.signal(());
+                    continue;
+                }
+                info!("Significant motion");
+                return Ok(());
+            }
+            Either4::Second(Either::First(_)) => push_daily_summary(flash_command_sender).await,
+            Either4::Second(Either::Second(_)) => {
+                measure_temperature(imu, state, flash_command_sender).await?
+            }
+            Either4::Third(config) => {
+                if config.motion_sleep_minutes == 0 || pedometer_sensitivity(&config) != sensitivity
+                {
+                    return Ok(());
+                }
+            }
+            Either4::Fourth(_) => return Ok(()),
+        }
+    }
+}
+
+/// Switch the configured IMU between counting the steps and sleeping until a motion. Returns
+/// if an operation of the IMU failed or it has to be configured with another sensitivity.
+async fn run_imu(
+    imu: Imu<'_, FifoEnabled>,
+    imu_int: &mut Input<'static>,
+    flash_command_sender: &FlashCommandSender,
+    state: &mut StepState,
+    sensitivity: PedometerSensitivity,
+) -> PedometerResult<()> {
+    let mut mode = ImuMode::Counting(imu);
+    loop {
+        mode = match mode {
+            ImuMode::Counting(imu) => {
+                match process_steps(imu, imu_int, flash_command_sender, state, sensitivity).await? {
+                    StepsExit::Reconfigure => return Ok(()),
+                    StepsExit::Sleep(imu) => {
+                        info!("No steps counted, IMU sleeps until a motion");
+                        ImuMode::Sleeping(imu_op(imu.enter_motion_wake()).await?)
+                    }
+                }
+            }
+            ImuMode::Sleeping(mut imu) => {
+                wait_for_wake(&mut imu, imu_int, flash_command_sender, state, sensitivity).await?;
+                // The steps that lead to the wake up were counted, but not stored in the FIFO
+                let mcu_now = Instant::now();
+                let steps = imu_op(imu.read_steps_from_registers()).await?;
+                let imu_now = imu_op(imu.read_timestamp()).await?;
+                if state.counter_offset.wrapping_add(steps.steps) != state.last_steps {
+                    state
+                        .count_steps(
+                            flash_command_sender,
+                            steps.steps,
+                            imu_timestamp_to_instant(steps.timestamp, mcu_now, imu_now),
+                        )
+                        .await;
+                }
+                let imu = imu_op(imu.wake()).await?;
+                info!("IMU woke up");
+                ImuMode::Counting(
+                    imu_op(imu.enable_fifo_for_pedometer(Some(IMU_FIFO_THRESHOLD))).await?,
+                )
+            }
+        };
+    }
+}
+
+/// Count the steps with the IMU. If the IMU does not respond anymore, it is power cycled and
+/// configured again.
+#[embassy_executor::task]
+async fn imu_task(
+    mut twi: Twim<'static, TWISPI0>,
+    mut imu_pwr: Output<'static>,
+    mut imu_int: Input<'static>,
+    flash_command_sender: FlashCommandSender,
+) {
+    let mut state = StepState::default();
+    let mut recovery_delay = IMU_MIN_RECOVERY_DELAY;
+
+    loop {
+        let sensitivity =
+            pedometer_sensitivity(&config::CONFIG_WATCH.try_get().unwrap_or_default());
+        let result = match configure_imu(Lsm6ds3::new(&mut twi), sensitivity).await {
+            Ok(imu) => {
+                recovery_delay = IMU_MIN_RECOVERY_DELAY;
+                // The IMU was just powered on and counts from zero, so its counter continues the
+                // last one
+                state.counter_offset = state.last_steps;
+                run_imu(
+                    imu,
+                    &mut imu_int,
+                    &flash_command_sender,
+                    &mut state,
+                    sensitivity,
+                )
+                .await
+            }
+            Err(e) => Err(e),
+        };
+        let Err(e) = result else {
+            // The IMU counts from zero again after a power cycle, as expected by process_steps
+            power_cycle_imu(&mut imu_pwr).await;
+            continue;
+        };
+        warn!(
+            "IMU failed, power cycle it in {}s! {:?}",
+            recovery_delay.as_secs(),
+            e
+        );
+        // A power cycle may take long after repeated failures, so do not hold back the steps
+        if let Some(pending) = state.pending_steps.take() {
+            push_steps(&flash_command_sender, pending).await;
+        }
+        state.diagnostics.imu_power_cycles += 1;
+        DIAGNOSTICS_WATCH.sender().send(state.diagnostics);
+        flash_command_sender
+            .send(FlashCommand::PushEvent((
+                PedometerEventType::Error(PedometerError::ImuPowerCycled),
+                None,
+            )))
+            .await;
+
+        select(
+            Timer::after(recovery_delay),
+            watchdog::keep_fed(WatchedTask::Imu),
+        )
+        .await;
+        recovery_delay = (recovery_delay * 2).min(IMU_MAX_RECOVERY_DELAY);
+        power_cycle_imu(&mut imu_pwr).await;
+    }
+}
+
+fn build_adv_data(device_name: &str) -> LegacyAdvertisementPayload {
+    let live_data = PedometerAdvertisingData {
+        daily_steps: DAILY_STEPS.load(Ordering::Relaxed),
+        soc: BAT_SOC_WATCH.try_get(),
+        data_waiting: UNSYNCED_EVENTS.load(Ordering::Relaxed) >= DATA_WAITING_EVENTS,
+    };
+    let mut manufacturer_data = [0; 2 + PedometerAdvertisingData::SIZE];
+    manufacturer_data[..2].copy_from_slice(&ADVERTISING_COMPANY_ID.to_le_bytes());
+    manufacturer_data[2..].copy_from_slice(&live_data.to_bytes());
+
+    LegacyAdvertisementBuilder::new()
+        .flags(&[Flag::GeneralDiscovery, Flag::LE_Only])
+        .full_name(device_name)
+        .raw(
+            AdvertisementDataType::MANUFACTURER_SPECIFIC_DATA,
+            &manufacturer_data,
+        )
+        .build()
+}
+
 bind_interrupts!(struct Irqs {
     SPIM0_SPIS0_TWIM0_TWIS0_SPI0_TWI0 => twim::InterruptHandler<peripherals::TWISPI0>;
     SAADC => saadc::InterruptHandler;
 });
 
+/// Read and clear the reset reason. This has to happen before the softdevice is enabled because
+/// it takes over the POWER peripheral.
+fn take_reset_reason() -> u32 {
+    let power = unsafe { &*embassy_nrf::pac::POWER::ptr() };
+    let reset_reason = power.resetreas.read().bits();
+    // The register accumulates the reasons until they are cleared by writing ones
+    power.resetreas.write(|w| unsafe { w.bits(reset_reason) });
+    reset_reason
+}
+
+/// Read the lower half of the random static address that is programmed into the FICR.
+fn device_address() -> u32 {
+    let ficr = unsafe { &*embassy_nrf::pac::FICR::ptr() };
+    ficr.deviceaddr[0].read().bits()
+}
+
 #[embassy_executor::main]
 async fn main(spawner: Spawner) {
     let mut nrf_hal_config = embassy_nrf::config::Config::default();
@@ -312,6 +1924,12 @@ async fn main(spawner: Spawner) {
     info!("Init nrf-hal");
     let mut peripherals = embassy_nrf::init(nrf_hal_config);
 
+    let reset_reason = take_reset_reason();
+    info!("Reset reason: {:#x}", reset_reason);
+
+    info!("Start watchdog");
+    let watchdog_handle = watchdog::start(peripherals.WDT);
+
     info!("Enable battery monitoring");
     let _read_bat_en = Output::new(peripherals.P0_14, Level::Low, OutputDrive::Standard);
     info!("Set high charge current (100mA)");
@@ -319,21 +1937,18 @@ async fn main(spawner: Spawner) {
 
     info!("Init IMU");
     let mut imu_pwr = Output::new(peripherals.P1_08, Level::Low, OutputDrive::HighDrive);
-    Timer::after_millis(20).await;
-    imu_pwr.set_high();
-    Timer::after_millis(20).await;
+    power_cycle_imu(&mut imu_pwr).await;
 
     interrupt::SPIM0_SPIS0_TWIM0_TWIS0_SPI0_TWI0.set_priority(interrupt::Priority::P3);
     let mut twi_config = twim::Config::default();
     twi_config.frequency = Frequency::K400;
-    let twi = Twim::new(
+    let mut twi = Twim::new(
         peripherals.TWISPI0,
         Irqs,
         peripherals.P0_07,
         peripherals.P0_27,
         twi_config,
     );
-    let imu = Imu::new(twi);
 
     let imu_int = Input::new(peripherals.P0_11, Pull::None);
 
@@ -345,13 +1960,22 @@ async fn main(spawner: Spawner) {
     saadc_channel_config.gain = Gain::GAIN1_3;
     saadc_channel_config.time = Time::_40US;
 
-    let saadc_bat = Saadc::new(
+    let mut saadc_bat = Saadc::new(
         peripherals.SAADC,
         Irqs,
         saadc_config,
         [saadc_channel_config],
     );
-    let bat_led = Output::new(peripherals.P0_26, Level::High, OutputDrive::HighDrive);
+    let led = Output::new(peripherals.P0_26, Level::High, OutputDrive::HighDrive);
+
+    info!("Run self test");
+    let mut self_test = PedometerSelfTest::default();
+    self_test::check_imu(&mut twi, &mut self_test).await;
+    self_test::check_battery(&mut saadc_bat, &mut self_test).await;
+
+    let device_name = DEVICE_NAME.init(pedomet_rs_common::device_name(device_address()));
+    let device_name_str = unwrap!(core::str::from_utf8(device_name));
+    info!("Device name: {}", device_name_str);
 
     let softdevice_config = nrf_softdevice::Config {
         clock: Some(raw::nrf_clock_lf_cfg_t {
@@ -376,9 +2000,9 @@ async fn main(spawner: Spawner) {
             _bitfield_1: raw::ble_gap_cfg_role_count_t::new_bitfield_1(0),
         }),
         gap_device_name: Some(raw::ble_gap_cfg_device_name_t {
-            p_value: b"pedomet-rs" as *const u8 as _,
-            current_len: 10,
-            max_len: 10,
+            p_value: device_name.as_ptr() as _,
+            current_len: DEVICE_NAME_LEN as u16,
+            max_len: DEVICE_NAME_LEN as u16,
             write_perm: unsafe { mem::zeroed() },
             _bitfield_1: raw::ble_gap_cfg_device_name_t::new_bitfield_1(
                 raw::BLE_GATTS_VLOC_STACK as u8,
@@ -391,120 +2015,361 @@ async fn main(spawner: Spawner) {
     let sd = Softdevice::enable(&softdevice_config);
 
     let server = unwrap!(Server::new(sd));
-    unwrap!(spawner.spawn(softdevice_task(sd)));
 
     let flash_command_channel = FLASH_COMMAND_CHANNEL.init(Channel::new());
+    let push_event_channel = PUSH_EVENT_CHANNEL.init(Channel::new());
+    let flash_command_sender = FlashCommandSender {
+        events: push_event_channel.sender(),
+        commands: flash_command_channel.sender(),
+    };
     let read_event_channel = READ_EVENT_CHANNEL.init(Channel::new());
 
+    unwrap!(spawner.spawn(softdevice_task(sd, flash_command_sender)));
+
     unwrap!(spawner.spawn(flash_task(
         sd,
+        reset_reason,
+        self_test,
+        push_event_channel.receiver(),
         flash_command_channel.receiver(),
         read_event_channel.sender()
     )));
 
-    unwrap!(spawner.spawn(imu_task(imu, imu_int, flash_command_channel.sender())));
-    unwrap!(spawner.spawn(read_battery_task(saadc_bat, bat_led)));
+    unwrap!(spawner.spawn(imu_task(twi, imu_pwr, imu_int, flash_command_sender)));
+    unwrap!(spawner.spawn(read_battery_task(saadc_bat)));
+    unwrap!(spawner.spawn(led_task(led)));
+    unwrap!(spawner.spawn(idle_alert::idle_alert_task()));
+    unwrap!(spawner.spawn(gesture::gesture_task()));
+    unwrap!(spawner.spawn(storage_maintenance_task(flash_command_sender)));
+    if let Some(handle) = watchdog_handle {
+        unwrap!(spawner.spawn(watchdog::watchdog_task(handle, flash_command_sender)));
+    }
+    #[cfg(feature = "addon-sensors")]
+    unwrap!(spawner.spawn(addon::addon_task(flash_command_sender)));
 
-    static ADV_DATA: LegacyAdvertisementPayload = LegacyAdvertisementBuilder::new()
-        .flags(&[Flag::GeneralDiscovery, Flag::LE_Only])
-        .services_16(ServiceList::Complete, &[ServiceUuid16::BATTERY])
-        .full_name("pedomet-rs")
-        .build();
+    static BONDER: StaticCell<bonds::Bonder> = StaticCell::new();
+    let bonder = BONDER.init(bonds::Bonder {
+        flash_command_sender,
+    });
 
+    // The battery service does not fit into the advertising data next to the name with its suffix
     static SCAN_DATA: LegacyAdvertisementPayload = LegacyAdvertisementBuilder::new()
+        .services_16(
+            ServiceList::Complete,
+            &[
+                ServiceUuid16::BATTERY,
+                ServiceUuid16::RUNNING_SPEED_AND_CADENCE,
+            ],
+        )
         .services_128(
             ServiceList::Complete,
-            &[0x9e7312e0_2354_11eb_9f10_fbc30a62cf38_u128.to_le_bytes()],
+            &[gatt::PEDOMETER_SERVICE.to_le_bytes()],
         )
         .build();
 
     loop {
         let config = peripheral::Config::default();
-        let adv = peripheral::ConnectableAdvertisement::ScannableUndirected {
-            adv_data: &ADV_DATA,
-            scan_data: &SCAN_DATA,
+        let conn = loop {
+            watchdog::feed(WatchedTask::Ble);
+            let adv_data = build_adv_data(device_name_str);
+            let adv = peripheral::ConnectableAdvertisement::ScannableUndirected {
+                adv_data: &adv_data,
+                scan_data: &SCAN_DATA,
+            };
+            // Dropping the advertising future stops the advertising, so it can be restarted with
+            // the current live data
+            match select(
+                peripheral::advertise_pairable(sd, adv, &config, bonder),
+                Timer::after(ADVERTISING_UPDATE_INTERVAL),
+            )
+            .await
+            {
+                Either::First(Ok(conn)) => break conn,
+                Either::First(Err(e)) => {
+                    warn!("Advertising failed! {:?}", e);
+                    if let AdvertiseError::Raw(e) = e {
+                        push_error(&flash_command_sender, PedometerError::Softdevice(e as u32));
+                    }
+                    Timer::after_secs(1).await;
+                }
+                Either::Second(_) => debug!("Update advertising data"),
+            }
         };
-        let conn = unwrap!(peripheral::advertise_connectable(sd, adv, &config).await);
 
         info!("advertising done!");
+        // The last sample may be as old as the sample interval, so the host would show a stale
+        // battery level until the next one
+        BAT_SAMPLE_SIGNAL.signal(());
+        // The subscription of the previous connection is not stored
+        rsc::RSC_NOTIFICATIONS_SIGNAL.reset();
+        accel_stream::set_enabled(false);
 
         // Run the GATT server on the connection. This returns when the connection gets disconnected.
         //
         // Event enums (ServerEvent's) are generated by nrf_softdevice::gatt_server
         // proc macro when applied to the Server struct above
-        let gatt_fut = gatt_server::run(&conn, &server, |e| match e {
-            ServerEvent::Bas(e) => match e {
-                BatteryServiceEvent::BatteryLevelCccdWrite { notifications } => {
-                    info!("battery notifications: {}", notifications)
-                }
-            },
-            ServerEvent::Pedometer(e) => match e {
-                PedometerServiceEvent::RequestEventsWrite(min_event_index) => {
-                    info!("pedometer request_events from: {}", min_event_index);
-                    if let Err(TrySendError::Full(_)) =
-                        flash_command_channel.try_send(FlashCommand::GetEvents(min_event_index))
-                    {
-                        warn!("Could not send command.");
+        let gatt_fut = gatt_server::run(&conn, &server, |e| {
+            watchdog::feed(WatchedTask::Ble);
+            match e {
+                ServerEvent::Bas(e) => match e {
+                    BatteryServiceEvent::BatteryLevelCccdWrite { notifications } => {
+                        info!("battery notifications: {}", notifications);
+                        // Notify a fresh value as soon as the host subscribed
+                        if notifications {
+                            BAT_SAMPLE_SIGNAL.signal(());
+                        }
                     }
-                }
-                PedometerServiceEvent::ResponseEventsCccdWrite { notifications } => {
-                    info!("pedometer response_events notifications: {}", notifications)
-                }
-                PedometerServiceEvent::DeleteEventsWrite(min_event_index) => {
-                    info!("pedometer delete_events: {}", min_event_index);
-                    if let Err(TrySendError::Full(_)) =
-                        flash_command_channel.try_send(FlashCommand::DeleteEvents(min_event_index))
-                    {
-                        warn!("Could not send command.");
+                },
+                ServerEvent::Rsc(e) => match e {
+                    RscServiceEvent::MeasurementCccdWrite { notifications } => {
+                        info!("RSC notifications: {}", notifications);
+                        rsc::RSC_NOTIFICATIONS_SIGNAL.signal(notifications);
                     }
-                }
-                PedometerServiceEvent::EpochMsWrite(epoch_ms) => {
-                    info!("pedometer time: {}", epoch_ms);
-                    if let Err(TrySendError::Full(_)) = flash_command_channel.try_send(
-                        FlashCommand::PushEvent((PedometerEventType::HostEpochMs(epoch_ms), None)),
-                    ) {
-                        warn!("Could not send command.");
-                    } else if let Err(e) = server
-                        .pedometer
-                        .epoch_ms_notify(&conn, &Instant::now().as_millis())
-                    {
-                        info!("send notification error: {:?}", e);
+                },
+                ServerEvent::Pedometer(e) => match e {
+                    PedometerServiceEvent::RequestEventsWrite(data) => handle_write(
+                        &server,
+                        &conn,
+                        &flash_command_sender,
+                        gatt::REQUEST_EVENTS,
+                        &data,
+                    ),
+                    PedometerServiceEvent::RequestEventsSinceWrite(data) => handle_write(
+                        &server,
+                        &conn,
+                        &flash_command_sender,
+                        gatt::REQUEST_EVENTS_SINCE,
+                        &data,
+                    ),
+                    PedometerServiceEvent::RequestEventsQueryWrite(data) => handle_write(
+                        &server,
+                        &conn,
+                        &flash_command_sender,
+                        gatt::REQUEST_EVENTS_QUERY,
+                        &data,
+                    ),
+                    PedometerServiceEvent::RequestEventsTransferWrite(data) => handle_write(
+                        &server,
+                        &conn,
+                        &flash_command_sender,
+                        gatt::REQUEST_EVENTS_TRANSFER,
+                        &data,
+                    ),
+                    PedometerServiceEvent::DeleteEventsWrite(data) => handle_write(
+                        &server,
+                        &conn,
+                        &flash_command_sender,
+                        gatt::DELETE_EVENTS,
+                        &data,
+                    ),
+                    PedometerServiceEvent::AckEventsWrite(data) => handle_write(
+                        &server,
+                        &conn,
+                        &flash_command_sender,
+                        gatt::ACK_EVENTS,
+                        &data,
+                    ),
+                    PedometerServiceEvent::DeleteBootWrite(data) => handle_write(
+                        &server,
+                        &conn,
+                        &flash_command_sender,
+                        gatt::DELETE_BOOT,
+                        &data,
+                    ),
+                    PedometerServiceEvent::EpochMsWrite(data) => {
+                        handle_write(&server, &conn, &flash_command_sender, gatt::EPOCH_MS, &data)
                     }
-                }
-                PedometerServiceEvent::EpochMsCccdWrite { notifications } => {
-                    info!("pedometer host_epoch_ms notifications: {}", notifications)
-                }
-                PedometerServiceEvent::MaxEventIdCccdWrite { notifications } => {
-                    info!("pedometer max_event_id notifications: {}", notifications)
-                }
-            },
+                    PedometerServiceEvent::ConfigWrite(data) => {
+                        handle_write(&server, &conn, &flash_command_sender, gatt::CONFIG, &data)
+                    }
+                    PedometerServiceEvent::TimeSyncWrite(data) => handle_write(
+                        &server,
+                        &conn,
+                        &flash_command_sender,
+                        gatt::TIME_SYNC,
+                        &data,
+                    ),
+                    PedometerServiceEvent::MarkerWrite(data) => {
+                        handle_write(&server, &conn, &flash_command_sender, gatt::MARKER, &data)
+                    }
+                    PedometerServiceEvent::AccelerationWrite(data) => handle_write(
+                        &server,
+                        &conn,
+                        &flash_command_sender,
+                        gatt::ACCELERATION,
+                        &data,
+                    ),
+                    PedometerServiceEvent::BootSummariesWrite(data) => handle_write(
+                        &server,
+                        &conn,
+                        &flash_command_sender,
+                        gatt::BOOT_SUMMARIES,
+                        &data,
+                    ),
+                    PedometerServiceEvent::ResponseEventsCccdWrite { notifications } => {
+                        info!("pedometer response_events notifications: {}", notifications)
+                    }
+                    PedometerServiceEvent::EpochMsCccdWrite { notifications } => {
+                        info!("pedometer host_epoch_ms notifications: {}", notifications)
+                    }
+                    PedometerServiceEvent::MaxEventIdCccdWrite { notifications } => {
+                        info!("pedometer max_event_id notifications: {}", notifications)
+                    }
+                    PedometerServiceEvent::TimeSyncCccdWrite { notifications } => {
+                        info!("pedometer time_sync notifications: {}", notifications)
+                    }
+                    PedometerServiceEvent::DailyStepsCccdWrite { notifications } => {
+                        info!("pedometer daily_steps notifications: {}", notifications)
+                    }
+                    PedometerServiceEvent::WriteErrorCccdWrite { notifications } => {
+                        info!("pedometer write_error notifications: {}", notifications)
+                    }
+                    PedometerServiceEvent::AccelerationCccdWrite { notifications } => {
+                        info!("pedometer acceleration notifications: {}", notifications)
+                    }
+                    PedometerServiceEvent::BootSummariesCccdWrite { notifications } => {
+                        info!("pedometer boot_summaries notifications: {}", notifications)
+                    }
+                    PedometerServiceEvent::TemperatureCccdWrite { notifications } => {
+                        info!("pedometer temperature notifications: {}", notifications)
+                    }
+                    PedometerServiceEvent::StorageFillPercentCccdWrite { notifications } => {
+                        info!(
+                            "pedometer storage_fill_percent notifications: {}",
+                            notifications
+                        )
+                    }
+                    PedometerServiceEvent::ConfigChangedCccdWrite { notifications } => {
+                        info!("pedometer config_changed notifications: {}", notifications)
+                    }
+                    PedometerServiceEvent::StorageUsageCccdWrite { notifications } => {
+                        info!("pedometer storage_usage notifications: {}", notifications)
+                    }
+                    PedometerServiceEvent::DeleteResultCccdWrite { notifications } => {
+                        info!("pedometer delete_result notifications: {}", notifications)
+                    }
+                },
+            }
         });
 
         if let Some(soc) = BAT_SOC_WATCH.try_get() {
-            unwrap!(server.bas.battery_level_set(&soc));
+            set_value(&flash_command_sender, server.bas.battery_level_set(&soc));
+        }
+        // Both are unknown if the event queue could not be initialized
+        if let Some(boot_id) = BOOT_ID_WATCH.try_get() {
+            set_value(
+                &flash_command_sender,
+                server.pedometer.boot_id_set(&boot_id),
+            );
+        }
+        if let Some(max_event_id) = MAX_EVENT_ID_WATCH.try_get() {
+            set_value(
+                &flash_command_sender,
+                server.pedometer.max_event_id_set(&max_event_id),
+            );
+        }
+        if let Some(config) = config::CONFIG_WATCH.try_get() {
+            set_value(
+                &flash_command_sender,
+                server
+                    .pedometer
+                    .config_set(&unwrap!(WriteValue::from_slice(&unwrap!(
+                        config.serialize_for_characteristic()
+                    )))),
+            );
+        }
+        set_value(
+            &flash_command_sender,
+            server
+                .pedometer
+                .daily_steps_set(&DAILY_STEPS.load(Ordering::Relaxed)),
+        );
+        if let Some(usage) = STORAGE_USAGE_WATCH.try_get() {
+            set_value(
+                &flash_command_sender,
+                server.pedometer.storage_usage_set(&usage.to_bytes()),
+            );
+            set_value(
+                &flash_command_sender,
+                server
+                    .pedometer
+                    .storage_fill_percent_set(&usage.fill_percent()),
+            );
+        }
+        if let Some(temperature) = TEMPERATURE_WATCH.try_get() {
+            set_value(
+                &flash_command_sender,
+                server.pedometer.temperature_set(&temperature),
+            );
+        }
+        if let Some(diagnostics) = DIAGNOSTICS_WATCH.try_get() {
+            set_value(
+                &flash_command_sender,
+                server
+                    .pedometer
+                    .diagnostics_set(&diagnostics_characteristic(diagnostics)),
+            );
+        }
+        if let Some(self_test) = self_test::SELF_TEST_WATCH.try_get() {
+            set_value(
+                &flash_command_sender,
+                server.pedometer.self_test_set(&self_test.0),
+            );
         }
-        unwrap!(server
-            .pedometer
-            .boot_id_set(&unwrap!(BOOT_ID_WATCH.try_get())));
-        unwrap!(server
-            .pedometer
-            .max_event_id_set(&unwrap!(MAX_EVENT_ID_WATCH.try_get())));
+        set_value(
+            &flash_command_sender,
+            server.rsc.feature_set(&rsc::RSC_FEATURE),
+        );
+        set_value(
+            &flash_command_sender,
+            server
+                .pedometer
+                .firmware_version_set(&FIRMWARE_VERSION.to_bytes()),
+        );
 
-        let notify_response_fut =
-            notify_response_events(&server, &conn, read_event_channel.receiver());
+        // Responses and chunks that were read for a previous host are not sent to this one
+        while read_event_channel.try_receive().is_ok() {}
+        let notify_response_fut = notify_response_events(
+            &server,
+            &conn,
+            read_event_channel.receiver(),
+            flash_command_sender,
+        );
 
-        let notify_bat_fut = handle_signals(&server, &conn);
+        let notify_bat_fut = handle_signals(&server, &conn, flash_command_sender);
+        let notify_rsc_fut = rsc::notify_rsc_measurements(&server, &conn);
+        let notify_acceleration_fut = accel_stream::notify_acceleration(&server, &conn);
+        // Results requested by a previous host are not sent to this one
+        BOOT_SUMMARIES_SIGNAL.reset();
+        DELETE_RESULT_SIGNAL.reset();
+        let notify_flash_results_fut = notify_flash_results(&server, &conn);
 
-        match select3(gatt_fut, notify_response_fut, notify_bat_fut).await {
-            Either3::First(e) => {
+        match select4(
+            gatt_fut,
+            notify_response_fut,
+            notify_bat_fut,
+            select3(
+                notify_rsc_fut,
+                notify_acceleration_fut,
+                notify_flash_results_fut,
+            ),
+        )
+        .await
+        {
+            Either4::First(e) => {
                 warn!("gatt_server run exited with error: {:?}", e);
             }
-            Either3::Second(_) => {
+            Either4::Second(_) => {
                 warn!("notify_response exited");
             }
-            Either3::Third(_) => {
+            Either4::Third(_) => {
                 warn!("notify_bat exited");
             }
+            Either4::Fourth(_) => {
+                warn!("notify_rsc, notify_acceleration or notify_flash_results exited");
+            }
         };
+        // The host cannot stop the stream anymore
+        accel_stream::set_enabled(false);
     }
 }
@@ -0,0 +1,85 @@
+use core::sync::atomic::Ordering;
+
+use embassy_futures::select::{select, Either};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, signal::Signal};
+use embassy_time::{Duration, Instant, Timer};
+use heapless::Deque;
+use nrf_softdevice::ble::Connection;
+
+use crate::fmt::{debug, info};
+use crate::{Server, DAILY_STEPS};
+
+/// Size of a measurement with only the mandatory fields, i.e. flags, speed and cadence
+pub const RSC_MEASUREMENT_SIZE: usize = 4;
+/// No optional fields of the measurement and no control point are supported
+pub const RSC_FEATURE: u16 = 0;
+/// The service specification expects a measurement about once per second
+const RSC_MEASUREMENT_INTERVAL: Duration = Duration::from_secs(1);
+/// Steps are counted in batches from the FIFO of the IMU, so the cadence is averaged
+const CADENCE_WINDOW_SAMPLES: usize = 10;
+
+/// Whether the host subscribed to the measurements, signaled by the CCCD write
+pub static RSC_NOTIFICATIONS_SIGNAL: Signal<CriticalSectionRawMutex, bool> = Signal::new();
+
+/// Measurement of the Running Speed and Cadence service with the given cadence in steps per
+/// minute. The speed is unknown without the stride length and reported as 0.
+fn rsc_measurement(cadence: u8) -> [u8; RSC_MEASUREMENT_SIZE] {
+    [0, 0, 0, cadence]
+}
+
+/// Cadence in steps per minute from the daily steps at the start and end of the window.
+fn cadence((start, start_steps): (Instant, u32), (end, end_steps): (Instant, u32)) -> Option<u8> {
+    let elapsed_ms = end.checked_duration_since(start)?.as_millis();
+    // The daily steps were reset at midnight
+    let steps = end_steps.checked_sub(start_steps)? as u64;
+    if elapsed_ms == 0 {
+        return None;
+    }
+    Some((steps * 60_000 / elapsed_ms).min(u8::MAX as u64) as u8)
+}
+
+/// Notify the cadence of the last seconds while the host is subscribed.
+pub async fn notify_rsc_measurements(server: &Server, connection: &Connection) -> ! {
+    let mut subscribed = false;
+    let mut samples: Deque<(Instant, u32), CADENCE_WINDOW_SAMPLES> = Deque::new();
+    loop {
+        if !subscribed {
+            samples.clear();
+            subscribed = RSC_NOTIFICATIONS_SIGNAL.wait().await;
+            continue;
+        }
+        match select(
+            Timer::after(RSC_MEASUREMENT_INTERVAL),
+            RSC_NOTIFICATIONS_SIGNAL.wait(),
+        )
+        .await
+        {
+            Either::First(_) => {}
+            Either::Second(notifications) => {
+                subscribed = notifications;
+                continue;
+            }
+        }
+
+        let sample = (Instant::now(), DAILY_STEPS.load(Ordering::Relaxed));
+        if samples.is_full() {
+            samples.pop_front();
+        }
+        let cadence = samples
+            .front()
+            .and_then(|start| cadence(*start, sample))
+            .unwrap_or_default();
+        if samples.back().is_some_and(|last| last.1 > sample.1) {
+            info!("Daily steps were reset, restart cadence window");
+            samples.clear();
+        }
+        let _ = samples.push_back(sample);
+
+        if let Err(e) = server
+            .rsc
+            .measurement_notify(connection, &rsc_measurement(cadence))
+        {
+            debug!("Could not send RSC measurement! {:?}", e);
+        }
+    }
+}
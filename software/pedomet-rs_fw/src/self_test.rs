@@ -0,0 +1,50 @@
+use core::ops::RangeInclusive;
+
+use embassy_nrf::{peripherals::TWISPI0, saadc::Saadc, twim::Twim};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, watch::Watch};
+use pedomet_rs_common::PedometerSelfTest;
+use pedomet_rs_imu::Lsm6ds3;
+
+use crate::fmt::{info, warn};
+use crate::{battery_voltage_mv, imu_op};
+
+/// Battery voltages outside of this range cannot be measured with a connected battery, so the
+/// SAADC reading is wrong
+const PLAUSIBLE_BATTERY_VOLTAGE_MV: RangeInclusive<u32> = 2500..=4500;
+/// The protection circuit of the battery cuts off shortly below this voltage
+const BATTERY_CUTOFF_MV: u32 = 3300;
+
+/// Result of the power-on self test, published once the storage was checked as well
+pub static SELF_TEST_WATCH: Watch<CriticalSectionRawMutex, PedometerSelfTest, 2> = Watch::new();
+
+pub async fn check_imu(twi: &mut Twim<'static, TWISPI0>, self_test: &mut PedometerSelfTest) {
+    match imu_op(Lsm6ds3::new(twi).check_who_am_i()).await {
+        Ok(true) => info!("Self test: IMU ok"),
+        Ok(false) => {
+            warn!("Self test: IMU answered with unexpected WHO_AM_I");
+            self_test.set_failed(PedometerSelfTest::IMU_NOT_RESPONDING);
+        }
+        Err(_) => {
+            warn!("Self test: IMU not responding");
+            self_test.set_failed(PedometerSelfTest::IMU_NOT_RESPONDING);
+        }
+    }
+}
+
+pub async fn check_battery(saadc: &mut Saadc<'static, 1>, self_test: &mut PedometerSelfTest) {
+    let mut buf = [0; 1];
+    saadc.sample(&mut buf).await;
+    let voltage_mv = battery_voltage_mv(buf[0]);
+    if !PLAUSIBLE_BATTERY_VOLTAGE_MV.contains(&voltage_mv) {
+        warn!(
+            "Self test: Implausible battery reading {} => {}mV",
+            buf[0], voltage_mv
+        );
+        self_test.set_failed(PedometerSelfTest::SAADC_IMPLAUSIBLE);
+    } else if voltage_mv < BATTERY_CUTOFF_MV {
+        warn!("Self test: Battery voltage {}mV below cutoff", voltage_mv);
+        self_test.set_failed(PedometerSelfTest::BATTERY_LOW);
+    } else {
+        info!("Self test: Battery ok with {}mV", voltage_mv);
+    }
+}
@@ -1,18 +1,34 @@
 use core::{cmp::max, ops::Range};
 
-use crate::fmt::{debug, info};
+use crate::fmt::{debug, info, warn};
 use embassy_time::Instant;
 use embedded_storage_async::nor_flash::MultiwriteNorFlash;
-use pedomet_rs_common::{PedometerEvent, PedometerEventType};
-use sequential_storage::{cache::PagePointerCache, queue};
+use pedomet_rs_common::{
+    PedometerEvent, PedometerEventType, PedometerMaintenance, PedometerStorageUsage,
+    EVENT_INDEX_BLOCK,
+};
+use sequential_storage::{
+    cache::{NoCache, PagePointerCache},
+    map, queue,
+};
 
-use crate::{error::PedometerResult, BOOT_ID_WATCH, MAX_EVENT_ID_WATCH};
+use crate::{bonds::BONDS_FLASH_RANGE, error::PedometerResult, BOOT_ID_WATCH, MAX_EVENT_ID_WATCH};
 
 const FLASH_SIZE: u32 = 1024 * 1024;
-const PAGE_SIZE: u32 = 4096;
+pub(crate) const PAGE_SIZE: u32 = 4096;
 const QUEUE_FLASH_SIZE: u32 = 512 * 1024;
-const QUEUE_FLASH_RANGE: Range<u32> = (FLASH_SIZE - QUEUE_FLASH_SIZE)..FLASH_SIZE;
+pub(crate) const QUEUE_FLASH_RANGE: Range<u32> = (FLASH_SIZE - QUEUE_FLASH_SIZE)..FLASH_SIZE;
 const QUEUE_FLASH_PAGE_COUNT: usize = (QUEUE_FLASH_SIZE / PAGE_SIZE) as usize;
+/// Reading the flash never waits, so an iteration yields to the other tasks after this many
+/// entries. Otherwise the steps could not even be sent to the flash task during a long iteration.
+const ENTRIES_PER_YIELD: u32 = 32;
+const COUNTERS_FLASH_SIZE: u32 = 2 * PAGE_SIZE;
+/// The counters are stored directly in front of the bonds
+const COUNTERS_FLASH_RANGE: Range<u32> =
+    (BONDS_FLASH_RANGE.start - COUNTERS_FLASH_SIZE)..BONDS_FLASH_RANGE.start;
+/// Boot id and the end of the reserved event indexes are stored as one item
+const COUNTERS_KEY: u8 = 0;
+const COUNTERS_DATA_SIZE: usize = 8;
 
 #[derive(Debug, PartialEq, Eq)]
 pub(crate) struct HandleEntry {
@@ -37,49 +53,137 @@ pub(crate) struct StorageEventQueue<S: embedded_storage_async::nor_flash::NorFla
     flash: S,
     cache: PagePointerCache<QUEUE_FLASH_PAGE_COUNT>,
     next_event_index: u32,
+    /// Indexes before this one are reserved by the stored counters, see [`EVENT_INDEX_BLOCK`]
+    reserved_event_index: u32,
     boot_id: u32,
+    /// Stored entries, counted again if unknown because the oldest page was overwritten
+    entries: Option<u32>,
 }
 
 impl<S: MultiwriteNorFlash> StorageEventQueue<S> {
-    pub async fn new(flash: S, clear: bool) -> PedometerResult<Self> {
+    pub fn new(flash: S) -> Self {
         debug!("FLASH_SIZE: {}, PAGE_SIZE: {}, QUEUE_FLASH_SIZE: {}, QUEUE_FLASH_RANGE: {}, QUEUE_FLASH_PAGE_COUNT: {}",
             FLASH_SIZE, PAGE_SIZE, QUEUE_FLASH_SIZE, QUEUE_FLASH_RANGE, QUEUE_FLASH_PAGE_COUNT);
-        let mut queue = Self {
+        Self {
             flash,
             cache: PagePointerCache::new(),
             next_event_index: 0,
+            reserved_event_index: 0,
             boot_id: 0,
-        };
+            entries: None,
+        }
+    }
+
+    /// Continue the boot id and event index of the stored counters and push the boot event. The
+    /// rest of the indexes reserved by the last boot is skipped.
+    ///
+    /// The counters are kept when the queue is cleared. Only if they were not stored, yet, all
+    /// stored events are read, which takes the longer the fuller the queue is. Unreadable events
+    /// are skipped by all iterations until the storage maintenance removes them.
+    ///
+    /// Can be retried with `clear` set if the stored events cannot be read.
+    pub async fn init(&mut self, clear: bool, reset_reason: u32) -> PedometerResult<()> {
         if clear {
-            queue.clear().await?;
+            self.clear().await?;
+        }
+
+        let (max_boot_id, next_event_index) = match self.load_counters().await {
+            Some(counters) => counters,
+            None => {
+                info!("No counters stored, read all events");
+                self.read_counters_from_events().await?
+            }
+        };
+        self.boot_id = max_boot_id + 1;
+        info!("max_boot_id: {}", max_boot_id);
+        BOOT_ID_WATCH.sender().send(self.boot_id);
+        self.next_event_index = next_event_index;
+        // The boot event reserves the first block of this boot
+        self.reserved_event_index = next_event_index;
+        info!("next_event_index: {}", next_event_index);
+        self.push_event(PedometerEventType::BootWithResetReason(reset_reason), None)
+            .await
+    }
+
+    /// Maximum boot id and the end of the reserved event indexes as stored, if they can be read.
+    async fn load_counters(&mut self) -> Option<(u32, u32)> {
+        let mut buf = [0_u8; COUNTERS_DATA_SIZE + 16];
+        let item: Result<Option<&[u8]>, _> = map::fetch_item(
+            &mut self.flash,
+            COUNTERS_FLASH_RANGE,
+            &mut NoCache::new(),
+            &mut buf,
+            &COUNTERS_KEY,
+        )
+        .await;
+        match item {
+            Ok(Some(&[b0, b1, b2, b3, i0, i1, i2, i3])) => Some((
+                u32::from_le_bytes([b0, b1, b2, b3]),
+                u32::from_le_bytes([i0, i1, i2, i3]),
+            )),
+            Ok(Some(_)) => {
+                warn!("Stored counters have an invalid size");
+                None
+            }
+            Ok(None) => None,
+            Err(_) => {
+                warn!("Could not read counters");
+                None
+            }
         }
+    }
 
+    /// Maximum boot id and next event index of the events in the queue.
+    async fn read_counters_from_events(&mut self) -> PedometerResult<(u32, u32)> {
         let mut max_event_index = 0;
         let mut max_boot_id = 0;
 
-        queue
-            .for_each(|event| {
-                max_event_index = max(max_event_index, event.index);
-                max_boot_id = max(max_boot_id, event.boot_id);
-                Ok(HandleEntry {
-                    pop: PopEntry::Keep,
-                    br: BreakIteration::Continue,
-                })
+        self.for_each(|event| {
+            max_event_index = max(max_event_index, event.index);
+            max_boot_id = max(max_boot_id, event.boot_id);
+            Ok(HandleEntry {
+                pop: PopEntry::Keep,
+                br: BreakIteration::Continue,
             })
-            .await?;
-        queue.boot_id = max_boot_id + 1;
-        info!("max_boot_id: {}", max_boot_id);
-        BOOT_ID_WATCH.sender().send(queue.boot_id);
-        queue.next_event_index = max_event_index + 1;
-        info!("max_event_index: {}", max_event_index);
-        queue.push_event(PedometerEventType::Boot, None).await?;
-        Ok(queue)
+        })
+        .await?;
+        Ok((max_boot_id, max_event_index + 1))
+    }
+
+    /// Persist the current boot id and the end of the reserved event indexes. If they cannot be
+    /// stored, they are erased, so the next boot reads them from the events instead of continuing
+    /// stale ones.
+    async fn store_counters(&mut self, reserved_event_index: u32) -> PedometerResult<()> {
+        let mut data = [0_u8; COUNTERS_DATA_SIZE];
+        data[..4].copy_from_slice(&self.boot_id.to_le_bytes());
+        data[4..].copy_from_slice(&reserved_event_index.to_le_bytes());
+        let mut buf = [0_u8; COUNTERS_DATA_SIZE + 16];
+        if let Err(e) = map::store_item(
+            &mut self.flash,
+            COUNTERS_FLASH_RANGE,
+            &mut NoCache::new(),
+            &mut buf,
+            &COUNTERS_KEY,
+            &&data[..],
+        )
+        .await
+        {
+            warn!("Could not store counters, erase them! {:?}", e);
+            sequential_storage::erase_all(&mut self.flash, COUNTERS_FLASH_RANGE).await?;
+        }
+        Ok(())
+    }
+
+    /// Access to the underlying flash for storage regions outside of the queue range.
+    pub fn flash(&mut self) -> &mut S {
+        &mut self.flash
     }
 
-    #[allow(unused)]
     pub async fn clear(&mut self) -> PedometerResult<()> {
         info!("Clear flash");
-        Ok(sequential_storage::erase_all(&mut self.flash, QUEUE_FLASH_RANGE).await?)
+        sequential_storage::erase_all(&mut self.flash, QUEUE_FLASH_RANGE).await?;
+        self.entries = Some(0);
+        Ok(())
     }
 
     pub async fn push_event(
@@ -88,6 +192,13 @@ impl<S: MultiwriteNorFlash> StorageEventQueue<S> {
         timestamp_ms: Option<u64>,
     ) -> PedometerResult<()> {
         let event_index = self.next_event_index;
+        if event_index >= self.reserved_event_index {
+            // Reserved before the event is stored, so an index is never used twice even if the
+            // power fails in between
+            let reserved_event_index = event_index + EVENT_INDEX_BLOCK;
+            self.store_counters(reserved_event_index).await?;
+            self.reserved_event_index = reserved_event_index;
+        }
         self.next_event_index += 1;
 
         let event = PedometerEvent {
@@ -99,6 +210,10 @@ impl<S: MultiwriteNorFlash> StorageEventQueue<S> {
 
         let data = event.serialize()?;
         info!("Push event {} with size {}", event, data.len());
+        let space_left = match self.entries {
+            Some(_) => Some(self.space_left().await?),
+            None => None,
+        };
         queue::push(
             &mut self.flash,
             QUEUE_FLASH_RANGE,
@@ -107,25 +222,119 @@ impl<S: MultiwriteNorFlash> StorageEventQueue<S> {
             true,
         )
         .await?;
+        if let (Some(entries), Some(space_left)) = (self.entries, space_left) {
+            // More space after the push means that the oldest page was erased for it
+            let overwritten = self.space_left().await? > space_left;
+            self.entries = (!overwritten).then_some(entries + 1);
+        }
         MAX_EVENT_ID_WATCH.sender().send(event_index);
         Ok(())
     }
 
+    async fn space_left(&mut self) -> PedometerResult<u32> {
+        Ok(queue::space_left(&mut self.flash, QUEUE_FLASH_RANGE, &mut self.cache).await?)
+    }
+
+    /// Used space of the queue in percent.
+    pub async fn fill_percent(&mut self) -> PedometerResult<u8> {
+        let space_left = self.space_left().await?;
+        Ok((100 - space_left as u64 * 100 / QUEUE_FLASH_SIZE as u64) as u8)
+    }
+
+    /// Used bytes and stored entries of the queue. The entries are counted once after boot and
+    /// whenever the oldest page was overwritten, otherwise they are tracked while pushing and
+    /// popping.
+    pub async fn usage(&mut self) -> PedometerResult<PedometerStorageUsage> {
+        let entries = match self.entries {
+            Some(entries) => entries,
+            None => {
+                let entries = self.count_entries().await?;
+                debug!("Counted {} entries", entries);
+                self.entries = Some(entries);
+                entries
+            }
+        };
+        let space_left = self.space_left().await?;
+        Ok(PedometerStorageUsage {
+            used_bytes: QUEUE_FLASH_SIZE - space_left,
+            capacity_bytes: QUEUE_FLASH_SIZE,
+            entries,
+        })
+    }
+
+    async fn count_entries(&mut self) -> PedometerResult<u32> {
+        let mut buf = [0_u8; PedometerEvent::get_max_serialized_size()];
+        let mut iterator = queue::iter(&mut self.flash, QUEUE_FLASH_RANGE, &mut self.cache).await?;
+        let mut entries = 0_u32;
+        while iterator.next(&mut buf).await?.is_some() {
+            entries += 1;
+            if entries % ENTRIES_PER_YIELD == 0 {
+                embassy_futures::yield_now().await;
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Check that all stored entries can be read and remove the ones that cannot.
+    ///
+    /// Popped entries are reclaimed by sequential-storage when their page is reused, so only
+    /// unreadable entries would stay in the storage forever.
+    pub async fn maintain(&mut self) -> PedometerResult<PedometerMaintenance> {
+        let mut maintenance = PedometerMaintenance::default();
+        let mut last_event: Option<(u32, u32)> = None;
+        let mut buf = [0_u8; PedometerEvent::get_max_serialized_size()];
+        let mut iterator = queue::iter(&mut self.flash, QUEUE_FLASH_RANGE, &mut self.cache).await?;
+        while let Some(entry) = iterator.next(&mut buf).await? {
+            match postcard::from_bytes::<PedometerEvent>(&entry) {
+                Ok(event) => {
+                    maintenance.events += 1;
+                    if last_event.is_some_and(|(boot_id, index)| {
+                        event.boot_id == boot_id && event.index <= index
+                    }) {
+                        maintenance.out_of_order_events =
+                            maintenance.out_of_order_events.saturating_add(1);
+                    }
+                    last_event = Some((event.boot_id, event.index));
+                }
+                Err(_) => {
+                    warn!("Remove unreadable entry {:?}", &entry[..]);
+                    entry.pop().await?;
+                    maintenance.removed_entries = maintenance.removed_entries.saturating_add(1);
+                }
+            }
+        }
+        drop(iterator);
+        self.entries = Some(maintenance.events);
+        maintenance.fill_percent = self.fill_percent().await?;
+        Ok(maintenance)
+    }
+
+    /// Handle the stored events from the oldest one on. Entries that cannot be read are skipped,
+    /// so that a single one does not block the syncs until [`Self::maintain`] removes it.
     pub async fn for_each<F>(&mut self, mut f: F) -> PedometerResult<()>
     where
         F: FnMut(PedometerEvent) -> PedometerResult<HandleEntry>,
     {
         let mut buf = [0_u8; PedometerEvent::get_max_serialized_size()];
         let mut iterator = queue::iter(&mut self.flash, QUEUE_FLASH_RANGE, &mut self.cache).await?;
+        let mut entries = 0_u32;
         while let Some(entry) = iterator.next(&mut buf).await? {
-            let event: PedometerEvent = postcard::from_bytes(&entry)?;
+            let Ok(event) = postcard::from_bytes::<PedometerEvent>(&entry) else {
+                warn!("Skip unreadable entry {:?}", &entry[..]);
+                continue;
+            };
             let handle_entry = f(event)?;
             if handle_entry.pop == PopEntry::Pop {
                 entry.pop().await?;
+                self.entries = self.entries.map(|entries| entries.saturating_sub(1));
             }
             if handle_entry.br == BreakIteration::Break {
                 break;
             }
+            entries = entries.wrapping_add(1);
+            if entries % ENTRIES_PER_YIELD == 0 {
+                embassy_futures::yield_now().await;
+            }
         }
         Ok(())
     }
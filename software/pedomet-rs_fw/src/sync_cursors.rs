@@ -0,0 +1,117 @@
+use core::ops::Range;
+
+use embedded_storage_async::nor_flash::NorFlash;
+use pedomet_rs_common::{PedometerEvent, PedometerSyncCursor, MAX_SYNC_HOSTS};
+use sequential_storage::{cache::NoCache, map};
+
+use crate::{
+    config::CONFIG_FLASH_RANGE,
+    error::PedometerResult,
+    fmt::{info, warn},
+    storage_event_queue::PAGE_SIZE,
+};
+
+const SYNC_CURSORS_FLASH_SIZE: u32 = 2 * PAGE_SIZE;
+/// The sync cursors are stored directly in front of the config
+pub(crate) const SYNC_CURSORS_FLASH_RANGE: Range<u32> =
+    (CONFIG_FLASH_RANGE.start - SYNC_CURSORS_FLASH_SIZE)..CONFIG_FLASH_RANGE.start;
+/// All cursors are stored as one item
+const SYNC_CURSORS_KEY: u8 = 0;
+const SYNC_CURSORS_DATA_SIZE: usize = MAX_SYNC_HOSTS * PedometerSyncCursor::SIZE;
+
+/// Cursors of the hosts that synced the events of the device.
+#[derive(Debug, Default)]
+pub(crate) struct SyncCursors {
+    cursors: heapless::Vec<PedometerSyncCursor, MAX_SYNC_HOSTS>,
+}
+
+impl SyncCursors {
+    /// Load the stored cursors. If they cannot be read, the events are deleted like before any
+    /// host acknowledged them.
+    pub async fn load<S: NorFlash>(flash: &mut S) -> Self {
+        let mut sync_cursors = Self::default();
+        let mut buf = [0_u8; SYNC_CURSORS_DATA_SIZE + 16];
+        let item: Result<Option<&[u8]>, _> = map::fetch_item(
+            flash,
+            SYNC_CURSORS_FLASH_RANGE,
+            &mut NoCache::new(),
+            &mut buf,
+            &SYNC_CURSORS_KEY,
+        )
+        .await;
+        match item {
+            Ok(Some(data)) => {
+                for chunk in data.chunks_exact(PedometerSyncCursor::SIZE) {
+                    // The chunks have the right size and there are not more than can be stored
+                    if let Ok(chunk) = chunk.try_into() {
+                        let _ = sync_cursors
+                            .cursors
+                            .push(PedometerSyncCursor::from_bytes(chunk));
+                    }
+                }
+            }
+            Ok(None) => {}
+            Err(_) => warn!("Could not read sync cursors"),
+        }
+        info!("Loaded sync cursors: {:?}", &sync_cursors.cursors[..]);
+        sync_cursors
+    }
+
+    /// Replace the cursor of the host and persist all cursors. If there is no space for a new
+    /// host, the cursor that is furthest behind is replaced, as it most likely belongs to a host
+    /// that is not used anymore.
+    pub async fn update<S: NorFlash>(
+        &mut self,
+        flash: &mut S,
+        cursor: PedometerSyncCursor,
+    ) -> PedometerResult<()> {
+        if let Some(existing) = self
+            .cursors
+            .iter_mut()
+            .find(|existing| existing.host_id == cursor.host_id)
+        {
+            *existing = cursor;
+        } else if let Err(cursor) = self.cursors.push(cursor) {
+            if let Some(oldest) = self
+                .cursors
+                .iter_mut()
+                .min_by_key(|existing| (existing.boot_id, existing.min_event_index))
+            {
+                info!("Forget sync cursor {:?}", oldest);
+                *oldest = cursor;
+            }
+        }
+
+        let mut data = [0_u8; SYNC_CURSORS_DATA_SIZE];
+        for (cursor, chunk) in self
+            .cursors
+            .iter()
+            .zip(data.chunks_exact_mut(PedometerSyncCursor::SIZE))
+        {
+            chunk.copy_from_slice(&cursor.to_bytes());
+        }
+        let mut buf = [0_u8; SYNC_CURSORS_DATA_SIZE + 16];
+        map::store_item(
+            flash,
+            SYNC_CURSORS_FLASH_RANGE,
+            &mut NoCache::new(),
+            &mut buf,
+            &SYNC_CURSORS_KEY,
+            &&data[..self.cursors.len() * PedometerSyncCursor::SIZE],
+        )
+        .await?;
+        info!("Stored sync cursors: {:?}", &self.cursors[..]);
+        Ok(())
+    }
+
+    /// All known hosts acknowledged the event. This is the case for every event as long as no
+    /// host sent its cursor.
+    pub fn all_synced(&self, event: &PedometerEvent) -> bool {
+        self.cursors.iter().all(|cursor| cursor.is_synced(event))
+    }
+
+    /// No host sent its cursor yet.
+    pub fn is_empty(&self) -> bool {
+        self.cursors.is_empty()
+    }
+}
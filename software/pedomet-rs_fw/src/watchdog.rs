@@ -0,0 +1,109 @@
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use embassy_nrf::{
+    peripherals::WDT,
+    wdt::{self, Watchdog, WatchdogHandle},
+};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, signal::Signal};
+use embassy_time::{Duration, Instant, Timer};
+
+use crate::fmt::{error, warn};
+use crate::{FlashCommand, FlashCommandSender, TEMPERATURE_INTERVAL};
+
+/// Interval in which the hardware watchdog is pet while all tasks are alive
+const PET_INTERVAL: Duration = Duration::from_secs(10);
+/// The device is reset if the watchdog was not pet for three intervals, in ticks of the
+/// 32.768 kHz clock
+const TIMEOUT_TICKS: u32 = 3 * 10 * 32_768;
+/// Interval in which the flash task and the connection are asked to feed the watchdog
+const PING_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Tasks that record the steps and have to feed the watchdog
+#[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum WatchedTask {
+    Imu,
+    Flash,
+    Ble,
+}
+
+impl WatchedTask {
+    const ALL: [Self; 3] = [Self::Imu, Self::Flash, Self::Ble];
+
+    /// Longest duration in which the task does not feed the watchdog although it works
+    fn max_silence(self) -> Duration {
+        match self {
+            // Waits at most until the next temperature measurement while the IMU sleeps
+            Self::Imu => TEMPERATURE_INTERVAL + Duration::from_secs(5 * 60),
+            // Pinged in every interval, but may be busy with the storage maintenance
+            Self::Flash => Duration::from_secs(5 * 60),
+            // Restarts the advertising regularly, pinged in every interval while connected
+            Self::Ble => Duration::from_secs(5 * 60),
+        }
+    }
+}
+
+/// Signaled in every ping interval, so that an idle connection still feeds the watchdog. It is
+/// handled by the response notification, so a stuck notification is not hidden.
+pub static BLE_PING_SIGNAL: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+/// Uptime in seconds at which each task fed the watchdog last
+static LAST_FEED_S: [AtomicU32; 3] = [AtomicU32::new(0), AtomicU32::new(0), AtomicU32::new(0)];
+
+/// Report that the task still works.
+pub fn feed(task: WatchedTask) {
+    LAST_FEED_S[task as usize].store(Instant::now().as_secs() as u32, Ordering::Relaxed);
+}
+
+/// Feed the watchdog for the task while it waits for something else in a select.
+pub async fn keep_fed(task: WatchedTask) -> ! {
+    loop {
+        feed(task);
+        Timer::after(PET_INTERVAL).await;
+    }
+}
+
+/// Start the hardware watchdog. It keeps running after a soft reset and cannot be reconfigured
+/// then, so a firmware with another config resets once by the watchdog.
+pub fn start(wdt: WDT) -> Option<WatchdogHandle> {
+    let mut config = wdt::Config::default();
+    config.timeout_ticks = TIMEOUT_TICKS;
+    // The CPU sleeps most of the time
+    config.run_during_sleep = true;
+    config.run_during_debug_halt = false;
+    match Watchdog::try_new(wdt, config) {
+        Ok((_, [handle])) => Some(handle),
+        Err(_) => {
+            warn!("Watchdog already runs with another config");
+            None
+        }
+    }
+}
+
+/// Pet the hardware watchdog as long as all watched tasks feed it. If one of them is stuck, e.g.
+/// because the flash command channel does not drain, the device resets instead of silently
+/// stopping to record the steps. The reset reason is stored with the next boot event.
+#[embassy_executor::task]
+pub async fn watchdog_task(
+    mut handle: WatchdogHandle,
+    flash_command_sender: FlashCommandSender,
+) -> ! {
+    let mut next_ping = Instant::now();
+    loop {
+        let now = Instant::now();
+        if now >= next_ping {
+            flash_command_sender.send_or_drop(FlashCommand::FeedWatchdog);
+            BLE_PING_SIGNAL.signal(());
+            next_ping = now + PING_INTERVAL;
+        }
+        let stuck = WatchedTask::ALL.into_iter().find(|task| {
+            let last_feed_s = LAST_FEED_S[*task as usize].load(Ordering::Relaxed) as u64;
+            Duration::from_secs(now.as_secs().saturating_sub(last_feed_s)) > task.max_silence()
+        });
+        match stuck {
+            None => handle.pet(),
+            Some(task) => error!("{:?} task did not feed the watchdog, reset soon!", task),
+        }
+        Timer::after(PET_INTERVAL).await;
+    }
+}
@@ -0,0 +1,9 @@
+create table daily_summaries(
+    event_id int not null,
+    timestamp_ms int not null,
+    boot_id int not null,
+    steps int not null
+);
+
+create index idx_daily_summaries_timestamp_ms on daily_summaries(timestamp_ms);
+create unique index idx_daily_summaries_unique on daily_summaries(event_id, boot_id);
@@ -0,0 +1,10 @@
+create table device_errors(
+    event_id int not null,
+    timestamp_ms int not null,
+    boot_id int not null,
+    kind int not null,
+    code int not null
+);
+
+create index idx_device_errors_timestamp_ms on device_errors(timestamp_ms);
+create unique index idx_device_errors_unique on device_errors(event_id, boot_id);
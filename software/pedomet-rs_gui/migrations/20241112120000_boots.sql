@@ -0,0 +1,6 @@
+create table boots(
+    boot_id int primary key not null,
+    first_timestamp_ms int not null,
+    last_timestamp_ms int not null,
+    reset_reason int
+);
@@ -0,0 +1,10 @@
+create table host_epochs(
+    event_id int not null,
+    device_timestamp_ms int not null,
+    boot_id int not null,
+    host_epoch_ms int not null
+);
+
+create unique index idx_host_epochs_unique on host_epochs(event_id, boot_id);
+
+alter table boots add column event_id int;
@@ -0,0 +1,4 @@
+create table sync_state(
+    boot_id int primary key not null,
+    last_event_id int not null
+);
@@ -0,0 +1,9 @@
+create table markers(
+    event_id int,
+    timestamp_ms int not null,
+    boot_id int,
+    kind int not null
+);
+
+create index idx_markers_timestamp_ms on markers(timestamp_ms);
+create unique index idx_markers_unique on markers(event_id, boot_id);
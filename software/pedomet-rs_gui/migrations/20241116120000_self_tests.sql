@@ -0,0 +1,8 @@
+create table self_tests(
+    event_id int not null,
+    timestamp_ms int not null,
+    boot_id int not null,
+    result int not null
+);
+
+create unique index idx_self_tests_unique on self_tests(event_id, boot_id);
@@ -0,0 +1,11 @@
+create table maintenances(
+    event_id int not null,
+    timestamp_ms int not null,
+    boot_id int not null,
+    events int not null,
+    removed_entries int not null,
+    out_of_order_events int not null,
+    fill_percent int not null
+);
+
+create unique index idx_maintenances_unique on maintenances(event_id, boot_id);
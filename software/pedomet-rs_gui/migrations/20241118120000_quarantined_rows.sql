@@ -0,0 +1,10 @@
+-- Rows that cannot be shown, e.g. because of an invalid timestamp, are moved here instead of
+-- being deleted. The timestamp has no type affinity to keep the original value.
+create table quarantined_rows(
+    source text not null,
+    event_id int,
+    timestamp_ms,
+    boot_id int,
+    steps int,
+    quarantined_at_ms int not null
+);
@@ -0,0 +1,7 @@
+-- GPS tracks that were imported for a session, which is identified by the timestamp of its start
+-- marker
+create table session_tracks(
+    session_start_ms int primary key not null,
+    distance_m real not null,
+    points int not null
+);
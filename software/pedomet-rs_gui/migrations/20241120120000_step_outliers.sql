@@ -0,0 +1,11 @@
+-- Step events with an improbable number of steps. They are excluded from the totals unless the
+-- user accepted them.
+create table step_outliers(
+    event_id int not null,
+    boot_id int not null,
+    timestamp_ms int not null,
+    steps int not null,
+    accepted int not null default 0
+);
+
+create unique index idx_step_outliers_unique on step_outliers(event_id, boot_id);
@@ -0,0 +1,22 @@
+create table archived_days(
+    -- Start of the following local day like the timestamp of a daily summary
+    timestamp_ms int not null primary key,
+    steps int not null
+);
+
+create table archives(
+    archived_at_ms int not null,
+    before_ms int not null,
+    first_event_id int not null,
+    last_event_id int not null,
+    events int not null,
+    raw_file text
+);
+
+-- Last archived step event of every boot, the steps of the next archived event are relative to it
+create table archive_anchors(
+    boot_id int not null primary key,
+    event_id int not null,
+    timestamp_ms int not null,
+    steps int not null
+);
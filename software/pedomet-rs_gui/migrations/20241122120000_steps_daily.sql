@@ -0,0 +1,10 @@
+create table steps_daily(
+    date text not null primary key,
+    steps int not null
+);
+
+-- Local hour at which the days of steps_daily start, the table is rebuilt if it does not match
+create table steps_daily_state(
+    id int not null primary key check (id = 0),
+    day_start_hour int not null
+);
@@ -0,0 +1,9 @@
+create table sensor_readings(
+    event_id int not null,
+    timestamp_ms int not null,
+    boot_id int not null,
+    sensor int not null,
+    value int not null
+);
+
+create unique index idx_sensor_readings_unique on sensor_readings(event_id, boot_id);
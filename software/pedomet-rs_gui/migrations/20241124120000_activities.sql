@@ -0,0 +1,9 @@
+create table activities(
+    event_id int not null,
+    timestamp_ms int not null,
+    boot_id int not null,
+    activity int not null
+);
+
+create index idx_activities_timestamp_ms on activities(timestamp_ms);
+create unique index idx_activities_unique on activities(event_id, boot_id);
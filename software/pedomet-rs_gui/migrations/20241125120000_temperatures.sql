@@ -0,0 +1,10 @@
+-- Temperatures of the IMU in 0.01 °C, which the device measures periodically
+create table temperatures(
+    event_id int not null,
+    timestamp_ms int not null,
+    boot_id int not null,
+    temperature int not null
+);
+
+create index idx_temperatures_timestamp_ms on temperatures(timestamp_ms);
+create unique index idx_temperatures_unique on temperatures(event_id, boot_id);
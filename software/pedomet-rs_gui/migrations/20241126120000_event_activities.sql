@@ -0,0 +1,10 @@
+-- Activity that the device classified last before the step event, null before the first one
+alter table events add column activity int;
+
+update events set activity = (
+    select activities.activity
+    from activities
+    where activities.boot_id = events.boot_id and activities.event_id < events.event_id
+    order by activities.event_id desc
+    limit 1
+);
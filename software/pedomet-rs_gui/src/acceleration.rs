@@ -0,0 +1,61 @@
+use std::collections::VecDeque;
+
+use pedomet_rs_common::{PedometerAcceleration, PedometerAccelerationSamples};
+
+/// Duration of the samples that are kept for the plot
+const ACCELERATION_WINDOW_MS: u32 = 10_000;
+
+/// Raw accelerometer samples that the device streams for evaluating the step detection.
+#[derive(Debug, Default)]
+pub(crate) struct PedometerAccelerationTrace {
+    /// Device uptime and sample
+    samples: VecDeque<(u32, PedometerAcceleration)>,
+}
+
+impl PedometerAccelerationTrace {
+    pub(crate) fn add(&mut self, samples: &PedometerAccelerationSamples) {
+        // The device was restarted in the meantime
+        if self
+            .samples
+            .back()
+            .is_some_and(|(last_ms, _)| samples.uptime_ms < *last_ms)
+        {
+            self.samples.clear();
+        }
+        self.samples.extend(samples.iter());
+        if let Some((last_ms, _)) = self.samples.back().copied() {
+            while self
+                .samples
+                .front()
+                .is_some_and(|(uptime_ms, _)| last_ms - uptime_ms > ACCELERATION_WINDOW_MS)
+            {
+                self.samples.pop_front();
+            }
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Points of the given axis with the seconds before the newest sample and the acceleration
+    /// in mg.
+    pub(crate) fn points(&self, axis: impl Fn(&PedometerAcceleration) -> i16) -> Vec<[f64; 2]> {
+        let Some((last_ms, _)) = self.samples.back() else {
+            return Vec::new();
+        };
+        self.samples
+            .iter()
+            .map(|(uptime_ms, sample)| {
+                [
+                    -f64::from(last_ms - uptime_ms) / 1000.0,
+                    f64::from(axis(sample)) * PedometerAcceleration::MILLI_G_PER_DIGIT,
+                ]
+            })
+            .collect()
+    }
+}
@@ -1,6 +1,6 @@
 use std::sync::OnceLock;
 
-use jni::objects::GlobalRef;
+use jni::objects::{GlobalRef, JObject, JValue};
 use jni::{JNIEnv, JavaVM};
 
 use thiserror::Error;
@@ -44,6 +44,72 @@ pub fn setup_class_loader(env: &JNIEnv) -> Result<GlobalRef, AndroidError> {
     Ok(env.new_global_ref(class_loader)?)
 }
 
+/// `NetworkCapabilities.TRANSPORT_WIFI`
+const TRANSPORT_WIFI: i32 = 1;
+
+fn get_system_service<'a>(env: &JNIEnv<'a>, name: &str) -> Result<JObject<'a>, AndroidError> {
+    let context = JObject::from(ndk_context::android_context().context() as jni::sys::jobject);
+    Ok(env
+        .call_method(
+            context,
+            "getSystemService",
+            "(Ljava/lang/String;)Ljava/lang/Object;",
+            &[JValue::from(env.new_string(name)?)],
+        )?
+        .l()?)
+}
+
+/// The phone is connected to a charger.
+pub fn is_charging() -> Result<bool, AndroidError> {
+    let env = JAVAVM
+        .get()
+        .ok_or(AndroidError::JavaVM)?
+        .attach_current_thread()?;
+    let battery_manager = get_system_service(&env, "batterymanager")?;
+    Ok(env
+        .call_method(battery_manager, "isCharging", "()Z", &[])?
+        .z()?)
+}
+
+/// The active network of the phone is a Wi-Fi.
+pub fn is_on_wifi() -> Result<bool, AndroidError> {
+    let env = JAVAVM
+        .get()
+        .ok_or(AndroidError::JavaVM)?
+        .attach_current_thread()?;
+    let connectivity_manager = get_system_service(&env, "connectivity")?;
+    let network = env
+        .call_method(
+            connectivity_manager,
+            "getActiveNetwork",
+            "()Landroid/net/Network;",
+            &[],
+        )?
+        .l()?;
+    if network.is_null() {
+        return Ok(false);
+    }
+    let capabilities = env
+        .call_method(
+            connectivity_manager,
+            "getNetworkCapabilities",
+            "(Landroid/net/Network;)Landroid/net/NetworkCapabilities;",
+            &[JValue::from(network)],
+        )?
+        .l()?;
+    if capabilities.is_null() {
+        return Ok(false);
+    }
+    Ok(env
+        .call_method(
+            capabilities,
+            "hasTransport",
+            "(I)Z",
+            &[JValue::Int(TRANSPORT_WIFI)],
+        )?
+        .z()?)
+}
+
 #[no_mangle]
 pub extern "C" fn JNI_OnLoad(vm: jni::JavaVM, _res: *const std::os::raw::c_void) -> jni::sys::jint {
     let env = vm.get_env().unwrap();
@@ -0,0 +1,280 @@
+use std::{net::Ipv4Addr, str::FromStr, sync::OnceLock, time::Duration as StdDuration};
+
+use anyhow::anyhow;
+use chrono::{DateTime, Duration, Local, Utc};
+use log::{debug, info, warn};
+use serde::Serialize;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::{oneshot, watch},
+};
+
+use crate::{
+    metrics,
+    persistence::{
+        PedometerDailyTotal, PedometerDatabaseCommand, PedometerPersistenceEvent, DB_CMD_TX,
+    },
+    settings::ApiPolicy,
+    steps::transform_events_to_relative_steps,
+};
+
+/// Requests with a larger header are rejected. There is no request with a body.
+const MAX_REQUEST_SIZE: usize = 8 * 1024;
+const REQUEST_TIMEOUT: StdDuration = StdDuration::from_secs(5);
+const DEFAULT_DAYS: u32 = 7;
+const MAX_DAYS: u32 = 366;
+const DEFAULT_HOURS: u32 = 24;
+const MAX_HOURS: u32 = 31 * 24;
+const CONTENT_TYPE_JSON: &str = "application/json";
+const CONTENT_TYPE_METRICS: &str = "text/plain; version=0.0.4";
+
+/// The API is (re)started whenever a new policy is sent.
+pub static API_POLICY_TX: OnceLock<watch::Sender<ApiPolicy>> = OnceLock::new();
+
+#[derive(Debug, Serialize)]
+struct ApiEvent {
+    event_id: i64,
+    boot_id: i64,
+    timestamp_ms: i64,
+    steps: i64,
+}
+
+impl From<PedometerPersistenceEvent> for ApiEvent {
+    fn from(event: PedometerPersistenceEvent) -> Self {
+        Self {
+            event_id: event.event_id,
+            boot_id: event.boot_id,
+            timestamp_ms: event.timestamp_ms,
+            steps: event.steps,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ApiError {
+    status: u16,
+    message: String,
+}
+
+impl ApiError {
+    fn new(status: u16, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(e: anyhow::Error) -> Self {
+        Self::new(500, e.to_string())
+    }
+}
+
+/// Serve the read-only HTTP API on localhost while it is enabled by the policy.
+///
+/// - `GET /daily?days=7`: Steps per local day, the last entry is today
+/// - `GET /events?hours=24`: Step events of the last hours with relative steps
+/// - `GET /metrics`: Metrics in the Prometheus text format, if enabled separately
+pub(crate) async fn run(mut policy_rx: watch::Receiver<ApiPolicy>) {
+    loop {
+        let policy = *policy_rx.borrow_and_update();
+        if policy.is_serving() {
+            match TcpListener::bind((Ipv4Addr::LOCALHOST, policy.port)).await {
+                Ok(listener) => {
+                    info!("API listening on port {}", policy.port);
+                    tokio::select! {
+                        () = accept_connections(listener, policy) => {}
+                        result = policy_rx.changed() => if result.is_err() {
+                            return;
+                        }
+                    }
+                    info!("Stop API on port {}", policy.port);
+                    continue;
+                }
+                Err(e) => warn!("Could not start API on port {}: {e}", policy.port),
+            }
+        }
+        if policy_rx.changed().await.is_err() {
+            return;
+        }
+    }
+}
+
+async fn accept_connections(listener: TcpListener, policy: ApiPolicy) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, addr)) => {
+                debug!("API connection from {addr}");
+                tokio::spawn(async move {
+                    if let Err(e) = handle_connection(stream, policy).await {
+                        warn!("Could not handle API request: {e}");
+                    }
+                });
+            }
+            Err(e) => warn!("Could not accept API connection: {e}"),
+        }
+    }
+}
+
+async fn handle_connection(mut stream: TcpStream, policy: ApiPolicy) -> anyhow::Result<()> {
+    let mut buf = vec![0; MAX_REQUEST_SIZE];
+    let mut len = 0;
+    while !buf[..len].windows(4).any(|w| w == b"\r\n\r\n") {
+        if len == buf.len() {
+            return respond(&mut stream, Err(ApiError::new(431, "Request too large"))).await;
+        }
+        let read = tokio::time::timeout(REQUEST_TIMEOUT, stream.read(&mut buf[len..])).await??;
+        if read == 0 {
+            return Ok(());
+        }
+        len += read;
+    }
+    let request = String::from_utf8_lossy(&buf[..len]);
+    let mut request_line = request
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .split_whitespace();
+    let method = request_line.next().unwrap_or_default();
+    let target = request_line.next().unwrap_or_default();
+    debug!("API request: {method} {target}");
+    let response = if method == "GET" {
+        route(target, policy).await
+    } else {
+        Err(ApiError::new(405, "Only GET is supported"))
+    };
+    respond(&mut stream, response).await
+}
+
+/// Content type and body of a successful request.
+type ApiResponse = (&'static str, String);
+
+async fn route(target: &str, policy: ApiPolicy) -> Result<ApiResponse, ApiError> {
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    match path {
+        "/daily" if policy.enabled => {
+            let days = query_param(query, "days")?
+                .unwrap_or(DEFAULT_DAYS)
+                .clamp(1, MAX_DAYS);
+            to_json(&daily_totals(days).await?)
+        }
+        "/events" if policy.enabled => {
+            let hours = query_param(query, "hours")?
+                .unwrap_or(DEFAULT_HOURS)
+                .clamp(1, MAX_HOURS);
+            to_json(&recent_events(hours).await?)
+        }
+        "/metrics" if policy.metrics => {
+            let steps_today = daily_totals(1)
+                .await?
+                .first()
+                .map_or(0, |total| total.steps);
+            Ok((CONTENT_TYPE_METRICS, metrics::render(steps_today)))
+        }
+        _ => Err(ApiError::new(404, format!("Unknown path: {path}"))),
+    }
+}
+
+async fn respond(
+    stream: &mut TcpStream,
+    response: Result<ApiResponse, ApiError>,
+) -> anyhow::Result<()> {
+    let (status, (content_type, body)) = match response {
+        Ok(response) => (200, response),
+        Err(e) => {
+            warn!("API error {}: {}", e.status, e.message);
+            (
+                e.status,
+                (
+                    CONTENT_TYPE_JSON,
+                    serde_json::json!({ "error": e.message }).to_string(),
+                ),
+            )
+        }
+    };
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        431 => "Request Header Fields Too Large",
+        _ => "Internal Server Error",
+    };
+    let header = format!(
+        "HTTP/1.1 {status} {reason}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    );
+    stream.write_all(header.as_bytes()).await?;
+    stream.write_all(body.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+fn query_param<T: FromStr>(query: &str, key: &str) -> Result<Option<T>, ApiError> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(k, _)| *k == key)
+        .map(|(_, value)| {
+            value
+                .parse()
+                .map_err(|_| ApiError::new(400, format!("Invalid value for {key}: {value}")))
+        })
+        .transpose()
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<ApiResponse, ApiError> {
+    Ok((
+        CONTENT_TYPE_JSON,
+        serde_json::to_string(value).map_err(anyhow::Error::from)?,
+    ))
+}
+
+async fn daily_totals(days: u32) -> anyhow::Result<Vec<PedometerDailyTotal>> {
+    let last_day = Local::now().date_naive();
+    let first_day = last_day - Duration::days(days as i64 - 1);
+    let (resp_tx, resp_rx) = oneshot::channel();
+    send_db_command(PedometerDatabaseCommand::GetDailyTotals {
+        first_day,
+        last_day,
+        responder: resp_tx,
+    })
+    .await?;
+    resp_rx.await?
+}
+
+async fn recent_events(hours: u32) -> anyhow::Result<Vec<ApiEvent>> {
+    let end = Utc::now();
+    let start = end - Duration::hours(hours as i64);
+    Ok(
+        transform_events_to_relative_steps(get_events(start, end).await?)
+            .into_iter()
+            .map(ApiEvent::from)
+            .collect(),
+    )
+}
+
+async fn get_events(
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> anyhow::Result<Vec<PedometerPersistenceEvent>> {
+    let (resp_tx, resp_rx) = oneshot::channel();
+    send_db_command(PedometerDatabaseCommand::GetEventsInTimeRange {
+        start,
+        end,
+        responder: resp_tx,
+    })
+    .await?;
+    resp_rx.await?
+}
+
+async fn send_db_command(command: PedometerDatabaseCommand) -> anyhow::Result<()> {
+    DB_CMD_TX
+        .get()
+        .ok_or_else(|| anyhow!("Database is not initialized"))?
+        .send(command)
+        .await
+        .map_err(|_| anyhow!("Database is not running"))
+}
@@ -0,0 +1,44 @@
+use std::{io::Write, path::PathBuf};
+
+use app_dirs2::{app_dir, AppDataType};
+use chrono::{DateTime, Utc};
+use flate2::{write::GzEncoder, Compression};
+
+use crate::{persistence::PedometerPersistenceEvent, APP_INFO};
+
+/// Result of rolling old step events into daily totals.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PedometerArchiveSummary {
+    /// Step events that were removed from the database
+    pub events: u64,
+    /// Days whose totals were stored
+    pub days: usize,
+    /// Compressed file with the raw step events, if they were kept
+    pub raw_file: Option<PathBuf>,
+}
+
+/// Path of a new file for the raw step events before the given time. The archive directory is
+/// next to the database.
+pub(crate) fn raw_file_path(before: DateTime<Utc>) -> anyhow::Result<PathBuf> {
+    let mut path = app_dir(AppDataType::UserData, &APP_INFO, "archive")?;
+    path.push(format!(
+        "events-before-{}-{}.csv.gz",
+        before.format("%Y%m%d"),
+        Utc::now().timestamp_millis()
+    ));
+    Ok(path)
+}
+
+/// Gzip compressed CSV with one line per step event and the absolute step counter as stored.
+pub(crate) fn compress_raw_events(events: &[PedometerPersistenceEvent]) -> anyhow::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+    writeln!(encoder, "event_id,boot_id,timestamp_ms,steps")?;
+    for event in events {
+        writeln!(
+            encoder,
+            "{},{},{},{}",
+            event.event_id, event.boot_id, event.timestamp_ms, event.steps
+        )?;
+    }
+    Ok(encoder.finish()?)
+}
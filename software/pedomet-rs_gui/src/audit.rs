@@ -0,0 +1,105 @@
+use std::{cmp::max, collections::HashMap, ops::RangeInclusive};
+
+use pedomet_rs_common::{PedometerEventRequest, EVENT_INDEX_BLOCK};
+
+use crate::persistence::{PedometerPersistenceEvent, PedometerPersistenceEventId};
+
+/// Timestamps before 2024-01-01 cannot be valid because the device did not exist yet.
+const MIN_PLAUSIBLE_TIMESTAMP_MS: i64 = 1_704_067_200_000;
+/// Tolerance for timestamps in the future and for timestamps that go backwards within a boot.
+/// The time offset of a boot is refined with every connection, so small jumps are expected.
+const TIMESTAMP_TOLERANCE_MS: i64 = 60 * 60 * 1000;
+
+/// Result of the consistency check of all synced events.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PedometerAuditReport {
+    /// Ranges of event indexes that are missing between the synced events together with the boot
+    /// of the event before the gap
+    pub missing_event_ids: Vec<(i64, RangeInclusive<i64>)>,
+    /// Pairs of consecutive step events of the same boot where the step counter decreased
+    pub negative_step_deltas: Vec<(PedometerPersistenceEvent, PedometerPersistenceEvent)>,
+    /// Events whose index was already stored for another event
+    pub duplicate_event_ids: Vec<PedometerPersistenceEventId>,
+    /// Events with a timestamp outside of the plausible range or before the previous event of the
+    /// same boot
+    pub invalid_timestamps: Vec<PedometerPersistenceEventId>,
+}
+
+impl PedometerAuditReport {
+    /// `event_ids` have to be sorted by index, `step_events` by boot and index. Gaps within the
+    /// `archived_event_ids` and the indexes that the device skips at a boot are expected.
+    pub(crate) fn new(
+        event_ids: &[PedometerPersistenceEventId],
+        step_events: &[PedometerPersistenceEvent],
+        archived_event_ids: &[RangeInclusive<i64>],
+        now_ms: i64,
+    ) -> Self {
+        let mut report = Self::default();
+
+        for window in event_ids.windows(2) {
+            let (previous, current) = (window[0], window[1]);
+            if current.event_id == previous.event_id {
+                report.duplicate_event_ids.push(current);
+            } else if current.boot_id != previous.boot_id
+                && current.event_id - previous.event_id <= i64::from(EVENT_INDEX_BLOCK)
+            {
+                // The rest of the indexes that the previous boot reserved
+                continue;
+            } else if current.event_id > previous.event_id + 1 {
+                let missing = previous.event_id + 1..=current.event_id - 1;
+                if !archived_event_ids.iter().any(|archived| {
+                    archived.contains(missing.start()) && archived.contains(missing.end())
+                }) {
+                    report.missing_event_ids.push((previous.boot_id, missing));
+                }
+            }
+        }
+
+        let mut last_timestamps = HashMap::new();
+        for event_id in event_ids {
+            if event_id.timestamp_ms < MIN_PLAUSIBLE_TIMESTAMP_MS
+                || event_id.timestamp_ms > now_ms + TIMESTAMP_TOLERANCE_MS
+            {
+                report.invalid_timestamps.push(*event_id);
+                continue;
+            }
+            let last_timestamp = last_timestamps
+                .entry(event_id.boot_id)
+                .or_insert(event_id.timestamp_ms);
+            if event_id.timestamp_ms + TIMESTAMP_TOLERANCE_MS < *last_timestamp {
+                report.invalid_timestamps.push(*event_id);
+            } else {
+                *last_timestamp = max(*last_timestamp, event_id.timestamp_ms);
+            }
+        }
+
+        for window in step_events.windows(2) {
+            let (previous, current) = (window[0], window[1]);
+            // The step counter of the IMU is 16 bit wide, so a large decrease is an overflow
+            if previous.boot_id == current.boot_id
+                && current.steps < previous.steps
+                && previous.steps - current.steps <= i64::from(u16::MAX / 2)
+            {
+                report.negative_step_deltas.push((previous, current));
+            }
+        }
+
+        report
+    }
+
+    pub(crate) fn is_ok(&self) -> bool {
+        self.missing_event_ids.is_empty()
+            && self.negative_step_deltas.is_empty()
+            && self.duplicate_event_ids.is_empty()
+            && self.invalid_timestamps.is_empty()
+    }
+
+    /// Position from which the events have to be requested again to fill all gaps.
+    pub(crate) fn first_missing_event(&self) -> Option<PedometerEventRequest> {
+        let (boot_id, range) = self.missing_event_ids.first()?;
+        Some(PedometerEventRequest {
+            boot_id: u32::try_from(*boot_id).ok()?,
+            min_event_index: u32::try_from(*range.start()).ok()?,
+        })
+    }
+}
@@ -1,135 +1,620 @@
 use anyhow::anyhow;
 use btleplug::api::{
-    Central, Characteristic, Manager as _, Peripheral as _, ScanFilter, ValueNotification,
+    Central, CentralEvent, CentralState, Characteristic, Manager as _, Peripheral as _, ScanFilter,
+    ValueNotification,
 };
 use btleplug::platform::{Adapter, Manager, Peripheral};
-use chrono::Utc;
+use chrono::{Days, Local, NaiveTime, Utc};
 use futures::StreamExt;
 use log::{debug, error, info, warn};
-use pedomet_rs_common::{PedometerEvent, PedometerEventType};
+use pedomet_rs_common::{
+    gatt, PedometerAccelerationSamples, PedometerAdvertisingData, PedometerBootSummaries,
+    PedometerBootSummary, PedometerCommonError, PedometerConfig, PedometerConfigValue,
+    PedometerDeleteResult, PedometerDiagnostics, PedometerEvent, PedometerEventChunkHeader,
+    PedometerEventFilter, PedometerEventQuery, PedometerEventRequest, PedometerEventType,
+    PedometerFirmwareVersion, PedometerMarker, PedometerSelfTest, PedometerStorageUsage,
+    PedometerSyncCursor, PedometerTimeSync, PedometerWriteStatus, ADVERTISING_COMPANY_ID,
+    DEVICE_NAME_PREFIX,
+};
 use std::cmp::max;
 use std::collections::{HashMap, VecDeque};
-use std::sync::OnceLock;
-use std::time::Duration;
-use tokio::sync::{mpsc, oneshot};
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, oneshot, Notify};
 use tokio::task::JoinHandle;
 use uuid::Uuid;
 
-use crate::gui::GUI_EVENT_TX;
-use crate::persistence::{PedometerDatabaseCommand, PedometerPersistenceEvent, DB_CMD_TX};
+use crate::error::PedometerGuiError;
+use crate::gui::{PedometerGuiEvent, GUI_EVENT_TX};
+use crate::metrics;
+use crate::notifications;
+use crate::persistence::{
+    PedometerDatabaseCommand, PedometerPersistenceMarker, PedometerPersistenceRecord,
+    PedometerPersistenceSyncState, DB_CMD_TX,
+};
+use crate::settings::ScanPolicy;
+use crate::supervisor::SharedReceiver;
 
 /// Only devices whose name contains this string will be tried.
-const PERIPHERAL_NAME_MATCH_FILTER: &str = "pedomet-rs";
+const PERIPHERAL_NAME_MATCH_FILTER: &str = DEVICE_NAME_PREFIX;
+
+/// Listening for the live data in the advertisements only scans for this duration in every
+/// interval to save power. The device updates its advertising data every 30s.
+const LISTEN_SCAN_DURATION: Duration = Duration::from_secs(5);
+const LISTEN_SCAN_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Not every platform reports a removed adapter in its events, so its state is polled as well.
+/// The same interval is used to look for a returned adapter.
+const ADAPTER_POLL_INTERVAL: Duration = Duration::from_secs(2);
 
 /// Characteristics
-const CHARACTERISTIC_UUID_SOC: Uuid = Uuid::from_u128(0x00002A19_0000_1000_8000_00805F9B34FB);
-const CHARACTERISTIC_UUID_REQUEST_EVENTS: Uuid =
-    Uuid::from_u128(0x1C2A0001_ABF2_4B98_BA1C_25D5EA728525);
-const CHARACTERISTIC_UUID_RESPONSE_EVENTS: Uuid =
-    Uuid::from_u128(0x1C2A0002_ABF2_4B98_BA1C_25D5EA728525);
-#[allow(unused)]
-const CHARACTERISTIC_UUID_DELETE_EVENTS: Uuid =
-    Uuid::from_u128(0x1C2A0003_ABF2_4B98_BA1C_25D5EA728525);
-const CHARACTERISTIC_UUID_EPOCH_MS: Uuid = Uuid::from_u128(0x1C2A0004_ABF2_4B98_BA1C_25D5EA728525);
-const CHARACTERISTIC_BOOT_ID: Uuid = Uuid::from_u128(0x1C2A0005_ABF2_4B98_BA1C_25D5EA728525);
-const CHARACTERISTIC_MAX_EVENT_ID: Uuid = Uuid::from_u128(0x1C2A0006_ABF2_4B98_BA1C_25D5EA728525);
+pub(crate) const CHARACTERISTIC_UUID_SOC: Uuid = Uuid::from_u128(gatt::BATTERY_LEVEL);
+const CHARACTERISTIC_UUID_REQUEST_EVENTS: Uuid = Uuid::from_u128(gatt::REQUEST_EVENTS);
+pub(crate) const CHARACTERISTIC_UUID_RESPONSE_EVENTS: Uuid = Uuid::from_u128(gatt::RESPONSE_EVENTS);
+pub(crate) const CHARACTERISTIC_UUID_DELETE_EVENTS: Uuid = Uuid::from_u128(gatt::DELETE_EVENTS);
+pub(crate) const CHARACTERISTIC_UUID_EPOCH_MS: Uuid = Uuid::from_u128(gatt::EPOCH_MS);
+pub(crate) const CHARACTERISTIC_BOOT_ID: Uuid = Uuid::from_u128(gatt::BOOT_ID);
+pub(crate) const CHARACTERISTIC_MAX_EVENT_ID: Uuid = Uuid::from_u128(gatt::MAX_EVENT_ID);
+const CHARACTERISTIC_UUID_CONFIG: Uuid = Uuid::from_u128(gatt::CONFIG);
+const CHARACTERISTIC_UUID_DIAGNOSTICS: Uuid = Uuid::from_u128(gatt::DIAGNOSTICS);
+const CHARACTERISTIC_UUID_STORAGE_FILL_PERCENT: Uuid = Uuid::from_u128(gatt::STORAGE_FILL_PERCENT);
+const CHARACTERISTIC_UUID_TIME_SYNC: Uuid = Uuid::from_u128(gatt::TIME_SYNC);
+pub(crate) const CHARACTERISTIC_UUID_DELETE_BOOT: Uuid = Uuid::from_u128(gatt::DELETE_BOOT);
+pub(crate) const CHARACTERISTIC_UUID_REQUEST_EVENTS_SINCE: Uuid =
+    Uuid::from_u128(gatt::REQUEST_EVENTS_SINCE);
+const CHARACTERISTIC_UUID_DAILY_STEPS: Uuid = Uuid::from_u128(gatt::DAILY_STEPS);
+pub(crate) const CHARACTERISTIC_UUID_MARKER: Uuid = Uuid::from_u128(gatt::MARKER);
+const CHARACTERISTIC_UUID_SELF_TEST: Uuid = Uuid::from_u128(gatt::SELF_TEST);
+const CHARACTERISTIC_UUID_CONFIG_CHANGED: Uuid = Uuid::from_u128(gatt::CONFIG_CHANGED);
+pub(crate) const CHARACTERISTIC_UUID_REQUEST_EVENTS_QUERY: Uuid =
+    Uuid::from_u128(gatt::REQUEST_EVENTS_QUERY);
+const CHARACTERISTIC_UUID_WRITE_ERROR: Uuid = Uuid::from_u128(gatt::WRITE_ERROR);
+pub(crate) const CHARACTERISTIC_UUID_ACK_EVENTS: Uuid = Uuid::from_u128(gatt::ACK_EVENTS);
+const CHARACTERISTIC_UUID_FIRMWARE_VERSION: Uuid = Uuid::from_u128(gatt::FIRMWARE_VERSION);
+const CHARACTERISTIC_UUID_ACCELERATION: Uuid = Uuid::from_u128(gatt::ACCELERATION);
+const CHARACTERISTIC_UUID_BOOT_SUMMARIES: Uuid = Uuid::from_u128(gatt::BOOT_SUMMARIES);
+const CHARACTERISTIC_UUID_TEMPERATURE: Uuid = Uuid::from_u128(gatt::TEMPERATURE);
+const CHARACTERISTIC_UUID_STORAGE_USAGE: Uuid = Uuid::from_u128(gatt::STORAGE_USAGE);
+const CHARACTERISTIC_UUID_DELETE_RESULT: Uuid = Uuid::from_u128(gatt::DELETE_RESULT);
+pub(crate) const CHARACTERISTIC_UUID_REQUEST_EVENTS_TRANSFER: Uuid =
+    Uuid::from_u128(gatt::REQUEST_EVENTS_TRANSFER);
+
+/// Number of event responses that may wait for the database during a sync. The processing of
+/// further notifications only waits for the database if it falls behind by more than this.
+pub(crate) const SYNC_WRITE_BUFFER_SIZE: usize = 32;
+
+/// The device aborted a transfer if the next chunk does not arrive within this duration, e.g.
+/// because its last chunk could not be notified.
+pub(crate) const TRANSFER_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// The events are requested this often again from the same position if they cannot be decoded
+/// before the sync is given up. An event that is not even decoded after that is most likely not
+/// supported by the app.
+const MAX_DECODE_RETRIES: u8 = 3;
 
-const SUB_CHARACTERISTICS: [Uuid; 4] = [
+/// Maximum duration of the round trip time synchronization.
+const TIME_SYNC_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Maximum duration until the device answers a request of the boot summaries. It reads the whole
+/// event storage for it.
+const BOOT_SUMMARIES_TIMEOUT: Duration = Duration::from_secs(10);
+
+const SUB_CHARACTERISTICS: [Uuid; 14] = [
     CHARACTERISTIC_UUID_SOC,
     CHARACTERISTIC_UUID_EPOCH_MS,
     CHARACTERISTIC_UUID_RESPONSE_EVENTS,
     CHARACTERISTIC_MAX_EVENT_ID,
+    CHARACTERISTIC_UUID_STORAGE_FILL_PERCENT,
+    CHARACTERISTIC_UUID_TIME_SYNC,
+    CHARACTERISTIC_UUID_DAILY_STEPS,
+    CHARACTERISTIC_UUID_CONFIG_CHANGED,
+    CHARACTERISTIC_UUID_WRITE_ERROR,
+    CHARACTERISTIC_UUID_ACCELERATION,
+    CHARACTERISTIC_UUID_BOOT_SUMMARIES,
+    CHARACTERISTIC_UUID_TEMPERATURE,
+    CHARACTERISTIC_UUID_STORAGE_USAGE,
+    CHARACTERISTIC_UUID_DELETE_RESULT,
 ];
 
+/// Events of one event response that are stored in one transaction together with the resulting
+/// sync state.
+#[derive(Debug, Default)]
+pub(crate) struct PedometerSyncBatch {
+    pub records: Vec<PedometerPersistenceRecord>,
+    pub sync_state: Option<PedometerPersistenceSyncState>,
+    /// The device sent all events, so the sync is finished once the batch was stored
+    pub complete: bool,
+}
+
+/// What the host does after an event response
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum PedometerEventsNext {
+    /// Wait for the next chunk of the transfer
+    Wait,
+    /// Request the events from the given position or from the last synced event if unknown
+    Request(Option<PedometerEventRequest>),
+    /// The device sent all events
+    Done,
+    /// The events cannot be decoded, so the sync is given up
+    Failed,
+}
+
+/// Progress of a chunked event transfer, see [`PedometerEventChunkHeader`].
+#[derive(Debug, Default)]
+struct PedometerEventTransfer {
+    next_sequence: u16,
+    /// A chunk was lost or corrupted, so the following ones are ignored until the transfer ends
+    broken: bool,
+    /// Position after the last event that was received
+    next_request: Option<PedometerEventRequest>,
+    /// When the last chunk arrived if more are expected
+    waiting_since: Option<tokio::time::Instant>,
+}
+
+impl PedometerEventTransfer {
+    /// Expect the first chunk of the next transfer, so that it is not mistaken for a continuation
+    /// of this one if it is lost. The position is kept to request the next transfer from it.
+    fn finish(&mut self) {
+        *self = Self {
+            next_request: self.next_request,
+            ..Default::default()
+        };
+    }
+}
+
+/// Events that could not be decoded in a row at the same position.
+#[derive(Debug, Copy, Clone)]
+struct PedometerDecodeFailures {
+    position: Option<PedometerEventRequest>,
+    failures: u8,
+    error: PedometerCommonError,
+}
+
+/// State of the processing of the event responses of a connection.
+#[derive(Debug, Default)]
+pub(crate) struct PedometerEventResponses {
+    /// Events that wait for the time offset of their boot
+    event_queue: VecDeque<PedometerEvent>,
+    device_time_offsets: HashMap<u32, Duration>,
+    max_time_offset_boot_id: u32,
+    pub(crate) device_max_event_id: u32,
+    /// The device sends the events in chunks, see [`PedometerEventChunkHeader`]
+    chunked: bool,
+    transfer: PedometerEventTransfer,
+    decode_failures: Option<PedometerDecodeFailures>,
+    /// See [`TRANSFER_TIMEOUT`]
+    pub(crate) transfer_timeout: Duration,
+}
+
+impl PedometerEventResponses {
+    pub(crate) fn new(device_max_event_id: u32, chunked: bool) -> Self {
+        Self {
+            device_max_event_id,
+            chunked,
+            transfer_timeout: TRANSFER_TIMEOUT,
+            ..Default::default()
+        }
+    }
+
+    /// Wait for the next notification. Returns `None` if the device did not continue the running
+    /// transfer in time, see [`PedometerDeviceHandler::process_transfer_timeout`].
+    pub(crate) async fn receive<T>(&self, notification: impl Future<Output = T>) -> Option<T> {
+        match self.transfer.waiting_since {
+            Some(since) => tokio::time::timeout_at(since + self.transfer_timeout, notification)
+                .await
+                .ok(),
+            None => Some(notification.await),
+        }
+    }
+}
+
+/// Phase of a sync with a device that supports event queries. The time references of all boots are
+/// requested first, so that the steps can be converted as soon as they are received instead of
+/// waiting in the event queue for the host epoch.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub(crate) enum PedometerSyncPhase {
+    /// The events are requested again from `start` afterwards
+    TimeReferences { start: PedometerEventRequest },
+    #[default]
+    Events,
+}
+
+/// The phase is set by the device handler when a sync starts and advanced by the processing of
+/// the event responses.
+pub(crate) type SharedSyncPhase = Arc<Mutex<PedometerSyncPhase>>;
+
+/// Query for the events from the given position in the current phase. A new sync starts with the
+/// time references.
+pub(crate) fn event_query(
+    sync_phase: &SharedSyncPhase,
+    since: PedometerEventRequest,
+    new_sync: bool,
+) -> PedometerEventQuery {
+    let mut sync_phase = sync_phase.lock().unwrap();
+    if new_sync {
+        *sync_phase = PedometerSyncPhase::TimeReferences { start: since };
+    }
+    PedometerEventQuery {
+        since,
+        filter: match *sync_phase {
+            PedometerSyncPhase::TimeReferences { .. } => PedometerEventFilter::TimeReferences,
+            PedometerSyncPhase::Events => PedometerEventFilter::All,
+        },
+    }
+}
+
 pub static BLE_CMD_TX: OnceLock<mpsc::Sender<PedometerDeviceHandlerCommand>> = OnceLock::new();
 
+/// Delete the synced events on the device once a sync is complete, see
+/// [`crate::settings::SyncPolicy`].
+pub(crate) static DELETE_AFTER_SYNC: AtomicBool = AtomicBool::new(false);
+
+/// ID of the app in the sync cursors of the device, see [`crate::settings::PedometerSettings`].
+pub(crate) static HOST_ID: AtomicU32 = AtomicU32::new(0);
+
+/// Daily target for the ongoing notification with the steps of today that is updated after the
+/// synced events were stored, 0 if it is not shown.
+pub(crate) static SYNC_PROGRESS_TARGET: AtomicU32 = AtomicU32::new(0);
+
+/// Abort a running connection attempt. The handler cannot receive further commands while it
+/// connects, so this cannot be a command.
+pub static CONNECT_CANCEL: Notify = Notify::const_new();
+
+/// Progress of the connection to the device.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum PedometerConnectionState {
+    #[default]
+    Disconnected,
+    /// Started the given scan attempt, which takes the given duration, while searching for the
+    /// device
+    Scanning {
+        attempt: u8,
+        attempts: u8,
+        duration: Duration,
+    },
+    Connecting,
+    /// Discovering the services and subscribing to the characteristics
+    Subscribing,
+    Connected,
+    /// Events were requested and not all of them were received, yet
+    Syncing,
+    Disconnecting,
+}
+
+/// Most likely reason why the scan did not find the device, so the user can be guided to fix it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum PedometerNotFoundCause {
+    NoAdapter,
+    AdapterOff,
+    /// The scan did not see any device at all
+    NoDevices,
+    /// Devices were seen, but none of them has the name of a pedometer
+    NameMismatch {
+        names: Vec<String>,
+    },
+}
+
+impl std::fmt::Display for PedometerNotFoundCause {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PedometerNotFoundCause::NoAdapter => write!(f, "no Bluetooth adapter"),
+            PedometerNotFoundCause::AdapterOff => write!(f, "Bluetooth is switched off"),
+            PedometerNotFoundCause::NoDevices => write!(f, "no devices in range"),
+            PedometerNotFoundCause::NameMismatch { names } => {
+                write!(f, "no pedometer among {} devices", names.len())
+            }
+        }
+    }
+}
+
+/// Clock of the device compared to the host clock at the same moment.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct PedometerDeviceClock {
+    /// Epoch of the device derived from its uptime and the last time sync
+    pub device_epoch_ms: u64,
+    pub host_epoch_ms: u64,
+    /// Round trip time of the request, which limits the accuracy of the comparison
+    pub round_trip_ms: u64,
+}
+
+impl PedometerDeviceClock {
+    /// Positive if the device clock is ahead of the host clock
+    pub(crate) fn offset_ms(&self) -> i64 {
+        self.device_epoch_ms as i64 - self.host_epoch_ms as i64
+    }
+}
+
+pub(crate) fn report_sync_error(error: impl std::fmt::Display) {
+    metrics::record_sync_error();
+    GUI_EVENT_TX
+        .get()
+        .unwrap()
+        .send(PedometerGuiEvent::SyncFailed(error.to_string()));
+}
+
+/// Count a command that could not be handed over to another task and alert if this happens
+/// frequently.
+pub(crate) fn report_dropped_command(error: impl std::fmt::Display) {
+    warn!("Dropped command: {error}");
+    if metrics::record_dropped_command() {
+        GUI_EVENT_TX
+            .get()
+            .unwrap()
+            .send(PedometerGuiEvent::CommandsDropped(
+                metrics::dropped_commands(),
+            ));
+    }
+}
+
+pub(crate) fn set_connection_state(state: PedometerConnectionState) {
+    GUI_EVENT_TX
+        .get()
+        .unwrap()
+        .send(PedometerGuiEvent::ConnectionState(state));
+}
+
 #[derive(Debug)]
 pub(crate) struct PedometerDeviceHandler {
     device: Option<Peripheral>,
+    listen_task: Option<JoinHandle<()>>,
+    adapter_watch_task: Option<JoinHandle<()>>,
+    sync_phase: SharedSyncPhase,
+    /// Difference between the epoch and the uptime of the device as set by the last time sync,
+    /// unknown if the device did not report its uptime
+    epoch_offset_ms: Mutex<Option<u64>>,
+}
+
+impl Drop for PedometerDeviceHandler {
+    fn drop(&mut self) {
+        for task in [self.listen_task.take(), self.adapter_watch_task.take()]
+            .into_iter()
+            .flatten()
+        {
+            task.abort();
+        }
+    }
 }
 
 impl PedometerDeviceHandler {
     pub(crate) async fn new() -> anyhow::Result<Self> {
-        Ok(Self { device: None })
+        Ok(Self {
+            device: None,
+            listen_task: None,
+            adapter_watch_task: None,
+            sync_phase: Default::default(),
+            epoch_offset_ms: Default::default(),
+        })
     }
 
     #[allow(unused_variables)]
     pub(crate) async fn spawn_message_handler(
         mut self,
-        mut event_receiver: mpsc::Receiver<PedometerDeviceHandlerCommand>,
+        event_receiver: SharedReceiver<PedometerDeviceHandlerCommand>,
     ) -> JoinHandle<()> {
         tokio::spawn(async move {
+            self.adapter_watch_task = Some(tokio::spawn(watch_adapter()));
+            let mut event_receiver = event_receiver.lock().await;
             while let Some(cmd) = event_receiver.recv().await {
                 match cmd {
-                    PedometerDeviceHandlerCommand::TryConnect { responder } => {
-                        let res = self.try_connect().await;
+                    PedometerDeviceHandlerCommand::TryConnect {
+                        address,
+                        scan_policy,
+                        responder,
+                    } => {
+                        let res = tokio::select! {
+                            res = self.try_connect(address, scan_policy) => res,
+                            () = CONNECT_CANCEL.notified() => {
+                                info!("Connecting was cancelled");
+                                // The device may already be connected without the subscriptions
+                                if let Err(e) = self.disconnect().await {
+                                    warn!("Could not disconnect after cancelling: {e}");
+                                }
+                                Err(anyhow!("Connecting was cancelled"))
+                            }
+                        };
                         if let Err(e) = &res {
                             warn!("Could not connect to device: {e}");
+                            set_connection_state(if self.is_connected().await.unwrap_or(false) {
+                                PedometerConnectionState::Connected
+                            } else {
+                                PedometerConnectionState::Disconnected
+                            });
                         }
                         let _ = responder.send(res);
                     }
                     PedometerDeviceHandlerCommand::IsConnected { responder } => {
                         let _ = responder.send(self.is_connected().await);
                     }
-                    PedometerDeviceHandlerCommand::RequestEvents {
-                        min_event_id,
+                    PedometerDeviceHandlerCommand::RequestEvents { since, responder } => {
+                        // The events deleted after the previous sync freed storage
+                        if let Some(device) = &self.device {
+                            if let Err(e) = Self::read_storage_fill_percent(device).await {
+                                warn!("Could not read storage fill level: {e}");
+                            }
+                            if let Err(e) = Self::read_storage_usage(device).await {
+                                info!("Could not read storage usage: {e}");
+                            }
+                        }
+                        let res = self.request_events(since).await;
+                        match &res {
+                            Ok(()) => set_connection_state(PedometerConnectionState::Syncing),
+                            Err(e) => report_sync_error(e),
+                        }
+                        let _ = responder.send(res);
+                    }
+                    PedometerDeviceHandlerCommand::DeleteEvents {
+                        max_event_id,
                         responder,
                     } => {
-                        let _ = responder.send(self.request_events(min_event_id).await);
+                        let res = self.delete_events(max_event_id).await;
+                        if let Err(e) = &res {
+                            warn!("Could not delete events: {e}");
+                        }
+                        let _ = responder.send(res);
+                    }
+                    PedometerDeviceHandlerCommand::DeleteBoot { boot_id, responder } => {
+                        let _ = responder.send(self.delete_boot(boot_id).await);
                     }
-                    PedometerDeviceHandlerCommand::DeleteEvents { .. } => {
-                        todo!()
+                    PedometerDeviceHandlerCommand::WriteConfig { values, responder } => {
+                        let _ = responder.send(self.write_config(&values).await);
+                    }
+                    PedometerDeviceHandlerCommand::SetMarker { marker, responder } => {
+                        let _ = responder.send(self.set_marker(marker).await);
                     }
                     PedometerDeviceHandlerCommand::Disconnect { responder } => {
-                        let _ = responder.send(self.disconnect().await);
+                        set_connection_state(PedometerConnectionState::Disconnecting);
+                        let res = self.disconnect().await;
+                        set_connection_state(if res.is_ok() {
+                            PedometerConnectionState::Disconnected
+                        } else {
+                            PedometerConnectionState::Connected
+                        });
+                        let _ = responder.send(res);
+                    }
+                    PedometerDeviceHandlerCommand::ReadDiagnostics { responder } => {
+                        let _ = responder.send(self.read_diagnostics().await);
+                    }
+                    PedometerDeviceHandlerCommand::ReadDeviceClock { responder } => {
+                        let _ = responder.send(self.read_device_clock().await);
+                    }
+                    PedometerDeviceHandlerCommand::ResyncTime { responder } => {
+                        let _ = responder.send(self.send_host_epoch().await);
+                    }
+                    PedometerDeviceHandlerCommand::ReadBootSummaries { responder } => {
+                        let _ = responder.send(self.read_boot_summaries().await);
+                    }
+                    PedometerDeviceHandlerCommand::StreamAcceleration { enabled, responder } => {
+                        let _ = responder.send(self.stream_acceleration(enabled).await);
+                    }
+                    PedometerDeviceHandlerCommand::StartListening { responder } => {
+                        let _ = responder.send(self.start_listening().await);
+                    }
+                    PedometerDeviceHandlerCommand::StopListening { responder } => {
+                        let _ = responder.send(self.stop_listening().await);
                     }
+                    PedometerDeviceHandlerCommand::AdapterRemoved => self.reset(),
                     PedometerDeviceHandlerCommand::Exit => break,
                 }
             }
         })
     }
 
-    async fn try_connect(&mut self) -> anyhow::Result<()> {
+    /// Forget everything that belongs to a removed adapter. The peripheral cannot be used again
+    /// even if the adapter returns, so it has to be found and connected again.
+    fn reset(&mut self) {
+        info!("Reset device handler");
+        if let Some(listen_task) = self.listen_task.take() {
+            listen_task.abort();
+        }
+        self.device = None;
+        *self.sync_phase.lock().unwrap() = Default::default();
+        *self.epoch_offset_ms.lock().unwrap() = None;
+        metrics::set_soc(None);
+        set_connection_state(PedometerConnectionState::Disconnected);
+        GUI_EVENT_TX
+            .get()
+            .unwrap()
+            .send(crate::gui::PedometerGuiEvent::Disconnected);
+    }
+
+    async fn try_connect(
+        &mut self,
+        address: Option<String>,
+        scan_policy: ScanPolicy,
+    ) -> anyhow::Result<()> {
         if self.is_connected().await? {
             return Ok(());
         }
         if self.device.is_none() {
-            let manager = Manager::new().await?;
-            let adapter_list = manager.adapters().await?;
-            if adapter_list.is_empty() {
-                error!("Could not find any adapters");
-                return Err(anyhow!("Could not find any adapters"));
-            }
-            let adapter = adapter_list.first().unwrap().clone();
+            let adapter = get_adapter().await?;
+            let mut rejected = false;
 
-            info!("Starting scan on {}...", adapter.adapter_info().await?);
+            // A known device can be connected directly without scanning for it first
+            if let Some(address) = address {
+                match find_known_device(&adapter, &address).await {
+                    Ok(Some(device)) => {
+                        info!("Try to connect to known device: {:?}", device);
+                        set_connection_state(PedometerConnectionState::Connecting);
+                        match device.connect().await {
+                            Ok(()) => self.device = Some(device),
+                            Err(e) => {
+                                warn!("Could not connect to known device: {e}");
+                                rejected = true;
+                            }
+                        }
+                    }
+                    Ok(None) => info!("Known device {address} not found"),
+                    Err(e) => warn!("Could not search for known device: {e}"),
+                }
+            }
+            if self.device.is_none() {
+                // Not every platform reports the state, so only a known off state is reported
+                if matches!(adapter.adapter_state().await, Ok(CentralState::PoweredOff)) {
+                    warn!("Adapter is switched off");
+                    return Err(PedometerGuiError::DeviceNotFound(
+                        PedometerNotFoundCause::AdapterOff,
+                    )
+                    .into());
+                }
+                info!("Starting scan on {}...", adapter.adapter_info().await?);
 
-            adapter
-                .start_scan(ScanFilter {
-                    //services: vec![SERVICE_UUID_PEDOMETER],
-                    services: vec![],
-                })
-                .await?;
+                adapter
+                    .start_scan(ScanFilter {
+                        //services: vec![SERVICE_UUID_PEDOMETER],
+                        services: vec![],
+                    })
+                    .await?;
 
-            if let Ok(Ok(Some(device))) = tokio::time::timeout(Duration::from_secs(5), async {
-                loop {
-                    match find_device(&adapter).await {
-                        Ok(None) => tokio::time::sleep(Duration::from_millis(200)).await,
-                        res => return res,
+                let attempts = scan_policy.attempts();
+                for attempt in 0..attempts {
+                    let scan_duration = scan_policy.scan_duration(attempt);
+                    info!(
+                        "Scan attempt {}/{attempts} for {scan_duration:?}",
+                        attempt + 1
+                    );
+                    set_connection_state(PedometerConnectionState::Scanning {
+                        attempt: attempt + 1,
+                        attempts,
+                        duration: scan_duration,
+                    });
+                    if let Ok(Ok(Some(device))) = tokio::time::timeout(scan_duration, async {
+                        loop {
+                            match find_device(&adapter).await {
+                                Ok(None) => tokio::time::sleep(scan_policy.poll_interval()).await,
+                                res => return res,
+                            }
+                        }
+                    })
+                    .await
+                    {
+                        info!("Found device: {:?}", device);
+                        self.device = Some(device);
+                        break;
                     }
                 }
-            })
-            .await
-            {
-                info!("Found device: {:?}", device);
-                self.device = Some(device);
-            } else {
-                warn!("Could not find device");
-                return Err(anyhow!("Could not find device"));
+                if self.device.is_none() {
+                    // The known device is there but does not advertise while it is connected
+                    if rejected {
+                        warn!("Known device rejected the connection and was not found");
+                        return Err(PedometerGuiError::ConnectionRejected.into());
+                    }
+                    let cause = diagnose_not_found(&adapter).await;
+                    warn!("Could not find device: {cause:?}");
+                    return Err(PedometerGuiError::DeviceNotFound(cause).into());
+                }
             }
         }
         if let Some(device) = &self.device {
-            device.connect().await?;
+            if !device.is_connected().await? {
+                set_connection_state(PedometerConnectionState::Connecting);
+                device.connect().await.map_err(|e| {
+                    warn!("Device rejected the connection: {e}");
+                    PedometerGuiError::ConnectionRejected
+                })?;
+            }
+            set_connection_state(PedometerConnectionState::Subscribing);
             device.discover_services().await?;
 
             tokio::time::sleep(Duration::from_millis(100)).await;
@@ -145,68 +630,222 @@ impl PedometerDeviceHandler {
             self.send_host_epoch().await?;
             let boot_id = u32::from_le_bytes(
                 device
-                    .read(&find_characteristic(device, CHARACTERISTIC_BOOT_ID).unwrap())
+                    .read(&get_characteristic(device, CHARACTERISTIC_BOOT_ID)?)
                     .await?[..]
                     .try_into()?,
             );
             let max_event_id = u32::from_le_bytes(
                 device
-                    .read(&find_characteristic(device, CHARACTERISTIC_MAX_EVENT_ID).unwrap())
+                    .read(&get_characteristic(device, CHARACTERISTIC_MAX_EVENT_ID)?)
                     .await?[..]
                     .try_into()?,
             );
-            let soc = device
-                .read(&find_characteristic(device, CHARACTERISTIC_UUID_SOC).unwrap())
-                .await?[0];
+            let soc = *device
+                .read(&get_characteristic(device, CHARACTERISTIC_UUID_SOC)?)
+                .await?
+                .first()
+                .ok_or_else(|| anyhow!("Empty soc characteristic"))?;
             info!("Connected: boot_id: {boot_id}, max_event_id: {max_event_id}, soc: {soc}");
+            metrics::set_soc(Some(soc));
 
-            if let Err(e) = GUI_EVENT_TX
+            GUI_EVENT_TX
                 .get()
                 .unwrap()
-                .send(crate::gui::PedometerGuiEvent::Soc(soc))
-                .await
-            {
-                error!("Could not send gui soc event: {e}");
+                .send(crate::gui::PedometerGuiEvent::Soc(soc));
+            GUI_EVENT_TX
+                .get()
+                .unwrap()
+                .send(crate::gui::PedometerGuiEvent::Connected {
+                    address: device.id().to_string(),
+                    name: device
+                        .properties()
+                        .await
+                        .ok()
+                        .flatten()
+                        .and_then(|properties| properties.local_name),
+                });
+
+            // Threshold notifications are only sent when a threshold is crossed, so check whether
+            // one was already reached before the connection
+            if let Err(e) = self.check_storage_fill_percent().await {
+                warn!("Could not check storage fill level: {e}");
+            }
+            if let Err(e) = self.check_self_test().await {
+                warn!("Could not check self test: {e}");
+            }
+            // Older firmware does not report its version
+            if let Err(e) = self.read_firmware_version().await {
+                info!("Could not read firmware version: {e}");
+            }
+            // Older firmware does not measure the temperature
+            if let Err(e) = self.read_temperature().await {
+                info!("Could not read temperature: {e}");
+            }
+            // Older firmware only reports the fill level of the storage
+            if let Some(device) = &self.device {
+                if let Err(e) = Self::read_storage_usage(device).await {
+                    info!("Could not read storage usage: {e}");
+                }
+            }
+            // The config may have been changed by another host since the last connection
+            if let Err(e) = self.read_config().await {
+                warn!("Could not read config: {e}");
             }
 
             let mut notification_stream = device.notifications().await?;
+            let (batch_tx, batch_rx) = mpsc::channel(SYNC_WRITE_BUFFER_SIZE);
+            tokio::spawn(Self::write_sync_batches(batch_rx));
+            let sync_phase = self.sync_phase.clone();
+            // Firmware that supports transfers sends all responses in chunks, see request_events
+            let chunked =
+                find_characteristic(device, CHARACTERISTIC_UUID_REQUEST_EVENTS_TRANSFER).is_some();
             tokio::spawn(async move {
-                let mut event_queue = VecDeque::new();
-                let mut device_time_offsets = HashMap::new();
-                let mut max_time_offset_boot_id = 0;
-                while let Some(notification) = notification_stream.next().await {
+                let mut responses = PedometerEventResponses::new(max_event_id, chunked);
+                loop {
+                    let Some(notification) = responses.receive(notification_stream.next()).await
+                    else {
+                        Self::process_transfer_timeout(&mut responses).await;
+                        continue;
+                    };
+                    let Some(notification) = notification else {
+                        break;
+                    };
                     match notification.uuid {
                         CHARACTERISTIC_UUID_RESPONSE_EVENTS => {
                             info!("Received event response");
                             Self::process_event_response(
                                 notification,
-                                &mut event_queue,
-                                &mut device_time_offsets,
-                                &mut max_time_offset_boot_id,
+                                &mut responses,
+                                &batch_tx,
+                                &sync_phase,
                             )
                             .await;
                         }
-                        CHARACTERISTIC_UUID_EPOCH_MS => {
+                        CHARACTERISTIC_UUID_BOOT_SUMMARIES => {
+                            // Processed by the request, see read_boot_summaries
+                            debug!("Received boot summaries: {:?}", notification.value);
+                        }
+                        CHARACTERISTIC_UUID_EPOCH_MS | CHARACTERISTIC_UUID_TIME_SYNC => {
                             // Process event instead
                             info!("Received epoch characteristic: {:?}", notification.value);
                         }
                         CHARACTERISTIC_UUID_SOC => {
                             info!("Received soc characteristic: {:?}", notification.value);
-                            if let Err(e) = GUI_EVENT_TX
+                            metrics::set_soc(Some(notification.value[0]));
+                            GUI_EVENT_TX
                                 .get()
                                 .unwrap()
-                                .send(crate::gui::PedometerGuiEvent::Soc(notification.value[0]))
-                                .await
-                            {
-                                error!("Could not send gui soc event: {e}");
+                                .send(crate::gui::PedometerGuiEvent::Soc(notification.value[0]));
+                        }
+                        CHARACTERISTIC_UUID_STORAGE_FILL_PERCENT => {
+                            info!(
+                                "Received storage fill characteristic: {:?}",
+                                notification.value
+                            );
+                            if let Some(fill_percent) = notification.value.first() {
+                                GUI_EVENT_TX.get().unwrap().send(
+                                    crate::gui::PedometerGuiEvent::StorageFill(*fill_percent),
+                                );
+                                GUI_EVENT_TX.get().unwrap().send(
+                                    crate::gui::PedometerGuiEvent::StorageWarning(*fill_percent),
+                                );
                             }
                         }
                         CHARACTERISTIC_MAX_EVENT_ID => {
-                            // Todo!
                             info!(
                                 "Received max_event_id characteristic: {:?}",
                                 notification.value
                             );
+                            if let Ok(value) = notification.value[..].try_into() {
+                                responses.device_max_event_id = u32::from_le_bytes(value);
+                            }
+                        }
+                        CHARACTERISTIC_UUID_DELETE_RESULT => {
+                            match notification.value[..].try_into() {
+                                Ok(value) => {
+                                    let result = PedometerDeleteResult::from_bytes(value);
+                                    info!("Events deleted on device: {result:?}");
+                                    GUI_EVENT_TX
+                                        .get()
+                                        .unwrap()
+                                        .send(crate::gui::PedometerGuiEvent::EventsDeleted(result));
+                                }
+                                Err(_) => {
+                                    warn!("Invalid delete result: {:?}", notification.value)
+                                }
+                            }
+                        }
+                        CHARACTERISTIC_UUID_STORAGE_USAGE => {
+                            debug!(
+                                "Received storage usage characteristic: {:?}",
+                                notification.value
+                            );
+                            if let Ok(value) = notification.value[..].try_into() {
+                                GUI_EVENT_TX.get().unwrap().send(
+                                    crate::gui::PedometerGuiEvent::StorageUsage(
+                                        PedometerStorageUsage::from_bytes(value),
+                                    ),
+                                );
+                            }
+                        }
+                        CHARACTERISTIC_UUID_TEMPERATURE => {
+                            debug!(
+                                "Received temperature characteristic: {:?}",
+                                notification.value
+                            );
+                            if let Ok(value) = notification.value[..].try_into() {
+                                GUI_EVENT_TX.get().unwrap().send(
+                                    crate::gui::PedometerGuiEvent::Temperature(i16::from_le_bytes(
+                                        value,
+                                    )),
+                                );
+                            }
+                        }
+                        CHARACTERISTIC_UUID_DAILY_STEPS => {
+                            debug!(
+                                "Received daily steps characteristic: {:?}",
+                                notification.value
+                            );
+                            if let Ok(value) = notification.value[..].try_into() {
+                                GUI_EVENT_TX.get().unwrap().send(
+                                    crate::gui::PedometerGuiEvent::DailySteps {
+                                        daily_steps: u32::from_le_bytes(value),
+                                        received: Instant::now(),
+                                    },
+                                );
+                            }
+                        }
+                        CHARACTERISTIC_UUID_CONFIG_CHANGED => {
+                            info!("Received config changed characteristic");
+                            match PedometerConfig::deserialize(&notification.value) {
+                                Ok(config) => GUI_EVENT_TX
+                                    .get()
+                                    .unwrap()
+                                    .send(crate::gui::PedometerGuiEvent::DeviceConfig(config)),
+                                Err(e) => warn!("Could not deserialize config: {e}"),
+                            }
+                        }
+                        CHARACTERISTIC_UUID_WRITE_ERROR => {
+                            match PedometerWriteStatus::deserialize(&notification.value) {
+                                Ok(status) => report_sync_error(format!(
+                                    "Device rejected the write to characteristic {:04x}: {:?}",
+                                    status.characteristic, status.error
+                                )),
+                                Err(e) => warn!("Could not deserialize write status: {e}"),
+                            }
+                        }
+                        CHARACTERISTIC_UUID_ACCELERATION => {
+                            match notification.value[..].try_into() {
+                                Ok(value) => GUI_EVENT_TX.get().unwrap().send(
+                                    crate::gui::PedometerGuiEvent::Acceleration(
+                                        PedometerAccelerationSamples::from_bytes(value),
+                                    ),
+                                ),
+                                Err(_) => warn!(
+                                    "Received acceleration samples with invalid length: {}",
+                                    notification.value.len()
+                                ),
+                            }
                         }
                         char => warn!("Received unknown characteristic: {char}"),
                     }
@@ -217,15 +856,47 @@ impl PedometerDeviceHandler {
                 while let Ok(true) = device.is_connected().await {
                     tokio::time::sleep(Duration::from_secs(5)).await;
                 }
-                if let Err(e) = GUI_EVENT_TX
+                metrics::set_soc(None);
+                set_connection_state(PedometerConnectionState::Disconnected);
+                GUI_EVENT_TX
                     .get()
                     .unwrap()
-                    .send(crate::gui::PedometerGuiEvent::Disconnected)
-                    .await
-                {
-                    error!("Could not send gui disconnected event: {e}");
-                }
+                    .send(crate::gui::PedometerGuiEvent::Disconnected);
             });
+            set_connection_state(PedometerConnectionState::Connected);
+        }
+        Ok(())
+    }
+
+    async fn start_listening(&mut self) -> anyhow::Result<()> {
+        if self
+            .listen_task
+            .as_ref()
+            .is_some_and(|listen_task| !listen_task.is_finished())
+        {
+            return Ok(());
+        }
+        let adapter = get_adapter().await?;
+        info!(
+            "Start listening for live data on {}",
+            adapter.adapter_info().await?
+        );
+        self.listen_task = Some(tokio::spawn(async move {
+            loop {
+                if let Err(e) = listen_for_live_data(&adapter).await {
+                    warn!("Could not listen for live data: {e}");
+                }
+                tokio::time::sleep(LISTEN_SCAN_INTERVAL - LISTEN_SCAN_DURATION).await;
+            }
+        }));
+        Ok(())
+    }
+
+    async fn stop_listening(&mut self) -> anyhow::Result<()> {
+        if let Some(listen_task) = self.listen_task.take() {
+            info!("Stop listening for live data");
+            listen_task.abort();
+            get_adapter().await?.stop_scan().await?;
         }
         Ok(())
     }
@@ -239,114 +910,307 @@ impl PedometerDeviceHandler {
         Ok(())
     }
 
-    async fn process_event_response(
+    pub(crate) async fn process_event_response(
         mut notification: ValueNotification,
-        event_queue: &mut VecDeque<PedometerEvent>,
-        device_time_offsets: &mut HashMap<u32, Duration>,
-        max_time_offset_boot_id: &mut u32,
+        responses: &mut PedometerEventResponses,
+        batch_tx: &mpsc::Sender<PedometerSyncBatch>,
+        sync_phase: &SharedSyncPhase,
     ) {
+        let time_references_start = match *sync_phase.lock().unwrap() {
+            PedometerSyncPhase::TimeReferences { start } => Some(start),
+            PedometerSyncPhase::Events => None,
+        };
         info!(
             "Got event response with length: {}",
             notification.value.len()
         );
-        let mut buf = &mut notification.value[..];
+        let (mut buf, header) = if responses.chunked {
+            let Some((header, events)) = notification
+                .value
+                .split_first_chunk_mut::<{ PedometerEventChunkHeader::SIZE }>()
+            else {
+                warn!("Got invalid event chunk: {:?}", notification.value);
+                return;
+            };
+            let header = PedometerEventChunkHeader::from_bytes(header);
+            debug!("Got event chunk: {header:?}");
+            let transfer = &mut responses.transfer;
+            if header.sequence == 0 {
+                *transfer = PedometerEventTransfer::default();
+            } else if header.sequence != transfer.next_sequence && !transfer.broken {
+                warn!(
+                    "Lost event chunk {}, request the events again after the transfer",
+                    transfer.next_sequence
+                );
+                transfer.broken = true;
+            }
+            transfer.next_sequence = header.sequence.wrapping_add(1);
+            // The events after a lost chunk would leave a gap in the synced events
+            let events: &mut [u8] = if transfer.broken { &mut [] } else { events };
+            (events, Some(header))
+        } else {
+            (&mut notification.value[..], None)
+        };
+        // Firmware that supports transfers appends a CRC to every event
+        let deserialize = if responses.chunked {
+            PedometerEvent::deserialize_from_transport
+        } else {
+            PedometerEvent::deserialize_from_transport_without_crc
+        };
         let mut max_event_id = 0;
+        let mut max_event_boot_id = 0;
         let mut received_events = false;
-        while let Ok((event, rest)) = PedometerEvent::deserialize_from_transport(buf) {
+        let mut decode_error = None;
+        // The rest of a response is filled with zeros
+        while buf.iter().any(|byte| *byte != 0) {
+            let (event, rest) = match deserialize(buf) {
+                Ok(frame) => frame,
+                Err(e) => {
+                    match e {
+                        PedometerCommonError::Crc => warn!(
+                            "Got corrupted event, request the events after the last valid one"
+                        ),
+                        PedometerCommonError::Postcard => warn!(
+                            "Could not decode event, request the events after the last valid one"
+                        ),
+                    }
+                    decode_error = Some(e);
+                    break;
+                }
+            };
             received_events = true;
             buf = rest;
             info!("Got event from device: {event:?}");
-            max_event_id = max(event.index, max_event_id);
+            if event.index >= max_event_id {
+                max_event_id = event.index;
+                max_event_boot_id = event.boot_id;
+            }
             debug!("Set max_event_id to {max_event_id}");
-            match event.event_type {
-                PedometerEventType::HostEpochMs(host_epoch_ms) => {
-                    if host_epoch_ms >= event.timestamp_ms {
-                        device_time_offsets.insert(
-                            event.boot_id,
-                            Duration::from_millis(host_epoch_ms - event.timestamp_ms),
-                        );
-                        *max_time_offset_boot_id = max(*max_time_offset_boot_id, event.boot_id);
-                    } else {
-                        warn!("Got invalid host epoch event: {event:?}");
-                    }
+            if let PedometerEventType::HostEpochMs(host_epoch_ms) = event.event_type {
+                if host_epoch_ms >= event.timestamp_ms {
+                    responses.device_time_offsets.insert(
+                        event.boot_id,
+                        Duration::from_millis(host_epoch_ms - event.timestamp_ms),
+                    );
+                    responses.max_time_offset_boot_id =
+                        max(responses.max_time_offset_boot_id, event.boot_id);
+                } else {
+                    warn!("Got invalid host epoch event: {event:?}");
                 }
-                PedometerEventType::Steps(_) => event_queue.push_back(event),
-                PedometerEventType::Boot => {}
+            }
+            // The time references are received again together with the other events
+            if time_references_start.is_none() {
+                responses.event_queue.push_back(event);
             }
         }
-        let mut events_retain = Vec::with_capacity(event_queue.len());
-        for event in event_queue.iter() {
-            if let PedometerEventType::Steps(_) = event.event_type {
-                match device_time_offsets.get(&event.boot_id) {
-                    None if event.boot_id < *max_time_offset_boot_id => {
-                        warn!("Dropped step event because the device time offset could not be determined anymore: {event:?}");
-                        events_retain.push(false);
-                        continue;
-                    }
-                    None => {
-                        info!("Wait for timestamp");
-                        events_retain.push(true);
-                    }
-                    Some(offset) => {
-                        match PedometerPersistenceEvent::from_common_event(*event, *offset) {
-                            Ok(persistence_event) => {
-                                let (responder_tx, responder_rx) = oneshot::channel();
-                                info!("Send event to db: {persistence_event:?}");
-                                if let Err(e) = DB_CMD_TX
-                                    .get()
-                                    .unwrap()
-                                    .send(PedometerDatabaseCommand::AddEvent {
-                                        event: persistence_event,
-                                        responder: responder_tx,
-                                    })
-                                    .await
-                                {
-                                    warn!("Could not send event to database! ({e})");
-                                    events_retain.push(true);
-                                } else if let Err(e) = responder_rx.await {
-                                    warn!("Could not add event to db: {e}");
-                                    events_retain.push(false);
-                                } else {
-                                    events_retain.push(false);
-                                }
-                            }
-                            Err(e) => {
-                                warn!("Could not convert event: {event:?} -> {e}");
-                                events_retain.push(false);
-                            }
-                        }
-                    }
+        if received_events {
+            responses.transfer.next_request = Some(PedometerEventRequest {
+                boot_id: max_event_boot_id,
+                min_event_index: max_event_id + 1,
+            });
+        }
+        let corrupted = decode_error.is_some();
+        // Like after a lost chunk, the following events would leave a gap
+        responses.transfer.broken |= corrupted && responses.chunked;
+        match decode_error {
+            Some(error) => {
+                let position = responses.transfer.next_request;
+                let failures = match responses.decode_failures {
+                    Some(previous) if previous.position == position => previous.failures + 1,
+                    _ => 1,
+                };
+                responses.decode_failures = Some(PedometerDecodeFailures {
+                    position,
+                    failures,
+                    error,
+                });
+            }
+            None if received_events => responses.decode_failures = None,
+            None => {}
+        }
+        let next = match header {
+            // Without chunks every response is requested on its own until one is empty
+            None if received_events || corrupted => {
+                PedometerEventsNext::Request(responses.transfer.next_request)
+            }
+            None => PedometerEventsNext::Done,
+            Some(header) if !header.last => PedometerEventsNext::Wait,
+            Some(header) if header.incomplete || responses.transfer.broken => {
+                PedometerEventsNext::Request(responses.transfer.next_request)
+            }
+            Some(_) => PedometerEventsNext::Done,
+        };
+        let next = match (next, responses.decode_failures) {
+            (PedometerEventsNext::Request(_), Some(decode_failures))
+                if decode_failures.failures > MAX_DECODE_RETRIES =>
+            {
+                responses.decode_failures = None;
+                let position = decode_failures.position.map_or_else(
+                    || "the last synced event".to_string(),
+                    |position| format!("{position:?}"),
+                );
+                report_sync_error(match decode_failures.error {
+                    PedometerCommonError::Crc => format!(
+                        "The events after {position} were corrupted {} times in a row",
+                        decode_failures.failures
+                    ),
+                    PedometerCommonError::Postcard => format!(
+                        "The event after {position} cannot be decoded, \
+                        it may be from a newer firmware"
+                    ),
+                });
+                set_connection_state(PedometerConnectionState::Connected);
+                PedometerEventsNext::Failed
+            }
+            (next, _) => next,
+        };
+        if header.is_some_and(|header| header.last) {
+            responses.transfer.finish();
+        } else if next == PedometerEventsNext::Wait {
+            responses.transfer.waiting_since = Some(tokio::time::Instant::now());
+        }
+        if let Some(start) = time_references_start {
+            match next {
+                PedometerEventsNext::Wait | PedometerEventsNext::Failed => {}
+                PedometerEventsNext::Request(since) => {
+                    info!("Try to read more time references");
+                    request_more_events(since).await;
+                }
+                PedometerEventsNext::Done => {
+                    info!("Got all time references, read the events from {start:?}");
+                    *sync_phase.lock().unwrap() = PedometerSyncPhase::Events;
+                    request_more_events(Some(start)).await;
                 }
-            } else {
-                error!("This event should not be here! {event:?}");
             }
+            return;
         }
+        let mut batch = PedometerSyncBatch::default();
+        let device_time_offsets = &responses.device_time_offsets;
+        let max_time_offset_boot_id = responses.max_time_offset_boot_id;
+        responses
+            .event_queue
+            .retain(|event| match device_time_offsets.get(&event.boot_id) {
+                None if event.boot_id < max_time_offset_boot_id => {
+                    warn!("Dropped event because the device time offset could not be determined anymore: {event:?}");
+                    false
+                }
+                None => {
+                    info!("Wait for timestamp");
+                    true
+                }
+                Some(offset) => {
+                    match PedometerPersistenceRecord::from_common_event(*event, *offset) {
+                        Ok(record) => batch.records.push(record),
+                        Err(e) => warn!("Could not convert event: {event:?} -> {e}"),
+                    }
+                    false
+                }
+            });
         info!("Max event id: {max_event_id}");
+        if let PedometerEventsNext::Request(since) = next {
+            info!("Try to read more events");
+            request_more_events(since).await;
+        }
         if received_events {
-            info!("Notify gui about new events");
-            if let Err(e) = GUI_EVENT_TX
+            // Events that still wait for their time offset are requested again after an
+            // interruption, so only the events before them are confirmed
+            batch.sync_state = match responses.event_queue.front() {
+                Some(waiting) => waiting
+                    .index
+                    .checked_sub(1)
+                    .map(|event_id| (waiting.boot_id, event_id)),
+                None => Some((max_event_boot_id, max_event_id)),
+            }
+            .map(|(boot_id, last_event_id)| PedometerPersistenceSyncState {
+                boot_id: boot_id as i64,
+                last_event_id: last_event_id as i64,
+            });
+            GUI_EVENT_TX
                 .get()
                 .unwrap()
-                .send(crate::gui::PedometerGuiEvent::NewEvents)
-                .await
-            {
-                error!("Could not send gui new_events event: {e}");
+                .send(crate::gui::PedometerGuiEvent::SyncProgress {
+                    event_id: max_event_id,
+                    max_event_id: max(responses.device_max_event_id, max_event_id),
+                });
+        }
+        // The sync is finished once the last events were stored
+        batch.complete = next == PedometerEventsNext::Done;
+        if received_events || batch.complete {
+            // Only waits if the database fell behind by more than the buffer size
+            if let Err(e) = batch_tx.send(batch).await {
+                report_dropped_command(format!("Could not send events to sync writer! ({e})"));
             }
+        }
+    }
 
-            info!("Try to read more events");
-            let (resp_tx, _resp_rx) = oneshot::channel();
-            let _ = BLE_CMD_TX
-                .get()
-                .unwrap()
-                .send(PedometerDeviceHandlerCommand::RequestEvents {
-                    min_event_id: Some(max_event_id + 1),
-                    responder: resp_tx,
-                })
-                .await;
+    /// The device did not continue the transfer, e.g. because it could not notify its last
+    /// chunk. The events are requested again after the last one that was received.
+    pub(crate) async fn process_transfer_timeout(responses: &mut PedometerEventResponses) {
+        warn!("Event transfer timed out, request the events again");
+        let next_request = responses.transfer.next_request;
+        responses.transfer.finish();
+        request_more_events(next_request).await;
+    }
+
+    /// Store the batches of a sync in the database until the sender is dropped. Batches that
+    /// queued up while the previous one was stored are merged into one transaction.
+    pub(crate) async fn write_sync_batches(mut batch_rx: mpsc::Receiver<PedometerSyncBatch>) {
+        while let Some(mut batch) = batch_rx.recv().await {
+            while let Ok(next) = batch_rx.try_recv() {
+                batch.records.extend(next.records);
+                batch.sync_state = next.sync_state.or(batch.sync_state);
+                batch.complete |= next.complete;
+            }
+            if !batch.records.is_empty() || batch.sync_state.is_some() {
+                // Only once the sync caught up, the total changes with every batch
+                Self::store_sync_batch(batch.records, batch.sync_state, batch_rx.is_empty()).await;
+            }
+            if batch.complete {
+                finish_sync().await;
+            }
+        }
+    }
+
+    async fn store_sync_batch(
+        records: Vec<PedometerPersistenceRecord>,
+        sync_state: Option<PedometerPersistenceSyncState>,
+        update_progress: bool,
+    ) {
+        info!("Send {} events to db", records.len());
+        let (responder_tx, responder_rx) = oneshot::channel();
+        let command = PedometerDatabaseCommand::AddRecords {
+            records,
+            sync_state,
+            responder: responder_tx,
+        };
+        if let Err(e) = DB_CMD_TX.get().unwrap().send(command).await {
+            warn!("Could not send events to database! ({e})");
+            report_sync_error(e);
+            return;
+        }
+        match responder_rx.await {
+            Ok(Ok(())) => {
+                metrics::record_sync();
+                info!("Notify gui about new events");
+                GUI_EVENT_TX
+                    .get()
+                    .unwrap()
+                    .send(crate::gui::PedometerGuiEvent::NewEvents);
+                if update_progress {
+                    if let Err(e) = update_sync_progress().await {
+                        warn!("Could not update sync progress: {e}");
+                    }
+                }
+            }
+            Ok(Err(e)) => {
+                warn!("Could not add events to db: {e}");
+                report_sync_error(e);
+            }
+            Err(e) => {
+                warn!("Could not add events to db: {e}");
+                report_sync_error(e);
+            }
         }
-        debug!("Retain events: {event_queue:?} {events_retain:?}");
-        let mut retain_iter = events_retain.iter();
-        event_queue.retain(|_| *retain_iter.next().unwrap());
     }
 
     async fn is_connected(&self) -> anyhow::Result<bool> {
@@ -356,69 +1220,82 @@ impl PedometerDeviceHandler {
         })
     }
 
-    async fn request_events(&self, min_event_id: Option<u32>) -> anyhow::Result<()> {
+    async fn request_events(&self, since: Option<PedometerEventRequest>) -> anyhow::Result<()> {
         match &self.device {
             Some(device) if device.is_connected().await? => {
-                let min_event_id = match min_event_id {
-                    Some(min_event_id) => min_event_id,
-                    None => {
-                        let (responder_tx, responder_rx) = oneshot::channel();
-                        info!("Get last event from db");
-                        DB_CMD_TX
-                            .get()
-                            .unwrap()
-                            .send(PedometerDatabaseCommand::GetLastEvent {
-                                responder: responder_tx,
-                            })
-                            .await?;
-                        if let Some(last_db_event) = responder_rx.await?? {
+                let new_sync = since.is_none();
+                let since = match since {
+                    Some(since) => since,
+                    None => match get_last_synced_event().await? {
+                        Some(last_synced) => {
                             let current_boot_id = u32::from_le_bytes(
                                 device
-                                    .read(
-                                        &find_characteristic(device, CHARACTERISTIC_BOOT_ID)
-                                            .ok_or_else(|| {
-                                                anyhow!("Could not find boot_id characteristic")
-                                            })?,
-                                    )
+                                    .read(&get_characteristic(device, CHARACTERISTIC_BOOT_ID)?)
                                     .await?[..]
                                     .try_into()?,
                             );
                             let current_max_event_id = u32::from_le_bytes(
                                 device
-                                    .read(
-                                        &find_characteristic(device, CHARACTERISTIC_MAX_EVENT_ID)
-                                            .ok_or_else(|| {
-                                            anyhow!("Could not find boot_id characteristic")
-                                        })?,
-                                    )
+                                    .read(&get_characteristic(device, CHARACTERISTIC_MAX_EVENT_ID)?)
                                     .await?[..]
                                     .try_into()?,
                             );
-                            info!(
-                            "last_db_event: {:?}, current_boot_id: {}, current_max_event_id: {}",
-                            last_db_event, current_boot_id, current_max_event_id
-                        );
-                            if current_max_event_id as i64 >= last_db_event.event_id
-                                && current_boot_id as i64 >= last_db_event.boot_id
-                            {
-                                (last_db_event.event_id + 1).try_into()?
-                            } else {
-                                0
-                            }
-                        } else {
-                            0
+                            next_event_request(last_synced, current_boot_id, current_max_event_id)?
                         }
-                    }
+                        None => PedometerEventRequest::default(),
+                    },
                 };
-                info!("Request events from id {}", min_event_id);
-                device
-                    .write(
-                        &find_characteristic(device, CHARACTERISTIC_UUID_REQUEST_EVENTS)
-                            .ok_or_else(|| anyhow!("Could not find characteristic"))?,
-                        &min_event_id.to_le_bytes(),
-                        btleplug::api::WriteType::WithResponse,
-                    )
-                    .await?
+                info!("Request events from {since:?}");
+                if let Some(request_events_transfer_char) =
+                    find_characteristic(device, CHARACTERISTIC_UUID_REQUEST_EVENTS_TRANSFER)
+                {
+                    let query = event_query(&self.sync_phase, since, new_sync);
+                    info!("Transfer events: {query:?}");
+                    device
+                        .write(
+                            &request_events_transfer_char,
+                            &query.to_bytes(),
+                            btleplug::api::WriteType::WithResponse,
+                        )
+                        .await?;
+                    return Ok(());
+                }
+                if let Some(request_events_query_char) =
+                    find_characteristic(device, CHARACTERISTIC_UUID_REQUEST_EVENTS_QUERY)
+                {
+                    let query = event_query(&self.sync_phase, since, new_sync);
+                    info!("Query events: {query:?}");
+                    device
+                        .write(
+                            &request_events_query_char,
+                            &query.to_bytes(),
+                            btleplug::api::WriteType::WithResponse,
+                        )
+                        .await?;
+                    return Ok(());
+                }
+                match find_characteristic(device, CHARACTERISTIC_UUID_REQUEST_EVENTS_SINCE) {
+                    Some(request_events_since_char) => {
+                        device
+                            .write(
+                                &request_events_since_char,
+                                &since.to_bytes(),
+                                btleplug::api::WriteType::WithResponse,
+                            )
+                            .await?
+                    }
+                    None => {
+                        // Older firmware only supports requesting events by index
+                        device
+                            .write(
+                                &find_characteristic(device, CHARACTERISTIC_UUID_REQUEST_EVENTS)
+                                    .ok_or_else(|| anyhow!("Could not find characteristic"))?,
+                                &since.min_event_index.to_le_bytes(),
+                                btleplug::api::WriteType::WithResponse,
+                            )
+                            .await?
+                    }
+                }
             }
             Some(_) => Err(anyhow!("Not connected"))?,
             None => Err(anyhow!("Device not seen, yet"))?,
@@ -429,58 +1306,868 @@ impl PedometerDeviceHandler {
     async fn send_host_epoch(&self) -> anyhow::Result<()> {
         if let Some(device) = &self.device {
             info!("Send current time to device...");
-            let epoch_ms_char = find_characteristic(device, CHARACTERISTIC_UUID_EPOCH_MS)
-                .ok_or_else(|| anyhow!("Could not find characteristic"))?;
-            Ok(device
+            let epoch_offset_ms = match sync_time_round_trip(device).await {
+                Ok(epoch_offset_ms) => Some(epoch_offset_ms),
+                Err(e) => {
+                    // Older firmware only supports setting the time directly
+                    warn!("Could not synchronize time with round trip: {e}");
+                    set_epoch_ms(device).await?
+                }
+            };
+            *self.epoch_offset_ms.lock().unwrap() = epoch_offset_ms;
+
+            // Sent on every connect to keep up with DST changes
+            let utc_offset_minutes = utc_offset_minutes_at_next_midnight();
+            info!("Send UTC offset of {utc_offset_minutes}min to device...");
+            if let Err(e) = self
+                .write_config_value(PedometerConfigValue::UtcOffsetMinutes(utc_offset_minutes))
+                .await
+            {
+                warn!("Could not send UTC offset: {e}");
+            }
+        }
+        Ok(())
+    }
+
+    /// The device only notifies the fill level when it crosses a threshold, so it is read to show
+    /// the current one.
+    async fn read_storage_fill_percent(device: &Peripheral) -> anyhow::Result<u8> {
+        let fill_percent = *device
+            .read(&get_characteristic(
+                device,
+                CHARACTERISTIC_UUID_STORAGE_FILL_PERCENT,
+            )?)
+            .await?
+            .first()
+            .ok_or_else(|| anyhow!("Empty storage fill characteristic"))?;
+        GUI_EVENT_TX
+            .get()
+            .unwrap()
+            .send(crate::gui::PedometerGuiEvent::StorageFill(fill_percent));
+        Ok(fill_percent)
+    }
+
+    async fn read_storage_usage(device: &Peripheral) -> anyhow::Result<()> {
+        let usage = PedometerStorageUsage::from_bytes(
+            device
+                .read(&get_characteristic(
+                    device,
+                    CHARACTERISTIC_UUID_STORAGE_USAGE,
+                )?)
+                .await?[..]
+                .try_into()?,
+        );
+        info!("Storage usage: {usage:?}");
+        GUI_EVENT_TX
+            .get()
+            .unwrap()
+            .send(crate::gui::PedometerGuiEvent::StorageUsage(usage));
+        Ok(())
+    }
+
+    async fn check_storage_fill_percent(&self) -> anyhow::Result<()> {
+        if let Some(device) = &self.device {
+            let fill_percent = Self::read_storage_fill_percent(device).await?;
+            let config = PedometerConfig::deserialize(
+                &device
+                    .read(&get_characteristic(device, CHARACTERISTIC_UUID_CONFIG)?)
+                    .await?,
+            )?;
+            info!("Storage fill level: {fill_percent}%");
+            if config.storage_warning_level(fill_percent) > 0 {
+                GUI_EVENT_TX
+                    .get()
+                    .unwrap()
+                    .send(crate::gui::PedometerGuiEvent::StorageWarning(fill_percent));
+            }
+        }
+        Ok(())
+    }
+
+    async fn read_config(&self) -> anyhow::Result<()> {
+        if let Some(device) = &self.device {
+            let config = PedometerConfig::deserialize(
+                &device
+                    .read(&get_characteristic(device, CHARACTERISTIC_UUID_CONFIG)?)
+                    .await?,
+            )?;
+            info!("Device config: {config:?}");
+            GUI_EVENT_TX
+                .get()
+                .unwrap()
+                .send(crate::gui::PedometerGuiEvent::DeviceConfig(config));
+        }
+        Ok(())
+    }
+
+    /// Tell the user right away if a check of the power-on self test failed.
+    async fn check_self_test(&self) -> anyhow::Result<()> {
+        if let Some(device) = &self.device {
+            let self_test = PedometerSelfTest(
+                *device
+                    .read(&get_characteristic(device, CHARACTERISTIC_UUID_SELF_TEST)?)
+                    .await?
+                    .first()
+                    .ok_or_else(|| anyhow!("Empty self test characteristic"))?,
+            );
+            info!("Self test: {self_test:?}");
+            if !self_test.passed() {
+                GUI_EVENT_TX
+                    .get()
+                    .unwrap()
+                    .send(crate::gui::PedometerGuiEvent::SelfTestFailed(self_test));
+            }
+        }
+        Ok(())
+    }
+
+    async fn read_temperature(&self) -> anyhow::Result<()> {
+        if let Some(device) = &self.device {
+            let temperature = i16::from_le_bytes(
+                device
+                    .read(&get_characteristic(
+                        device,
+                        CHARACTERISTIC_UUID_TEMPERATURE,
+                    )?)
+                    .await?[..]
+                    .try_into()?,
+            );
+            info!("Temperature: {temperature} c°C");
+            GUI_EVENT_TX
+                .get()
+                .unwrap()
+                .send(crate::gui::PedometerGuiEvent::Temperature(temperature));
+        }
+        Ok(())
+    }
+
+    async fn read_firmware_version(&self) -> anyhow::Result<()> {
+        if let Some(device) = &self.device {
+            let firmware_version = PedometerFirmwareVersion::from_bytes(
+                device
+                    .read(&get_characteristic(
+                        device,
+                        CHARACTERISTIC_UUID_FIRMWARE_VERSION,
+                    )?)
+                    .await?
+                    .as_slice()
+                    .try_into()?,
+            );
+            info!("Firmware version: {firmware_version}");
+            GUI_EVENT_TX
+                .get()
+                .unwrap()
+                .send(crate::gui::PedometerGuiEvent::FirmwareVersion(
+                    firmware_version,
+                ));
+        }
+        Ok(())
+    }
+
+    /// Compare the clock of the device with the one of the host. The device only knows its
+    /// uptime, so its epoch is derived from the last time sync.
+    async fn read_device_clock(&self) -> anyhow::Result<PedometerDeviceClock> {
+        let device = self
+            .device
+            .as_ref()
+            .ok_or_else(|| anyhow!("Device not seen, yet"))?;
+        let epoch_offset_ms = self
+            .epoch_offset_ms
+            .lock()
+            .unwrap()
+            .ok_or_else(|| anyhow!("Device did not report its uptime at the last time sync"))?;
+        let (host_t1_ms, device_ms) = request_device_ms(device).await?;
+        let round_trip_ms = (Utc::now().timestamp_millis() as u64).saturating_sub(host_t1_ms);
+        Ok(PedometerDeviceClock {
+            device_epoch_ms: epoch_offset_ms + device_ms,
+            host_epoch_ms: host_t1_ms + round_trip_ms / 2,
+            round_trip_ms,
+        })
+    }
+
+    async fn read_diagnostics(&self) -> anyhow::Result<PedometerDiagnostics> {
+        match &self.device {
+            Some(device) if device.is_connected().await? => Ok(PedometerDiagnostics::deserialize(
+                &device
+                    .read(&get_characteristic(
+                        device,
+                        CHARACTERISTIC_UUID_DIAGNOSTICS,
+                    )?)
+                    .await?,
+            )?),
+            Some(_) => Err(anyhow!("Not connected")),
+            None => Err(anyhow!("Device not seen, yet")),
+        }
+    }
+
+    /// Delete the events up to the given index, or up to the last synced one, from the start of
+    /// the storage of the device. The device stops at the first event with a larger index, so
+    /// events of a newer boot with a smaller index may remain.
+    async fn delete_events(&self, max_event_id: Option<u32>) -> anyhow::Result<()> {
+        // Devices with sync cursors only delete what the other hosts synced, too
+        if let (None, Some(device)) = (max_event_id, &self.device) {
+            if let Some(ack_char) = find_characteristic(device, CHARACTERISTIC_UUID_ACK_EVENTS) {
+                let Some(cursor) = get_sync_cursor().await? else {
+                    info!("Nothing synced that could be deleted");
+                    return Ok(());
+                };
+                info!("Acknowledge events before {cursor:?} on device");
+                return Ok(device
+                    .write(
+                        &ack_char,
+                        &cursor.to_bytes(),
+                        btleplug::api::WriteType::WithResponse,
+                    )
+                    .await?);
+            }
+        }
+        let Some(max_event_id) = get_max_deletable_event_id(max_event_id).await? else {
+            info!("Nothing synced that could be deleted");
+            return Ok(());
+        };
+        match &self.device {
+            Some(device) if device.is_connected().await? => {
+                info!("Delete events up to {max_event_id} on device");
+                Ok(device
+                    .write(
+                        &get_characteristic(device, CHARACTERISTIC_UUID_DELETE_EVENTS)?,
+                        &(max_event_id + 1).to_le_bytes(),
+                        btleplug::api::WriteType::WithResponse,
+                    )
+                    .await?)
+            }
+            _ => Err(anyhow!("Device not connected")),
+        }
+    }
+
+    async fn delete_boot(&self, boot_id: u32) -> anyhow::Result<()> {
+        match &self.device {
+            Some(device) if device.is_connected().await? => {
+                info!("Delete events of boot {boot_id} on device");
+                Ok(device
+                    .write(
+                        &get_characteristic(device, CHARACTERISTIC_UUID_DELETE_BOOT)?,
+                        &boot_id.to_le_bytes(),
+                        btleplug::api::WriteType::WithResponse,
+                    )
+                    .await?)
+            }
+            _ => Err(anyhow!("Device not connected")),
+        }
+    }
+
+    async fn set_marker(&self, marker: PedometerMarker) -> anyhow::Result<()> {
+        if let Some(device) = &self.device {
+            if device.is_connected().await? {
+                if let Some(marker_char) = find_characteristic(device, CHARACTERISTIC_UUID_MARKER) {
+                    info!("Set marker {marker:?} on device");
+                    device
+                        .write(
+                            &marker_char,
+                            &[marker as u8],
+                            btleplug::api::WriteType::WithResponse,
+                        )
+                        .await?;
+                    // The device stores the marker before it handles the request
+                    return self.request_events(None).await;
+                }
+                warn!("Device does not support markers");
+            }
+        }
+
+        info!("Set marker {marker:?} on host");
+        let (responder_tx, responder_rx) = oneshot::channel();
+        DB_CMD_TX
+            .get()
+            .unwrap()
+            .send(PedometerDatabaseCommand::AddRecords {
+                records: vec![PedometerPersistenceRecord::Marker(
+                    PedometerPersistenceMarker::new_on_host(marker),
+                )],
+                sync_state: None,
+                responder: responder_tx,
+            })
+            .await?;
+        responder_rx.await??;
+        GUI_EVENT_TX
+            .get()
+            .unwrap()
+            .send(crate::gui::PedometerGuiEvent::NewEvents);
+        Ok(())
+    }
+
+    /// Summaries of all boots that are stored on the device. They are requested page by page
+    /// until the device reports no more boots.
+    async fn read_boot_summaries(&self) -> anyhow::Result<Vec<PedometerBootSummary>> {
+        let device = match &self.device {
+            Some(device) if device.is_connected().await? => device,
+            _ => return Err(anyhow!("Device not connected")),
+        };
+        let boot_summaries_char =
+            find_characteristic(device, CHARACTERISTIC_UUID_BOOT_SUMMARIES)
+                .ok_or_else(|| anyhow!("Device does not support the boot summaries"))?;
+        let mut boot_summaries = Vec::new();
+        let mut min_boot_id = 0_u32;
+        loop {
+            // Subscribe before the request so that the response cannot be missed
+            let mut notifications = device.notifications().await?;
+            device
                 .write(
-                    &epoch_ms_char,
-                    &((Utc::now().timestamp_millis()) as u64).to_le_bytes(),
+                    &boot_summaries_char,
+                    &min_boot_id.to_le_bytes(),
                     btleplug::api::WriteType::WithResponse,
                 )
-                .await?)
-        } else {
-            Ok(())
+                .await?;
+            let page = tokio::time::timeout(BOOT_SUMMARIES_TIMEOUT, async {
+                while let Some(notification) = notifications.next().await {
+                    if notification.uuid != CHARACTERISTIC_UUID_BOOT_SUMMARIES {
+                        continue;
+                    }
+                    match notification.value[..]
+                        .try_into()
+                        .ok()
+                        .and_then(PedometerBootSummaries::from_bytes)
+                    {
+                        Some(page) => return Some(page),
+                        None => warn!("Invalid boot summaries: {:?}", notification.value),
+                    }
+                }
+                None
+            })
+            .await?
+            .ok_or_else(|| anyhow!("Notification stream ended"))?;
+            boot_summaries.extend(page.summaries.iter().copied());
+            match page.summaries.last() {
+                Some(last) if page.more => min_boot_id = last.boot_id + 1,
+                _ => break,
+            }
+        }
+        info!("Boots on the device: {}", boot_summaries.len());
+        Ok(boot_summaries)
+    }
+
+    async fn stream_acceleration(&self, enabled: bool) -> anyhow::Result<()> {
+        match &self.device {
+            Some(device) if device.is_connected().await? => {
+                let acceleration_char =
+                    find_characteristic(device, CHARACTERISTIC_UUID_ACCELERATION).ok_or_else(
+                        || anyhow!("Device does not support the acceleration stream"),
+                    )?;
+                info!("Stream acceleration: {enabled}");
+                Ok(device
+                    .write(
+                        &acceleration_char,
+                        &[enabled as u8],
+                        btleplug::api::WriteType::WithResponse,
+                    )
+                    .await?)
+            }
+            _ => Err(anyhow!("Device not connected")),
+        }
+    }
+
+    async fn write_config(&self, values: &[PedometerConfigValue]) -> anyhow::Result<()> {
+        match &self.device {
+            Some(device) if device.is_connected().await? => {
+                for value in values {
+                    info!("Write config value {value:?}");
+                    self.write_config_value(*value).await?;
+                }
+                Ok(())
+            }
+            _ => Err(anyhow!("Device not connected")),
+        }
+    }
+
+    async fn write_config_value(&self, value: PedometerConfigValue) -> anyhow::Result<()> {
+        match &self.device {
+            Some(device) => Ok(device
+                .write(
+                    &get_characteristic(device, CHARACTERISTIC_UUID_CONFIG)?,
+                    &value.serialize_for_characteristic()?,
+                    btleplug::api::WriteType::WithResponse,
+                )
+                .await?),
+            None => Err(anyhow!("Device not seen, yet")),
         }
     }
 }
 
 #[allow(unused)]
 pub(crate) enum PedometerDeviceHandlerCommand {
+    /// Connect directly to the device with the given address if it is known, otherwise scan for
+    /// any pedometer
     TryConnect {
+        address: Option<String>,
+        scan_policy: ScanPolicy,
         responder: oneshot::Sender<anyhow::Result<()>>,
     },
     IsConnected {
         responder: oneshot::Sender<anyhow::Result<bool>>,
     },
+    /// Request the events from the given position on or after the last synced event
     RequestEvents {
-        min_event_id: Option<u32>,
+        since: Option<PedometerEventRequest>,
         responder: oneshot::Sender<anyhow::Result<()>>,
     },
+    /// Delete the events up to the given index or up to the last synced one on the device
     DeleteEvents {
         max_event_id: Option<u32>,
         responder: oneshot::Sender<anyhow::Result<()>>,
     },
+    /// Delete all events of an old boot on the device
+    DeleteBoot {
+        boot_id: u32,
+        responder: oneshot::Sender<anyhow::Result<()>>,
+    },
+    /// Write the given config values to the device one after another
+    WriteConfig {
+        values: Vec<PedometerConfigValue>,
+        responder: oneshot::Sender<anyhow::Result<()>>,
+    },
+    /// Store a marker on the device or on the host if the device is not connected
+    SetMarker {
+        marker: PedometerMarker,
+        responder: oneshot::Sender<anyhow::Result<()>>,
+    },
     Disconnect {
         responder: oneshot::Sender<Result<(), anyhow::Error>>,
     },
+    ReadDiagnostics {
+        responder: oneshot::Sender<anyhow::Result<PedometerDiagnostics>>,
+    },
+    /// Compare the clock of the device with the host clock
+    ReadDeviceClock {
+        responder: oneshot::Sender<anyhow::Result<PedometerDeviceClock>>,
+    },
+    /// Send the host time to the device again
+    ResyncTime {
+        responder: oneshot::Sender<anyhow::Result<()>>,
+    },
+    /// Summarize the boots that are stored on the device
+    ReadBootSummaries {
+        responder: oneshot::Sender<anyhow::Result<Vec<PedometerBootSummary>>>,
+    },
+    /// Start or stop the notifications of the raw accelerometer samples
+    StreamAcceleration {
+        enabled: bool,
+        responder: oneshot::Sender<anyhow::Result<()>>,
+    },
+    /// Periodically scan for the live data in the advertisements without connecting
+    StartListening {
+        responder: oneshot::Sender<anyhow::Result<()>>,
+    },
+    StopListening {
+        responder: oneshot::Sender<anyhow::Result<()>>,
+    },
+    /// The adapter disappeared, e.g. because the dongle was unplugged
+    AdapterRemoved,
     Exit,
 }
 
-async fn find_device(central: &Adapter) -> anyhow::Result<Option<Peripheral>> {
-    for p in central.peripherals().await? {
-        if let Some(pp) = p.properties().await? {
-            if pp
-                .local_name
-                .iter()
-                .any(|name| name.contains(PERIPHERAL_NAME_MATCH_FILTER))
+/// Reset the handler when the adapter disappears and tell the gui when one is available again.
+async fn watch_adapter() {
+    let mut available = true;
+    loop {
+        if let Ok(adapter) = get_adapter().await {
+            if let Err(e) = wait_for_adapter_removal(&adapter, &mut available).await {
+                warn!("Could not watch adapter: {e}");
+            }
+        }
+        if available {
+            available = false;
+            warn!("Adapter was removed");
+            if let Err(e) = BLE_CMD_TX
+                .get()
+                .unwrap()
+                .send(PedometerDeviceHandlerCommand::AdapterRemoved)
+                .await
             {
-                return Ok(Some(p));
+                report_dropped_command(format!("Could not reset device handler! ({e})"));
+            }
+            GUI_EVENT_TX
+                .get()
+                .unwrap()
+                .send(crate::gui::PedometerGuiEvent::AdapterRemoved);
+        }
+        tokio::time::sleep(ADAPTER_POLL_INTERVAL).await;
+    }
+}
+
+/// Return once the adapter is powered off or gone. Reports a returned adapter to the gui first.
+async fn wait_for_adapter_removal(adapter: &Adapter, available: &mut bool) -> anyhow::Result<()> {
+    let mut events = adapter.events().await?;
+    if adapter.adapter_state().await? == CentralState::PoweredOff {
+        return Ok(());
+    }
+    if !*available {
+        *available = true;
+        info!(
+            "Adapter is available again: {}",
+            adapter.adapter_info().await?
+        );
+        GUI_EVENT_TX
+            .get()
+            .unwrap()
+            .send(crate::gui::PedometerGuiEvent::AdapterAvailable);
+    }
+    let mut poll_interval = tokio::time::interval(ADAPTER_POLL_INTERVAL);
+    loop {
+        tokio::select! {
+            event = events.next() => match event {
+                Some(CentralEvent::StateUpdate(CentralState::PoweredOff)) | None => return Ok(()),
+                Some(_) => {}
+            },
+            _ = poll_interval.tick() => {
+                // A removed adapter is reported as powered off
+                if adapter.adapter_state().await? == CentralState::PoweredOff {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+/// Show the steps of today in the ongoing notification, see [`SYNC_PROGRESS_TARGET`].
+async fn update_sync_progress() -> anyhow::Result<()> {
+    let target = SYNC_PROGRESS_TARGET.load(Ordering::Relaxed);
+    if target == 0 {
+        return Ok(());
+    }
+    let today = Local::now().date_naive();
+    let (responder_tx, responder_rx) = oneshot::channel();
+    DB_CMD_TX
+        .get()
+        .unwrap()
+        .send(PedometerDatabaseCommand::GetDailyTotals {
+            first_day: today,
+            last_day: today,
+            responder: responder_tx,
+        })
+        .await?;
+    let steps: i64 = responder_rx.await??.iter().map(|total| total.steps).sum();
+    notifications::show_sync_progress(steps.try_into().unwrap_or_default(), target);
+    Ok(())
+}
+
+/// Request the events from the given position or start a new sync from the last synced event if
+/// it is unknown.
+async fn request_more_events(since: Option<PedometerEventRequest>) {
+    let (resp_tx, _resp_rx) = oneshot::channel();
+    let _ = BLE_CMD_TX
+        .get()
+        .unwrap()
+        .send(PedometerDeviceHandlerCommand::RequestEvents {
+            since,
+            responder: resp_tx,
+        })
+        .await;
+}
+
+/// The device sent all events and they were stored.
+async fn finish_sync() {
+    set_connection_state(PedometerConnectionState::Connected);
+    if DELETE_AFTER_SYNC.load(Ordering::Relaxed) {
+        let (resp_tx, _resp_rx) = oneshot::channel();
+        if let Err(e) = BLE_CMD_TX
+            .get()
+            .unwrap()
+            .send(PedometerDeviceHandlerCommand::DeleteEvents {
+                max_event_id: None,
+                responder: resp_tx,
+            })
+            .await
+        {
+            report_dropped_command(format!("Could not delete events after sync! ({e})"));
+        }
+    }
+}
+
+/// Boot and index of the last synced event according to the database.
+pub(crate) async fn get_last_synced_event() -> anyhow::Result<Option<(i64, i64)>> {
+    let (responder_tx, responder_rx) = oneshot::channel();
+    info!("Get sync state from db");
+    DB_CMD_TX
+        .get()
+        .unwrap()
+        .send(PedometerDatabaseCommand::GetSyncState {
+            responder: responder_tx,
+        })
+        .await?;
+    if let Some(sync_state) = responder_rx.await?? {
+        return Ok(Some((sync_state.boot_id, sync_state.last_event_id)));
+    }
+    // Databases from before the sync state only know the step events
+    let (responder_tx, responder_rx) = oneshot::channel();
+    info!("Get last event from db");
+    DB_CMD_TX
+        .get()
+        .unwrap()
+        .send(PedometerDatabaseCommand::GetLastEvent {
+            responder: responder_tx,
+        })
+        .await?;
+    Ok(responder_rx
+        .await??
+        .map(|last_db_event| (last_db_event.boot_id, last_db_event.event_id)))
+}
+
+/// Position after the last synced event. All events are requested if the device does not know
+/// it anymore, e.g. because its storage was erased.
+pub(crate) fn next_event_request(
+    (last_boot_id, last_event_id): (i64, i64),
+    current_boot_id: u32,
+    current_max_event_id: u32,
+) -> anyhow::Result<PedometerEventRequest> {
+    info!(
+        "last_boot_id: {}, last_event_id: {}, current_boot_id: {}, current_max_event_id: {}",
+        last_boot_id, last_event_id, current_boot_id, current_max_event_id
+    );
+    Ok(
+        if current_max_event_id as i64 >= last_event_id && current_boot_id as i64 >= last_boot_id {
+            PedometerEventRequest {
+                boot_id: last_boot_id.try_into()?,
+                min_event_index: (last_event_id + 1).try_into()?,
             }
+        } else {
+            PedometerEventRequest::default()
+        },
+    )
+}
+
+/// The given index or the index of the last synced event, because only events that were already
+/// stored in the database may be deleted.
+pub(crate) async fn get_max_deletable_event_id(
+    max_event_id: Option<u32>,
+) -> anyhow::Result<Option<u32>> {
+    if max_event_id.is_some() {
+        return Ok(max_event_id);
+    }
+    let (responder_tx, responder_rx) = oneshot::channel();
+    DB_CMD_TX
+        .get()
+        .unwrap()
+        .send(PedometerDatabaseCommand::GetSyncState {
+            responder: responder_tx,
+        })
+        .await?;
+    Ok(responder_rx
+        .await??
+        .map(|sync_state| u32::try_from(sync_state.last_event_id))
+        .transpose()?)
+}
+
+/// Cursor of this app behind the last synced event, up to which the device may delete the events.
+pub(crate) async fn get_sync_cursor() -> anyhow::Result<Option<PedometerSyncCursor>> {
+    get_last_synced_event()
+        .await?
+        .map(|(boot_id, last_event_id)| {
+            Ok(PedometerSyncCursor {
+                host_id: HOST_ID.load(Ordering::Relaxed),
+                boot_id: boot_id.try_into()?,
+                min_event_index: u32::try_from(last_event_id)? + 1,
+            })
+        })
+        .transpose()
+}
+
+async fn get_adapter() -> anyhow::Result<Adapter> {
+    let manager = Manager::new().await?;
+    let adapter_list = manager.adapters().await?;
+    match adapter_list.into_iter().next() {
+        Some(adapter) => Ok(adapter),
+        None => {
+            error!("Could not find any adapters");
+            Err(PedometerGuiError::DeviceNotFound(PedometerNotFoundCause::NoAdapter).into())
+        }
+    }
+}
+
+/// Tell apart an empty scan from one that only found other devices.
+async fn diagnose_not_found(adapter: &Adapter) -> PedometerNotFoundCause {
+    let peripherals = match adapter.peripherals().await {
+        Ok(peripherals) => peripherals,
+        Err(e) => {
+            warn!("Could not get the scanned devices: {e}");
+            return PedometerNotFoundCause::NoDevices;
+        }
+    };
+    if peripherals.is_empty() {
+        return PedometerNotFoundCause::NoDevices;
+    }
+    let mut names = Vec::new();
+    for peripheral in peripherals {
+        if let Ok(Some(properties)) = peripheral.properties().await {
+            names.push(
+                properties
+                    .local_name
+                    .unwrap_or_else(|| properties.address.to_string()),
+            );
+        }
+    }
+    names.sort();
+    names.dedup();
+    PedometerNotFoundCause::NameMismatch { names }
+}
+
+async fn find_device(central: &Adapter) -> anyhow::Result<Option<Peripheral>> {
+    for p in central.peripherals().await? {
+        if is_pedometer(&p).await? {
+            return Ok(Some(p));
         }
     }
     Ok(None)
 }
 
+/// Find a device that was connected before by its address without scanning.
+async fn find_known_device(central: &Adapter, address: &str) -> anyhow::Result<Option<Peripheral>> {
+    for p in central.peripherals().await? {
+        if p.id().to_string() == address {
+            return Ok(Some(p));
+        }
+    }
+    Ok(None)
+}
+
+async fn is_pedometer(peripheral: &Peripheral) -> anyhow::Result<bool> {
+    Ok(peripheral.properties().await?.is_some_and(|properties| {
+        properties
+            .local_name
+            .iter()
+            .any(|name| name.contains(PERIPHERAL_NAME_MATCH_FILTER))
+    }))
+}
+
+/// Scan for [`LISTEN_SCAN_DURATION`] and forward the live data of all advertisements to the gui.
+async fn listen_for_live_data(adapter: &Adapter) -> anyhow::Result<()> {
+    let mut events = adapter.events().await?;
+    adapter.start_scan(ScanFilter::default()).await?;
+    let _ = tokio::time::timeout(LISTEN_SCAN_DURATION, async {
+        while let Some(event) = events.next().await {
+            let CentralEvent::ManufacturerDataAdvertisement {
+                id,
+                manufacturer_data,
+            } = event
+            else {
+                continue;
+            };
+            let Some(live_data) = manufacturer_data
+                .get(&ADVERTISING_COMPANY_ID)
+                .and_then(|data| PedometerAdvertisingData::from_bytes(data))
+            else {
+                continue;
+            };
+            // The company id is reserved for testing, so other devices may use it as well
+            match adapter.peripheral(&id).await {
+                Ok(peripheral) if is_pedometer(&peripheral).await.unwrap_or(false) => {
+                    debug!("Received live data: {live_data:?}");
+                    GUI_EVENT_TX
+                        .get()
+                        .unwrap()
+                        .send(crate::gui::PedometerGuiEvent::LiveData(live_data));
+                }
+                _ => debug!("Ignore manufacturer data of {id:?}"),
+            }
+        }
+    })
+    .await;
+    adapter.stop_scan().await?;
+    Ok(())
+}
+
+/// Set the device time directly, which older firmware without [`PedometerTimeSync`] requires.
+///
+/// Returns the resulting epoch offset of the device if it answered with its uptime.
+async fn set_epoch_ms(device: &Peripheral) -> anyhow::Result<Option<u64>> {
+    let epoch_ms_char = find_characteristic(device, CHARACTERISTIC_UUID_EPOCH_MS)
+        .ok_or_else(|| anyhow!("Could not find characteristic"))?;
+    // Subscribe before the write so that the answer cannot be missed
+    let mut notifications = device.notifications().await?;
+    let epoch_ms = Utc::now().timestamp_millis() as u64;
+    device
+        .write(
+            &epoch_ms_char,
+            &epoch_ms.to_le_bytes(),
+            btleplug::api::WriteType::WithResponse,
+        )
+        .await?;
+    let device_ms = tokio::time::timeout(TIME_SYNC_TIMEOUT, async {
+        while let Some(notification) = notifications.next().await {
+            if notification.uuid == CHARACTERISTIC_UUID_EPOCH_MS {
+                return notification.value[..]
+                    .try_into()
+                    .ok()
+                    .map(u64::from_le_bytes);
+            }
+        }
+        None
+    })
+    .await
+    .ok()
+    .flatten();
+    info!("Device uptime at time sync: {device_ms:?}");
+    Ok(device_ms.map(|device_ms| epoch_ms.saturating_sub(device_ms)))
+}
+
+/// Ask the device for its uptime. Returns the host time of the request and the answer.
+async fn request_device_ms(device: &Peripheral) -> anyhow::Result<(u64, u64)> {
+    let time_sync_char = get_characteristic(device, CHARACTERISTIC_UUID_TIME_SYNC)?;
+    // Subscribe before the request so that the response cannot be missed
+    let mut notifications = device.notifications().await?;
+    let host_t1_ms = Utc::now().timestamp_millis() as u64;
+    device
+        .write(
+            &time_sync_char,
+            &PedometerTimeSync::Request { host_t1_ms }.serialize_for_characteristic()?,
+            btleplug::api::WriteType::WithResponse,
+        )
+        .await?;
+    let device_ms = tokio::time::timeout(TIME_SYNC_TIMEOUT, async {
+        while let Some(notification) = notifications.next().await {
+            if notification.uuid != CHARACTERISTIC_UUID_TIME_SYNC {
+                continue;
+            }
+            match PedometerTimeSync::deserialize(&notification.value) {
+                Ok(PedometerTimeSync::Response {
+                    host_t1_ms: response_t1_ms,
+                    device_ms,
+                }) if response_t1_ms == host_t1_ms => return Some(device_ms),
+                response => warn!("Unexpected time sync response: {response:?}"),
+            }
+        }
+        None
+    })
+    .await?
+    .ok_or_else(|| anyhow!("Notification stream ended"))?;
+    Ok((host_t1_ms, device_ms))
+}
+
+/// Synchronize the device time with compensation of the transmission delay, see
+/// [`PedometerTimeSync`].
+///
+/// Returns the resulting difference between the epoch and the uptime of the device.
+async fn sync_time_round_trip(device: &Peripheral) -> anyhow::Result<u64> {
+    let (host_t1_ms, device_ms) = request_device_ms(device).await?;
+    let host_t4_ms = Utc::now().timestamp_millis() as u64;
+    let round_trip_ms = host_t4_ms.saturating_sub(host_t1_ms);
+    let host_epoch_ms = host_t1_ms + round_trip_ms / 2;
+    info!(
+        "Time sync round trip took {round_trip_ms}ms, device time {device_ms}ms is {host_epoch_ms}"
+    );
+    device
+        .write(
+            &get_characteristic(device, CHARACTERISTIC_UUID_TIME_SYNC)?,
+            &PedometerTimeSync::Result {
+                device_ms,
+                host_epoch_ms,
+            }
+            .serialize_for_characteristic()?,
+            btleplug::api::WriteType::WithResponse,
+        )
+        .await?;
+    Ok(host_epoch_ms.saturating_sub(device_ms))
+}
+
 fn find_characteristic(peripheral: &Peripheral, uuid: Uuid) -> Option<Characteristic> {
     for c in peripheral.characteristics() {
         debug!("Characteristic: {:?}", c);
@@ -490,3 +2177,20 @@ fn find_characteristic(peripheral: &Peripheral, uuid: Uuid) -> Option<Characteri
     }
     None
 }
+
+/// UTC offset at the next local midnight, so that the daily rollover on the device already takes
+/// a DST change during the day into account.
+fn utc_offset_minutes_at_next_midnight() -> i16 {
+    let now = Local::now();
+    let next_midnight = (now.date_naive() + Days::new(1)).and_time(NaiveTime::MIN);
+    let offset_seconds = match next_midnight.and_local_timezone(Local).earliest() {
+        Some(next_midnight) => next_midnight.offset().local_minus_utc(),
+        None => now.offset().local_minus_utc(),
+    };
+    (offset_seconds / 60) as i16
+}
+
+fn get_characteristic(peripheral: &Peripheral, uuid: Uuid) -> anyhow::Result<Characteristic> {
+    find_characteristic(peripheral, uuid)
+        .ok_or_else(|| anyhow!("Could not find characteristic: {uuid}"))
+}
@@ -0,0 +1,61 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// Window over which the cadence is averaged. The device reports the steps in batches of a few
+/// steps, so shorter windows make the cadence jump.
+const CADENCE_WINDOW: Duration = Duration::from_secs(15);
+/// The cadence is unknown until the daily steps were received for at least this duration.
+const CADENCE_MIN_DURATION: Duration = Duration::from_secs(3);
+
+/// Current cadence estimated from successive daily step counts of the device.
+#[derive(Debug, Default)]
+pub(crate) struct PedometerCadence {
+    /// Reception time and daily steps. The oldest sample is the last one before the window, so
+    /// that it holds the steps at the start of the window.
+    samples: VecDeque<(Instant, u32)>,
+}
+
+impl PedometerCadence {
+    pub(crate) fn add(&mut self, received: Instant, daily_steps: u32) {
+        // The daily steps are reset at midnight
+        if self
+            .samples
+            .back()
+            .is_some_and(|(_, last_steps)| daily_steps < *last_steps)
+        {
+            self.samples.clear();
+        }
+        self.samples.push_back((received, daily_steps));
+        self.remove_old_samples(received);
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    /// Steps per minute within the window before `now`.
+    pub(crate) fn steps_per_minute(&mut self, now: Instant) -> Option<f64> {
+        self.remove_old_samples(now);
+        let (first_received, first_steps) = *self.samples.front()?;
+        let (_, last_steps) = *self.samples.back()?;
+        let duration = now
+            .saturating_duration_since(first_received)
+            .min(CADENCE_WINDOW);
+        if duration < CADENCE_MIN_DURATION {
+            return None;
+        }
+        Some(f64::from(last_steps - first_steps) * 60.0 / duration.as_secs_f64())
+    }
+
+    fn remove_old_samples(&mut self, now: Instant) {
+        while self
+            .samples
+            .get(1)
+            .is_some_and(|(received, _)| now.saturating_duration_since(*received) >= CADENCE_WINDOW)
+        {
+            self.samples.pop_front();
+        }
+    }
+}
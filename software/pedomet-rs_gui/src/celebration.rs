@@ -0,0 +1,101 @@
+//! Confetti on the overview when the daily target is reached while the app is open.
+
+use std::{
+    f32::consts::TAU,
+    hash::{BuildHasher, RandomState},
+    time::{Duration, Instant},
+};
+
+use egui::{Color32, Painter, Pos2, Rect, Vec2};
+
+const CELEBRATION_DURATION: Duration = Duration::from_millis(3000);
+/// The confetti fades out during the last part of the animation
+const CELEBRATION_FADE_OUT: Duration = Duration::from_millis(1000);
+const CONFETTI_COUNT: usize = 120;
+const CONFETTI_COLORS: [Color32; 6] = [
+    Color32::from_rgb(0xe5, 0x39, 0x35),
+    Color32::from_rgb(0xfb, 0x8c, 0x00),
+    Color32::from_rgb(0xfd, 0xd8, 0x35),
+    Color32::from_rgb(0x43, 0xa0, 0x47),
+    Color32::from_rgb(0x1e, 0x88, 0xe5),
+    Color32::from_rgb(0x8e, 0x24, 0xaa),
+];
+/// Downward acceleration in heights of the painted area per second²
+const GRAVITY: f32 = 1.2;
+
+#[derive(Debug, Copy, Clone)]
+struct Confetti {
+    /// Start position relative to the painted area
+    start: Vec2,
+    /// Relative to the painted area per second
+    velocity: Vec2,
+    /// Rotations per second
+    spin: f32,
+    size: f32,
+    color: Color32,
+}
+
+impl Confetti {
+    fn position(&self, rect: Rect, t: f32) -> Pos2 {
+        let relative = self.start + self.velocity * t + Vec2::new(0.0, GRAVITY * t * t / 2.0);
+        rect.min + relative * rect.size()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct PedometerCelebration {
+    start: Instant,
+    confetti: Vec<Confetti>,
+}
+
+impl PedometerCelebration {
+    /// Confetti that is shot up from both bottom corners.
+    pub(crate) fn new(start: Instant) -> Self {
+        let state = RandomState::new();
+        let mut counter = 0_u64;
+        let mut random = || {
+            counter += 1;
+            (state.hash_one(counter) % 10_000) as f32 / 10_000.0
+        };
+        let confetti = (0..CONFETTI_COUNT)
+            .map(|i| {
+                let left = i % 2 == 0;
+                let angle = 0.15 + random() * 0.25;
+                let speed = 0.9 + random() * 0.6;
+                Confetti {
+                    start: Vec2::new(if left { 0.0 } else { 1.0 }, 1.0),
+                    velocity: Vec2::new(if left { angle } else { -angle } * speed, -speed * 1.1),
+                    spin: 0.5 + random() * 2.0,
+                    size: 3.0 + random() * 4.0,
+                    color: CONFETTI_COLORS[i % CONFETTI_COLORS.len()],
+                }
+            })
+            .collect();
+        Self { start, confetti }
+    }
+
+    pub(crate) fn is_finished(&self, now: Instant) -> bool {
+        now.duration_since(self.start) >= CELEBRATION_DURATION
+    }
+
+    pub(crate) fn paint(&self, painter: &Painter, rect: Rect, now: Instant) {
+        let elapsed = now.duration_since(self.start);
+        let t = elapsed.as_secs_f32();
+        let alpha = (CELEBRATION_DURATION.saturating_sub(elapsed).as_secs_f32()
+            / CELEBRATION_FADE_OUT.as_secs_f32())
+        .min(1.0);
+        for confetti in &self.confetti {
+            let center = confetti.position(rect, t);
+            if !rect.contains(center) {
+                continue;
+            }
+            // Flipping confetti looks narrower from the side
+            let width = confetti.size * (confetti.spin * t * TAU).cos().abs().max(0.2);
+            painter.rect_filled(
+                Rect::from_center_size(center, Vec2::new(width, confetti.size)),
+                0.0,
+                confetti.color.gamma_multiply(alpha),
+            );
+        }
+    }
+}
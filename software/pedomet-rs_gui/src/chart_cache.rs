@@ -0,0 +1,114 @@
+//! Bars of the charts by the selected date, so that they are neither computed again on every
+//! frame nor when navigating back to a date whose data did not change.
+
+use std::collections::HashMap;
+
+use chrono::NaiveDate;
+
+use crate::settings::Language;
+
+/// Number of dates that are cached per chart. The cache is cleared when it is full.
+const MAX_CACHED_DATES: usize = 64;
+
+/// Everything the bars of a chart depend on besides the data of the selected date.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct PedometerChartKey {
+    pub selected_date: NaiveDate,
+    /// See [`crate::persistence::DB_GENERATION`]
+    pub db_generation: u64,
+    pub day_start_hour: u8,
+    /// The bars are named by the formatted day
+    pub language: Language,
+}
+
+impl PedometerChartKey {
+    fn is_same_data(&self, other: &Self) -> bool {
+        Self {
+            selected_date: other.selected_date,
+            ..*self
+        } == *other
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct PedometerChartCache<V> {
+    entries: HashMap<PedometerChartKey, V>,
+}
+
+impl<V> Default for PedometerChartCache<V> {
+    fn default() -> Self {
+        Self {
+            entries: HashMap::new(),
+        }
+    }
+}
+
+impl<V: Clone> PedometerChartCache<V> {
+    /// Cached value of the key or the computed one. The computed value is only cached if it
+    /// belongs to the selected date, i.e. if no request for its data is pending.
+    pub(crate) fn get_or_compute(
+        &mut self,
+        key: PedometerChartKey,
+        pending: bool,
+        compute: impl FnOnce() -> Option<V>,
+    ) -> Option<V> {
+        if let Some(value) = self.entries.get(&key) {
+            return Some(value.clone());
+        }
+        let value = compute()?;
+        if !pending {
+            // Entries of other data can never be hit again
+            self.entries.retain(|cached, _| cached.is_same_data(&key));
+            if self.entries.len() >= MAX_CACHED_DATES {
+                self.entries.clear();
+            }
+            self.entries.insert(key, value.clone());
+        }
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(selected_date: &str, db_generation: u64) -> PedometerChartKey {
+        PedometerChartKey {
+            selected_date: selected_date.parse().unwrap(),
+            db_generation,
+            day_start_hour: 0,
+            language: Language::default(),
+        }
+    }
+
+    #[test]
+    fn computed_again_only_for_new_data() {
+        let mut cache = PedometerChartCache::default();
+        assert_eq!(
+            cache.get_or_compute(key("2024-03-03", 1), true, || Some(1)),
+            Some(1)
+        );
+        // Pending data is not cached
+        assert_eq!(
+            cache.get_or_compute(key("2024-03-03", 1), false, || Some(2)),
+            Some(2)
+        );
+        assert_eq!(
+            cache.get_or_compute(key("2024-03-02", 1), false, || Some(3)),
+            Some(3)
+        );
+        assert_eq!(
+            cache.get_or_compute(key("2024-03-03", 1), false, || None),
+            Some(2)
+        );
+        assert_eq!(
+            cache.get_or_compute(key("2024-03-03", 2), false, || Some(4)),
+            Some(4)
+        );
+        assert_eq!(cache.entries.len(), 1);
+        assert_eq!(
+            cache.get_or_compute(key("2024-03-02", 2), false, || None),
+            None
+        );
+    }
+}
@@ -0,0 +1,107 @@
+use std::{collections::BTreeSet, fmt::Write};
+
+use chrono::{Local, NaiveDate};
+use strum::{EnumIter, IntoEnumIterator};
+
+/// Kind of a stored record in the event list of the debug view. The discriminant identifies the
+/// table in the query.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, EnumIter, strum::Display)]
+pub(crate) enum PedometerDebugEventType {
+    #[strum(to_string = "Schritte")]
+    Steps = 0,
+    #[strum(to_string = "Tageszusammenfassung")]
+    DailySummary = 1,
+    #[strum(to_string = "Gerätefehler")]
+    DeviceError = 2,
+    #[strum(to_string = "Zeitabgleich")]
+    HostEpoch = 3,
+    #[strum(to_string = "Markierung")]
+    Marker = 4,
+    #[strum(to_string = "Selbsttest")]
+    SelfTest = 5,
+    #[strum(to_string = "Speicherwartung")]
+    Maintenance = 6,
+    #[strum(to_string = "Sensormesswert")]
+    SensorReading = 7,
+    #[strum(to_string = "Aktivität")]
+    Activity = 8,
+    #[strum(to_string = "Temperatur")]
+    Temperature = 9,
+}
+
+impl PedometerDebugEventType {
+    pub(crate) fn from_discriminant(discriminant: i64) -> Option<Self> {
+        Self::iter().find(|event_type| *event_type as i64 == discriminant)
+    }
+}
+
+/// Where a record was created. Only markers can be set on the host while the device is not
+/// connected.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, EnumIter, strum::Display)]
+pub(crate) enum PedometerDebugEventSource {
+    #[strum(to_string = "Schrittzähler")]
+    Device,
+    #[strum(to_string = "App")]
+    Host,
+}
+
+/// Stored record of any type with its type specific values summarized in `details`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct PedometerDebugEvent {
+    pub event_type: PedometerDebugEventType,
+    pub source: PedometerDebugEventSource,
+    pub event_id: Option<i64>,
+    pub boot_id: Option<i64>,
+    pub timestamp_ms: i64,
+    pub details: String,
+}
+
+/// Filters of the event list in the debug view. The days are queried from the database, the
+/// other filters are applied to the result, so they can be changed without a new query.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct PedometerDebugEventFilter {
+    pub first_day: NaiveDate,
+    pub last_day: NaiveDate,
+    pub boot_id: Option<i64>,
+    pub event_types: BTreeSet<PedometerDebugEventType>,
+    pub source: Option<PedometerDebugEventSource>,
+}
+
+impl Default for PedometerDebugEventFilter {
+    fn default() -> Self {
+        let today = Local::now().date_naive();
+        Self {
+            first_day: today,
+            last_day: today,
+            boot_id: None,
+            event_types: PedometerDebugEventType::iter().collect(),
+            source: None,
+        }
+    }
+}
+
+impl PedometerDebugEventFilter {
+    pub(crate) fn matches(&self, event: &PedometerDebugEvent) -> bool {
+        (self.boot_id.is_none() || event.boot_id == self.boot_id)
+            && self.event_types.contains(&event.event_type)
+            && (self.source.is_none() || Some(event.source) == self.source)
+    }
+}
+
+/// Events as CSV with a header line, e.g. to paste them into a bug report.
+pub(crate) fn to_csv<'a>(events: impl IntoIterator<Item = &'a PedometerDebugEvent>) -> String {
+    let mut csv = "type,source,boot_id,event_id,timestamp_ms,details\n".to_string();
+    for event in events {
+        let _ = writeln!(
+            csv,
+            "{:?},{:?},{},{},{},\"{}\"",
+            event.event_type,
+            event.source,
+            event.boot_id.map(|id| id.to_string()).unwrap_or_default(),
+            event.event_id.map(|id| id.to_string()).unwrap_or_default(),
+            event.timestamp_ms,
+            event.details.replace('"', "\"\"")
+        );
+    }
+    csv
+}
@@ -1,8 +1,24 @@
 use pedomet_rs_common::PedometerEventType;
 use thiserror::Error;
 
+use crate::ble::PedometerNotFoundCause;
+
 #[derive(Debug, Clone, Error)]
 pub(crate) enum PedometerGuiError {
     #[error("Invalid event type for persistence: {:?}", .0)]
     InvalidEventType(PedometerEventType),
+    /// The device only accepts a single central, so it is most likely connected to another host.
+    #[error("The device did not accept the connection, it is probably connected to another host")]
+    ConnectionRejected,
+    #[error("Could not find the device: {0}")]
+    DeviceNotFound(PedometerNotFoundCause),
+    /// A newer build of the app migrated the database
+    #[error("The database schema {database_version} is newer than the known {app_version}")]
+    DatabaseTooNew {
+        database_version: i64,
+        app_version: i64,
+    },
+    /// The database is opened read-only then, so the steps can still be shown and backed up
+    #[error("Could not migrate the database: {0}")]
+    MigrationFailed(String),
 }
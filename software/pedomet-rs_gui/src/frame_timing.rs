@@ -0,0 +1,98 @@
+use std::{cell::Cell, collections::VecDeque, time::Duration};
+
+use log::debug;
+use strum::{EnumCount, EnumIter, IntoEnumIterator};
+
+/// Frames that take longer are logged. Below 30 fps the app feels sluggish on a phone.
+pub(crate) const FRAME_BUDGET: Duration = Duration::from_millis(33);
+/// Number of frames over which the statistics are computed
+const HISTORY_FRAMES: usize = 120;
+
+/// Part of a frame whose duration is measured. The sections may overlap, e.g. the plots are
+/// part of the main view.
+#[derive(Debug, Copy, Clone, PartialEq, EnumIter, EnumCount, strum::Display)]
+pub(crate) enum FrameSection {
+    #[strum(to_string = "Ereignisse")]
+    Events,
+    #[strum(to_string = "Kopfzeile")]
+    Header,
+    #[strum(to_string = "Fußzeile")]
+    Footer,
+    #[strum(to_string = "Hauptansicht")]
+    MainView,
+    #[strum(to_string = "Plots")]
+    Plots,
+    #[strum(to_string = "Gesamt")]
+    Total,
+}
+
+/// Duration of the last, the average and the slowest frame of a section.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub(crate) struct FrameSectionStats {
+    pub last: Duration,
+    pub average: Duration,
+    pub max: Duration,
+}
+
+/// Durations of the sections of the last frames.
+///
+/// The durations of the running frame are kept in cells, so that they can be added while parts
+/// of the app are borrowed for drawing.
+#[derive(Debug, Default)]
+pub(crate) struct FrameTimings {
+    current: [Cell<Duration>; FrameSection::COUNT],
+    history: VecDeque<[Duration; FrameSection::COUNT]>,
+    /// Frames in the history that exceeded the [`FRAME_BUDGET`]
+    slow_frames: usize,
+}
+
+impl FrameTimings {
+    /// Add the duration to the section of the running frame. A section may be measured several
+    /// times per frame.
+    pub(crate) fn add(&self, section: FrameSection, duration: Duration) {
+        let current = &self.current[section as usize];
+        current.set(current.get() + duration);
+    }
+
+    /// Move the durations of the running frame to the history.
+    pub(crate) fn end_frame(&mut self, total: Duration) {
+        self.add(FrameSection::Total, total);
+        let frame = self.current.each_ref().map(Cell::take);
+        if total > FRAME_BUDGET {
+            debug!(
+                "Slow frame: {}",
+                FrameSection::iter()
+                    .map(|section| format!("{section} {:?}", frame[section as usize]))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+            self.slow_frames += 1;
+        }
+        if self.history.len() == HISTORY_FRAMES {
+            if let Some(oldest) = self.history.pop_front() {
+                if oldest[FrameSection::Total as usize] > FRAME_BUDGET {
+                    self.slow_frames -= 1;
+                }
+            }
+        }
+        self.history.push_back(frame);
+    }
+
+    pub(crate) fn stats(&self, section: FrameSection) -> FrameSectionStats {
+        let durations = self.history.iter().map(|frame| frame[section as usize]);
+        FrameSectionStats {
+            last: self
+                .history
+                .back()
+                .map(|frame| frame[section as usize])
+                .unwrap_or_default(),
+            average: durations.clone().sum::<Duration>() / self.history.len().max(1) as u32,
+            max: durations.max().unwrap_or_default(),
+        }
+    }
+
+    /// Frames in the history that exceeded the [`FRAME_BUDGET`] and the number of frames in it.
+    pub(crate) fn slow_frames(&self) -> (usize, usize) {
+        (self.slow_frames, self.history.len())
+    }
+}
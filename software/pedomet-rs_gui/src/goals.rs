@@ -0,0 +1,33 @@
+use crate::persistence::PedometerDailyTotal;
+
+/// Roll-up of the daily step target over several days.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) struct PedometerGoalSummary {
+    /// Days on which the target was reached
+    pub days_hit: usize,
+    pub days: usize,
+    /// Average completion of the target in percent. A day counts with at most 100% so that a
+    /// single long walk cannot hide the missed days.
+    pub average_completion_percent: f64,
+}
+
+impl PedometerGoalSummary {
+    pub(crate) fn new(totals: &[PedometerDailyTotal], daily_target: u32) -> Option<Self> {
+        if totals.is_empty() || daily_target == 0 {
+            return None;
+        }
+        let target = daily_target as f64;
+        Some(Self {
+            days_hit: totals
+                .iter()
+                .filter(|total| total.steps as f64 >= target)
+                .count(),
+            days: totals.len(),
+            average_completion_percent: totals
+                .iter()
+                .map(|total| (total.steps as f64 / target).min(1.0) * 100.0)
+                .sum::<f64>()
+                / totals.len() as f64,
+        })
+    }
+}
@@ -0,0 +1,89 @@
+use anyhow::anyhow;
+use chrono::DateTime;
+
+/// Mean radius of the earth for the haversine formula
+const EARTH_RADIUS_M: f64 = 6_371_000.0;
+
+/// Point of a GPS track with a timestamp.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) struct GpxPoint {
+    pub lat: f64,
+    pub lon: f64,
+    pub timestamp_ms: i64,
+}
+
+impl GpxPoint {
+    /// Great-circle distance to the other point.
+    fn distance_m(&self, other: &Self) -> f64 {
+        let (lat1, lat2) = (self.lat.to_radians(), other.lat.to_radians());
+        let d_lat = lat2 - lat1;
+        let d_lon = (other.lon - self.lon).to_radians();
+        let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS_M * a.sqrt().asin()
+    }
+}
+
+/// Part of a GPS track that was recorded during a session.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) struct GpxSegment {
+    pub distance_m: f64,
+    pub points: usize,
+}
+
+/// Parse the track points of a GPX file sorted by timestamp. Points without timestamp cannot be
+/// aligned with the steps and are skipped.
+pub(crate) fn parse(gpx: &str) -> anyhow::Result<Vec<GpxPoint>> {
+    let mut points = Vec::new();
+    for trkpt in gpx.split("<trkpt").skip(1) {
+        let trkpt = trkpt.split("</trkpt>").next().unwrap_or_default();
+        let attributes = trkpt
+            .split('>')
+            .next()
+            .ok_or_else(|| anyhow!("Invalid track point"))?;
+        let Some(time) = element(trkpt, "time") else {
+            continue;
+        };
+        points.push(GpxPoint {
+            lat: attribute(attributes, "lat")?.parse()?,
+            lon: attribute(attributes, "lon")?.parse()?,
+            timestamp_ms: DateTime::parse_from_rfc3339(time.trim())?.timestamp_millis(),
+        });
+    }
+    if points.is_empty() {
+        return Err(anyhow!("No track points with timestamp found"));
+    }
+    points.sort_by_key(|point| point.timestamp_ms);
+    Ok(points)
+}
+
+/// Part of the track between the given timestamps, if it has at least two points.
+pub(crate) fn segment(points: &[GpxPoint], start_ms: i64, end_ms: i64) -> Option<GpxSegment> {
+    let points: Vec<_> = points
+        .iter()
+        .filter(|point| (start_ms..=end_ms).contains(&point.timestamp_ms))
+        .collect();
+    (points.len() >= 2).then(|| GpxSegment {
+        distance_m: points.windows(2).map(|w| w[0].distance_m(w[1])).sum(),
+        points: points.len(),
+    })
+}
+
+fn attribute<'a>(attributes: &'a str, name: &str) -> anyhow::Result<&'a str> {
+    for quote in ['"', '\''] {
+        let prefix = format!("{name}={quote}");
+        if let Some(value) = attributes
+            .split_once(&prefix)
+            .and_then(|(_, rest)| rest.split_once(quote))
+            .map(|(value, _)| value)
+        {
+            return Ok(value);
+        }
+    }
+    Err(anyhow!("Track point without {name}"))
+}
+
+fn element<'a>(content: &'a str, name: &str) -> Option<&'a str> {
+    let (_, rest) = content.split_once(&format!("<{name}>"))?;
+    let (value, _) = rest.split_once(&format!("</{name}>"))?;
+    Some(value)
+}
@@ -1,66 +1,366 @@
-use chrono::{Duration, Local, NaiveDate, NaiveDateTime, NaiveTime, Timelike};
-use egui::{Align2, Button, Direction, Frame, Margin, ScrollArea, Slider, TopBottomPanel, Vec2};
+use chrono::{DateTime, Datelike, Duration, Local, Months, NaiveDate, Utc};
+use egui::{
+    Align2, Button, ComboBox, Direction, DragValue, Frame, Grid, Margin, ProgressBar, ScrollArea,
+    Slider, TextEdit, TopBottomPanel, Vec2,
+};
 use egui_extras::DatePickerButton;
-use egui_plot::{uniform_grid_spacer, Bar, BarChart, HLine, Legend, Plot};
+use egui_plot::{uniform_grid_spacer, Bar, BarChart, HLine, Legend, Line, Plot};
 use egui_toast::{ToastKind, Toasts};
-use log::{debug, info};
+use log::{debug, error, info, warn};
+use pedomet_rs_common::{
+    PedometerAccelerationSamples, PedometerActivity, PedometerAdvertisingData,
+    PedometerBootSummary, PedometerConfig, PedometerConfigValue, PedometerDeleteResult,
+    PedometerDiagnostics, PedometerError, PedometerEventRequest, PedometerFirmwareVersion,
+    PedometerMarker, PedometerSelfTest, PedometerStorageUsage,
+};
 use serde::{Deserialize, Serialize};
-use std::{cmp::min, sync::OnceLock};
+use std::{
+    cmp::min,
+    collections::HashSet,
+    path::PathBuf,
+    sync::{atomic::Ordering, OnceLock},
+    time::Instant,
+};
 use strum::{EnumIter, IntoEnumIterator};
-use tokio::sync::{mpsc, oneshot};
+use tokio::sync::{mpsc, oneshot, oneshot::error::TryRecvError, watch};
 
 use crate::{
-    ble::{PedometerDeviceHandlerCommand, BLE_CMD_TX},
+    acceleration::PedometerAccelerationTrace,
+    api::API_POLICY_TX,
+    archive::PedometerArchiveSummary,
+    audit::PedometerAuditReport,
+    ble::{
+        PedometerConnectionState, PedometerDeviceClock, PedometerDeviceHandlerCommand,
+        PedometerNotFoundCause, BLE_CMD_TX, CONNECT_CANCEL, DELETE_AFTER_SYNC, HOST_ID,
+        SYNC_PROGRESS_TARGET,
+    },
+    cadence::PedometerCadence,
+    celebration::PedometerCelebration,
+    chart_cache::{PedometerChartCache, PedometerChartKey},
+    debug_events::{
+        self, PedometerDebugEvent, PedometerDebugEventFilter, PedometerDebugEventSource,
+        PedometerDebugEventType,
+    },
+    error::PedometerGuiError,
+    frame_timing::{FrameSection, FrameTimings, FRAME_BUDGET},
+    goals::PedometerGoalSummary,
+    locale::{
+        date_pattern, format_date_time, format_day, format_day_axis, format_month, format_number,
+        parse_number,
+    },
+    metrics,
+    notifications::{hide_sync_progress, notify, PedometerNotification},
     persistence::{
-        PedometerDatabaseCommand, PedometerDatabaseGetEventsInTimeRangeReceiver,
-        PedometerPersistenceEvent, DB_CMD_TX,
+        local_day_start, PedometerDailyTotal, PedometerDatabaseCommand,
+        PedometerDatabaseGetEventsInTimeRangeReceiver, PedometerPersistenceActivity,
+        PedometerPersistenceBoot, PedometerPersistenceError, PedometerPersistenceEvent,
+        PedometerPersistenceMaintenance, PedometerPersistenceOutlier, PedometerSchemaInfo,
+        DAY_START_HOUR, DB_CMD_TX, DB_GENERATION,
+    },
+    profile::DeviceProfile,
+    research::{PedometerResearchExport, RESEARCH_JITTER_MAX},
+    session::PedometerSession,
+    settings::{
+        BatteryPolicy, IdleAlertPolicy, Language, PedometerSettings, ScanPolicy,
+        StepDetectionPolicy, UnitSystem,
+    },
+    steps::{
+        comparison_first_day, daily_summary_day, transform_events_to_relative_steps,
+        week_bar_position, week_first_day, PedometerActivityBreakdown, PedometerDaySteps,
+        PedometerHourRange, PedometerWeekComparison, COMPARISON_WEEKS, WEEK_DAYS,
     },
+    supervisor::PedometerBackend,
+    time_sync::PedometerTimeSyncQuality,
 };
 
-pub static GUI_EVENT_TX: OnceLock<mpsc::Sender<PedometerGuiEvent>> = OnceLock::new();
+pub static GUI_EVENT_TX: OnceLock<PedometerGuiEventSender> = OnceLock::new();
+
+/// Cadence at which the gauge is full, which is about the cadence of running.
+const CADENCE_GAUGE_MAX: f64 = 180.0;
+/// State of charge below which a notification is shown once
+const LOW_BATTERY_NOTIFICATION_PERCENT: u8 = 20;
+/// A failing sync is usually retried right away, so only notify once in this interval
+const SYNC_FAILED_NOTIFICATION_INTERVAL: std::time::Duration =
+    std::time::Duration::from_secs(10 * 60);
+/// Dropped commands of the device since its boot from which on a warning is shown
+const DEVICE_DROPPED_COMMANDS_WARNING: u32 = 10;
+/// Delay of the first retry after the device rejected the connection, doubled for every further one
+const CONNECT_RETRY_DELAY: std::time::Duration = std::time::Duration::from_secs(30);
+const CONNECT_RETRY_ATTEMPTS: u32 = 5;
+/// Delay of the reconnect after the connection was lost during a sync
+const SYNC_RESUME_DELAY: std::time::Duration = std::time::Duration::from_secs(5);
+/// Fill level of the device storage from which on the user is asked to sync
+const STORAGE_SYNC_REMINDER_PERCENT: u8 = 80;
+/// Minimum interval of the syncs that the device asks for, in case they fail
+const DATA_WAITING_SYNC_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5 * 60);
+
+/// Scheduled connection attempt after the device rejected the connection or the connection was
+/// lost during a sync
+#[derive(Debug, Clone, Copy)]
+struct ConnectRetry {
+    at: Instant,
+    attempt: u32,
+    /// Sync after connecting, e.g. because the rejected connection attempt was started by the
+    /// automatic sync
+    auto_sync: bool,
+}
 
 pub(crate) struct PedometerApp {
     state: PedometerAppState,
+    settings: PedometerSettings,
+    saved_settings: PedometerSettings,
     db_events_rx: MessageReceiver<PedometerDatabaseGetEventsInTimeRangeReceiver>,
+    db_summaries_rx: MessageReceiver<PedometerDatabaseGetEventsInTimeRangeReceiver>,
+    week_totals_rx: MessageReceiver<anyhow::Result<Vec<PedometerDailyTotal>>>,
+    month_totals_rx: MessageReceiver<anyhow::Result<Vec<PedometerDailyTotal>>>,
+    /// Daily totals of the week view and the weeks before it for the week comparison
+    comparison_totals_rx: MessageReceiver<anyhow::Result<Vec<PedometerDailyTotal>>>,
+    /// Activities of the selected day and the one before it
+    day_activities_rx: MessageReceiver<anyhow::Result<Vec<PedometerPersistenceActivity>>>,
+    /// Generation of the database when the data of the charts was requested, so that the charts
+    /// of the previous data are not cached as the new ones
+    chart_db_generation: u64,
+    day_chart: PedometerChartCache<(PedometerDaySteps, Vec<PedometerDayChartLayer>)>,
+    activity_breakdown: PedometerChartCache<PedometerActivityBreakdown>,
+    /// Position in the day view at which the selection of hours was started
+    hour_selection_start: Option<f64>,
+    /// Hours selected in the day view, whose steps are shown in a tooltip
+    hour_selection: Option<PedometerHourRange>,
+    week_chart: PedometerChartCache<(Vec<Bar>, i64)>,
+    comparison_chart: PedometerChartCache<[Vec<Bar>; 3]>,
+    month_chart: PedometerChartCache<(Vec<Bar>, i64)>,
     connect_events_rx: MessageReceiver<anyhow::Result<()>>,
-    gui_events_rx: mpsc::Receiver<PedometerGuiEvent>,
-    event_id: u32,
+    listen_events_rx: MessageReceiver<anyhow::Result<()>>,
+    diagnostics_rx: MessageReceiver<anyhow::Result<PedometerDiagnostics>>,
+    device_clock_rx: MessageReceiver<anyhow::Result<PedometerDeviceClock>>,
+    resync_time_rx: MessageReceiver<anyhow::Result<()>>,
+    stream_acceleration_rx: MessageReceiver<anyhow::Result<()>>,
+    /// Whether the raw accelerometer samples were requested from the device
+    acceleration_streaming: bool,
+    acceleration: PedometerAccelerationTrace,
+    last_disconnect_rx: MessageReceiver<anyhow::Result<Option<PedometerPersistenceError>>>,
+    last_maintenance_rx: MessageReceiver<anyhow::Result<Option<PedometerPersistenceMaintenance>>>,
+    quarantined_rows_rx: MessageReceiver<anyhow::Result<i64>>,
+    outliers_rx: MessageReceiver<anyhow::Result<Vec<PedometerPersistenceOutlier>>>,
+    set_outlier_rx: MessageReceiver<anyhow::Result<()>>,
+    boots_rx: MessageReceiver<anyhow::Result<Vec<PedometerPersistenceBoot>>>,
+    delete_boot_rx: MessageReceiver<anyhow::Result<()>>,
+    device_boots_rx: MessageReceiver<anyhow::Result<Vec<PedometerBootSummary>>>,
+    schema_info_rx: MessageReceiver<anyhow::Result<PedometerSchemaInfo>>,
+    audit_rx: MessageReceiver<anyhow::Result<PedometerAuditReport>>,
+    time_sync_rx: MessageReceiver<anyhow::Result<Vec<PedometerTimeSyncQuality>>>,
+    sessions_rx: MessageReceiver<anyhow::Result<Vec<PedometerSession>>>,
+    import_track_rx: MessageReceiver<anyhow::Result<usize>>,
+    archive_rx: MessageReceiver<anyhow::Result<PedometerArchiveSummary>>,
+    research_export_rx: MessageReceiver<anyhow::Result<PedometerResearchExport>>,
+    set_marker_rx: MessageReceiver<anyhow::Result<()>>,
+    write_config_rx: MessageReceiver<anyhow::Result<()>>,
+    gui_events_rx: PedometerGuiEventReceiver,
+    debug_filter: PedometerDebugEventFilter,
+    debug_events_rx: MessageReceiver<anyhow::Result<Vec<PedometerDebugEvent>>>,
     request_repaint_db: bool,
     request_repaint_ble: bool,
+    connection_state: PedometerConnectionState,
+    /// The user cancelled the running connection attempt
+    connect_cancelled: bool,
+    last_auto_sync: Instant,
+    /// The running connection attempt was started by the automatic sync
+    auto_sync_pending: bool,
+    connect_retry: Option<ConnectRetry>,
+    /// False after the Bluetooth adapter was removed until one is available again
+    adapter_available: bool,
+    /// Last received and maximum event index of the running sync
+    sync_progress: Option<(u32, u32)>,
     connected: bool,
     soc: Option<u8>,
+    /// Fill level of the event storage of the connected device in percent
+    storage_fill: Option<u8>,
+    /// Unknown if the firmware of the connected device does not report it
+    storage_usage: Option<PedometerStorageUsage>,
+    /// Last deletion on the connected device, so that the user knows which events only the
+    /// database keeps
+    device_delete_result: Option<PedometerDeleteResult>,
+    /// Unknown if the firmware of the connected device does not report it
+    firmware_version: Option<PedometerFirmwareVersion>,
+    /// Last temperature of the IMU of the connected device in 0.01 °C
+    temperature: Option<i16>,
+    live_data: Option<PedometerAdvertisingData>,
+    cadence: PedometerCadence,
+    /// Last config read from the device
+    device_config: Option<PedometerConfig>,
+    profile_name: String,
+    profiles: Vec<String>,
+    selected_profile: Option<String>,
+    /// Outcome of the last profile operation to show to the user
+    profile_result: Option<anyhow::Result<String>>,
+    /// GPX file to import for the sessions
+    gpx_path: String,
+    /// Daily target while the user enters it, until it is valid and the field lost the focus
+    daily_target_text: Option<String>,
+    /// Day for which the reached goal was already notified
+    goal_notified: Option<NaiveDate>,
+    /// Last live daily steps to tell when the daily target is crossed
+    last_daily_steps: Option<u32>,
+    celebration: Option<PedometerCelebration>,
+    low_battery_notified: bool,
+    sync_failed_notified: Option<Instant>,
+    show_outlier_review: bool,
+    /// Days up to today that are exported for research
+    research_days: u32,
+    /// Whether the consent dialog of the research export is shown and the user agreed in it
+    research_consent: Option<bool>,
+    frame_timings: FrameTimings,
+    show_frame_timings: bool,
+    /// Why the last connection attempt of the user did not find the device, shown with hints
+    not_found_cause: Option<PedometerNotFoundCause>,
+    /// Schema version of the database and the one known by the app if the database was not
+    /// opened because it is newer
+    database_too_new: Option<(i64, i64)>,
+    /// Error of the failed migration while the database is opened read-only
+    database_read_only: Option<String>,
+    show_database_read_only: bool,
+    backup_rx: MessageReceiver<anyhow::Result<PathBuf>>,
+    retry_migration_rx: MessageReceiver<anyhow::Result<()>>,
+    /// Start of the current scan attempt to show its progress
+    scan_started: Option<Instant>,
 }
 
 impl PedometerApp {
     pub(crate) fn new(
         cc: &eframe::CreationContext<'_>,
-        gui_events_rx: mpsc::Receiver<PedometerGuiEvent>,
+        gui_events_rx: PedometerGuiEventReceiver,
     ) -> Self {
-        let state = if let Some(storage) = cc.storage {
+        let (state, legacy_settings) = if let Some(storage) = cc.storage {
             info!("Get state from storage");
-            eframe::get_value(storage, eframe::APP_KEY).unwrap_or_default()
+            (
+                eframe::get_value(storage, eframe::APP_KEY).unwrap_or_default(),
+                eframe::get_value(storage, eframe::APP_KEY),
+            )
         } else {
             Default::default()
         };
         info!("Current state: {:?}", state);
+        let settings = PedometerSettings::load(legacy_settings);
+        info!("Current settings: {:?}", settings);
         let mut app = Self {
             state,
+            saved_settings: settings.clone(),
+            settings,
             db_events_rx: Default::default(),
+            db_summaries_rx: Default::default(),
+            week_totals_rx: Default::default(),
+            month_totals_rx: Default::default(),
+            comparison_totals_rx: Default::default(),
+            day_activities_rx: Default::default(),
+            chart_db_generation: 0,
+            day_chart: Default::default(),
+            activity_breakdown: Default::default(),
+            hour_selection_start: None,
+            hour_selection: None,
+            week_chart: Default::default(),
+            comparison_chart: Default::default(),
+            month_chart: Default::default(),
             connect_events_rx: Default::default(),
+            listen_events_rx: Default::default(),
+            diagnostics_rx: Default::default(),
+            device_clock_rx: Default::default(),
+            resync_time_rx: Default::default(),
+            stream_acceleration_rx: Default::default(),
+            acceleration_streaming: false,
+            acceleration: Default::default(),
+            last_disconnect_rx: Default::default(),
+            last_maintenance_rx: Default::default(),
+            quarantined_rows_rx: Default::default(),
+            outliers_rx: Default::default(),
+            set_outlier_rx: Default::default(),
+            boots_rx: Default::default(),
+            delete_boot_rx: Default::default(),
+            device_boots_rx: Default::default(),
+            schema_info_rx: Default::default(),
+            audit_rx: Default::default(),
+            time_sync_rx: Default::default(),
+            sessions_rx: Default::default(),
+            import_track_rx: Default::default(),
+            archive_rx: Default::default(),
+            research_export_rx: Default::default(),
+            set_marker_rx: Default::default(),
+            write_config_rx: Default::default(),
             gui_events_rx,
-            event_id: 0,
+            debug_filter: Default::default(),
+            debug_events_rx: Default::default(),
             request_repaint_db: false,
             request_repaint_ble: false,
+            connection_state: Default::default(),
+            connect_cancelled: false,
+            last_auto_sync: Instant::now(),
+            auto_sync_pending: false,
+            connect_retry: None,
+            adapter_available: true,
+            sync_progress: None,
             connected: false,
             soc: None,
+            storage_fill: None,
+            storage_usage: None,
+            device_delete_result: None,
+            firmware_version: None,
+            temperature: None,
+            live_data: None,
+            cadence: Default::default(),
+            device_config: None,
+            profile_name: String::new(),
+            profiles: Vec::new(),
+            selected_profile: None,
+            profile_result: None,
+            gpx_path: String::new(),
+            daily_target_text: None,
+            goal_notified: None,
+            last_daily_steps: None,
+            celebration: None,
+            low_battery_notified: false,
+            sync_failed_notified: None,
+            show_outlier_review: false,
+            research_days: 30,
+            research_consent: None,
+            frame_timings: Default::default(),
+            show_frame_timings: false,
+            not_found_cause: None,
+            database_too_new: None,
+            database_read_only: None,
+            show_database_read_only: false,
+            backup_rx: Default::default(),
+            retry_migration_rx: Default::default(),
+            scan_started: None,
         };
+        DAY_START_HOUR.store(app.settings.day_start_hour, Ordering::Relaxed);
+        HOST_ID.store(app.settings.host_id, Ordering::Relaxed);
+        DELETE_AFTER_SYNC.store(
+            app.settings.sync_policy.delete_after_sync,
+            Ordering::Relaxed,
+        );
+        app.set_sync_progress_notification();
         app.get_db_events();
+        if app.settings.listen_for_live_data {
+            app.set_listening(true);
+        }
+        app.set_api_policy();
+        app.get_last_disconnect();
+        app.get_last_maintenance();
+        app.get_quarantined_row_count();
+        app.get_outliers();
+        app.get_boots();
+        app.get_time_sync_quality();
+        app.get_sessions();
+        app.refresh_profiles();
+        if app.settings.archive.months > 0 {
+            app.archive_events();
+        }
         app
     }
 }
 
 impl eframe::App for PedometerApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        let frame_start = Instant::now();
         let mut toasts = Toasts::new()
             .anchor(Align2::LEFT_TOP, (10.0, 10.0))
             .direction(Direction::TopDown);
@@ -71,7 +371,16 @@ impl eframe::App for PedometerApp {
             style.spacing.button_padding = Vec2::new(12.0, 4.0);
         });
 
-        self.recv_events();
+        self.recv_events(&mut toasts);
+        if self
+            .celebration
+            .as_ref()
+            .is_some_and(|celebration| celebration.is_finished(Instant::now()))
+        {
+            self.celebration = None;
+        }
+        self.auto_sync();
+        self.retry_connect();
 
         if self.db_events_rx.try_recv(Some(
             |events: anyhow::Result<Vec<PedometerPersistenceEvent>>| {
@@ -88,33 +397,617 @@ impl eframe::App for PedometerApp {
             }
         }
 
+        if self.db_summaries_rx.try_recv(
+            None::<
+                fn(
+                    PedometerDatabaseGetEventsInTimeRangeReceiver,
+                ) -> PedometerDatabaseGetEventsInTimeRangeReceiver,
+            >,
+        ) {
+            if let Some(Err(e)) = &self.db_summaries_rx.current {
+                warn!("Could not get daily summaries: {e}");
+            }
+        }
+
+        for totals_rx in [
+            &mut self.week_totals_rx,
+            &mut self.month_totals_rx,
+            &mut self.comparison_totals_rx,
+        ] {
+            if totals_rx.try_recv(
+                None::<
+                    fn(
+                        anyhow::Result<Vec<PedometerDailyTotal>>,
+                    ) -> anyhow::Result<Vec<PedometerDailyTotal>>,
+                >,
+            ) {
+                if let Some(Err(e)) = &totals_rx.current {
+                    warn!("Could not get daily totals: {e}");
+                }
+            }
+        }
+
+        if self.day_activities_rx.try_recv(
+            None::<
+                fn(
+                    anyhow::Result<Vec<PedometerPersistenceActivity>>,
+                ) -> anyhow::Result<Vec<PedometerPersistenceActivity>>,
+            >,
+        ) {
+            if let Some(Err(e)) = &self.day_activities_rx.current {
+                warn!("Could not get activities: {e}");
+            }
+        }
+
         if self
             .connect_events_rx
             .try_recv(None::<fn(anyhow::Result<()>) -> anyhow::Result<()>>)
         {
             self.request_repaint_ble = false;
-            if let Some(Err(e)) = &self.connect_events_rx.current {
+            let cancelled = std::mem::take(&mut self.connect_cancelled);
+            let auto_sync = std::mem::take(&mut self.auto_sync_pending);
+            match &self.connect_events_rx.current {
+                Some(Err(_)) if cancelled => {
+                    self.connect_retry = None;
+                    toasts.add(egui_toast::Toast {
+                        kind: ToastKind::Warning,
+                        text: "Der Verbindungsaufbau wurde abgebrochen".into(),
+                        ..Default::default()
+                    });
+                }
+                Some(Err(e))
+                    if matches!(
+                        e.downcast_ref::<PedometerGuiError>(),
+                        Some(PedometerGuiError::ConnectionRejected)
+                    ) =>
+                {
+                    let attempt = self
+                        .connect_retry
+                        .take()
+                        .map_or(0, |retry| retry.attempt + 1);
+                    let text = if attempt < CONNECT_RETRY_ATTEMPTS {
+                        let delay = CONNECT_RETRY_DELAY * 2_u32.pow(attempt);
+                        self.connect_retry = Some(ConnectRetry {
+                            at: Instant::now() + delay,
+                            attempt,
+                            auto_sync,
+                        });
+                        format!(
+                            "Der Schrittzähler hat die Verbindung abgelehnt. Er ist vermutlich mit einem anderen Gerät (z.B. der Android-App) verbunden.\nNeuer Versuch in {} s",
+                            delay.as_secs()
+                        )
+                    } else {
+                        "Der Schrittzähler hat die Verbindung wiederholt abgelehnt. Bitte die Verbindung des anderen Geräts trennen und erneut verbinden.".to_string()
+                    };
+                    toasts.add(egui_toast::Toast {
+                        kind: ToastKind::Warning,
+                        text: text.into(),
+                        ..Default::default()
+                    });
+                }
+                Some(Err(e)) => {
+                    self.connect_retry = None;
+                    match e.downcast_ref::<PedometerGuiError>() {
+                        // Automatic attempts should not interrupt the user with a dialog
+                        Some(PedometerGuiError::DeviceNotFound(cause)) if !auto_sync => {
+                            self.not_found_cause = Some(cause.clone());
+                        }
+                        _ => {
+                            toasts.add(egui_toast::Toast {
+                                kind: ToastKind::Error,
+                                text: format!("Es ist ein Fehler aufgetreten:\n{}", e).into(),
+                                ..Default::default()
+                            });
+                        }
+                    }
+                }
+                Some(Ok(())) => {
+                    self.connect_retry = None;
+                    if self.connected {
+                        self.soc = None;
+                        self.storage_fill = None;
+                        self.storage_usage = None;
+                        self.device_delete_result = None;
+                        self.firmware_version = None;
+                        self.temperature = None;
+                        self.cadence.clear();
+                    }
+                    self.connected = !self.connected;
+                    if self.connected && (self.settings.sync_policy.sync_on_connect || auto_sync) {
+                        self.request_events(None);
+                    }
+                }
+                None => {}
+            }
+        }
+
+        if self
+            .listen_events_rx
+            .try_recv(None::<fn(anyhow::Result<()>) -> anyhow::Result<()>>)
+        {
+            if let Some(Err(e)) = &self.listen_events_rx.current {
                 toasts.add(egui_toast::Toast {
                     kind: ToastKind::Error,
                     text: format!("Es ist ein Fehler aufgetreten:\n{}", e).into(),
                     ..Default::default()
                 });
-            } else {
-                if self.connected {
-                    self.soc = None;
+            }
+        }
+
+        if self.diagnostics_rx.try_recv(
+            None::<
+                fn(
+                    anyhow::Result<PedometerDiagnostics>,
+                ) -> anyhow::Result<PedometerDiagnostics>,
+            >,
+        ) {
+            match &self.diagnostics_rx.current {
+                Some(Ok(diagnostics))
+                    if diagnostics.dropped_commands >= DEVICE_DROPPED_COMMANDS_WARNING =>
+                {
+                    toasts.add(egui_toast::Toast {
+                        kind: ToastKind::Warning,
+                        text: format!(
+                            "Der Schrittzähler hat seit dem Start {} Befehle verworfen",
+                            diagnostics.dropped_commands
+                        )
+                        .into(),
+                        ..Default::default()
+                    });
+                }
+                Some(Err(e)) => {
+                    toasts.add(egui_toast::Toast {
+                        kind: ToastKind::Error,
+                        text: format!("Es ist ein Fehler aufgetreten:\n{}", e).into(),
+                        ..Default::default()
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        self.device_clock_rx.try_recv(
+            None::<
+                fn(
+                    anyhow::Result<PedometerDeviceClock>,
+                ) -> anyhow::Result<PedometerDeviceClock>,
+            >,
+        );
+
+        if self
+            .stream_acceleration_rx
+            .try_recv(None::<fn(anyhow::Result<()>) -> anyhow::Result<()>>)
+        {
+            if let Some(Err(e)) = &self.stream_acceleration_rx.current {
+                self.acceleration_streaming = false;
+                toasts.add(egui_toast::Toast {
+                    kind: ToastKind::Error,
+                    text: format!("Die Übertragung der Beschleunigung ist fehlgeschlagen:\n{e}")
+                        .into(),
+                    ..Default::default()
+                });
+            }
+        }
+
+        if self
+            .resync_time_rx
+            .try_recv(None::<fn(anyhow::Result<()>) -> anyhow::Result<()>>)
+        {
+            match &self.resync_time_rx.current {
+                Some(Ok(())) => {
+                    toasts.add(egui_toast::Toast {
+                        kind: ToastKind::Success,
+                        text: "Die Zeit wurde neu synchronisiert".into(),
+                        ..Default::default()
+                    });
+                    self.read_device_clock();
+                }
+                Some(Err(e)) => {
+                    toasts.add(egui_toast::Toast {
+                        kind: ToastKind::Error,
+                        text: format!("Die Zeit konnte nicht synchronisiert werden:\n{e}").into(),
+                        ..Default::default()
+                    });
+                }
+                None => {}
+            }
+        }
+
+        if self.last_disconnect_rx.try_recv(
+            None::<
+                fn(
+                    anyhow::Result<Option<PedometerPersistenceError>>,
+                ) -> anyhow::Result<Option<PedometerPersistenceError>>,
+            >,
+        ) {
+            if let Some(Err(e)) = &self.last_disconnect_rx.current {
+                warn!("Could not get last disconnect: {e}");
+            }
+        }
+
+        if self.last_maintenance_rx.try_recv(
+            None::<
+                fn(
+                    anyhow::Result<Option<PedometerPersistenceMaintenance>>,
+                ) -> anyhow::Result<Option<PedometerPersistenceMaintenance>>,
+            >,
+        ) {
+            if let Some(Err(e)) = &self.last_maintenance_rx.current {
+                warn!("Could not get last maintenance: {e}");
+            }
+        }
+
+        if self
+            .quarantined_rows_rx
+            .try_recv(None::<fn(anyhow::Result<i64>) -> anyhow::Result<i64>>)
+        {
+            if let Some(Err(e)) = &self.quarantined_rows_rx.current {
+                warn!("Could not get quarantined row count: {e}");
+            }
+        }
+
+        if self.outliers_rx.try_recv(
+            None::<
+                fn(
+                    anyhow::Result<Vec<PedometerPersistenceOutlier>>,
+                ) -> anyhow::Result<Vec<PedometerPersistenceOutlier>>,
+            >,
+        ) {
+            if let Some(Err(e)) = &self.outliers_rx.current {
+                warn!("Could not get outliers: {e}");
+            }
+        }
+
+        if self
+            .set_outlier_rx
+            .try_recv(None::<fn(anyhow::Result<()>) -> anyhow::Result<()>>)
+        {
+            match &self.set_outlier_rx.current {
+                Some(Ok(())) => {
+                    self.get_outliers();
+                    self.get_db_events();
+                }
+                Some(Err(e)) => {
+                    toasts.add(egui_toast::Toast {
+                        kind: ToastKind::Error,
+                        text: format!("Es ist ein Fehler aufgetreten:\n{}", e).into(),
+                        ..Default::default()
+                    });
+                }
+                None => {}
+            }
+        }
+
+        if self.boots_rx.try_recv(
+            None::<
+                fn(
+                    anyhow::Result<Vec<PedometerPersistenceBoot>>,
+                ) -> anyhow::Result<Vec<PedometerPersistenceBoot>>,
+            >,
+        ) {
+            if let Some(Err(e)) = &self.boots_rx.current {
+                warn!("Could not get boots: {e}");
+            }
+        }
+
+        if self
+            .delete_boot_rx
+            .try_recv(None::<fn(anyhow::Result<()>) -> anyhow::Result<()>>)
+        {
+            if let Some(Err(e)) = &self.delete_boot_rx.current {
+                toasts.add(egui_toast::Toast {
+                    kind: ToastKind::Error,
+                    text: format!("Es ist ein Fehler aufgetreten:\n{}", e).into(),
+                    ..Default::default()
+                });
+            }
+        }
+
+        if self.device_boots_rx.try_recv(
+            None::<
+                fn(
+                    anyhow::Result<Vec<PedometerBootSummary>>,
+                ) -> anyhow::Result<Vec<PedometerBootSummary>>,
+            >,
+        ) {
+            if let Some(Err(e)) = &self.device_boots_rx.current {
+                warn!("Could not get boots of the device: {e}");
+            }
+        }
+
+        if self.schema_info_rx.try_recv(
+            None::<fn(anyhow::Result<PedometerSchemaInfo>) -> anyhow::Result<PedometerSchemaInfo>>,
+        ) {
+            if let Some(Err(e)) = &self.schema_info_rx.current {
+                warn!("Could not get schema info: {e}");
+            }
+        }
+
+        if self
+            .backup_rx
+            .try_recv(None::<fn(anyhow::Result<PathBuf>) -> anyhow::Result<PathBuf>>)
+        {
+            match &self.backup_rx.current {
+                Some(Ok(path)) => {
+                    toasts.add(egui_toast::Toast {
+                        kind: ToastKind::Success,
+                        text: format!("Datenbank gesichert\nDatei: {}", path.display()).into(),
+                        ..Default::default()
+                    });
+                }
+                Some(Err(e)) => {
+                    toasts.add(egui_toast::Toast {
+                        kind: ToastKind::Error,
+                        text: format!("Die Datenbank konnte nicht gesichert werden:\n{e}").into(),
+                        ..Default::default()
+                    });
+                }
+                None => {}
+            }
+        }
+
+        if self
+            .retry_migration_rx
+            .try_recv(None::<fn(anyhow::Result<()>) -> anyhow::Result<()>>)
+        {
+            match &self.retry_migration_rx.current {
+                Some(Ok(())) => {
+                    self.database_read_only = None;
+                    self.show_database_read_only = false;
+                    toasts.add(egui_toast::Toast {
+                        kind: ToastKind::Success,
+                        text: "Die Datenbank wurde aktualisiert und kann wieder beschrieben werden"
+                            .into(),
+                        ..Default::default()
+                    });
+                    self.get_stored_data();
+                }
+                Some(Err(e)) => {
+                    self.database_read_only = Some(e.to_string());
                 }
-                self.connected = !self.connected;
+                None => {}
             }
         }
 
+        if self.time_sync_rx.try_recv(
+            None::<
+                fn(
+                    anyhow::Result<Vec<PedometerTimeSyncQuality>>,
+                ) -> anyhow::Result<Vec<PedometerTimeSyncQuality>>,
+            >,
+        ) {
+            if let Some(Err(e)) = &self.time_sync_rx.current {
+                warn!("Could not get time sync quality: {e}");
+            }
+        }
+
+        if self.debug_events_rx.try_recv(
+            None::<
+                fn(
+                    anyhow::Result<Vec<PedometerDebugEvent>>,
+                ) -> anyhow::Result<Vec<PedometerDebugEvent>>,
+            >,
+        ) {
+            if let Some(Err(e)) = &self.debug_events_rx.current {
+                warn!("Could not get debug events: {e}");
+            }
+        }
+
+        if self.audit_rx.try_recv(
+            None::<
+                fn(
+                    anyhow::Result<PedometerAuditReport>,
+                ) -> anyhow::Result<PedometerAuditReport>,
+            >,
+        ) {
+            if let Some(Err(e)) = &self.audit_rx.current {
+                toasts.add(egui_toast::Toast {
+                    kind: ToastKind::Error,
+                    text: format!("Es ist ein Fehler aufgetreten:\n{}", e).into(),
+                    ..Default::default()
+                });
+            }
+        }
+
+        if self.sessions_rx.try_recv(
+            None::<
+                fn(anyhow::Result<Vec<PedometerSession>>) -> anyhow::Result<Vec<PedometerSession>>,
+            >,
+        ) {
+            if let Some(Err(e)) = &self.sessions_rx.current {
+                warn!("Could not get sessions: {e}");
+            }
+        }
+
+        if self
+            .import_track_rx
+            .try_recv(None::<fn(anyhow::Result<usize>) -> anyhow::Result<usize>>)
+        {
+            match &self.import_track_rx.current {
+                Some(Ok(annotated)) => {
+                    toasts.add(egui_toast::Toast {
+                        kind: ToastKind::Success,
+                        text: format!("Der GPS-Track wurde {annotated} Sessions zugeordnet").into(),
+                        ..Default::default()
+                    });
+                    self.get_sessions();
+                }
+                Some(Err(e)) => {
+                    toasts.add(egui_toast::Toast {
+                        kind: ToastKind::Error,
+                        text: format!("Der GPS-Track konnte nicht importiert werden:\n{e}").into(),
+                        ..Default::default()
+                    });
+                }
+                None => {}
+            }
+        }
+
+        if self.archive_rx.try_recv(
+            None::<
+                fn(
+                    anyhow::Result<PedometerArchiveSummary>,
+                ) -> anyhow::Result<PedometerArchiveSummary>,
+            >,
+        ) {
+            match &self.archive_rx.current {
+                Some(Ok(summary)) if summary.events > 0 => {
+                    toasts.add(egui_toast::Toast {
+                        kind: ToastKind::Success,
+                        text: match &summary.raw_file {
+                            Some(raw_file) => format!(
+                                "{} Ereignisse von {} Tagen archiviert\nRohdaten: {}",
+                                format_number(self.settings.language, summary.events as i64),
+                                summary.days,
+                                raw_file.display()
+                            ),
+                            None => format!(
+                                "{} Ereignisse von {} Tagen archiviert",
+                                format_number(self.settings.language, summary.events as i64),
+                                summary.days
+                            ),
+                        }
+                        .into(),
+                        ..Default::default()
+                    });
+                    self.get_db_events();
+                    self.get_outliers();
+                }
+                Some(Ok(_)) => {}
+                Some(Err(e)) => {
+                    toasts.add(egui_toast::Toast {
+                        kind: ToastKind::Error,
+                        text: format!("Die Schritte konnten nicht archiviert werden:\n{e}").into(),
+                        ..Default::default()
+                    });
+                }
+                None => {}
+            }
+        }
+
+        if self.research_export_rx.try_recv(
+            None::<
+                fn(
+                    anyhow::Result<PedometerResearchExport>,
+                ) -> anyhow::Result<PedometerResearchExport>,
+            >,
+        ) {
+            match &self.research_export_rx.current {
+                Some(Ok(export)) => {
+                    toasts.add(egui_toast::Toast {
+                        kind: ToastKind::Success,
+                        text: format!(
+                            "{} Stunden anonymisiert exportiert\nDatei: {}",
+                            format_number(self.settings.language, export.hours as i64),
+                            export.file.display()
+                        )
+                        .into(),
+                        ..Default::default()
+                    });
+                }
+                Some(Err(e)) => {
+                    toasts.add(egui_toast::Toast {
+                        kind: ToastKind::Error,
+                        text: format!("Die Schritte konnten nicht exportiert werden:\n{e}").into(),
+                        ..Default::default()
+                    });
+                }
+                None => {}
+            }
+        }
+
+        if self
+            .set_marker_rx
+            .try_recv(None::<fn(anyhow::Result<()>) -> anyhow::Result<()>>)
+        {
+            if let Some(Err(e)) = &self.set_marker_rx.current {
+                toasts.add(egui_toast::Toast {
+                    kind: ToastKind::Error,
+                    text: format!("Es ist ein Fehler aufgetreten:\n{}", e).into(),
+                    ..Default::default()
+                });
+            }
+        }
+
+        if self
+            .write_config_rx
+            .try_recv(None::<fn(anyhow::Result<()>) -> anyhow::Result<()>>)
+        {
+            if let Some(Err(e)) = &self.write_config_rx.current {
+                toasts.add(egui_toast::Toast {
+                    kind: ToastKind::Error,
+                    text: format!("Es ist ein Fehler aufgetreten:\n{}", e).into(),
+                    ..Default::default()
+                });
+            }
+        }
+
+        self.frame_timings
+            .add(FrameSection::Events, frame_start.elapsed());
+        let start = Instant::now();
         self.draw_header(ctx);
+        self.frame_timings
+            .add(FrameSection::Header, start.elapsed());
+        let start = Instant::now();
         self.draw_footer(ctx);
+        self.frame_timings
+            .add(FrameSection::Footer, start.elapsed());
+        let start = Instant::now();
         self.draw_main_view(ctx);
+        self.frame_timings
+            .add(FrameSection::MainView, start.elapsed());
+        self.draw_outlier_review(ctx);
+        self.draw_research_consent(ctx);
+        self.draw_not_found_help(ctx);
+        self.draw_database_too_new(ctx);
+        self.draw_database_read_only(ctx);
 
         toasts.show(ctx);
+        self.frame_timings.end_frame(frame_start.elapsed());
+        self.draw_frame_timings(ctx);
 
-        if self.request_repaint_db || self.request_repaint_ble {
+        if self.request_repaint_db
+            || self.request_repaint_ble
+            || self.db_summaries_rx.receiver.is_some()
+            || self.diagnostics_rx.receiver.is_some()
+            || self.device_clock_rx.receiver.is_some()
+            || self.resync_time_rx.receiver.is_some()
+            || self.stream_acceleration_rx.receiver.is_some()
+            || self.last_disconnect_rx.receiver.is_some()
+            || self.last_maintenance_rx.receiver.is_some()
+            || self.quarantined_rows_rx.receiver.is_some()
+            || self.outliers_rx.receiver.is_some()
+            || self.set_outlier_rx.receiver.is_some()
+            || self.boots_rx.receiver.is_some()
+            || self.delete_boot_rx.receiver.is_some()
+            || self.device_boots_rx.receiver.is_some()
+            || self.schema_info_rx.receiver.is_some()
+            || self.backup_rx.receiver.is_some()
+            || self.retry_migration_rx.receiver.is_some()
+            || self.audit_rx.receiver.is_some()
+            || self.time_sync_rx.receiver.is_some()
+            || self.debug_events_rx.receiver.is_some()
+            || self.sessions_rx.receiver.is_some()
+            || self.week_totals_rx.receiver.is_some()
+            || self.month_totals_rx.receiver.is_some()
+            || self.comparison_totals_rx.receiver.is_some()
+            || self.day_activities_rx.receiver.is_some()
+            || self.import_track_rx.receiver.is_some()
+            || self.archive_rx.receiver.is_some()
+            || self.research_export_rx.receiver.is_some()
+            || self.set_marker_rx.receiver.is_some()
+            || self.write_config_rx.receiver.is_some()
+        {
             ctx.request_repaint_after(std::time::Duration::from_millis(50));
+        } else if self.celebration.is_some() {
+            // Animate the confetti
+            ctx.request_repaint();
+        } else if self.acceleration_streaming {
+            // The device notifies a batch of samples about three times per second
+            ctx.request_repaint_after(std::time::Duration::from_millis(100));
+        } else if self.connected {
+            // Keep the cadence up to date
+            ctx.request_repaint_after(std::time::Duration::from_secs(1));
         } else {
             ctx.request_repaint_after(std::time::Duration::from_secs(5));
         }
@@ -123,6 +1016,12 @@ impl eframe::App for PedometerApp {
     fn save(&mut self, storage: &mut dyn eframe::Storage) {
         info!("Save state to storage: {:?}", self.state);
         eframe::set_value(storage, eframe::APP_KEY, &self.state);
+        if self.settings != self.saved_settings {
+            match self.settings.save() {
+                Ok(()) => self.saved_settings = self.settings.clone(),
+                Err(e) => warn!("Could not save settings: {e}"),
+            }
+        }
     }
 
     fn auto_save_interval(&self) -> std::time::Duration {
@@ -130,34 +1029,6 @@ impl eframe::App for PedometerApp {
     }
 }
 
-fn transform_events_to_relative_steps(
-    mut events: Vec<PedometerPersistenceEvent>,
-) -> Vec<PedometerPersistenceEvent> {
-    if events.is_empty() {
-        return events;
-    }
-    let first_steps = events.first().unwrap().steps;
-    let first_boot_id = events.first().unwrap().boot_id;
-    debug!("Db events: {events:?}");
-    events = events
-        .into_iter()
-        .scan(
-            (first_steps, first_boot_id),
-            |(last_steps, last_boot_id), mut event| {
-                let event_steps = event.steps as u16;
-                if *last_boot_id == event.boot_id {
-                    event.steps = (event_steps).overflowing_sub(*last_steps as u16).0 as i64;
-                }
-                *last_steps = event_steps as i64;
-                *last_boot_id = event.boot_id;
-                Some(event)
-            },
-        )
-        .collect();
-    debug!("Mapped events: {events:?}");
-    events
-}
-
 #[derive(
     Debug, Copy, Clone, Default, PartialEq, EnumIter, strum::Display, Serialize, Deserialize,
 )]
@@ -165,6 +1036,10 @@ enum MainView {
     #[default]
     #[strum(to_string = "Übersicht")]
     Overview,
+    #[strum(to_string = "Monat")]
+    Month,
+    #[strum(to_string = "Sessions")]
+    Sessions,
     #[strum(to_string = "Einstellungen")]
     Settings,
     #[strum(to_string = "Debug")]
@@ -183,16 +1058,96 @@ impl PedometerApp {
                 ui.separator();
                 ui.horizontal(|ui| {
                     ui.label(format!(
-                        "Schrittzähler {}",
+                        "Schrittzähler {}{}",
+                        self.settings
+                            .device_name
+                            .as_ref()
+                            .map(|name| format!("{name} "))
+                            .unwrap_or_default(),
                         if self.connected {
                             "verbunden"
                         } else {
                             "getrennt"
                         }
                     ));
+                    if let Some(firmware_version) = self.firmware_version {
+                        ui.label(format!("v{firmware_version}"))
+                            .on_hover_text("Firmware-Version");
+                    }
                     if let Some(soc) = self.soc {
                         ui.label(format!("🔋{}%", soc));
                     }
+                    if self.database_read_only.is_some()
+                        && ui
+                            .button("⚠ Datenbank nur lesbar")
+                            .on_hover_text("Die Schritte können nicht gespeichert werden")
+                            .clicked()
+                    {
+                        self.show_database_read_only = true;
+                    }
+                    if let Some(temperature) = self.temperature {
+                        ui.label(format!("🌡{:.1} °C", f32::from(temperature) / 100.0))
+                            .on_hover_text("Temperatur des Sensors");
+                    }
+                    if let Some(fill_percent) = self.storage_fill {
+                        let reminder = fill_percent >= STORAGE_SYNC_REMINDER_PERCENT;
+                        let mut bar = ProgressBar::new(fill_percent as f32 / 100.0)
+                            .desired_width(80.0)
+                            .text(format!("💾{fill_percent}%"));
+                        if reminder {
+                            bar = bar.fill(ui.visuals().warn_fg_color);
+                        }
+                        let bar = ui.add(bar);
+                        if let Some(usage) = self.storage_usage {
+                            bar.on_hover_text(describe_storage_usage(
+                                self.settings.language,
+                                &usage,
+                            ));
+                        }
+                        if reminder
+                            && ui
+                                .add_enabled(
+                                    self.connection_state == PedometerConnectionState::Connected,
+                                    Button::new("⚠ Speicher fast voll, jetzt abrufen"),
+                                )
+                                .clicked()
+                        {
+                            self.request_events(None);
+                        }
+                    }
+                    if let Some(description) = describe_connection_state(self.connection_state) {
+                        ui.spinner();
+                        ui.label(description);
+                    }
+                    if let (PedometerConnectionState::Scanning { duration, .. }, Some(started)) =
+                        (self.connection_state, self.scan_started)
+                    {
+                        let elapsed = started.elapsed().min(duration);
+                        ui.add(
+                            ProgressBar::new(
+                                elapsed.as_secs_f32() / duration.as_secs_f32().max(1.0),
+                            )
+                            .desired_width(100.0)
+                            .text(format!(
+                                "{}/{} s",
+                                elapsed.as_secs(),
+                                duration.as_secs()
+                            )),
+                        );
+                    }
+                    if !self.adapter_available {
+                        ui.label("⚠ Kein Bluetooth-Adapter");
+                    }
+                    if let Some((event_id, max_event_id)) = self
+                        .sync_progress
+                        .filter(|(event_id, max_event_id)| event_id < max_event_id)
+                    {
+                        ui.add(
+                            ProgressBar::new(event_id as f32 / max_event_id as f32)
+                                .desired_width(150.0)
+                                .text(format!("Events {event_id}/{max_event_id}")),
+                        );
+                    }
                 });
                 ui.horizontal(|ui| {
                     if ui
@@ -206,32 +1161,76 @@ impl PedometerApp {
                         )
                         .clicked()
                     {
-                        let (resp_tx, resp_rx) = oneshot::channel();
-                        self.connect_events_rx.receiver = Some(resp_rx);
-                        let event = if !self.connected {
-                            PedometerDeviceHandlerCommand::TryConnect { responder: resp_tx }
-                        } else {
-                            PedometerDeviceHandlerCommand::Disconnect { responder: resp_tx }
-                        };
-                        BLE_CMD_TX.get().unwrap().blocking_send(event).unwrap();
-                        self.request_repaint_ble = true;
+                        self.connect_retry = None;
+                        self.set_connected(!self.connected);
+                    }
+                    let connecting = matches!(
+                        self.connection_state,
+                        PedometerConnectionState::Scanning { .. }
+                            | PedometerConnectionState::Connecting
+                            | PedometerConnectionState::Subscribing
+                    );
+                    if self.request_repaint_ble
+                        && connecting
+                        && ui
+                            .add_enabled(!self.connect_cancelled, Button::new("Abbrechen"))
+                            .clicked()
+                    {
+                        self.connect_cancelled = true;
+                        CONNECT_CANCEL.notify_waiters();
+                    }
+                    if let Some(retry) = self.connect_retry {
+                        ui.label(format!(
+                            "Neuer Versuch in {} s",
+                            retry.at.saturating_duration_since(Instant::now()).as_secs()
+                        ));
+                        if ui.button("Nicht erneut versuchen").clicked() {
+                            self.connect_retry = None;
+                        }
                     }
                 });
                 ui.add_space(12.0);
-                if ui
-                    .add_enabled(self.connected, Button::new("Schritte abrufen"))
-                    .clicked()
-                {
-                    let (resp_tx, _resp_rx) = oneshot::channel();
-                    BLE_CMD_TX
-                        .get()
-                        .unwrap()
-                        .blocking_send(PedometerDeviceHandlerCommand::RequestEvents {
-                            min_event_id: None,
-                            responder: resp_tx,
-                        })
-                        .unwrap();
-                }
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(self.connected, Button::new("Schritte abrufen"))
+                        .clicked()
+                    {
+                        self.request_events(None);
+                    }
+                    // The running session is only known once the sessions were loaded
+                    let running_session = match &self.sessions_rx.current {
+                        Some(Ok(sessions)) => Some(
+                            sessions
+                                .last()
+                                .filter(|session| session.end_ms.is_none())
+                                .copied(),
+                        ),
+                        _ => None,
+                    };
+                    if ui
+                        .add_enabled(
+                            running_session.is_some() && self.set_marker_rx.receiver.is_none(),
+                            Button::new(if let Some(Some(_)) = running_session {
+                                "Session beenden"
+                            } else {
+                                "Session starten"
+                            }),
+                        )
+                        .clicked()
+                    {
+                        self.set_marker(if let Some(Some(_)) = running_session {
+                            PedometerMarker::SessionEnd
+                        } else {
+                            PedometerMarker::SessionStart
+                        });
+                    }
+                    if let Some(Some(session)) = running_session {
+                        ui.label(format!(
+                            "Session läuft seit {} min",
+                            session.duration_ms(Local::now().timestamp_millis()) / 60_000
+                        ));
+                    }
+                });
             });
     }
 
@@ -240,6 +1239,8 @@ impl PedometerApp {
             ScrollArea::vertical().show(ui, |ui| {
                 match self.state.main_view {
                     MainView::Overview => self.draw_main_view_overview(ui),
+                    MainView::Month => self.draw_main_view_month(ui),
+                    MainView::Sessions => self.draw_main_view_sessions(ui),
                     MainView::Settings => self.draw_main_view_settings(ui),
                     MainView::Debug => self.draw_main_view_debug(ui),
                 };
@@ -253,7 +1254,11 @@ impl PedometerApp {
             if ui.button("<").clicked() {
                 self.state.selected_date -= chrono::Duration::days(1);
             }
-            ui.add(DatePickerButton::new(&mut self.state.selected_date).calendar_week(false));
+            ui.add(
+                DatePickerButton::new(&mut self.state.selected_date)
+                    .calendar_week(false)
+                    .format(date_pattern(self.settings.language)),
+            );
             if ui.button(">").clicked() {
                 self.state.selected_date += chrono::Duration::days(1);
             }
@@ -264,25 +1269,124 @@ impl PedometerApp {
         });
         if date_before != self.state.selected_date {
             debug!("Selected date changed to: {:?}", self.state.selected_date);
+            self.hour_selection = None;
             self.get_db_events();
         }
+        if let Some(Ok(count @ 1..)) = self.quarantined_rows_rx.current {
+            ui.colored_label(
+                ui.visuals().warn_fg_color,
+                format!("{count} Einträge mit ungültiger Uhrzeit wurden aussortiert"),
+            );
+        }
+        let excluded_outliers = self
+            .get_outliers_ref()
+            .iter()
+            .filter(|outlier| !outlier.accepted)
+            .count();
+        if excluded_outliers > 0 {
+            ui.horizontal(|ui| {
+                ui.colored_label(
+                    ui.visuals().warn_fg_color,
+                    format!("{excluded_outliers} unplausible Schrittspitzen werden nicht gezählt"),
+                );
+                if ui.button("Prüfen").clicked() {
+                    self.show_outlier_review = true;
+                }
+            });
+        }
+        if let Some(celebration) = &self.celebration {
+            celebration.paint(
+                &ui.ctx().layer_painter(egui::LayerId::new(
+                    egui::Order::Foreground,
+                    egui::Id::new("celebration"),
+                )),
+                ui.clip_rect(),
+                Instant::now(),
+            );
+        }
+        if let Some(live_data) = self.live_data.filter(|_| !self.connected) {
+            ui.label(format!(
+                "Heute (live): {} Schritte",
+                format_number(self.settings.language, live_data.daily_steps.into())
+            ));
+        }
+        if let Some(cadence) = self
+            .cadence
+            .steps_per_minute(Instant::now())
+            .filter(|_| self.connected)
+        {
+            ui.add(
+                ProgressBar::new((cadence / CADENCE_GAUGE_MAX) as f32)
+                    .desired_width(200.0)
+                    .text(format!("Kadenz: {cadence:.0} Schritte/min")),
+            );
+        }
         ui.separator();
         ui.heading("Tag");
-        if let Some(Ok(events)) = &self.db_events_rx.current {
-            let mut bars: Vec<_> = (0..24)
-                .map(|h| Bar::new(h as f64, 0.0).width(1.0))
-                .collect();
-            let mut steps_day = 0;
-            for event in events.iter().filter(|e| {
-                let event_dt = e.get_date_time_local().unwrap();
-                self.state.selected_date == event_dt.naive_local().into()
-            }) {
-                let event_dt = event.get_date_time_local().unwrap();
-                bars.get_mut(event_dt.hour() as usize).unwrap().value += event.steps as f64;
-                steps_day += event.steps;
-            }
-            ui.label(format!("Schritte gesamt: {steps_day}"));
-            Plot::new("day_plot")
+        if let Some((
+            PedometerDaySteps {
+                hourly,
+                total: steps_day,
+                boot_ids,
+                ..
+            },
+            layers,
+        )) = self.get_day_chart()
+        {
+            match self.get_daily_summary(self.state.selected_date) {
+                Some(summary_steps) if steps_day == 0 => ui.label(format!(
+                    "Schritte gesamt: {} (aus Tageszusammenfassung)",
+                    format_number(self.settings.language, summary_steps)
+                )),
+                _ => ui.label(format!(
+                    "Schritte gesamt: {}",
+                    format_number(self.settings.language, steps_day)
+                )),
+            };
+            if let Some(breakdown) = self.get_activity_breakdown() {
+                if breakdown.is_active() {
+                    ui.label(
+                        [PedometerActivity::Walking, PedometerActivity::Running]
+                            .into_iter()
+                            .map(|activity| {
+                                format!(
+                                    "{}: {} min, {} Schritte",
+                                    describe_activity(activity),
+                                    breakdown.duration_ms[activity as usize] / 60_000,
+                                    format_number(
+                                        self.settings.language,
+                                        breakdown.steps[activity as usize]
+                                    )
+                                )
+                            })
+                            .collect::<Vec<_>>()
+                            .join("\n"),
+                    );
+                }
+            }
+            if let Some(max_deviation_ms) = self.get_time_sync_warning(&boot_ids) {
+                ui.colored_label(
+                    ui.visuals().warn_fg_color,
+                    format!(
+                        "Die Uhrzeit der Schritte ist bis zu {} min ungenau, die Stunden können verschoben sein",
+                        (max_deviation_ms + 59_999) / 60_000
+                    ),
+                );
+            }
+            // The bars of the selected hours are drawn again on top
+            let selected_bars: Vec<_> = match self.hour_selection {
+                Some(range) => hourly
+                    .iter()
+                    .enumerate()
+                    .filter(|(hour, _)| range.contains(*hour))
+                    .map(|(hour, steps)| Bar::new(hour as f64, *steps as f64).width(1.0))
+                    .collect(),
+                None => Vec::new(),
+            };
+            let show_legend = layers.iter().any(|layer| layer.activity.is_some());
+            let selection_color = ui.visuals().selection.bg_fill;
+            let plot_start = Instant::now();
+            let plot_response = Plot::new("day_plot")
                 .height(200.0)
                 .include_y(0)
                 .allow_zoom(false)
@@ -291,42 +1395,75 @@ impl PedometerApp {
                 .clamp_grid(true)
                 .x_grid_spacer(uniform_grid_spacer(|_| [6., 3., 1.]))
                 .y_axis_min_width(40.)
+                .y_axis_formatter(|mark, _range| {
+                    format_number(self.settings.language, mark.value as i64)
+                })
                 .set_margin_fraction((0.01, 0.1).into())
+                .legend(Legend::default())
                 .reset()
                 .show(ui, |plot_ui| {
-                    plot_ui.bar_chart(BarChart::new(bars));
-                });
-        }
-        ui.separator();
-        ui.heading("Woche");
-        if let Some(Ok(events)) = &self.db_events_rx.current {
-            let mut bars: Vec<_> = (0..7)
-                .map(|i| {
-                    let day = self.state.selected_date - Duration::days(i);
-                    Bar::new(-i as f64, 0.0)
-                        .name(day.format("%a %d.%m"))
-                        .width(1.0)
-                })
-                .collect();
-            let mut steps_week = 0;
-            for event in events.iter().filter(|e| {
-                let event_dt = e.get_date_time_local().unwrap();
-                let local = event_dt.naive_local();
-
-                let selected_dt: NaiveDateTime = self.state.selected_date.into();
-
-                local > selected_dt - Duration::days(6) && local <= selected_dt + Duration::days(1)
-            }) {
-                let event_dt = event.get_date_time_local().unwrap();
-                let naive_event_dt = event_dt.naive_local();
-                bars.get_mut(
-                    (self.state.selected_date - naive_event_dt.date()).num_days() as usize,
-                )
-                .unwrap()
-                .value += event.steps as f64;
-                steps_week += event.steps;
+                    // Each activity is stacked on the steps of the layers below
+                    let mut charts: Vec<BarChart> = Vec::new();
+                    for layer in layers {
+                        let mut chart = BarChart::new(layer.bars);
+                        if let Some(activity) = layer.activity {
+                            chart = chart.color(activity_color(activity));
+                        }
+                        if show_legend {
+                            chart = chart.name(
+                                layer
+                                    .activity
+                                    .map_or("Nicht eingeordnet", describe_activity),
+                            );
+                        }
+                        charts.push(chart.stack_on(&charts.iter().collect::<Vec<_>>()));
+                    }
+                    for chart in charts {
+                        plot_ui.bar_chart(chart);
+                    }
+                    if !selected_bars.is_empty() {
+                        plot_ui.bar_chart(
+                            BarChart::new(selected_bars)
+                                .color(selection_color)
+                                .highlight(true),
+                        );
+                    }
+                    plot_ui.pointer_coordinate()
+                });
+            let response = &plot_response.response;
+            if let Some(pointer) = plot_response.inner {
+                if response.drag_started() {
+                    self.hour_selection_start = Some(pointer.x);
+                }
+                if let (true, Some(start)) = (response.dragged(), self.hour_selection_start) {
+                    self.hour_selection = Some(PedometerHourRange::new(start, pointer.x));
+                }
             }
-            ui.label(format!("Schritte gesamt: {steps_week}"));
+            if response.clicked() {
+                self.hour_selection = None;
+            }
+            if let Some(range) = self.hour_selection {
+                response.clone().on_hover_text(format!(
+                    "{:02}:00 – {:02}:00\nSchritte: {}\nDauer: {} h",
+                    range.first_hour,
+                    range.last_hour + 1,
+                    format_number(self.settings.language, range.steps(&hourly)),
+                    range.duration().num_hours()
+                ));
+            }
+            self.frame_timings
+                .add(FrameSection::Plots, plot_start.elapsed());
+        }
+        ui.separator();
+        ui.heading("Woche");
+        // The daily totals are pre-aggregated by the database and already include the daily
+        // summaries for days without detailed events
+        if let Some((bars, steps_week)) = self.get_week_chart() {
+            ui.label(format!(
+                "Schritte gesamt: {}",
+                format_number(self.settings.language, steps_week)
+            ));
+            let plot_start = Instant::now();
             Plot::new("week_plot")
                 .height(200.0)
                 .include_y(0)
@@ -336,110 +1473,2646 @@ impl PedometerApp {
                 .show_grid([false, true])
                 .x_axis_formatter(|mark, _range| {
                     let day = self.state.selected_date + Duration::days(mark.value as i64);
-                    day.format("%d.%m\n%a").to_string()
+                    format_day_axis(self.settings.language, day)
                 })
                 .x_grid_spacer(uniform_grid_spacer(|_| [2., 2., 1.]))
                 .y_axis_min_width(40.)
+                .y_axis_formatter(|mark, _range| {
+                    format_number(self.settings.language, mark.value as i64)
+                })
                 .clamp_grid(true)
                 .set_margin_fraction((0.01, 0.1).into())
                 .legend(Legend::default())
                 .reset()
                 .show(ui, |plot_ui| {
                     plot_ui.hline(
-                        HLine::new(self.state.daily_target)
+                        HLine::new(self.settings.daily_target)
                             .name("Schrittziel")
                             .highlight(true),
                     );
                     plot_ui.bar_chart(BarChart::new(bars));
                 });
+            self.frame_timings
+                .add(FrameSection::Plots, plot_start.elapsed());
+        }
+        ui.separator();
+        ui.heading("Wochenvergleich");
+        if let Some([this_week, last_week, average]) = self.get_comparison_chart() {
+            let plot_start = Instant::now();
+            Plot::new("week_comparison_plot")
+                .height(200.0)
+                .include_y(0)
+                .allow_zoom(false)
+                .allow_drag(false)
+                .allow_scroll(false)
+                .show_grid([false, true])
+                .x_axis_formatter(|mark, _range| {
+                    let day = self.state.selected_date + Duration::days(mark.value as i64);
+                    format_day_axis(self.settings.language, day)
+                })
+                .x_grid_spacer(uniform_grid_spacer(|_| [2., 2., 1.]))
+                .y_axis_min_width(40.)
+                .y_axis_formatter(|mark, _range| {
+                    format_number(self.settings.language, mark.value as i64)
+                })
+                .clamp_grid(true)
+                .set_margin_fraction((0.01, 0.1).into())
+                .legend(Legend::default())
+                .reset()
+                .show(ui, |plot_ui| {
+                    plot_ui.bar_chart(BarChart::new(this_week).name("Diese Woche"));
+                    plot_ui.bar_chart(BarChart::new(last_week).name("Vorwoche"));
+                    plot_ui.bar_chart(
+                        BarChart::new(average).name(format!("Ø {COMPARISON_WEEKS} Wochen")),
+                    );
+                });
+            self.frame_timings
+                .add(FrameSection::Plots, plot_start.elapsed());
+        }
+        ui.separator();
+        if let Some(summary) = self.get_goal_summary(&self.week_totals_rx) {
+            ui.label(format!("Woche: {}", describe_goal_summary(summary)));
+        }
+        if let Some(summary) = self.get_goal_summary(&self.month_totals_rx) {
+            ui.label(format!("Monat: {}", describe_goal_summary(summary)));
+        }
+    }
+
+    fn draw_main_view_month(&mut self, ui: &mut egui::Ui) {
+        let date_before = self.state.selected_date;
+        ui.horizontal(|ui| {
+            if ui.button("<").clicked() {
+                self.state.selected_date = self.state.selected_date - Months::new(1);
+            }
+            ui.label(format_month(
+                self.settings.language,
+                self.state.selected_date,
+            ));
+            if ui.button(">").clicked() {
+                self.state.selected_date = self.state.selected_date + Months::new(1);
+            }
+            self.state.selected_date = min(self.state.selected_date, Local::now().date_naive());
+        });
+        if date_before != self.state.selected_date {
+            debug!("Selected date changed to: {:?}", self.state.selected_date);
+            self.hour_selection = None;
+            self.get_db_events();
+        }
+        let Some((bars, steps_month)) = self.get_month_chart() else {
+            return;
+        };
+        ui.label(format!(
+            "Schritte gesamt: {}",
+            format_number(self.settings.language, steps_month)
+        ));
+        if let Some(summary) = self.get_goal_summary(&self.month_totals_rx) {
+            ui.label(describe_goal_summary(summary));
+        }
+        let plot_start = Instant::now();
+        Plot::new("month_plot")
+            .height(300.0)
+            .include_y(0)
+            .include_x(0.5)
+            .include_x(31.5)
+            .allow_zoom(false)
+            .allow_drag(false)
+            .allow_scroll(false)
+            .show_grid([false, true])
+            .x_grid_spacer(uniform_grid_spacer(|_| [7., 7., 1.]))
+            .y_axis_min_width(40.)
+            .y_axis_formatter(|mark, _range| {
+                format_number(self.settings.language, mark.value as i64)
+            })
+            .clamp_grid(true)
+            .set_margin_fraction((0.01, 0.1).into())
+            .legend(Legend::default())
+            .reset()
+            .show(ui, |plot_ui| {
+                plot_ui.hline(
+                    HLine::new(self.settings.daily_target)
+                        .name("Schrittziel")
+                        .highlight(true),
+                );
+                plot_ui.bar_chart(BarChart::new(bars));
+            });
+        self.frame_timings
+            .add(FrameSection::Plots, plot_start.elapsed());
+    }
+
+    fn get_goal_summary(
+        &self,
+        totals_rx: &MessageReceiver<anyhow::Result<Vec<PedometerDailyTotal>>>,
+    ) -> Option<PedometerGoalSummary> {
+        let Some(Ok(totals)) = &totals_rx.current else {
+            return None;
+        };
+        PedometerGoalSummary::new(totals, self.settings.daily_target)
+    }
+
+    fn draw_main_view_settings(&mut self, ui: &mut egui::Ui) {
+        let language = self.settings.language;
+        ui.horizontal(|ui| {
+            ui.label("Tägliches Schrittziel");
+            for preset in PedometerSettings::DAILY_TARGET_PRESETS {
+                if ui
+                    .selectable_label(
+                        self.settings.daily_target == preset,
+                        format_number(language, preset as i64),
+                    )
+                    .clicked()
+                {
+                    self.daily_target_text = None;
+                    self.set_daily_target(preset);
+                }
+            }
+            let mut text = self
+                .daily_target_text
+                .clone()
+                .unwrap_or_else(|| format_number(language, self.settings.daily_target as i64));
+            let response = ui.add(TextEdit::singleline(&mut text).desired_width(80.0));
+            let daily_target = parse_daily_target(language, &text);
+            if response.changed() {
+                if let Some(daily_target) = daily_target {
+                    self.set_daily_target(daily_target);
+                }
+                self.daily_target_text = Some(text);
+            }
+            // Show the formatted target again once it was entered completely
+            if response.lost_focus() && daily_target.is_some() {
+                self.daily_target_text = None;
+            }
+        });
+        if self
+            .daily_target_text
+            .as_ref()
+            .is_some_and(|text| parse_daily_target(language, text).is_none())
+        {
+            ui.colored_label(
+                ui.visuals().warn_fg_color,
+                format!(
+                    "Bitte eine ganze Zahl von {} bis {} eingeben",
+                    format_number(
+                        language,
+                        *PedometerSettings::DAILY_TARGET_RANGE.start() as i64
+                    ),
+                    format_number(
+                        language,
+                        *PedometerSettings::DAILY_TARGET_RANGE.end() as i64
+                    )
+                ),
+            );
+        }
+        if ui
+            .add(
+                Slider::new(&mut self.settings.day_start_hour, 0..=6)
+                    .text("Tageswechsel für Monatsansicht und Zielauswertung (Uhr)"),
+            )
+            .changed()
+        {
+            DAY_START_HOUR.store(self.settings.day_start_hour, Ordering::Relaxed);
+            self.get_daily_totals();
+        }
+        ComboBox::from_label("Sprache")
+            .selected_text(self.settings.language.to_string())
+            .show_ui(ui, |ui| {
+                for language in Language::iter() {
+                    ui.selectable_value(
+                        &mut self.settings.language,
+                        language,
+                        language.to_string(),
+                    );
+                }
+            });
+        ComboBox::from_label("Einheiten")
+            .selected_text(self.settings.unit_system.to_string())
+            .show_ui(ui, |ui| {
+                for unit_system in UnitSystem::iter() {
+                    ui.selectable_value(
+                        &mut self.settings.unit_system,
+                        unit_system,
+                        unit_system.to_string(),
+                    );
+                }
+            });
+        ui.add(
+            Slider::new(&mut self.settings.scan_policy.timeout_ms, 1000..=30000)
+                .step_by(1000.0)
+                .text("Suchdauer (ms)"),
+        );
+        ui.add(
+            Slider::new(&mut self.settings.scan_policy.poll_interval_ms, 50..=1000)
+                .step_by(50.0)
+                .text("Abfrageintervall der Suche (ms)"),
+        );
+        ui.add(
+            Slider::new(&mut self.settings.scan_policy.retries, 0..=5)
+                .text("Wiederholungen der Suche"),
+        );
+        ui.add(
+            Slider::new(&mut self.settings.scan_policy.long_scan_ms, 30000..=60000)
+                .step_by(5000.0)
+                .text("Dauer der langen Suche (ms)"),
+        );
+        if ui
+            .checkbox(
+                &mut self.settings.listen_for_live_data,
+                "Live-Daten ohne Verbindung empfangen",
+            )
+            .changed()
+        {
+            self.set_listening(self.settings.listen_for_live_data);
+        }
+        ui.separator();
+        ui.heading("Synchronisation");
+        ui.checkbox(
+            &mut self.settings.sync_policy.sync_on_connect,
+            "Schritte nach dem Verbinden abrufen",
+        );
+        if ui
+            .add(
+                Slider::new(
+                    &mut self.settings.sync_policy.auto_sync_interval_mins,
+                    0..=240,
+                )
+                .step_by(15.0)
+                .text("Automatisch abrufen alle Minuten (0 = aus)"),
+            )
+            .changed()
+        {
+            self.set_sync_progress_notification();
+        }
+        ui.add_enabled(
+            self.settings.listen_for_live_data,
+            egui::Checkbox::new(
+                &mut self.settings.sync_policy.sync_when_data_waiting,
+                "Abrufen, wenn der Schrittzähler viele neue Schritte hat",
+            ),
+        )
+        .on_disabled_hover_text("Erfordert das Empfangen der Live-Daten");
+        if ui
+            .checkbox(
+                &mut self.settings.sync_policy.delete_after_sync,
+                "Abgerufene Schritte auf dem Schrittzähler löschen",
+            )
+            .changed()
+        {
+            DELETE_AFTER_SYNC.store(
+                self.settings.sync_policy.delete_after_sync,
+                Ordering::Relaxed,
+            );
+        }
+        if cfg!(target_os = "android") {
+            ui.checkbox(
+                &mut self.settings.sync_policy.wifi_only,
+                "Automatisch nur im WLAN abrufen",
+            );
+            ui.checkbox(
+                &mut self.settings.sync_policy.charging_only,
+                "Automatisch nur beim Laden abrufen",
+            );
+        }
+        ui.separator();
+        ui.heading("Bewegungserinnerung");
+        ui.add(
+            Slider::new(&mut self.settings.idle_alert.minutes, 0..=180)
+                .step_by(15.0)
+                .text("Erinnern nach Minuten ohne Schritte (0 = aus)"),
+        );
+        ui.add(
+            Slider::new(&mut self.settings.idle_alert.active_hours_start, 0..=23)
+                .text("Aktiv ab (Uhr)"),
+        );
+        ui.add(
+            Slider::new(&mut self.settings.idle_alert.active_hours_end, 0..=24)
+                .text("Aktiv bis (Uhr)"),
+        );
+        ui.separator();
+        ui.heading("Batterie");
+        ui.add(
+            Slider::new(&mut self.settings.battery.sample_interval_secs, 60..=3600)
+                .step_by(60.0)
+                .text("Messintervall (s)"),
+        );
+        ui.add(
+            Slider::new(
+                &mut self.settings.battery.low_sample_interval_secs,
+                10..=600,
+            )
+            .step_by(10.0)
+            .text("Messintervall bei niedriger Spannung (s)"),
+        );
+        ui.add(
+            Slider::new(&mut self.settings.battery.low_mv, 3300..=4000)
+                .step_by(10.0)
+                .text("Warnung unter (mV)"),
+        );
+        ui.separator();
+        ui.heading("Schritterkennung");
+        ui.add(
+            Slider::new(&mut self.settings.step_detection.threshold, 1..=31)
+                .text("Mindestbeschleunigung (× 16 mg)"),
+        );
+        ui.add(
+            Slider::new(&mut self.settings.step_detection.debounce_steps, 0..=7)
+                .text("Schritte in Folge, bevor gezählt wird"),
+        );
+        ui.label("Höhere Werte zählen z. B. beim Radfahren weniger falsche Schritte");
+        ui.add(
+            Slider::new(
+                &mut self.settings.step_detection.motion_sleep_minutes,
+                0..=120,
+            )
+            .text("Ruhemodus nach Minuten ohne Schritte (0 = nie)"),
+        );
+        ui.label("Im Ruhemodus spart der Sensor Strom und wacht bei Bewegung wieder auf");
+        // The diagnostics are read in the debug view
+        if let Some(Ok(diagnostics)) = &self.diagnostics_rx.current {
+            if diagnostics.detected_steps > 0 {
+                ui.label(format!(
+                    "Seit dem Start des Schrittzählers wurden {} von {} erkannten Schritten nicht gezählt ({:.0}%)",
+                    diagnostics.filtered_steps(),
+                    diagnostics.detected_steps,
+                    f64::from(diagnostics.filtered_steps()) * 100.0
+                        / f64::from(diagnostics.detected_steps)
+                ));
+            }
+        }
+        if ui
+            .add_enabled(
+                self.connected && self.write_config_rx.receiver.is_none(),
+                Button::new("Auf den Schrittzähler übertragen"),
+            )
+            .clicked()
+        {
+            self.write_device_config(
+                [
+                    self.settings.idle_alert.config_values(),
+                    self.settings.battery.config_values(),
+                    self.settings.step_detection.config_values(),
+                ]
+                .concat(),
+            );
+        }
+        ui.separator();
+        ui.heading("Benachrichtigungen");
+        ui.checkbox(
+            &mut self.settings.notifications.goal_reached,
+            "Tagesziel erreicht",
+        );
+        ui.checkbox(
+            &mut self.settings.celebrate_goal,
+            "Konfetti beim Erreichen des Tagesziels",
+        );
+        ui.checkbox(
+            &mut self.settings.notifications.low_battery,
+            format!("Akku unter {LOW_BATTERY_NOTIFICATION_PERCENT}%"),
+        );
+        ui.checkbox(
+            &mut self.settings.notifications.sync_failed,
+            "Synchronisation fehlgeschlagen",
+        );
+        if ui
+            .checkbox(
+                &mut self.settings.notifications.sync_progress,
+                "Fortschritt zum Tagesziel beim automatischen Abrufen",
+            )
+            .changed()
+        {
+            self.set_sync_progress_notification();
+        }
+        ui.separator();
+        ui.heading("Datenschnittstelle");
+        let mut api_changed = ui
+            .checkbox(
+                &mut self.settings.api.enabled,
+                "HTTP-Schnittstelle für andere Programme (nur localhost)",
+            )
+            .changed();
+        api_changed |= ui
+            .checkbox(
+                &mut self.settings.api.metrics,
+                "Prometheus-Metriken für die Überwachung",
+            )
+            .changed();
+        api_changed |= ui
+            .add(DragValue::new(&mut self.settings.api.port).range(1024..=65535))
+            .changed();
+        if api_changed {
+            self.set_api_policy();
+        }
+        if self.settings.api.enabled {
+            ui.label(format!(
+                "http://127.0.0.1:{0}/daily?days=7\nhttp://127.0.0.1:{0}/events?hours=24",
+                self.settings.api.port
+            ));
+        }
+        if self.settings.api.metrics {
+            ui.label(format!(
+                "http://127.0.0.1:{}/metrics",
+                self.settings.api.port
+            ));
+        }
+        ui.separator();
+        ui.heading("Archiv");
+        ui.add(
+            Slider::new(&mut self.settings.archive.months, 0..=60)
+                .text("Schritte archivieren nach Monaten (0 = aus)"),
+        );
+        ui.checkbox(
+            &mut self.settings.archive.keep_raw,
+            "Rohdaten komprimiert aufbewahren",
+        );
+        if ui
+            .add_enabled(
+                self.settings.archive.months > 0 && self.archive_rx.receiver.is_none(),
+                Button::new("Jetzt archivieren"),
+            )
+            .clicked()
+        {
+            self.archive_events();
+        }
+        ui.label("Archivierte Tage werden nur noch als Tagessumme angezeigt.");
+        ui.separator();
+        ui.heading("Forschung");
+        ui.add(Slider::new(&mut self.research_days, 1..=365).text("Tage exportieren"));
+        if ui
+            .add_enabled(
+                self.research_export_rx.receiver.is_none(),
+                Button::new("Anonymisiert exportieren..."),
+            )
+            .clicked()
+        {
+            self.research_consent = Some(false);
+        }
+        ui.separator();
+        ui.heading("Profile");
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut self.profile_name);
+            if ui
+                .add_enabled(
+                    !self.profile_name.is_empty(),
+                    Button::new("Als Profil speichern"),
+                )
+                .clicked()
+            {
+                self.save_profile();
+            }
+        });
+        ui.horizontal(|ui| {
+            ComboBox::from_label("Profil")
+                .selected_text(self.selected_profile.clone().unwrap_or_default())
+                .show_ui(ui, |ui| {
+                    for name in &self.profiles {
+                        ui.selectable_value(&mut self.selected_profile, Some(name.clone()), name);
+                    }
+                });
+            if ui
+                .add_enabled(
+                    self.selected_profile.is_some()
+                        && self.connected
+                        && self.write_config_rx.receiver.is_none(),
+                    Button::new("Auf den Schrittzähler anwenden"),
+                )
+                .clicked()
+            {
+                self.apply_profile();
+            }
+        });
+        match &self.profile_result {
+            Some(Ok(message)) => {
+                ui.label(message);
+            }
+            Some(Err(e)) => {
+                ui.colored_label(ui.visuals().error_fg_color, e.to_string());
+            }
+            None => {}
+        }
+    }
+
+    fn draw_main_view_sessions(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Sessions");
+        match &self.sessions_rx.current {
+            Some(Ok(sessions)) if !sessions.is_empty() => {
+                let now_ms = Local::now().timestamp_millis();
+                Grid::new("sessions").striped(true).show(ui, |ui| {
+                    ui.strong("Start");
+                    ui.strong("Dauer");
+                    ui.strong("Schritte");
+                    ui.strong("Kadenz");
+                    ui.strong("Strecke");
+                    ui.end_row();
+                    for session in sessions.iter().rev() {
+                        ui.label(
+                            session
+                                .get_start_date_time_local()
+                                .map(|dt| format_date_time(self.settings.language, &dt, false))
+                                .unwrap_or_default(),
+                        );
+                        let duration = format!("{} min", session.duration_ms(now_ms) / 60_000);
+                        ui.label(if session.end_ms.is_some() {
+                            duration
+                        } else {
+                            format!("{duration} (läuft)")
+                        });
+                        ui.label(format_number(self.settings.language, session.steps));
+                        ui.label(
+                            session
+                                .steps_per_minute(now_ms)
+                                .map(|cadence| format!("{cadence:.0} Schritte/min"))
+                                .unwrap_or_else(|| "-".to_string()),
+                        );
+                        ui.label(
+                            session
+                                .distance_m
+                                .map(|distance_m| format!("{:.2} km", distance_m / 1000.0))
+                                .unwrap_or_else(|| "-".to_string()),
+                        );
+                        ui.end_row();
+                    }
+                });
+                ui.label(
+                    "Die Schritte einer Session werden beim Abrufen der Schritte aktualisiert.",
+                );
+            }
+            Some(Ok(_)) => {
+                ui.label("Noch keine Sessions aufgezeichnet");
+            }
+            _ => {}
+        }
+        self.draw_gpx_import(ui);
+    }
+
+    fn draw_gpx_import(&mut self, ui: &mut egui::Ui) {
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.label("GPX-Datei");
+            ui.text_edit_singleline(&mut self.gpx_path);
+            if ui
+                .add_enabled(
+                    !self.gpx_path.is_empty() && self.import_track_rx.receiver.is_none(),
+                    Button::new("Importieren"),
+                )
+                .clicked()
+            {
+                self.import_track();
+            }
+        });
+        ui.label(
+            "Die Strecke des Tracks wird allen beendeten Sessions in seinem Zeitraum zugeordnet.",
+        );
+    }
+
+    fn draw_main_view_debug(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.show_frame_timings, "Frame-Zeiten anzeigen");
+        ui.separator();
+        self.draw_debug_events(ui);
+        ui.separator();
+        if ui
+            .add_enabled(self.connected, Button::new("Diagnosedaten lesen"))
+            .clicked()
+        {
+            self.read_diagnostics();
+        }
+        if let Some(Ok(diagnostics)) = &self.diagnostics_rx.current {
+            ui.label(format!("FIFO-Interrupts: {}", diagnostics.fifo_interrupts));
+            ui.label(format!("Timer-Fallbacks: {}", diagnostics.timer_fallbacks));
+            ui.label(format!("FIFO-Überläufe: {}", diagnostics.fifo_overruns));
+            ui.label(format!(
+                "Erkannte Schritte: {}, gezählt: {}, gefiltert: {}",
+                diagnostics.detected_steps,
+                diagnostics.counted_steps,
+                diagnostics.filtered_steps()
+            ));
+            ui.label(format!(
+                "Neustarts des Bewegungssensors: {}",
+                diagnostics.imu_power_cycles
+            ));
+            ui.label(format!(
+                "Verworfene Befehle des Geräts: {}",
+                diagnostics.dropped_commands
+            ));
+        }
+        ui.label(format!(
+            "Verworfene Befehle der App: {}",
+            metrics::dropped_commands()
+        ));
+        ui.separator();
+        self.draw_device_clock(ui);
+        ui.separator();
+        self.draw_acceleration(ui);
+        ui.separator();
+        match &self.last_disconnect_rx.current {
+            Some(Ok(Some(disconnect))) => {
+                let time = disconnect
+                    .get_date_time_local()
+                    .map(|dt| format_date_time(self.settings.language, &dt, true))
+                    .unwrap_or_default();
+                let reason = disconnect
+                    .get_error()
+                    .map(describe_device_error)
+                    .unwrap_or_else(|e| e.to_string());
+                ui.label(format!("Letzter Verbindungsabbruch: {time}\n{reason}"));
+            }
+            Some(Ok(None)) => {
+                ui.label("Kein Verbindungsabbruch aufgezeichnet");
+            }
+            _ => {}
+        }
+        match &self.last_maintenance_rx.current {
+            Some(Ok(Some(maintenance))) => {
+                let time = maintenance
+                    .get_date_time_local()
+                    .map(|dt| format_date_time(self.settings.language, &dt, true))
+                    .unwrap_or_default();
+                ui.label(format!(
+                    "Letzte Speicherwartung: {time}\n{} Ereignisse, {} entfernt, {} in falscher Reihenfolge, {}% belegt",
+                    maintenance.events,
+                    maintenance.removed_entries,
+                    maintenance.out_of_order_events,
+                    maintenance.fill_percent
+                ));
+            }
+            Some(Ok(None)) => {
+                ui.label("Keine Speicherwartung aufgezeichnet");
+            }
+            _ => {}
+        }
+        ui.separator();
+        self.draw_audit_report(ui);
+        ui.separator();
+        self.draw_schema_info(ui);
+        ui.separator();
+        ui.heading("Zeitsynchronisation");
+        if let Some(Ok(qualities)) = &self.time_sync_rx.current {
+            for quality in qualities.iter().rev() {
+                let text = format!(
+                    "Start {}: Versatz {} ms, Abweichung {} ms, Drift {}, {} Messungen",
+                    quality.boot_id,
+                    quality.offset_ms,
+                    quality.max_deviation_ms,
+                    quality
+                        .drift_ppm
+                        .map(|drift_ppm| format!("{drift_ppm:.1} ppm"))
+                        .unwrap_or_else(|| "unbekannt".to_string()),
+                    quality.samples
+                );
+                if quality.is_accurate() {
+                    ui.label(text);
+                } else {
+                    ui.colored_label(ui.visuals().warn_fg_color, text);
+                }
+            }
+        }
+        ui.separator();
+        ui.heading("Neustarts");
+        let mut delete_boot_id = None;
+        if let Some(Ok(boots)) = &self.boots_rx.current {
+            let format_time = |time: anyhow::Result<DateTime<Local>>| {
+                time.map(|dt| format_date_time(self.settings.language, &dt, true))
+                    .unwrap_or_default()
+            };
+            ScrollArea::vertical()
+                .id_salt("boots")
+                .max_height(200.0)
+                .show(ui, |ui| {
+                    let mut previous_boot: Option<&PedometerPersistenceBoot> = None;
+                    for boot in boots.iter().rev() {
+                        ui.label(format!(
+                            "Start {}: {} bis {}\n{}",
+                            boot.boot_id,
+                            format_time(boot.get_first_date_time_local()),
+                            format_time(boot.get_last_date_time_local()),
+                            boot.reset_reason
+                                .map(|reset_reason| describe_reset_reason(reset_reason as u32))
+                                .unwrap_or_else(|| "Unbekannter Grund".to_string()),
+                        ));
+                        if let Some(self_test) = boot.get_self_test() {
+                            let text = describe_self_test(self_test);
+                            if self_test.passed() {
+                                ui.label(text);
+                            } else {
+                                ui.colored_label(ui.visuals().error_fg_color, text);
+                            }
+                        }
+                        // Boots are listed from the newest one, so the previous one is later
+                        if let Some(later_boot) = previous_boot {
+                            // Only older boots are fully synced
+                            if ui
+                                .add_enabled(
+                                    self.connected && self.delete_boot_rx.receiver.is_none(),
+                                    Button::new("Auf dem Schrittzähler löschen"),
+                                )
+                                .clicked()
+                            {
+                                delete_boot_id = Some(boot.boot_id);
+                            }
+                            let gap_minutes =
+                                (later_boot.first_timestamp_ms - boot.last_timestamp_ms) / 60_000;
+                            if gap_minutes > 0 {
+                                ui.label(format!(
+                                    "Lücke bis zum nächsten Start: {gap_minutes} min"
+                                ));
+                            }
+                        }
+                        previous_boot = Some(boot);
+                    }
+                });
+        }
+        if let Some(boot_id) = delete_boot_id.and_then(|boot_id| u32::try_from(boot_id).ok()) {
+            self.delete_boot(boot_id);
+        }
+        self.draw_device_boots(ui);
+    }
+
+    /// Boots whose events are still stored on the device, so the sync coverage can be checked
+    /// without requesting the events.
+    fn draw_device_boots(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Auf dem Schrittzähler gespeichert");
+            if ui
+                .add_enabled(
+                    self.connected && self.device_boots_rx.receiver.is_none(),
+                    Button::new("Abfragen"),
+                )
+                .clicked()
+            {
+                self.read_device_boots();
+            }
+        });
+        match &self.device_boots_rx.current {
+            Some(Ok(summaries)) => {
+                let synced_boot_ids: HashSet<i64> = match &self.boots_rx.current {
+                    Some(Ok(boots)) => boots.iter().map(|boot| boot.boot_id).collect(),
+                    _ => HashSet::new(),
+                };
+                for summary in summaries.iter().rev() {
+                    ui.label(format!(
+                        "Start {}: Ereignisse {} bis {}, {} min, {} Schritte{}",
+                        summary.boot_id,
+                        summary.first_index,
+                        summary.last_index,
+                        (summary.last_timestamp_ms - summary.first_timestamp_ms) / 60_000,
+                        summary.steps,
+                        if synced_boot_ids.contains(&i64::from(summary.boot_id)) {
+                            ""
+                        } else {
+                            " (nicht synchronisiert)"
+                        }
+                    ));
+                }
+            }
+            Some(Err(e)) => {
+                ui.label(format!("Die Starts konnten nicht abgefragt werden: {e}"));
+            }
+            None => {}
+        }
+        if let Some(result) = self.device_delete_result {
+            ui.label(match result.oldest {
+                Some((boot_id, index)) => format!(
+                    "Zuletzt {} Ereignisse gelöscht, ältere als Start {boot_id}, Ereignis \
+                     {index} sind nur noch in der Datenbank",
+                    result.deleted
+                ),
+                None => format!(
+                    "Zuletzt {} Ereignisse gelöscht, alle Ereignisse sind nur noch in der \
+                     Datenbank",
+                    result.deleted
+                ),
+            });
+        }
+    }
+
+    /// Clock of the device compared to the host clock, e.g. to check the drift since the last
+    /// time sync.
+    fn draw_device_clock(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(self.connected, Button::new("Uhr des Schrittzählers lesen"))
+                .clicked()
+            {
+                self.read_device_clock();
+            }
+            if ui
+                .add_enabled(
+                    self.connected && self.resync_time_rx.receiver.is_none(),
+                    Button::new("Zeit neu synchronisieren"),
+                )
+                .clicked()
+            {
+                self.resync_time();
+            }
+        });
+        match &self.device_clock_rx.current {
+            Some(Ok(clock)) => {
+                let format = |epoch_ms: u64| {
+                    DateTime::from_timestamp_millis(epoch_ms as i64)
+                        .map(|dt| {
+                            format_date_time(
+                                self.settings.language,
+                                &dt.with_timezone(&Local),
+                                true,
+                            )
+                        })
+                        .unwrap_or_default()
+                };
+                ui.label(format!(
+                    "Schrittzähler: {}\nComputer: {}\nAbweichung: {:+} ms (Umlaufzeit {} ms)",
+                    format(clock.device_epoch_ms),
+                    format(clock.host_epoch_ms),
+                    clock.offset_ms(),
+                    clock.round_trip_ms
+                ));
+            }
+            Some(Err(e)) => {
+                ui.label(format!("Die Uhr konnte nicht gelesen werden: {e}"));
+            }
+            None => {}
+        }
+    }
+
+    /// Raw accelerometer samples of the last seconds to evaluate the step detection settings.
+    fn draw_acceleration(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Beschleunigung");
+        let enabled = self.connected && self.stream_acceleration_rx.receiver.is_none();
+        let text = if self.acceleration_streaming {
+            "Übertragung beenden"
+        } else {
+            "Rohdaten übertragen"
+        };
+        if ui.add_enabled(enabled, Button::new(text)).clicked() {
+            self.stream_acceleration(!self.acceleration_streaming);
+        }
+        if self.acceleration.is_empty() {
+            return;
+        }
+        Plot::new("acceleration_plot")
+            .height(200.0)
+            .allow_zoom(false)
+            .allow_drag(false)
+            .allow_scroll(false)
+            .y_axis_min_width(40.)
+            .y_axis_formatter(|mark, _range| format!("{:.0} mg", mark.value))
+            .legend(Legend::default())
+            .show(ui, |plot_ui| {
+                plot_ui.line(Line::new(self.acceleration.points(|sample| sample.x)).name("X"));
+                plot_ui.line(Line::new(self.acceleration.points(|sample| sample.y)).name("Y"));
+                plot_ui.line(Line::new(self.acceleration.points(|sample| sample.z)).name("Z"));
+            });
+    }
+
+    fn draw_debug_events(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Events");
+        ui.horizontal(|ui| {
+            ui.label("Von");
+            ui.add(
+                DatePickerButton::new(&mut self.debug_filter.first_day)
+                    .id_salt("debug_first_day")
+                    .calendar_week(false)
+                    .format(date_pattern(self.settings.language)),
+            );
+            ui.label("bis");
+            ui.add(
+                DatePickerButton::new(&mut self.debug_filter.last_day)
+                    .id_salt("debug_last_day")
+                    .calendar_week(false)
+                    .format(date_pattern(self.settings.language)),
+            );
+            if ui
+                .add_enabled(
+                    self.debug_events_rx.receiver.is_none(),
+                    Button::new("Events aus DB holen"),
+                )
+                .clicked()
+            {
+                self.get_debug_events();
+            }
+        });
+        ui.horizontal(|ui| {
+            let mut filter_boot = self.debug_filter.boot_id.is_some();
+            if ui.checkbox(&mut filter_boot, "Nur Start").changed() {
+                self.debug_filter.boot_id = filter_boot.then_some(0);
+            }
+            if let Some(boot_id) = &mut self.debug_filter.boot_id {
+                ui.add(egui::DragValue::new(boot_id).range(0..=i64::MAX));
+            }
+            ComboBox::from_label("Quelle")
+                .selected_text(
+                    self.debug_filter
+                        .source
+                        .map(|source| source.to_string())
+                        .unwrap_or_else(|| "Alle".to_string()),
+                )
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.debug_filter.source, None, "Alle");
+                    for source in PedometerDebugEventSource::iter() {
+                        ui.selectable_value(
+                            &mut self.debug_filter.source,
+                            Some(source),
+                            source.to_string(),
+                        );
+                    }
+                });
+        });
+        ui.horizontal_wrapped(|ui| {
+            for event_type in PedometerDebugEventType::iter() {
+                let mut selected = self.debug_filter.event_types.contains(&event_type);
+                if ui.checkbox(&mut selected, event_type.to_string()).changed() {
+                    if selected {
+                        self.debug_filter.event_types.insert(event_type);
+                    } else {
+                        self.debug_filter.event_types.remove(&event_type);
+                    }
+                }
+            }
+        });
+        match &self.debug_events_rx.current {
+            Some(Ok(events)) => {
+                let filtered: Vec<_> = events
+                    .iter()
+                    .filter(|event| self.debug_filter.matches(event))
+                    .collect();
+                ui.horizontal(|ui| {
+                    ui.label(format!("{} von {} Events", filtered.len(), events.len()));
+                    if ui
+                        .add_enabled(!filtered.is_empty(), Button::new("Als CSV kopieren"))
+                        .clicked()
+                    {
+                        ui.ctx()
+                            .copy_text(debug_events::to_csv(filtered.iter().copied()));
+                    }
+                });
+                ScrollArea::vertical()
+                    .id_salt("debug_events")
+                    .max_height(300.0)
+                    .show(ui, |ui| {
+                        for event in filtered {
+                            let time = DateTime::from_timestamp_millis(event.timestamp_ms)
+                                .map(|dt| {
+                                    format_date_time(
+                                        self.settings.language,
+                                        &dt.with_timezone(&Local),
+                                        true,
+                                    )
+                                })
+                                .unwrap_or_else(|| event.timestamp_ms.to_string());
+                            ui.label(format!(
+                                "{time} {} ({}), Start {}, Event {}: {}",
+                                event.event_type,
+                                event.source,
+                                event
+                                    .boot_id
+                                    .map(|boot_id| boot_id.to_string())
+                                    .unwrap_or_else(|| "-".to_string()),
+                                event
+                                    .event_id
+                                    .map(|event_id| event_id.to_string())
+                                    .unwrap_or_else(|| "-".to_string()),
+                                event.details
+                            ));
+                        }
+                    });
+            }
+            Some(Err(e)) => {
+                ui.label(format!("Fehler: {e}"));
+            }
+            None => {}
+        }
+    }
+
+    fn draw_audit_report(&mut self, ui: &mut egui::Ui) {
+        if ui
+            .add_enabled(
+                self.audit_rx.receiver.is_none(),
+                Button::new("Datenprüfung starten"),
+            )
+            .clicked()
+        {
+            self.run_audit();
+        }
+        let Some(Ok(report)) = &self.audit_rx.current else {
+            return;
+        };
+        if report.is_ok() {
+            ui.label("Keine Auffälligkeiten gefunden");
+            return;
+        }
+        ScrollArea::vertical()
+            .id_salt("audit")
+            .max_height(200.0)
+            .show(ui, |ui| {
+                for (_, missing) in &report.missing_event_ids {
+                    ui.label(format!(
+                        "Fehlende Events: {} bis {}",
+                        missing.start(),
+                        missing.end()
+                    ));
+                }
+                for (previous, current) in &report.negative_step_deltas {
+                    ui.label(format!(
+                        "Negative Schrittdifferenz: Event {} ({}) nach Event {} ({}) in Start {}",
+                        current.event_id,
+                        current.steps,
+                        previous.event_id,
+                        previous.steps,
+                        current.boot_id
+                    ));
+                }
+                for duplicate in &report.duplicate_event_ids {
+                    ui.label(format!(
+                        "Doppeltes Event: {} in Start {}",
+                        duplicate.event_id, duplicate.boot_id
+                    ));
+                }
+                for invalid in &report.invalid_timestamps {
+                    ui.label(format!(
+                        "Unmöglicher Zeitstempel: Event {} in Start {} ({} ms)",
+                        invalid.event_id, invalid.boot_id, invalid.timestamp_ms
+                    ));
+                }
+            });
+        if let Some(first_missing_event) = report.first_missing_event() {
+            if ui
+                .add_enabled(
+                    self.connected,
+                    Button::new("Fehlende Events erneut anfordern"),
+                )
+                .clicked()
+            {
+                self.request_events(Some(first_missing_event));
+            }
+        }
+    }
+
+    /// Dialog to decide which of the flagged step spikes are real and should be counted.
+    fn draw_outlier_review(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_outlier_review;
+        let mut changed = None;
+        egui::Window::new("Schrittspitzen prüfen")
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.label("Diese Ereignisse enthalten unplausibel viele Schritte in kurzer Zeit.");
+                ui.label("Nur angehakte Ereignisse werden in den Summen gezählt.");
+                ui.separator();
+                let outliers = self.get_outliers_ref();
+                if outliers.is_empty() {
+                    ui.label("Keine Schrittspitzen gefunden");
+                    return;
+                }
+                ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                    Grid::new("outliers").striped(true).show(ui, |ui| {
+                        ui.strong("Zeitpunkt");
+                        ui.strong("Schritte");
+                        ui.strong("Zählen");
+                        ui.end_row();
+                        for outlier in outliers {
+                            ui.label(outlier.get_date_time_local().map_or_else(
+                                |_| "-".to_string(),
+                                |dt| format_date_time(self.settings.language, &dt, false),
+                            ));
+                            ui.label(format_number(self.settings.language, outlier.steps));
+                            let mut accepted = outlier.accepted;
+                            if ui
+                                .add_enabled(
+                                    self.set_outlier_rx.receiver.is_none(),
+                                    egui::Checkbox::without_text(&mut accepted),
+                                )
+                                .changed()
+                            {
+                                changed = Some((*outlier, accepted));
+                            }
+                            ui.end_row();
+                        }
+                    });
+                });
+            });
+        self.show_outlier_review = open;
+        if let Some((outlier, accepted)) = changed {
+            self.set_outlier_accepted(&outlier, accepted);
+        }
+    }
+
+    /// Explain what the research export contains and only start it with the consent of the user.
+    fn draw_research_consent(&mut self, ctx: &egui::Context) {
+        let Some(mut consented) = self.research_consent else {
+            return;
+        };
+        let mut open = true;
+        let mut closed = false;
+        let mut export = false;
+        egui::Window::new("Schritte für die Forschung exportieren")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .anchor(Align2::CENTER_CENTER, Vec2::ZERO)
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "Exportiert werden die Schritte pro Stunde der letzten {} Tage.",
+                    self.research_days
+                ));
+                ui.label(
+                    "Name und Adresse des Schrittzählers sowie die Nummern der Starts und \
+                     Ereignisse sind nicht enthalten.",
+                );
+                ui.label(format!(
+                    "Jede Stunde wird zufällig um bis zu {} Minuten verschoben.",
+                    RESEARCH_JITTER_MAX.num_minutes()
+                ));
+                ui.label(
+                    "Die Datei lässt trotzdem Rückschlüsse auf den Tagesablauf zu und sollte \
+                     nur mit vertrauenswürdigen Stellen geteilt werden.",
+                );
+                ui.separator();
+                ui.checkbox(
+                    &mut consented,
+                    "Ich bin damit einverstanden, dass diese Daten weitergegeben werden",
+                );
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(consented, Button::new("Exportieren"))
+                        .clicked()
+                    {
+                        export = true;
+                    }
+                    if ui.button("Abbrechen").clicked() {
+                        closed = true;
+                    }
+                });
+            });
+        self.research_consent = (open && !closed && !export).then_some(consented);
+        if export {
+            self.export_research_data();
+        }
+    }
+
+    /// Hints for the cause why the device was not found instead of a bare error.
+    fn draw_schema_info(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.heading("Datenbankschema");
+            if ui
+                .add_enabled(
+                    self.schema_info_rx.receiver.is_none(),
+                    Button::new("Aktualisieren"),
+                )
+                .clicked()
+            {
+                self.get_schema_info();
+            }
+        });
+        let Some(Ok(schema_info)) = &self.schema_info_rx.current else {
+            return;
+        };
+        ui.label(format!(
+            "Version {}, die App kennt bis {}",
+            schema_info
+                .version()
+                .map(|version| version.to_string())
+                .unwrap_or_else(|| "unbekannt".to_string()),
+            schema_info.app_version
+        ));
+        ui.collapsing(
+            format!("{} angewendete Migrationen", schema_info.migrations.len()),
+            |ui| {
+                ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
+                    Grid::new("schema_migrations").striped(true).show(ui, |ui| {
+                        for migration in schema_info.migrations.iter().rev() {
+                            ui.label(migration.version.to_string());
+                            ui.label(&migration.description);
+                            ui.label(&migration.installed_on);
+                            ui.end_row();
+                        }
+                    });
+                });
+            },
+        );
+    }
+
+    /// Explain why the database was not opened. Without it nothing is stored, but the steps stay
+    /// on the device until they are synced.
+    fn draw_database_too_new(&mut self, ctx: &egui::Context) {
+        let Some((database_version, app_version)) = self.database_too_new else {
+            return;
+        };
+        let mut open = true;
+        let mut closed = false;
+        egui::Window::new("Datenbank von neuerer App-Version")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .anchor(Align2::CENTER_CENTER, Vec2::ZERO)
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "Die Datenbank wurde von einer neueren Version der App angelegt (Schema \
+                     {database_version}, diese Version kennt bis {app_version}). Damit sie nicht \
+                     beschädigt wird, wird sie nicht geöffnet."
+                ));
+                ui.separator();
+                for hint in [
+                    "Die neueste Version der App installieren, dann sind alle Daten wieder da.",
+                    "Bis dahin nicht abrufen: Die Schritte bleiben auf dem Schrittzähler \
+                     gespeichert.",
+                ] {
+                    ui.label(format!("• {hint}"));
+                }
+                if ui.button("Schließen").clicked() {
+                    closed = true;
+                }
+            });
+        if !open || closed {
+            self.database_too_new = None;
+        }
+    }
+
+    /// Explain that nothing can be stored until the migration succeeds and offer to back up the
+    /// database before retrying it.
+    fn draw_database_read_only(&mut self, ctx: &egui::Context) {
+        let Some(error) = &self.database_read_only else {
+            return;
+        };
+        if !self.show_database_read_only {
+            return;
+        }
+        let error = error.clone();
+        let mut open = true;
+        let mut closed = false;
+        let mut backup = false;
+        let mut retry = false;
+        egui::Window::new("Datenbank nur lesbar")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .anchor(Align2::CENTER_CENTER, Vec2::ZERO)
+            .show(ctx, |ui| {
+                ui.label(
+                    "Die Datenbank konnte nicht auf die Version dieser App aktualisiert werden. \
+                     Damit keine Daten verloren gehen, ist sie nur lesbar geöffnet.",
+                );
+                ui.label(format!("Fehler: {error}"));
+                ui.separator();
+                for hint in [
+                    "Die gespeicherten Schritte können weiter angesehen werden.",
+                    "Bis dahin nicht abrufen: Die Schritte bleiben auf dem Schrittzähler \
+                     gespeichert.",
+                    "Vor einem neuen Versuch die Datenbank sichern.",
+                ] {
+                    ui.label(format!("• {hint}"));
+                }
+                ui.horizontal(|ui| {
+                    backup = ui
+                        .add_enabled(
+                            self.backup_rx.receiver.is_none(),
+                            Button::new("Sicherung erstellen"),
+                        )
+                        .clicked();
+                    retry = ui
+                        .add_enabled(
+                            self.retry_migration_rx.receiver.is_none(),
+                            Button::new("Erneut aktualisieren"),
+                        )
+                        .clicked();
+                    if self.backup_rx.receiver.is_some()
+                        || self.retry_migration_rx.receiver.is_some()
+                    {
+                        ui.spinner();
+                    }
+                    if ui.button("Schließen").clicked() {
+                        closed = true;
+                    }
+                });
+            });
+        if backup {
+            self.backup_database();
+        }
+        if retry {
+            self.retry_migration();
+        }
+        if !open || closed {
+            self.show_database_read_only = false;
+        }
+    }
+
+    fn draw_not_found_help(&mut self, ctx: &egui::Context) {
+        let Some(cause) = &self.not_found_cause else {
+            return;
+        };
+        let mut open = true;
+        let mut closed = false;
+        let mut retry = false;
+        let mut long_scan = false;
+        let long_scan_s = self.settings.scan_policy.long_scan_ms / 1000;
+        // A longer scan does not help without a working adapter
+        let scanned = matches!(
+            cause,
+            PedometerNotFoundCause::NoDevices | PedometerNotFoundCause::NameMismatch { .. }
+        );
+        egui::Window::new("Schrittzähler nicht gefunden")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .anchor(Align2::CENTER_CENTER, Vec2::ZERO)
+            .show(ctx, |ui| {
+                let hints: &[&str] = match cause {
+                    PedometerNotFoundCause::NoAdapter => {
+                        ui.label("Es wurde kein Bluetooth-Adapter gefunden.");
+                        &[
+                            "Ist ein Bluetooth-Adapter eingebaut oder eingesteckt?",
+                            "Ist Bluetooth im Betriebssystem aktiviert?",
+                        ]
+                    }
+                    PedometerNotFoundCause::AdapterOff => {
+                        ui.label("Bluetooth ist ausgeschaltet.");
+                        &["Bluetooth in den Systemeinstellungen einschalten und erneut suchen."]
+                    }
+                    PedometerNotFoundCause::NoDevices => {
+                        ui.label("Die Suche hat überhaupt keine Bluetooth-Geräte gefunden.");
+                        &[
+                            "Darf die App Bluetooth verwenden? Unter Android wird dafür auch die \
+                             Berechtigung für den Standort benötigt.",
+                            "Ist der Schrittzähler in der Nähe und geladen?",
+                        ]
+                    }
+                    PedometerNotFoundCause::NameMismatch { names } => {
+                        ui.label(format!(
+                            "Es wurden {} Bluetooth-Geräte gefunden, aber kein Schrittzähler.",
+                            names.len()
+                        ));
+                        ui.collapsing("Gefundene Geräte", |ui| {
+                            ScrollArea::vertical().max_height(150.0).show(ui, |ui| {
+                                for name in names {
+                                    ui.label(name);
+                                }
+                            });
+                        });
+                        &[
+                            "Ist der Schrittzähler mit einem anderen Gerät (z.B. der \
+                             Android-App) verbunden? Dann ist er für andere nicht sichtbar.",
+                            "Ist der Schrittzähler geladen? Bei leerem Akku sendet er nicht.",
+                            "Im Stromsparmodus sendet der Schrittzähler nur selten. Dann findet \
+                             ihn eventuell erst die lange Suche.",
+                        ]
+                    }
+                };
+                ui.separator();
+                for hint in hints {
+                    ui.label(format!("• {hint}"));
+                }
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(!self.connected, Button::new("Erneut suchen"))
+                        .clicked()
+                    {
+                        retry = true;
+                    }
+                    if scanned
+                        && ui
+                            .add_enabled(
+                                !self.connected,
+                                Button::new(format!("Lange suchen ({long_scan_s} s)")),
+                            )
+                            .on_hover_text("Sucht einmal ohne Wiederholungen, lässt sich abbrechen")
+                            .clicked()
+                    {
+                        long_scan = true;
+                    }
+                    if ui.button("Schließen").clicked() {
+                        closed = true;
+                    }
+                });
+            });
+        if !open || closed || retry || long_scan {
+            self.not_found_cause = None;
+        }
+        if retry {
+            self.set_connected(true);
+        } else if long_scan {
+            self.set_connected_with_scan_policy(true, self.settings.scan_policy.long_scan());
+        }
+    }
+
+    /// Overlay with the durations of the sections of the last frames to find slow parts of the
+    /// GUI on the phone.
+    fn draw_frame_timings(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_frame_timings;
+        egui::Window::new("Frame-Zeiten")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                Grid::new("frame_timings_grid")
+                    .num_columns(4)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.label("Abschnitt");
+                        ui.label("Letzter (ms)");
+                        ui.label("Mittel (ms)");
+                        ui.label("Max (ms)");
+                        ui.end_row();
+                        for section in FrameSection::iter() {
+                            let stats = self.frame_timings.stats(section);
+                            ui.label(section.to_string());
+                            for duration in [stats.last, stats.average, stats.max] {
+                                ui.label(format!("{:.1}", duration.as_secs_f64() * 1000.0));
+                            }
+                            ui.end_row();
+                        }
+                    });
+                let (slow_frames, frames) = self.frame_timings.slow_frames();
+                ui.label(format!(
+                    "{slow_frames} von {frames} Frames über {} ms",
+                    FRAME_BUDGET.as_millis()
+                ));
+            });
+        self.show_frame_timings = open;
+    }
+
+    fn draw_footer(&mut self, ctx: &egui::Context) {
+        TopBottomPanel::bottom("bottom_panel")
+            .frame(Frame {
+                inner_margin: Margin::symmetric(8.0, 12.0),
+                ..Frame::side_top_panel(&ctx.style())
+            })
+            .show(ctx, |ui| {
+                ui.horizontal_centered(|ui| {
+                    for view in MainView::iter() {
+                        ui.selectable_value(&mut self.state.main_view, view, view.to_string());
+                    }
+                });
+            });
+    }
+
+    fn get_db_events(&mut self) {
+        self.chart_db_generation = DB_GENERATION.load(Ordering::Relaxed);
+        let (Ok(start), Ok(day_start), Ok(end)) = (
+            local_day_start(&Local, week_first_day(self.state.selected_date), 0),
+            local_day_start(&Local, self.state.selected_date, 0),
+            local_day_start(&Local, self.state.selected_date + Duration::days(1), 0),
+        ) else {
+            error!("Invalid week of {}", self.state.selected_date);
+            return;
+        };
+
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.db_events_rx.receiver = Some(resp_rx);
+        DB_CMD_TX
+            .get()
+            .unwrap()
+            .blocking_send(PedometerDatabaseCommand::GetEventsInTimeRange {
+                start,
+                end,
+                responder: resp_tx,
+            })
+            .unwrap();
+        self.request_repaint_db = true;
+
+        // Daily summaries are created at the end of a day and the device clock may drift a bit
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.db_summaries_rx.receiver = Some(resp_rx);
+        DB_CMD_TX
+            .get()
+            .unwrap()
+            .blocking_send(PedometerDatabaseCommand::GetDailySummariesInTimeRange {
+                start: start + Duration::days(1) - Duration::hours(1),
+                end: end + Duration::hours(1),
+                responder: resp_tx,
+            })
+            .unwrap();
+
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.day_activities_rx.receiver = Some(resp_rx);
+        DB_CMD_TX
+            .get()
+            .unwrap()
+            .blocking_send(PedometerDatabaseCommand::GetActivitiesInTimeRange {
+                start: day_start,
+                end,
+                responder: resp_tx,
+            })
+            .unwrap();
+
+        self.get_daily_totals();
+    }
+
+    /// Request the daily totals of the week and the month of the selected date for the goal
+    /// summaries and of the previous weeks for the week comparison.
+    fn get_daily_totals(&mut self) {
+        let today = Local::now().date_naive();
+        let first_day_of_month = self.state.selected_date.with_day(1).unwrap();
+        for (totals_rx, first_day, last_day) in [
+            (
+                &mut self.week_totals_rx,
+                week_first_day(self.state.selected_date),
+                self.state.selected_date,
+            ),
+            (
+                &mut self.month_totals_rx,
+                first_day_of_month,
+                min(
+                    first_day_of_month + Months::new(1) - Duration::days(1),
+                    today,
+                ),
+            ),
+            (
+                &mut self.comparison_totals_rx,
+                comparison_first_day(self.state.selected_date),
+                self.state.selected_date,
+            ),
+        ] {
+            let (resp_tx, resp_rx) = oneshot::channel();
+            totals_rx.receiver = Some(resp_rx);
+            DB_CMD_TX
+                .get()
+                .unwrap()
+                .blocking_send(PedometerDatabaseCommand::GetDailyTotals {
+                    first_day,
+                    last_day,
+                    responder: resp_tx,
+                })
+                .unwrap();
+        }
+    }
+
+    fn get_chart_key(&self) -> PedometerChartKey {
+        PedometerChartKey {
+            selected_date: self.state.selected_date,
+            db_generation: self.chart_db_generation,
+            day_start_hour: self.settings.day_start_hour,
+            language: self.settings.language,
+        }
+    }
+
+    /// Steps of the selected day and their bars per hour, split by the classified activity.
+    fn get_day_chart(&mut self) -> Option<(PedometerDaySteps, Vec<PedometerDayChartLayer>)> {
+        let key = self.get_chart_key();
+        let pending = self.db_events_rx.receiver.is_some() || self.outliers_rx.receiver.is_some();
+        // The outliers are looked up via self while the chart is computed
+        let mut cache = std::mem::take(&mut self.day_chart);
+        let chart = cache.get_or_compute(key, pending, || {
+            let Some(Ok(events)) = &self.db_events_rx.current else {
+                return None;
+            };
+            let day_steps = PedometerDaySteps::new(
+                &Local,
+                key.selected_date,
+                events.iter().filter(|e| !self.is_excluded_outlier(e)),
+            );
+            let bars = |hourly: [i64; 24]| -> Vec<Bar> {
+                hourly
+                    .iter()
+                    .enumerate()
+                    .map(|(hour, steps)| Bar::new(hour as f64, *steps as f64).width(1.0))
+                    .collect()
+            };
+            let mut unclassified = day_steps.hourly;
+            let mut layers = Vec::new();
+            for activity in [
+                PedometerActivity::Idle,
+                PedometerActivity::Walking,
+                PedometerActivity::Running,
+            ] {
+                let hourly = day_steps.hourly_by_activity[activity as usize];
+                if hourly.iter().all(|steps| *steps == 0) {
+                    continue;
+                }
+                for (unclassified, steps) in unclassified.iter_mut().zip(hourly) {
+                    *unclassified -= steps;
+                }
+                layers.push(PedometerDayChartLayer {
+                    activity: Some(activity),
+                    bars: bars(hourly),
+                });
+            }
+            // Older steps and the ones of older firmware are at the bottom
+            layers.insert(
+                0,
+                PedometerDayChartLayer {
+                    activity: None,
+                    bars: bars(unclassified),
+                },
+            );
+            Some((day_steps, layers))
+        });
+        self.day_chart = cache;
+        chart
+    }
+
+    /// Time and steps of the selected day per activity. An activity of today lasts until now.
+    fn get_activity_breakdown(&mut self) -> Option<PedometerActivityBreakdown> {
+        let key = self.get_chart_key();
+        let pending =
+            self.db_events_rx.receiver.is_some() || self.day_activities_rx.receiver.is_some();
+        // The outliers are looked up via self while the breakdown is computed
+        let mut cache = std::mem::take(&mut self.activity_breakdown);
+        let breakdown = cache.get_or_compute(key, pending, || {
+            let (Some(Ok(events)), Some(Ok(activities))) =
+                (&self.db_events_rx.current, &self.day_activities_rx.current)
+            else {
+                return None;
+            };
+            let start = local_day_start(&Local, key.selected_date, 0).ok()?;
+            let end = local_day_start(&Local, key.selected_date + Duration::days(1), 0)
+                .ok()?
+                .min(Utc::now());
+            Some(PedometerActivityBreakdown::new(
+                start.timestamp_millis(),
+                end.timestamp_millis(),
+                activities,
+                events.iter().filter(|e| !self.is_excluded_outlier(e)),
+            ))
+        });
+        self.activity_breakdown = cache;
+        breakdown
+    }
+
+    /// Bars of the days of the week view and the steps of the week.
+    fn get_week_chart(&mut self) -> Option<(Vec<Bar>, i64)> {
+        let key = self.get_chart_key();
+        self.week_chart
+            .get_or_compute(key, self.week_totals_rx.receiver.is_some(), || {
+                let Some(Ok(totals)) = &self.week_totals_rx.current else {
+                    return None;
+                };
+                let bars = totals
+                    .iter()
+                    .map(|total| {
+                        Bar::new(
+                            week_bar_position(key.selected_date, total.date),
+                            total.steps as f64,
+                        )
+                        .name(format_day(key.language, total.date))
+                        .width(1.0)
+                    })
+                    .collect();
+                Some((bars, totals.iter().map(|total| total.steps).sum()))
+            })
+    }
+
+    /// Bars of this week, the previous week and the average of the previous weeks.
+    fn get_comparison_chart(&mut self) -> Option<[Vec<Bar>; 3]> {
+        let key = self.get_chart_key();
+        self.comparison_chart.get_or_compute(
+            key,
+            self.comparison_totals_rx.receiver.is_some(),
+            || {
+                let Some(Ok(totals)) = &self.comparison_totals_rx.current else {
+                    return None;
+                };
+                let comparison = PedometerWeekComparison::new(key.selected_date, totals);
+                // The bars of a day are grouped around the position of the day in the week view
+                let bars = |offset: f64, steps: [f64; WEEK_DAYS as usize]| {
+                    steps
+                        .iter()
+                        .enumerate()
+                        .map(|(day, steps)| {
+                            Bar::new((day as i64 - WEEK_DAYS + 1) as f64 + offset, *steps)
+                                .width(0.3)
+                        })
+                        .collect()
+                };
+                Some([
+                    bars(-0.3, comparison.this_week.map(|steps| steps as f64)),
+                    bars(0.0, comparison.last_week.map(|steps| steps as f64)),
+                    bars(0.3, comparison.average),
+                ])
+            },
+        )
+    }
+
+    /// Bars of the days of the month view and the steps of the month.
+    fn get_month_chart(&mut self) -> Option<(Vec<Bar>, i64)> {
+        let key = self.get_chart_key();
+        self.month_chart
+            .get_or_compute(key, self.month_totals_rx.receiver.is_some(), || {
+                let Some(Ok(totals)) = &self.month_totals_rx.current else {
+                    return None;
+                };
+                let bars = totals
+                    .iter()
+                    .map(|total| {
+                        Bar::new(total.date.day() as f64, total.steps as f64)
+                            .name(format_day(key.language, total.date))
+                            .width(1.0)
+                    })
+                    .collect();
+                Some((bars, totals.iter().map(|total| total.steps).sum()))
+            })
+    }
+
+    /// Get the steps of the given day from the daily summaries created by the device.
+    fn get_daily_summary(&self, day: NaiveDate) -> Option<i64> {
+        let Some(Ok(summaries)) = &self.db_summaries_rx.current else {
+            return None;
+        };
+        summaries
+            .iter()
+            .filter(|summary| {
+                summary
+                    .get_date_time_local()
+                    .is_ok_and(|summary_dt| daily_summary_day(&summary_dt) == day)
+            })
+            .map(|summary| summary.steps)
+            .max()
+    }
+
+    fn set_connected(&mut self, connected: bool) {
+        self.set_connected_with_scan_policy(connected, self.settings.scan_policy);
+    }
+
+    /// Like [`Self::set_connected`], but scans for the device with the given policy, e.g. the
+    /// long scan after it was not found.
+    fn set_connected_with_scan_policy(&mut self, connected: bool, scan_policy: ScanPolicy) {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.connect_events_rx.receiver = Some(resp_rx);
+        let event = if connected {
+            PedometerDeviceHandlerCommand::TryConnect {
+                address: self.settings.device_address.clone(),
+                scan_policy,
+                responder: resp_tx,
+            }
+        } else {
+            PedometerDeviceHandlerCommand::Disconnect { responder: resp_tx }
+        };
+        BLE_CMD_TX.get().unwrap().blocking_send(event).unwrap();
+        self.request_repaint_ble = true;
+    }
+
+    /// Connect and sync in the interval of the sync policy.
+    fn auto_sync(&mut self) {
+        let Some(interval) = self.settings.sync_policy.auto_sync_interval() else {
+            return;
+        };
+        if self.last_auto_sync.elapsed() < interval || self.request_repaint_ble {
+            return;
+        }
+        self.last_auto_sync = Instant::now();
+        if !self.settings.sync_policy.allows_auto_sync() {
+            info!("Skip auto sync because of its constraints");
+            return;
+        }
+        info!("Auto sync");
+        if self.connected {
+            self.request_events(None);
+        } else {
+            self.auto_sync_pending = true;
+            self.set_connected(true);
+        }
+    }
+
+    /// Connect and sync because the device asks for it in its advertising data.
+    fn sync_data_waiting(&mut self) {
+        if !self.settings.sync_policy.sync_when_data_waiting
+            || self.connected
+            || self.request_repaint_ble
+            || self.last_auto_sync.elapsed() < DATA_WAITING_SYNC_INTERVAL
+        {
+            return;
+        }
+        self.last_auto_sync = Instant::now();
+        if !self.settings.sync_policy.allows_auto_sync() {
+            info!("Skip sync of the waiting data because of the auto sync constraints");
+            return;
+        }
+        info!("Sync because the device has data waiting");
+        self.auto_sync_pending = true;
+        self.set_connected(true);
+    }
+
+    /// Connect again once the retry after a rejected connection is due.
+    fn retry_connect(&mut self) {
+        let Some(retry) = self.connect_retry else {
+            return;
+        };
+        if Instant::now() < retry.at || self.request_repaint_ble || self.connected {
+            return;
+        }
+        info!("Retry connection, attempt {}", retry.attempt + 1);
+        self.auto_sync_pending = retry.auto_sync;
+        self.set_connected(true);
+    }
+
+    /// Request the events from the given position on or after the last synced one.
+    fn request_events(&self, since: Option<PedometerEventRequest>) {
+        let (resp_tx, _resp_rx) = oneshot::channel();
+        BLE_CMD_TX
+            .get()
+            .unwrap()
+            .blocking_send(PedometerDeviceHandlerCommand::RequestEvents {
+                since,
+                responder: resp_tx,
+            })
+            .unwrap();
+    }
+
+    fn get_last_disconnect(&mut self) {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.last_disconnect_rx.receiver = Some(resp_rx);
+        DB_CMD_TX
+            .get()
+            .unwrap()
+            .blocking_send(PedometerDatabaseCommand::GetLastDisconnect { responder: resp_tx })
+            .unwrap();
+    }
+
+    fn get_last_maintenance(&mut self) {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.last_maintenance_rx.receiver = Some(resp_rx);
+        DB_CMD_TX
+            .get()
+            .unwrap()
+            .blocking_send(PedometerDatabaseCommand::GetLastMaintenance { responder: resp_tx })
+            .unwrap();
+    }
+
+    /// Request everything that is shown from the database again, e.g. after new events were
+    /// stored.
+    fn get_stored_data(&mut self) {
+        self.get_db_events();
+        self.get_last_disconnect();
+        self.get_last_maintenance();
+        self.get_quarantined_row_count();
+        self.get_outliers();
+        self.get_boots();
+        self.get_time_sync_quality();
+        self.get_sessions();
+    }
+
+    fn backup_database(&mut self) {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.backup_rx.receiver = Some(resp_rx);
+        DB_CMD_TX
+            .get()
+            .unwrap()
+            .blocking_send(PedometerDatabaseCommand::BackupDatabase { responder: resp_tx })
+            .unwrap();
+    }
+
+    fn retry_migration(&mut self) {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.retry_migration_rx.receiver = Some(resp_rx);
+        DB_CMD_TX
+            .get()
+            .unwrap()
+            .blocking_send(PedometerDatabaseCommand::RetryMigration { responder: resp_tx })
+            .unwrap();
+    }
+
+    fn get_schema_info(&mut self) {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.schema_info_rx.receiver = Some(resp_rx);
+        DB_CMD_TX
+            .get()
+            .unwrap()
+            .blocking_send(PedometerDatabaseCommand::GetSchemaInfo { responder: resp_tx })
+            .unwrap();
+    }
+
+    fn get_quarantined_row_count(&mut self) {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.quarantined_rows_rx.receiver = Some(resp_rx);
+        DB_CMD_TX
+            .get()
+            .unwrap()
+            .blocking_send(PedometerDatabaseCommand::GetQuarantinedRowCount { responder: resp_tx })
+            .unwrap();
+    }
+
+    /// Show the steps of today in an ongoing notification after every sync while the automatic
+    /// sync is active.
+    fn set_daily_target(&mut self, daily_target: u32) {
+        self.settings.daily_target = daily_target;
+        self.set_sync_progress_notification();
+    }
+
+    fn set_sync_progress_notification(&self) {
+        let target = if self.settings.notifications.sync_progress
+            && self.settings.sync_policy.auto_sync_interval().is_some()
+        {
+            self.settings.daily_target
+        } else {
+            0
+        };
+        if SYNC_PROGRESS_TARGET.swap(target, Ordering::Relaxed) != 0 && target == 0 {
+            hide_sync_progress();
+        }
+    }
+
+    /// Notify once per day when the steps since midnight reach the target.
+    fn notify_goal_reached(&mut self, daily_steps: u32) {
+        let previous_steps = self.last_daily_steps.replace(daily_steps);
+        // Only when the target is crossed while the app is open, not for the first steps received
+        if self.settings.celebrate_goal
+            && previous_steps.is_some_and(|steps| steps < self.settings.daily_target)
+            && daily_steps >= self.settings.daily_target
+        {
+            self.celebration = Some(PedometerCelebration::new(Instant::now()));
+        }
+        let today = Local::now().date_naive();
+        if daily_steps < self.settings.daily_target || self.goal_notified == Some(today) {
+            return;
+        }
+        self.goal_notified = Some(today);
+        if self.settings.notifications.goal_reached {
+            notify(PedometerNotification::GoalReached {
+                steps: daily_steps,
+                target: self.settings.daily_target,
+            });
+        }
+    }
+
+    /// Notify once when the battery becomes low, again only after it was charged.
+    fn notify_low_battery(&mut self, soc: u8) {
+        if soc >= LOW_BATTERY_NOTIFICATION_PERCENT {
+            self.low_battery_notified = false;
+        } else if !self.low_battery_notified {
+            self.low_battery_notified = true;
+            if self.settings.notifications.low_battery {
+                notify(PedometerNotification::LowBattery { soc });
+            }
+        }
+    }
+
+    fn get_outliers(&mut self) {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.outliers_rx.receiver = Some(resp_rx);
+        DB_CMD_TX
+            .get()
+            .unwrap()
+            .blocking_send(PedometerDatabaseCommand::GetOutliers { responder: resp_tx })
+            .unwrap();
+    }
+
+    fn get_outliers_ref(&self) -> &[PedometerPersistenceOutlier] {
+        match &self.outliers_rx.current {
+            Some(Ok(outliers)) => outliers,
+            _ => &[],
+        }
+    }
+
+    /// The steps of the event are improbable and the user did not accept them.
+    fn is_excluded_outlier(&self, event: &PedometerPersistenceEvent) -> bool {
+        self.get_outliers_ref().iter().any(|outlier| {
+            !outlier.accepted
+                && outlier.event_id == event.event_id
+                && outlier.boot_id == event.boot_id
+        })
+    }
+
+    fn set_outlier_accepted(&mut self, outlier: &PedometerPersistenceOutlier, accepted: bool) {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.set_outlier_rx.receiver = Some(resp_rx);
+        DB_CMD_TX
+            .get()
+            .unwrap()
+            .blocking_send(PedometerDatabaseCommand::SetOutlierAccepted {
+                event_id: outlier.event_id,
+                boot_id: outlier.boot_id,
+                accepted,
+                responder: resp_tx,
+            })
+            .unwrap();
+    }
+
+    fn run_audit(&mut self) {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.audit_rx.receiver = Some(resp_rx);
+        DB_CMD_TX
+            .get()
+            .unwrap()
+            .blocking_send(PedometerDatabaseCommand::GetAuditReport { responder: resp_tx })
+            .unwrap();
+    }
+
+    fn get_debug_events(&mut self) {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.debug_events_rx.receiver = Some(resp_rx);
+        DB_CMD_TX
+            .get()
+            .unwrap()
+            .blocking_send(PedometerDatabaseCommand::GetDebugEvents {
+                first_day: self.debug_filter.first_day,
+                last_day: self.debug_filter.last_day,
+                responder: resp_tx,
+            })
+            .unwrap();
+    }
+
+    fn get_time_sync_quality(&mut self) {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.time_sync_rx.receiver = Some(resp_rx);
+        DB_CMD_TX
+            .get()
+            .unwrap()
+            .blocking_send(PedometerDatabaseCommand::GetTimeSyncQuality { responder: resp_tx })
+            .unwrap();
+    }
+
+    /// Largest possible time shift of the events of the given boots if it is too large.
+    fn get_time_sync_warning(&self, boot_ids: &HashSet<i64>) -> Option<i64> {
+        let Some(Ok(qualities)) = &self.time_sync_rx.current else {
+            return None;
+        };
+        qualities
+            .iter()
+            .filter(|quality| boot_ids.contains(&quality.boot_id) && !quality.is_accurate())
+            .map(|quality| quality.max_deviation_ms)
+            .max()
+    }
+
+    fn delete_boot(&mut self, boot_id: u32) {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.delete_boot_rx.receiver = Some(resp_rx);
+        BLE_CMD_TX
+            .get()
+            .unwrap()
+            .blocking_send(PedometerDeviceHandlerCommand::DeleteBoot {
+                boot_id,
+                responder: resp_tx,
+            })
+            .unwrap();
+    }
+
+    fn read_device_boots(&mut self) {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.device_boots_rx.receiver = Some(resp_rx);
+        BLE_CMD_TX
+            .get()
+            .unwrap()
+            .blocking_send(PedometerDeviceHandlerCommand::ReadBootSummaries { responder: resp_tx })
+            .unwrap();
+    }
+
+    fn import_track(&mut self) {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.import_track_rx.receiver = Some(resp_rx);
+        DB_CMD_TX
+            .get()
+            .unwrap()
+            .blocking_send(PedometerDatabaseCommand::ImportTrack {
+                path: self.gpx_path.clone().into(),
+                responder: resp_tx,
+            })
+            .unwrap();
+    }
+
+    fn export_research_data(&mut self) {
+        let last_day = Local::now().date_naive();
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.research_export_rx.receiver = Some(resp_rx);
+        DB_CMD_TX
+            .get()
+            .unwrap()
+            .blocking_send(PedometerDatabaseCommand::ExportResearchData {
+                first_day: last_day - Duration::days(self.research_days as i64 - 1),
+                last_day,
+                responder: resp_tx,
+            })
+            .unwrap();
+    }
+
+    /// Roll the step events older than the archive policy into daily totals.
+    fn archive_events(&mut self) {
+        let Some(first_kept_day) = self
+            .settings
+            .archive
+            .first_kept_day(Local::now().date_naive())
+        else {
+            return;
+        };
+        let Ok(before) = local_day_start(&Local, first_kept_day, 0) else {
+            error!("Invalid archive day {first_kept_day}");
+            return;
+        };
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.archive_rx.receiver = Some(resp_rx);
+        DB_CMD_TX
+            .get()
+            .unwrap()
+            .blocking_send(PedometerDatabaseCommand::ArchiveEvents {
+                before,
+                keep_raw: self.settings.archive.keep_raw,
+                responder: resp_tx,
+            })
+            .unwrap();
+    }
+
+    fn get_sessions(&mut self) {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.sessions_rx.receiver = Some(resp_rx);
+        DB_CMD_TX
+            .get()
+            .unwrap()
+            .blocking_send(PedometerDatabaseCommand::GetSessions { responder: resp_tx })
+            .unwrap();
+    }
+
+    /// Write config values that are evaluated by the device itself.
+    fn write_device_config(&mut self, values: Vec<PedometerConfigValue>) {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.write_config_rx.receiver = Some(resp_rx);
+        BLE_CMD_TX
+            .get()
+            .unwrap()
+            .blocking_send(PedometerDeviceHandlerCommand::WriteConfig {
+                values,
+                responder: resp_tx,
+            })
+            .unwrap();
+    }
+
+    fn refresh_profiles(&mut self) {
+        match DeviceProfile::list() {
+            Ok(profiles) => self.profiles = profiles,
+            Err(e) => warn!("Could not list profiles: {e}"),
         }
     }
 
-    fn draw_main_view_settings(&mut self, ui: &mut egui::Ui) {
-        ui.add(
-            Slider::new(&mut self.state.daily_target, 1000..=20000)
-                .step_by(1000.0)
-                .text("Tägliches Schrittziel"),
+    /// Save the current settings and the device config as profile. Config values without a
+    /// setting are taken from the last read device config.
+    fn save_profile(&mut self) {
+        let profile = DeviceProfile::new(&self.settings, &self.device_config.unwrap_or_default());
+        self.profile_result = Some(
+            profile
+                .save(&self.profile_name)
+                .map(|()| format!("Profil \"{}\" gespeichert", self.profile_name)),
         );
+        self.refresh_profiles();
     }
 
-    fn draw_main_view_debug(&mut self, ui: &mut egui::Ui) {
-        ui.add(egui::DragValue::new(&mut self.event_id));
-        if ui.button("Events aus DB holen").clicked() {
-            self.get_db_events();
+    /// Take over the selected profile into the settings and write it to the device.
+    fn apply_profile(&mut self) {
+        let Some(name) = self.selected_profile.clone() else {
+            return;
         };
-        if let Some(events) = &self.db_events_rx.current {
-            if let Err(err) = events {
-                ui.label(format!("Error: {err}"));
-            } else {
-                ui.label("Ok!");
-            }
-            if let Ok(events) = events {
-                for event in events {
-                    ui.label(format!("{event:?}"));
-                }
-            }
-        }
+        self.profile_result = Some(DeviceProfile::load(&name).map(|profile| {
+            profile.apply(&mut self.settings);
+            self.write_device_config(profile.config_values());
+            format!("Profil \"{name}\" angewendet")
+        }));
     }
 
-    fn draw_footer(&mut self, ctx: &egui::Context) {
-        TopBottomPanel::bottom("bottom_panel")
-            .frame(Frame {
-                inner_margin: Margin::symmetric(8.0, 12.0),
-                ..Frame::side_top_panel(&ctx.style())
+    fn set_marker(&mut self, marker: PedometerMarker) {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.set_marker_rx.receiver = Some(resp_rx);
+        BLE_CMD_TX
+            .get()
+            .unwrap()
+            .blocking_send(PedometerDeviceHandlerCommand::SetMarker {
+                marker,
+                responder: resp_tx,
             })
-            .show(ctx, |ui| {
-                ui.horizontal_centered(|ui| {
-                    for view in MainView::iter() {
-                        ui.selectable_value(&mut self.state.main_view, view, view.to_string());
-                    }
-                });
-            });
+            .unwrap();
     }
 
-    fn get_db_events(&mut self) {
+    fn get_boots(&mut self) {
         let (resp_tx, resp_rx) = oneshot::channel();
-        self.db_events_rx.receiver = Some(resp_rx);
+        self.boots_rx.receiver = Some(resp_rx);
         DB_CMD_TX
             .get()
             .unwrap()
-            .blocking_send(PedometerDatabaseCommand::GetEventsInTimeRange {
-                start: (self.state.selected_date - Duration::days(6))
-                    .and_time(NaiveTime::from_hms_opt(0, 0, 0).unwrap())
-                    .and_local_timezone(Local)
-                    .unwrap()
-                    .to_utc(),
-                end: (self.state.selected_date + Duration::days(1))
-                    .and_time(NaiveTime::from_hms_opt(0, 0, 0).unwrap())
-                    .and_local_timezone(Local)
-                    .unwrap()
-                    .to_utc(),
+            .blocking_send(PedometerDatabaseCommand::GetBoots { responder: resp_tx })
+            .unwrap();
+    }
+
+    fn set_listening(&mut self, listen: bool) {
+        if !listen {
+            self.live_data = None;
+        }
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.listen_events_rx.receiver = Some(resp_rx);
+        let command = if listen {
+            PedometerDeviceHandlerCommand::StartListening { responder: resp_tx }
+        } else {
+            PedometerDeviceHandlerCommand::StopListening { responder: resp_tx }
+        };
+        BLE_CMD_TX.get().unwrap().blocking_send(command).unwrap();
+    }
+
+    fn set_api_policy(&self) {
+        API_POLICY_TX.get().unwrap().send_replace(self.settings.api);
+    }
+
+    fn read_diagnostics(&mut self) {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.diagnostics_rx.receiver = Some(resp_rx);
+        BLE_CMD_TX
+            .get()
+            .unwrap()
+            .blocking_send(PedometerDeviceHandlerCommand::ReadDiagnostics { responder: resp_tx })
+            .unwrap();
+    }
+
+    fn read_device_clock(&mut self) {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.device_clock_rx.receiver = Some(resp_rx);
+        BLE_CMD_TX
+            .get()
+            .unwrap()
+            .blocking_send(PedometerDeviceHandlerCommand::ReadDeviceClock { responder: resp_tx })
+            .unwrap();
+    }
+
+    fn stream_acceleration(&mut self, enabled: bool) {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.stream_acceleration_rx.receiver = Some(resp_rx);
+        self.acceleration_streaming = enabled;
+        if enabled {
+            self.acceleration.clear();
+        }
+        BLE_CMD_TX
+            .get()
+            .unwrap()
+            .blocking_send(PedometerDeviceHandlerCommand::StreamAcceleration {
+                enabled,
                 responder: resp_tx,
             })
             .unwrap();
-        self.request_repaint_db = true;
     }
 
-    fn recv_events(&mut self) {
-        while let Ok(event) = self.gui_events_rx.try_recv() {
+    fn resync_time(&mut self) {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.resync_time_rx.receiver = Some(resp_rx);
+        BLE_CMD_TX
+            .get()
+            .unwrap()
+            .blocking_send(PedometerDeviceHandlerCommand::ResyncTime { responder: resp_tx })
+            .unwrap();
+    }
+
+    fn recv_events(&mut self, toasts: &mut Toasts) {
+        while let Some(event) = self.gui_events_rx.try_recv() {
             info!("Received gui event: {:?}", event);
             match event {
-                PedometerGuiEvent::Soc(soc) => self.soc = Some(soc),
+                PedometerGuiEvent::Soc(soc) => {
+                    self.soc = Some(soc);
+                    self.notify_low_battery(soc);
+                }
                 PedometerGuiEvent::Disconnected => {
                     self.soc = None;
+                    self.storage_fill = None;
+                    self.storage_usage = None;
+                    self.device_delete_result = None;
+                    self.firmware_version = None;
+                    self.temperature = None;
                     self.connected = false;
+                    self.sync_progress = None;
+                    self.cadence.clear();
+                    // The device stops the stream when the connection is lost
+                    self.acceleration_streaming = false;
+                }
+                PedometerGuiEvent::AdapterRemoved => {
+                    self.adapter_available = false;
+                    self.connect_retry = None;
+                    self.live_data = None;
+                    toasts.add(egui_toast::Toast {
+                        kind: ToastKind::Warning,
+                        text: "Der Bluetooth-Adapter wurde entfernt".into(),
+                        ..Default::default()
+                    });
+                }
+                PedometerGuiEvent::DatabaseTooNew {
+                    database_version,
+                    app_version,
+                } => {
+                    self.database_too_new = Some((database_version, app_version));
+                }
+                PedometerGuiEvent::DatabaseReadOnly { error } => {
+                    self.database_read_only = Some(error);
+                    self.show_database_read_only = true;
+                }
+                PedometerGuiEvent::AdapterAvailable => {
+                    self.adapter_available = true;
+                    toasts.add(egui_toast::Toast {
+                        kind: ToastKind::Info,
+                        text: "Der Bluetooth-Adapter ist wieder verfügbar. Bitte erneut verbinden."
+                            .into(),
+                        ..Default::default()
+                    });
+                    if self.settings.listen_for_live_data {
+                        self.set_listening(true);
+                    }
+                }
+                PedometerGuiEvent::NewEvents => {
+                    self.get_stored_data();
+                }
+                PedometerGuiEvent::BackendRestarted(backend) => {
+                    toasts.add(egui_toast::Toast {
+                        kind: ToastKind::Warning,
+                        text: format!("{backend} wurde neu gestartet").into(),
+                        ..Default::default()
+                    });
+                    match backend {
+                        PedometerBackend::Database => self.get_stored_data(),
+                        PedometerBackend::Device => {
+                            self.soc = None;
+                            self.storage_fill = None;
+                            self.storage_usage = None;
+                            self.device_delete_result = None;
+                            self.firmware_version = None;
+                            self.temperature = None;
+                            self.cadence.clear();
+                            self.connected = false;
+                            if self.settings.listen_for_live_data {
+                                self.set_listening(true);
+                            }
+                        }
+                    }
+                }
+                PedometerGuiEvent::SyncProgress {
+                    event_id,
+                    max_event_id,
+                } => {
+                    self.sync_progress = Some((event_id, max_event_id));
+                }
+                PedometerGuiEvent::ConnectionState(state) => {
+                    // A disconnect by the user goes through Disconnecting
+                    if self.connection_state == PedometerConnectionState::Syncing
+                        && state == PedometerConnectionState::Disconnected
+                        && self.adapter_available
+                    {
+                        info!("Connection lost during sync, reconnect to resume it");
+                        self.connect_retry = Some(ConnectRetry {
+                            at: Instant::now() + SYNC_RESUME_DELAY,
+                            attempt: 0,
+                            auto_sync: true,
+                        });
+                        toasts.add(egui_toast::Toast {
+                            kind: ToastKind::Info,
+                            text: "Die Verbindung wurde während des Abrufs unterbrochen. Der Abruf wird nach dem erneuten Verbinden fortgesetzt.".into(),
+                            ..Default::default()
+                        });
+                    }
+                    if let PedometerConnectionState::Scanning { .. } = state {
+                        if state != self.connection_state {
+                            self.scan_started = Some(Instant::now());
+                        }
+                    } else {
+                        self.scan_started = None;
+                    }
+                    self.connection_state = state;
+                }
+                PedometerGuiEvent::Connected { address, name } => {
+                    info!("Connected to {address} ({name:?})");
+                    self.settings.device_address = Some(address);
+                    self.settings.device_name = name;
+                }
+                PedometerGuiEvent::LiveData(live_data) => {
+                    if !self.connected {
+                        self.soc = live_data.soc;
+                    }
+                    if let Some(soc) = live_data.soc {
+                        self.notify_low_battery(soc);
+                    }
+                    self.notify_goal_reached(live_data.daily_steps);
+                    if live_data.data_waiting {
+                        self.sync_data_waiting();
+                    }
+                    self.live_data = Some(live_data);
+                }
+                PedometerGuiEvent::DailySteps {
+                    daily_steps,
+                    received,
+                } => {
+                    self.cadence.add(received, daily_steps);
+                    self.notify_goal_reached(daily_steps);
+                }
+                PedometerGuiEvent::SyncFailed(error) => {
+                    toasts.add(egui_toast::Toast {
+                        kind: ToastKind::Error,
+                        text: format!("Die Schritte konnten nicht abgerufen werden:\n{error}")
+                            .into(),
+                        ..Default::default()
+                    });
+                    if self.settings.notifications.sync_failed
+                        && self.sync_failed_notified.is_none_or(|notified| {
+                            notified.elapsed() >= SYNC_FAILED_NOTIFICATION_INTERVAL
+                        })
+                    {
+                        self.sync_failed_notified = Some(Instant::now());
+                        notify(PedometerNotification::SyncFailed { error });
+                    }
+                }
+                PedometerGuiEvent::StorageFill(fill_percent) => {
+                    self.storage_fill = Some(fill_percent);
+                }
+                PedometerGuiEvent::EventsDeleted(result) => {
+                    self.device_delete_result = Some(result);
+                }
+                PedometerGuiEvent::StorageUsage(usage) => {
+                    self.storage_fill = Some(usage.fill_percent());
+                    self.storage_usage = Some(usage);
+                }
+                PedometerGuiEvent::FirmwareVersion(firmware_version) => {
+                    self.firmware_version = Some(firmware_version);
+                }
+                PedometerGuiEvent::Temperature(temperature) => {
+                    self.temperature = Some(temperature);
+                }
+                PedometerGuiEvent::Acceleration(samples) => {
+                    self.acceleration.add(&samples);
+                }
+                PedometerGuiEvent::StorageWarning(fill_percent) => {
+                    toasts.add(egui_toast::Toast {
+                        kind: ToastKind::Warning,
+                        text: format!(
+                            "Der Speicher des Schrittzählers ist zu {fill_percent}% belegt"
+                        )
+                        .into(),
+                        ..Default::default()
+                    });
+                    // Sync now so that no events have to be dropped on the device
+                    self.request_events(None);
+                }
+                PedometerGuiEvent::DeviceConfig(config) => {
+                    info!("Take over device config: {config:?}");
+                    self.device_config = Some(config);
+                    self.settings.idle_alert = IdleAlertPolicy::from_config(&config);
+                    self.settings.battery = BatteryPolicy::from_config(&config);
+                    self.settings.step_detection = StepDetectionPolicy::from_config(&config);
+                }
+                PedometerGuiEvent::CommandsDropped(dropped_commands) => {
+                    toasts.add(egui_toast::Toast {
+                        kind: ToastKind::Warning,
+                        text: format!(
+                            "Die App verwirft häufig Befehle ({dropped_commands} seit dem Start)"
+                        )
+                        .into(),
+                        ..Default::default()
+                    });
+                }
+                PedometerGuiEvent::SelfTestFailed(self_test) => {
+                    toasts.add(egui_toast::Toast {
+                        kind: ToastKind::Error,
+                        text: format!(
+                            "Der Selbsttest des Schrittzählers ist fehlgeschlagen:\n{}",
+                            describe_self_test(self_test)
+                        )
+                        .into(),
+                        ..Default::default()
+                    });
                 }
-                PedometerGuiEvent::NewEvents => self.get_db_events(),
             }
         }
     }
 }
 
+fn describe_device_error(error: PedometerError) -> String {
+    match error {
+        PedometerError::Disconnected(reason) => {
+            let description = match reason {
+                0x08 => "Zeitüberschreitung der Verbindung",
+                0x13 => "Von der Gegenseite beendet",
+                0x16 => "Vom Schrittzähler beendet",
+                0x22 => "Zeitüberschreitung des Link-Layers",
+                0x3B => "Unpassende Verbindungsparameter",
+                0x3D => "Integritätsprüfung fehlgeschlagen",
+                0x3E => "Verbindungsaufbau fehlgeschlagen",
+                _ => "Unbekannter Grund",
+            };
+            format!("{description} (HCI 0x{reason:02X})")
+        }
+        PedometerError::Softdevice(code) => format!("Softdevice-Fehler {code}"),
+        PedometerError::ImuPowerCycled => {
+            "Der Bewegungssensor antwortete nicht und wurde neu gestartet".to_string()
+        }
+    }
+}
+
+fn describe_activity(activity: PedometerActivity) -> &'static str {
+    match activity {
+        PedometerActivity::Idle => "Ruhe",
+        PedometerActivity::Walking => "Gehen",
+        PedometerActivity::Running => "Laufen",
+    }
+}
+
+fn activity_color(activity: PedometerActivity) -> egui::Color32 {
+    match activity {
+        PedometerActivity::Idle => egui::Color32::GRAY,
+        PedometerActivity::Walking => egui::Color32::from_rgb(0x4c, 0xaf, 0x50),
+        PedometerActivity::Running => egui::Color32::from_rgb(0xff, 0x98, 0x00),
+    }
+}
+
+/// Bars of the hourly steps of one activity in the day chart. The steps without a classified
+/// activity have none.
+#[derive(Debug, Clone)]
+struct PedometerDayChartLayer {
+    activity: Option<PedometerActivity>,
+    bars: Vec<Bar>,
+}
+
+/// Daily target entered by the user if it is a valid one.
+fn parse_daily_target(language: Language, text: &str) -> Option<u32> {
+    parse_number(language, text)
+        .and_then(|n| u32::try_from(n).ok())
+        .filter(|n| PedometerSettings::DAILY_TARGET_RANGE.contains(n))
+}
+
+fn describe_goal_summary(summary: PedometerGoalSummary) -> String {
+    format!(
+        "Ziel an {} von {} Tagen erreicht, durchschnittlich {:.0}%",
+        summary.days_hit, summary.days, summary.average_completion_percent
+    )
+}
+
+/// Description of the states in which the user has to wait for the device.
+fn describe_connection_state(state: PedometerConnectionState) -> Option<String> {
+    match state {
+        PedometerConnectionState::Disconnected | PedometerConnectionState::Connected => None,
+        PedometerConnectionState::Scanning {
+            attempt, attempts, ..
+        } => Some(format!(
+            "Suche Schrittzähler (Versuch {attempt}/{attempts})..."
+        )),
+        PedometerConnectionState::Connecting => Some("Verbinde...".to_string()),
+        PedometerConnectionState::Subscribing => Some("Richte Verbindung ein...".to_string()),
+        PedometerConnectionState::Syncing => Some("Rufe Schritte ab...".to_string()),
+        PedometerConnectionState::Disconnecting => Some("Trenne...".to_string()),
+    }
+}
+
+/// Used and free space of the event storage and how many more events roughly fit into it before
+/// the oldest ones are overwritten.
+fn describe_storage_usage(language: Language, usage: &PedometerStorageUsage) -> String {
+    let kib = |bytes: u32| format_number(language, (bytes / 1024) as i64);
+    let mut description = format!(
+        "{} von {} KiB belegt, {} Einträge gespeichert",
+        kib(usage.used_bytes),
+        kib(usage.capacity_bytes),
+        format_number(language, usage.entries as i64),
+    );
+    if let Some(entries_left) = usage.entries_left() {
+        description.push_str(&format!(
+            "\nPlatz für etwa {} weitere Einträge, danach werden die ältesten überschrieben",
+            format_number(language, entries_left as i64)
+        ));
+    }
+    description
+}
+
+/// Describe the content of the RESETREAS register of the nRF52840.
+fn describe_self_test(self_test: PedometerSelfTest) -> String {
+    const CHECKS: [(u8, &str); 4] = [
+        (
+            PedometerSelfTest::IMU_NOT_RESPONDING,
+            "Der Bewegungssensor antwortet nicht",
+        ),
+        (
+            PedometerSelfTest::STORAGE_UNREADABLE,
+            "Der Speicher war nicht lesbar und wurde gelöscht",
+        ),
+        (
+            PedometerSelfTest::SAADC_IMPLAUSIBLE,
+            "Die Batteriemessung ist unplausibel",
+        ),
+        (PedometerSelfTest::BATTERY_LOW, "Die Batterie ist fast leer"),
+    ];
+    if self_test.passed() {
+        return "Selbsttest bestanden".to_string();
+    }
+    let failures: Vec<_> = CHECKS
+        .iter()
+        .filter(|(check, _)| self_test.has_failed(*check))
+        .map(|(_, failure)| *failure)
+        .collect();
+    if failures.is_empty() {
+        format!("Unbekannter Fehler im Selbsttest (0x{:X})", self_test.0)
+    } else {
+        failures.join("\n")
+    }
+}
+
+fn describe_reset_reason(reset_reason: u32) -> String {
+    const REASONS: [(u32, &str); 9] = [
+        (1 << 0, "Reset-Pin"),
+        (1 << 1, "Watchdog"),
+        (1 << 2, "Software-Reset"),
+        (1 << 3, "CPU-Lockup"),
+        (1 << 16, "Aufwachen aus System-OFF (GPIO)"),
+        (1 << 17, "Aufwachen aus System-OFF (LPCOMP)"),
+        (1 << 18, "Debug-Interface"),
+        (1 << 19, "Aufwachen aus System-OFF (NFC)"),
+        (1 << 20, "Aufwachen aus System-OFF (VBUS)"),
+    ];
+    if reset_reason == 0 {
+        return "Einschalten".to_string();
+    }
+    let reasons: Vec<_> = REASONS
+        .iter()
+        .filter(|(mask, _)| reset_reason & mask != 0)
+        .map(|(_, reason)| *reason)
+        .collect();
+    if reasons.is_empty() {
+        format!("Unbekannter Grund (0x{reset_reason:X})")
+    } else {
+        reasons.join(", ")
+    }
+}
+
+/// Transient view state that is persisted via eframe. Settings are stored in
+/// [`PedometerSettings`] instead.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) struct PedometerAppState {
     main_view: MainView,
     selected_date: NaiveDate,
-    daily_target: u32,
 }
 
 impl Default for PedometerAppState {
@@ -448,7 +4121,6 @@ impl Default for PedometerAppState {
         Self {
             main_view: Default::default(),
             selected_date: now.date_naive(),
-            daily_target: 10_000,
         }
     }
 }
@@ -469,16 +4141,27 @@ impl<T> Default for MessageReceiver<T> {
 }
 
 impl<T> MessageReceiver<T> {
+    /// Returns `true` if the pending request is finished, i.e. either new data arrived or the
+    /// responder was dropped because the backend died.
     fn try_recv<F: Fn(T) -> T>(&mut self, data_modifier: Option<F>) -> bool {
         if let Some(receiver) = &mut self.receiver {
-            if let Ok(data) = receiver.try_recv() {
-                if let Some(data_modifier) = data_modifier {
-                    self.current = Some(data_modifier(data));
-                } else {
-                    self.current = Some(data);
+            match receiver.try_recv() {
+                Ok(data) => {
+                    if let Some(data_modifier) = data_modifier {
+                        self.current = Some(data_modifier(data));
+                    } else {
+                        self.current = Some(data);
+                    }
+                    self.receiver = None;
+                    return true;
+                }
+                Err(TryRecvError::Closed) => {
+                    warn!("Responder was dropped without an answer");
+                    self.current = None;
+                    self.receiver = None;
+                    return true;
                 }
-                self.receiver = None;
-                return true;
+                Err(TryRecvError::Empty) => {}
             }
         }
         false
@@ -488,6 +4171,179 @@ impl<T> MessageReceiver<T> {
 #[derive(Debug)]
 pub(crate) enum PedometerGuiEvent {
     Soc(u8),
+    ConnectionState(PedometerConnectionState),
+    /// Connected to the device with the given address and advertised name
+    Connected {
+        address: String,
+        name: Option<String>,
+    },
     Disconnected,
     NewEvents,
+    BackendRestarted(PedometerBackend),
+    /// Current fill level of the event storage of the device in percent
+    StorageFill(u8),
+    /// The event storage of the device reached the given fill level in percent
+    StorageWarning(u8),
+    /// Used bytes and entries of the event storage of the device, received on connect and
+    /// whenever the fill level changed
+    StorageUsage(PedometerStorageUsage),
+    /// The device finished deleting events
+    EventsDeleted(PedometerDeleteResult),
+    /// Version of the firmware of the connected device
+    FirmwareVersion(PedometerFirmwareVersion),
+    /// Temperature of the IMU of the device in 0.01 °C, received on connect and whenever it was
+    /// measured
+    Temperature(i16),
+    /// Checks of the power-on self test of the device failed
+    SelfTestFailed(PedometerSelfTest),
+    /// Current config of the device, received on connect and whenever it was changed
+    DeviceConfig(PedometerConfig),
+    /// Live data from the advertisements of the device
+    LiveData(PedometerAdvertisingData),
+    /// The device counted steps, which are reported as total since the last local midnight
+    DailySteps {
+        daily_steps: u32,
+        received: Instant,
+    },
+    /// Events could not be requested or stored
+    SyncFailed(String),
+    /// Events up to the given index were received during a sync
+    SyncProgress {
+        event_id: u32,
+        max_event_id: u32,
+    },
+    /// Commands of the app are dropped frequently, the given number since it was started
+    CommandsDropped(u64),
+    /// Raw accelerometer samples while the stream is enabled
+    Acceleration(PedometerAccelerationSamples),
+    /// The Bluetooth adapter disappeared and the connection state was reset
+    AdapterRemoved,
+    /// A Bluetooth adapter is available again after one was removed
+    AdapterAvailable,
+    /// A newer build of the app migrated the database, so it was not opened
+    DatabaseTooNew {
+        database_version: i64,
+        app_version: i64,
+    },
+    /// The migration of the database failed with the given error, so it was opened read-only
+    DatabaseReadOnly {
+        error: String,
+    },
+}
+
+/// Sending side of the GUI event channels.
+///
+/// State-like events are sent via watch channels so that only the latest value is kept, all other
+/// events are queued without limit. This way no update can get lost while the GUI is busy.
+#[derive(Debug)]
+pub(crate) struct PedometerGuiEventSender {
+    events: mpsc::UnboundedSender<PedometerGuiEvent>,
+    soc: watch::Sender<Option<u8>>,
+    new_events: watch::Sender<u64>,
+    live_data: watch::Sender<Option<PedometerAdvertisingData>>,
+    sync_progress: watch::Sender<Option<(u32, u32)>>,
+    connection_state: watch::Sender<PedometerConnectionState>,
+}
+
+impl PedometerGuiEventSender {
+    pub(crate) fn send(&self, event: PedometerGuiEvent) {
+        match event {
+            PedometerGuiEvent::Soc(soc) => {
+                self.soc.send_replace(Some(soc));
+            }
+            PedometerGuiEvent::NewEvents => {
+                self.new_events.send_modify(|generation| *generation += 1);
+            }
+            PedometerGuiEvent::LiveData(live_data) => {
+                self.live_data.send_replace(Some(live_data));
+            }
+            PedometerGuiEvent::SyncProgress {
+                event_id,
+                max_event_id,
+            } => {
+                self.sync_progress
+                    .send_replace(Some((event_id, max_event_id)));
+            }
+            PedometerGuiEvent::ConnectionState(state) => {
+                self.connection_state.send_replace(state);
+            }
+            event => {
+                if let Err(e) = self.events.send(event) {
+                    // The GUI is gone, so the drop can only be counted
+                    metrics::record_dropped_command();
+                    error!("Could not send gui event: {e}");
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct PedometerGuiEventReceiver {
+    events: mpsc::UnboundedReceiver<PedometerGuiEvent>,
+    soc: watch::Receiver<Option<u8>>,
+    new_events: watch::Receiver<u64>,
+    live_data: watch::Receiver<Option<PedometerAdvertisingData>>,
+    sync_progress: watch::Receiver<Option<(u32, u32)>>,
+    connection_state: watch::Receiver<PedometerConnectionState>,
+}
+
+impl PedometerGuiEventReceiver {
+    fn try_recv(&mut self) -> Option<PedometerGuiEvent> {
+        if self.soc.has_changed().unwrap_or(false) {
+            if let Some(soc) = *self.soc.borrow_and_update() {
+                return Some(PedometerGuiEvent::Soc(soc));
+            }
+        }
+        if self.new_events.has_changed().unwrap_or(false) {
+            self.new_events.mark_unchanged();
+            return Some(PedometerGuiEvent::NewEvents);
+        }
+        if self.live_data.has_changed().unwrap_or(false) {
+            if let Some(live_data) = *self.live_data.borrow_and_update() {
+                return Some(PedometerGuiEvent::LiveData(live_data));
+            }
+        }
+        if self.sync_progress.has_changed().unwrap_or(false) {
+            if let Some((event_id, max_event_id)) = *self.sync_progress.borrow_and_update() {
+                return Some(PedometerGuiEvent::SyncProgress {
+                    event_id,
+                    max_event_id,
+                });
+            }
+        }
+        if self.connection_state.has_changed().unwrap_or(false) {
+            return Some(PedometerGuiEvent::ConnectionState(
+                *self.connection_state.borrow_and_update(),
+            ));
+        }
+        self.events.try_recv().ok()
+    }
+}
+
+pub(crate) fn gui_event_channel() -> (PedometerGuiEventSender, PedometerGuiEventReceiver) {
+    let (events_tx, events_rx) = mpsc::unbounded_channel();
+    let (soc_tx, soc_rx) = watch::channel(None);
+    let (new_events_tx, new_events_rx) = watch::channel(0);
+    let (live_data_tx, live_data_rx) = watch::channel(None);
+    let (sync_progress_tx, sync_progress_rx) = watch::channel(None);
+    let (connection_state_tx, connection_state_rx) = watch::channel(Default::default());
+    (
+        PedometerGuiEventSender {
+            events: events_tx,
+            soc: soc_tx,
+            new_events: new_events_tx,
+            live_data: live_data_tx,
+            sync_progress: sync_progress_tx,
+            connection_state: connection_state_tx,
+        },
+        PedometerGuiEventReceiver {
+            events: events_rx,
+            soc: soc_rx,
+            new_events: new_events_rx,
+            live_data: live_data_rx,
+            sync_progress: sync_progress_rx,
+            connection_state: connection_state_rx,
+        },
+    )
 }
@@ -1,48 +1,132 @@
+// The demo replaces the device handler, so most of the BLE code is not used
+#![cfg_attr(feature = "demo", allow(dead_code))]
+
+mod acceleration;
 #[cfg(target_os = "android")]
 mod android;
+mod api;
+mod archive;
+mod audit;
 mod ble;
+mod cadence;
+mod celebration;
+mod chart_cache;
+mod debug_events;
 mod error;
+mod frame_timing;
+mod goals;
+mod gpx;
 mod gui;
+mod locale;
+mod metrics;
+mod notifications;
 mod persistence;
+mod profile;
+mod research;
+mod retry_journal;
 mod runtime;
+mod session;
+mod settings;
+#[cfg(feature = "demo")]
+mod simulation;
+mod steps;
+mod supervisor;
+mod time_sync;
 
+use api::API_POLICY_TX;
 #[cfg(target_os = "android")]
 use app_dirs2::app_root;
 use app_dirs2::AppInfo;
-use ble::{PedometerDeviceHandler, PedometerDeviceHandlerCommand, BLE_CMD_TX};
+#[cfg(not(feature = "demo"))]
+use ble::PedometerDeviceHandler;
+use ble::{PedometerDeviceHandlerCommand, BLE_CMD_TX};
 use eframe::{NativeOptions, Renderer};
-use gui::{PedometerApp, GUI_EVENT_TX};
-use log::{debug, info};
+use error::PedometerGuiError;
+use gui::{gui_event_channel, PedometerApp, PedometerGuiEvent, GUI_EVENT_TX};
+use log::{debug, error, info};
 use persistence::{PedometerDatabase, PedometerDatabaseCommand, DB_CMD_TX};
-use tokio::sync::mpsc;
+use settings::ApiPolicy;
+#[cfg(feature = "demo")]
+use simulation::SimulatedDeviceHandler as PedometerDeviceHandler;
+use supervisor::{supervise, PedometerBackend};
+use tokio::sync::{mpsc, watch};
 #[cfg(target_os = "android")]
 use winit::platform::android::activity::AndroidApp;
 
+#[cfg(not(feature = "demo"))]
 pub const APP_INFO: AppInfo = AppInfo {
     name: "pedomet-rs",
     author: "DerFetzer",
 };
 
+/// The demo keeps its settings and database apart from the real ones
+#[cfg(feature = "demo")]
+pub const APP_INFO: AppInfo = AppInfo {
+    name: "pedomet-rs-demo",
+    author: "DerFetzer",
+};
+
 fn tokio_thread(
     database_cmd_rx: mpsc::Receiver<PedometerDatabaseCommand>,
     device_cmd_rx: mpsc::Receiver<PedometerDeviceHandlerCommand>,
+    api_policy_rx: watch::Receiver<ApiPolicy>,
 ) {
     debug!("tokio_thread");
     runtime::create_runtime_and_block(async {
         debug!("inside future");
-        let db_handle = PedometerDatabase::new()
-            .await
-            .unwrap()
-            .spawn_message_handler(database_cmd_rx)
-            .await;
-        let dev_handle = PedometerDeviceHandler::new()
-            .await
-            .unwrap()
-            .spawn_message_handler(device_cmd_rx)
-            .await;
-
-        db_handle.await.unwrap();
-        dev_handle.await.unwrap();
+        let db_handle =
+            tokio::spawn(supervise(
+                PedometerBackend::Database,
+                database_cmd_rx,
+                |rx| async move {
+                    match PedometerDatabase::new().await {
+                        Ok(db) => Ok(db.spawn_message_handler(rx).await),
+                        Err(e) => match e.downcast_ref::<PedometerGuiError>() {
+                            // Restarting does not help until the app is updated
+                            Some(&PedometerGuiError::DatabaseTooNew {
+                                database_version,
+                                app_version,
+                            }) => {
+                                error!("{e}");
+                                GUI_EVENT_TX.get().unwrap().send(
+                                    PedometerGuiEvent::DatabaseTooNew {
+                                        database_version,
+                                        app_version,
+                                    },
+                                );
+                                Ok(PedometerDatabase::spawn_unavailable_handler(rx))
+                            }
+                            // Restarting would fail the same way, so the user decides when to retry
+                            Some(PedometerGuiError::MigrationFailed(error)) => {
+                                error!("{e}");
+                                let db = PedometerDatabase::new_read_only().await?;
+                                GUI_EVENT_TX.get().unwrap().send(
+                                    PedometerGuiEvent::DatabaseReadOnly {
+                                        error: error.clone(),
+                                    },
+                                );
+                                Ok(db.spawn_message_handler(rx).await)
+                            }
+                            _ => Err(e),
+                        },
+                    }
+                },
+            ));
+        let dev_handle = tokio::spawn(supervise(
+            PedometerBackend::Device,
+            device_cmd_rx,
+            |rx| async move {
+                Ok(PedometerDeviceHandler::new()
+                    .await?
+                    .spawn_message_handler(rx)
+                    .await)
+            },
+        ));
+
+        tokio::spawn(api::run(api_policy_rx));
+
+        let _ = db_handle.await;
+        let _ = dev_handle.await;
     });
 }
 
@@ -51,14 +135,16 @@ fn _main(mut options: NativeOptions) -> eframe::Result<()> {
 
     let (database_cmd_tx, database_cmd_rx) = mpsc::channel(1000);
     let (device_cmd_tx, device_cmd_rx) = mpsc::channel(1000);
-    let (gui_events_tx, gui_events_rx) = mpsc::channel(1000);
+    let (gui_events_tx, gui_events_rx) = gui_event_channel();
+    let (api_policy_tx, api_policy_rx) = watch::channel(ApiPolicy::default());
     BLE_CMD_TX.get_or_init(|| device_cmd_tx);
     DB_CMD_TX.get_or_init(|| database_cmd_tx);
     GUI_EVENT_TX.get_or_init(|| gui_events_tx);
+    API_POLICY_TX.get_or_init(|| api_policy_tx);
 
     let thread_builder = std::thread::Builder::new().name("tokio".to_string());
     thread_builder
-        .spawn(move || tokio_thread(database_cmd_rx, device_cmd_rx))
+        .spawn(move || tokio_thread(database_cmd_rx, device_cmd_rx, api_policy_rx))
         .expect("Could not spawn tokio thread");
 
     options.renderer = Renderer::Wgpu;
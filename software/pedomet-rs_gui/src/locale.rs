@@ -0,0 +1,99 @@
+use chrono::{DateTime, Datelike, Locale, NaiveDate, TimeZone};
+
+use crate::settings::Language;
+
+impl Language {
+    fn locale(&self) -> Locale {
+        match self {
+            Language::German => Locale::de_DE,
+            Language::English => Locale::en_US,
+        }
+    }
+
+    fn thousands_separator(&self) -> char {
+        match self {
+            Language::German => '.',
+            Language::English => ',',
+        }
+    }
+}
+
+/// Integer with thousands separators, e.g. 10.000 or 10,000.
+pub(crate) fn format_number(language: Language, n: i64) -> String {
+    let digits = n.unsigned_abs().to_string();
+    let mut out = String::with_capacity(digits.len() + digits.len() / 3 + 1);
+    if n < 0 {
+        out.push('-');
+    }
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(language.thousands_separator());
+        }
+        out.push(digit);
+    }
+    out
+}
+
+/// Integer as entered by the user, optionally with the thousands separators of the language.
+pub(crate) fn parse_number(language: Language, text: &str) -> Option<i64> {
+    let digits: String = text
+        .trim()
+        .chars()
+        .filter(|c| *c != language.thousands_separator() && !c.is_whitespace())
+        .collect();
+    digits.parse().ok()
+}
+
+/// Pattern for the date picker, which cannot format localized names.
+pub(crate) fn date_pattern(language: Language) -> &'static str {
+    match language {
+        Language::German => "%d.%m.%Y",
+        Language::English => "%m/%d/%Y",
+    }
+}
+
+/// Short day with weekday, e.g. for the names of the bars.
+pub(crate) fn format_day(language: Language, date: NaiveDate) -> String {
+    let pattern = match language {
+        Language::German => "%a %d.%m.",
+        Language::English => "%a %m/%d",
+    };
+    date.format_localized(pattern, language.locale())
+        .to_string()
+}
+
+/// Day and weekday in two lines for the axis of a plot.
+pub(crate) fn format_day_axis(language: Language, date: NaiveDate) -> String {
+    let pattern = match language {
+        Language::German => "%d.%m.\n%a",
+        Language::English => "%m/%d\n%a",
+    };
+    date.format_localized(pattern, language.locale())
+        .to_string()
+}
+
+pub(crate) fn format_month(language: Language, date: NaiveDate) -> String {
+    // Only the name of the month differs
+    format!(
+        "{} {}",
+        date.format_localized("%B", language.locale()),
+        date.year()
+    )
+}
+
+pub(crate) fn format_date_time<Tz: TimeZone>(
+    language: Language,
+    dt: &DateTime<Tz>,
+    seconds: bool,
+) -> String
+where
+    Tz::Offset: std::fmt::Display,
+{
+    let pattern = match (language, seconds) {
+        (Language::German, false) => "%d.%m.%Y %H:%M",
+        (Language::German, true) => "%d.%m.%Y %H:%M:%S",
+        (Language::English, false) => "%m/%d/%Y %I:%M %p",
+        (Language::English, true) => "%m/%d/%Y %I:%M:%S %p",
+    };
+    dt.format_localized(pattern, language.locale()).to_string()
+}
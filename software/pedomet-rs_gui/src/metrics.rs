@@ -0,0 +1,125 @@
+use std::{
+    collections::VecDeque,
+    fmt::Write,
+    sync::{Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+/// Dropped commands are frequent if this many of them were dropped within
+/// [`DROPPED_COMMANDS_WINDOW`].
+const DROPPED_COMMANDS_FREQUENT: usize = 5;
+const DROPPED_COMMANDS_WINDOW: Duration = Duration::from_secs(10 * 60);
+
+/// Metrics of the app and the device that are not stored in the database.
+#[derive(Debug)]
+struct PedometerMetrics {
+    soc: Option<u8>,
+    last_sync: Option<Instant>,
+    sync_errors: u64,
+    dropped_commands: u64,
+    /// Times of the dropped commands within the last [`DROPPED_COMMANDS_WINDOW`]
+    recent_dropped_commands: VecDeque<Instant>,
+}
+
+static METRICS: Mutex<PedometerMetrics> = Mutex::new(PedometerMetrics {
+    soc: None,
+    last_sync: None,
+    sync_errors: 0,
+    dropped_commands: 0,
+    recent_dropped_commands: VecDeque::new(),
+});
+
+fn update(f: impl FnOnce(&mut PedometerMetrics)) {
+    f(&mut METRICS.lock().unwrap_or_else(PoisonError::into_inner));
+}
+
+/// State of charge of the connected device, `None` while disconnected.
+pub(crate) fn set_soc(soc: Option<u8>) {
+    update(|metrics| metrics.soc = soc);
+}
+
+/// Synced events were stored in the database.
+pub(crate) fn record_sync() {
+    update(|metrics| metrics.last_sync = Some(Instant::now()));
+}
+
+pub(crate) fn record_sync_error() {
+    update(|metrics| metrics.sync_errors += 1);
+}
+
+/// A command could not be handed over to another task of the app.
+///
+/// Returns whether commands are dropped frequently. This is only returned once per window, so
+/// that an alert is not repeated for every further command.
+pub(crate) fn record_dropped_command() -> bool {
+    let mut frequent = false;
+    update(|metrics| {
+        let now = Instant::now();
+        metrics.dropped_commands += 1;
+        metrics
+            .recent_dropped_commands
+            .retain(|dropped| now.duration_since(*dropped) < DROPPED_COMMANDS_WINDOW);
+        metrics.recent_dropped_commands.push_back(now);
+        if metrics.recent_dropped_commands.len() >= DROPPED_COMMANDS_FREQUENT {
+            metrics.recent_dropped_commands.clear();
+            frequent = true;
+        }
+    });
+    frequent
+}
+
+/// Commands dropped since the app was started.
+pub(crate) fn dropped_commands() -> u64 {
+    METRICS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .dropped_commands
+}
+
+/// Render the metrics in the Prometheus text format. Metrics without a value are left out.
+pub(crate) fn render(steps_today: i64) -> String {
+    let metrics = METRICS.lock().unwrap_or_else(PoisonError::into_inner);
+    let mut out = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, value: Option<String>| {
+        if let Some(value) = value {
+            // Writing to a String cannot fail
+            let _ = write!(
+                out,
+                "# HELP {name} {help}\n# TYPE {name} {kind}\n{name} {value}\n"
+            );
+        }
+    };
+    metric(
+        "pedometrs_steps_today",
+        "gauge",
+        "Steps of the current local day",
+        Some(steps_today.to_string()),
+    );
+    metric(
+        "pedometrs_battery_soc_percent",
+        "gauge",
+        "State of charge of the battery of the connected device",
+        metrics.soc.map(|soc| soc.to_string()),
+    );
+    metric(
+        "pedometrs_last_sync_age_seconds",
+        "gauge",
+        "Time since synced events were last stored",
+        metrics
+            .last_sync
+            .map(|last_sync| last_sync.elapsed().as_secs().to_string()),
+    );
+    metric(
+        "pedometrs_sync_errors_total",
+        "counter",
+        "Failed syncs since the app was started",
+        Some(metrics.sync_errors.to_string()),
+    );
+    metric(
+        "pedometrs_dropped_commands_total",
+        "counter",
+        "Commands that could not be handed over to another task since the app was started",
+        Some(metrics.dropped_commands.to_string()),
+    );
+    out
+}
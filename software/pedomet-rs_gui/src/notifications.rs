@@ -0,0 +1,290 @@
+use std::sync::OnceLock;
+
+use log::{info, warn};
+
+const APP_NAME: &str = "pedomet-rs";
+
+/// Notification that is shown by the operating system, also while the app is in the background.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum PedometerNotification {
+    GoalReached { steps: u32, target: u32 },
+    LowBattery { soc: u8 },
+    SyncFailed { error: String },
+}
+
+impl PedometerNotification {
+    fn title(&self) -> &'static str {
+        match self {
+            PedometerNotification::GoalReached { .. } => "Tagesziel erreicht",
+            PedometerNotification::LowBattery { .. } => "Akku fast leer",
+            PedometerNotification::SyncFailed { .. } => "Synchronisation fehlgeschlagen",
+        }
+    }
+
+    fn body(&self) -> String {
+        match self {
+            PedometerNotification::GoalReached { steps, target } => {
+                format!("{steps} von {target} Schritten")
+            }
+            PedometerNotification::LowBattery { soc } => {
+                format!("Der Akku des Schrittzählers ist noch zu {soc}% geladen")
+            }
+            PedometerNotification::SyncFailed { error } => {
+                format!("Die Schritte konnten nicht abgerufen werden: {error}")
+            }
+        }
+    }
+}
+
+/// Platform specific way to show a notification.
+trait NotificationBackend: Send + Sync {
+    fn show(&self, title: &str, body: &str) -> anyhow::Result<()>;
+
+    /// Show or replace the single ongoing notification with a progress bar. Only platforms with
+    /// a place for ongoing notifications support it.
+    fn show_ongoing(
+        &self,
+        _title: &str,
+        _body: &str,
+        _progress: u32,
+        _max: u32,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn cancel_ongoing(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(not(target_os = "android"))]
+struct DesktopNotifications;
+
+#[cfg(not(target_os = "android"))]
+impl NotificationBackend for DesktopNotifications {
+    fn show(&self, title: &str, body: &str) -> anyhow::Result<()> {
+        notify_rust::Notification::new()
+            .appname(APP_NAME)
+            .summary(title)
+            .body(body)
+            .show()?;
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "android")]
+struct AndroidNotifications;
+
+#[cfg(target_os = "android")]
+impl AndroidNotifications {
+    const CHANNEL_ID: &'static str = "pedomet-rs";
+    /// `NotificationManager.IMPORTANCE_DEFAULT`
+    const IMPORTANCE_DEFAULT: i32 = 3;
+    /// The ongoing notification keeps its id to be updated in place, the others count up from
+    /// the next one.
+    const ONGOING_ID: i32 = 0;
+
+    fn manager<'a>(env: &jni::JNIEnv<'a>) -> anyhow::Result<jni::objects::JObject<'a>> {
+        use jni::objects::{JObject, JValue};
+
+        let context = JObject::from(ndk_context::android_context().context() as jni::sys::jobject);
+        Ok(env
+            .call_method(
+                context,
+                "getSystemService",
+                "(Ljava/lang/String;)Ljava/lang/Object;",
+                &[JValue::from(env.new_string("notification")?)],
+            )?
+            .l()?)
+    }
+
+    /// Create the channel if needed and a builder with the icon, title and body set.
+    fn builder<'a>(
+        env: &jni::JNIEnv<'a>,
+        manager: jni::objects::JObject<'a>,
+        title: &str,
+        body: &str,
+    ) -> anyhow::Result<jni::objects::JObject<'a>> {
+        use jni::objects::{JObject, JValue};
+
+        let context = JObject::from(ndk_context::android_context().context() as jni::sys::jobject);
+        let channel_id = env.new_string(Self::CHANNEL_ID)?;
+        // Creating an existing channel does nothing
+        let channel = env.new_object(
+            "android/app/NotificationChannel",
+            "(Ljava/lang/String;Ljava/lang/CharSequence;I)V",
+            &[
+                JValue::from(channel_id),
+                JValue::from(env.new_string(APP_NAME)?),
+                JValue::Int(Self::IMPORTANCE_DEFAULT),
+            ],
+        )?;
+        env.call_method(
+            manager,
+            "createNotificationChannel",
+            "(Landroid/app/NotificationChannel;)V",
+            &[JValue::from(channel)],
+        )?;
+        let builder = env.new_object(
+            "android/app/Notification$Builder",
+            "(Landroid/content/Context;Ljava/lang/String;)V",
+            &[JValue::from(context), JValue::from(channel_id)],
+        )?;
+        let icon = env
+            .get_static_field("android/R$drawable", "ic_dialog_info", "I")?
+            .i()?;
+        env.call_method(
+            builder,
+            "setSmallIcon",
+            "(I)Landroid/app/Notification$Builder;",
+            &[JValue::Int(icon)],
+        )?;
+        for (method, text) in [("setContentTitle", title), ("setContentText", body)] {
+            env.call_method(
+                builder,
+                method,
+                "(Ljava/lang/CharSequence;)Landroid/app/Notification$Builder;",
+                &[JValue::from(env.new_string(text)?)],
+            )?;
+        }
+        Ok(builder)
+    }
+
+    fn notify<'a>(
+        env: &jni::JNIEnv<'a>,
+        manager: jni::objects::JObject<'a>,
+        builder: jni::objects::JObject<'a>,
+        id: i32,
+    ) -> anyhow::Result<()> {
+        use jni::objects::JValue;
+
+        let notification = env
+            .call_method(builder, "build", "()Landroid/app/Notification;", &[])?
+            .l()?;
+        env.call_method(
+            manager,
+            "notify",
+            "(ILandroid/app/Notification;)V",
+            &[JValue::Int(id), JValue::from(notification)],
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "android")]
+impl NotificationBackend for AndroidNotifications {
+    fn show(&self, title: &str, body: &str) -> anyhow::Result<()> {
+        use std::sync::atomic::{AtomicI32, Ordering};
+
+        use crate::android::{AndroidError, JAVAVM};
+
+        // Every notification gets its own id so that they do not replace each other
+        static NOTIFICATION_ID: AtomicI32 = AtomicI32::new(AndroidNotifications::ONGOING_ID + 1);
+
+        let env = JAVAVM
+            .get()
+            .ok_or(AndroidError::JavaVM)?
+            .attach_current_thread()?;
+        let manager = Self::manager(&env)?;
+        let builder = Self::builder(&env, manager, title, body)?;
+        Self::notify(
+            &env,
+            manager,
+            builder,
+            NOTIFICATION_ID.fetch_add(1, Ordering::Relaxed),
+        )
+    }
+
+    fn show_ongoing(&self, title: &str, body: &str, progress: u32, max: u32) -> anyhow::Result<()> {
+        use jni::objects::JValue;
+
+        use crate::android::{AndroidError, JAVAVM};
+
+        let env = JAVAVM
+            .get()
+            .ok_or(AndroidError::JavaVM)?
+            .attach_current_thread()?;
+        let manager = Self::manager(&env)?;
+        let builder = Self::builder(&env, manager, title, body)?;
+        for method in ["setOngoing", "setOnlyAlertOnce"] {
+            env.call_method(
+                builder,
+                method,
+                "(Z)Landroid/app/Notification$Builder;",
+                &[JValue::Bool(1)],
+            )?;
+        }
+        env.call_method(
+            builder,
+            "setProgress",
+            "(IIZ)Landroid/app/Notification$Builder;",
+            &[
+                JValue::Int(max.try_into().unwrap_or(i32::MAX)),
+                JValue::Int(progress.min(max).try_into().unwrap_or(i32::MAX)),
+                JValue::Bool(0),
+            ],
+        )?;
+        Self::notify(&env, manager, builder, Self::ONGOING_ID)
+    }
+
+    fn cancel_ongoing(&self) -> anyhow::Result<()> {
+        use jni::objects::JValue;
+
+        use crate::android::{AndroidError, JAVAVM};
+
+        let env = JAVAVM
+            .get()
+            .ok_or(AndroidError::JavaVM)?
+            .attach_current_thread()?;
+        let manager = Self::manager(&env)?;
+        env.call_method(manager, "cancel", "(I)V", &[JValue::Int(Self::ONGOING_ID)])?;
+        Ok(())
+    }
+}
+
+fn backend() -> &'static dyn NotificationBackend {
+    static BACKEND: OnceLock<Box<dyn NotificationBackend>> = OnceLock::new();
+    BACKEND
+        .get_or_init(|| {
+            #[cfg(target_os = "android")]
+            return Box::new(AndroidNotifications);
+            #[cfg(not(target_os = "android"))]
+            return Box::new(DesktopNotifications);
+        })
+        .as_ref()
+}
+
+/// Show the notification without blocking the caller, showing it may take a while.
+pub(crate) fn notify(notification: PedometerNotification) {
+    info!("Show notification: {notification:?}");
+    std::thread::spawn(move || {
+        if let Err(e) = backend().show(notification.title(), &notification.body()) {
+            warn!("Could not show notification: {e}");
+        }
+    });
+}
+
+/// Show or update the ongoing notification with the steps of today. Only shown on platforms that
+/// support ongoing notifications.
+pub(crate) fn show_sync_progress(steps: u32, target: u32) {
+    info!("Show sync progress: {steps}/{target}");
+    std::thread::spawn(move || {
+        let percent = steps as u64 * 100 / target.max(1) as u64;
+        if let Err(e) = backend().show_ongoing(
+            "Schritte heute",
+            &format!("{steps} von {target} Schritten ({percent}%)"),
+            steps,
+            target,
+        ) {
+            warn!("Could not show sync progress: {e}");
+        }
+    });
+}
+
+pub(crate) fn hide_sync_progress() {
+    std::thread::spawn(move || {
+        if let Err(e) = backend().cancel_ongoing() {
+            warn!("Could not hide sync progress: {e}");
+        }
+    });
+}
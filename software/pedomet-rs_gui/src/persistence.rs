@@ -1,26 +1,64 @@
-use std::{sync::OnceLock, time::Duration};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, AtomicU8, Ordering},
+        OnceLock,
+    },
+    time::Duration,
+};
 
 use anyhow::anyhow;
-use app_dirs2::{app_root, AppDataType};
-use chrono::{DateTime, Local, Utc};
+use app_dirs2::{app_dir, app_root, AppDataType};
+use chrono::{DateTime, Days, Local, NaiveDate, TimeDelta, TimeZone, Timelike, Utc};
 use log::{info, warn};
-use pedomet_rs_common::{PedometerEvent, PedometerEventType};
-use sqlx::{prelude::FromRow, SqlitePool};
+use pedomet_rs_common::{
+    PedometerActivity, PedometerError, PedometerEvent, PedometerEventType, PedometerMarker,
+    PedometerSelfTest,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::{migrate::Migrator, prelude::FromRow, SqliteConnection, SqlitePool};
 use tokio::{
     sync::{mpsc, oneshot},
     task::JoinHandle,
 };
 
-use crate::{error::PedometerGuiError, APP_INFO};
+use crate::{
+    archive::{self, PedometerArchiveSummary},
+    audit::PedometerAuditReport,
+    debug_events::{PedometerDebugEvent, PedometerDebugEventSource, PedometerDebugEventType},
+    error::PedometerGuiError,
+    gpx,
+    research::{self, PedometerResearchExport},
+    retry_journal,
+    session::PedometerSession,
+    steps::transform_events_to_relative_steps,
+    supervisor::SharedReceiver,
+    time_sync::PedometerTimeSyncQuality,
+    APP_INFO,
+};
 
 pub static DB_CMD_TX: OnceLock<mpsc::Sender<PedometerDatabaseCommand>> = OnceLock::new();
+static MIGRATOR: Migrator = sqlx::migrate!();
+/// Cadence above which steps are flagged as outlier. Even sprinters hardly exceed this.
+const MAX_PLAUSIBLE_STEPS_PER_MINUTE: i64 = 250;
+/// Local hour at which a day starts for the daily totals, see
+/// [`crate::settings::PedometerSettings::day_start_hour`].
+pub(crate) static DAY_START_HOUR: AtomicU8 = AtomicU8::new(0);
+/// Incremented whenever the stored steps may have changed, e.g. to tell if cached charts are
+/// outdated. It is incremented before the response of the changing command is sent.
+pub(crate) static DB_GENERATION: AtomicU64 = AtomicU64::new(0);
 
-#[derive(Debug, Copy, Clone, FromRow)]
+#[derive(Debug, Copy, Clone, FromRow, Serialize, Deserialize)]
 pub(crate) struct PedometerPersistenceEvent {
     pub event_id: i64,
     pub timestamp_ms: i64,
     pub boot_id: i64,
     pub steps: i64,
+    /// Activity that the device classified last before the steps, see [`PedometerActivity`].
+    /// It is looked up when the event is stored, daily summaries have none.
+    #[serde(default)]
+    pub activity: Option<i64>,
 }
 
 impl PedometerPersistenceEvent {
@@ -32,14 +70,21 @@ impl PedometerPersistenceEvent {
             event_id: common_event.index as i64,
             timestamp_ms: (common_event.timestamp_ms + offset.as_millis() as u64).try_into()?,
             boot_id: common_event.boot_id as i64,
-            steps: if let PedometerEventType::Steps(steps) = common_event.event_type {
-                steps as i64
-            } else {
-                return Err(PedometerGuiError::InvalidEventType(common_event.event_type).into());
+            steps: match common_event.event_type {
+                PedometerEventType::Steps(steps) => steps as i64,
+                PedometerEventType::DailySummary(steps) => steps as i64,
+                event_type => return Err(PedometerGuiError::InvalidEventType(event_type).into()),
             },
+            activity: None,
         })
     }
 
+    pub fn get_activity(&self) -> Option<PedometerActivity> {
+        u8::try_from(self.activity?)
+            .ok()
+            .and_then(PedometerActivity::from_u8)
+    }
+
     pub fn get_date_time(&self) -> anyhow::Result<DateTime<Utc>> {
         DateTime::from_timestamp_millis(self.timestamp_ms).ok_or_else(|| anyhow!("Invalid epoch"))
     }
@@ -49,30 +94,608 @@ impl PedometerPersistenceEvent {
     }
 }
 
+/// Error event of the device, see [`PedometerError`].
+#[derive(Debug, Copy, Clone, FromRow, Serialize, Deserialize)]
+pub(crate) struct PedometerPersistenceError {
+    pub event_id: i64,
+    pub timestamp_ms: i64,
+    pub boot_id: i64,
+    pub kind: i64,
+    pub code: i64,
+}
+
+impl PedometerPersistenceError {
+    pub const KIND_DISCONNECTED: i64 = 0;
+    pub const KIND_SOFTDEVICE: i64 = 1;
+    pub const KIND_IMU_POWER_CYCLED: i64 = 2;
+
+    pub fn from_common_event(
+        common_event: PedometerEvent,
+        offset: Duration,
+    ) -> anyhow::Result<Self> {
+        let PedometerEventType::Error(error) = common_event.event_type else {
+            return Err(PedometerGuiError::InvalidEventType(common_event.event_type).into());
+        };
+        let (kind, code) = match error {
+            PedometerError::Disconnected(reason) => (Self::KIND_DISCONNECTED, reason as i64),
+            PedometerError::Softdevice(code) => (Self::KIND_SOFTDEVICE, code as i64),
+            PedometerError::ImuPowerCycled => (Self::KIND_IMU_POWER_CYCLED, 0),
+        };
+        Ok(Self {
+            event_id: common_event.index as i64,
+            timestamp_ms: (common_event.timestamp_ms + offset.as_millis() as u64).try_into()?,
+            boot_id: common_event.boot_id as i64,
+            kind,
+            code,
+        })
+    }
+
+    pub fn get_error(&self) -> anyhow::Result<PedometerError> {
+        match self.kind {
+            Self::KIND_DISCONNECTED => Ok(PedometerError::Disconnected(self.code.try_into()?)),
+            Self::KIND_SOFTDEVICE => Ok(PedometerError::Softdevice(self.code.try_into()?)),
+            Self::KIND_IMU_POWER_CYCLED => Ok(PedometerError::ImuPowerCycled),
+            kind => Err(anyhow!("Invalid error kind: {kind}")),
+        }
+    }
+
+    pub fn get_date_time_local(&self) -> anyhow::Result<DateTime<Local>> {
+        timestamp_ms_to_local(self.timestamp_ms)
+    }
+}
+
+/// Boot of the device and the time range covered by its events.
+#[derive(Debug, Copy, Clone, FromRow, Serialize, Deserialize)]
+pub(crate) struct PedometerPersistenceBoot {
+    pub boot_id: i64,
+    pub first_timestamp_ms: i64,
+    pub last_timestamp_ms: i64,
+    /// Content of the RESETREAS register, unknown for devices with older firmware
+    pub reset_reason: Option<i64>,
+    /// Index of the boot event, unknown if it was not synced
+    pub event_id: Option<i64>,
+    /// Failed checks of the power-on self test, unknown for devices with older firmware
+    pub self_test: Option<i64>,
+}
+
+impl PedometerPersistenceBoot {
+    pub fn from_common_event(
+        common_event: PedometerEvent,
+        offset: Duration,
+    ) -> anyhow::Result<Self> {
+        let reset_reason = match common_event.event_type {
+            PedometerEventType::Boot => None,
+            PedometerEventType::BootWithResetReason(reset_reason) => Some(reset_reason as i64),
+            event_type => return Err(PedometerGuiError::InvalidEventType(event_type).into()),
+        };
+        let timestamp_ms = (common_event.timestamp_ms + offset.as_millis() as u64).try_into()?;
+        Ok(Self {
+            boot_id: common_event.boot_id as i64,
+            first_timestamp_ms: timestamp_ms,
+            last_timestamp_ms: timestamp_ms,
+            reset_reason,
+            event_id: Some(common_event.index as i64),
+            self_test: None,
+        })
+    }
+
+    pub fn get_self_test(&self) -> Option<PedometerSelfTest> {
+        self.self_test
+            .and_then(|self_test| u8::try_from(self_test).ok())
+            .map(PedometerSelfTest)
+    }
+
+    pub fn get_first_date_time_local(&self) -> anyhow::Result<DateTime<Local>> {
+        timestamp_ms_to_local(self.first_timestamp_ms)
+    }
+
+    pub fn get_last_date_time_local(&self) -> anyhow::Result<DateTime<Local>> {
+        timestamp_ms_to_local(self.last_timestamp_ms)
+    }
+}
+
+/// Sample of the host time that was sent to the device, see [`PedometerEventType::HostEpochMs`].
+#[derive(Debug, Copy, Clone, FromRow, Serialize, Deserialize)]
+pub(crate) struct PedometerPersistenceHostEpoch {
+    pub event_id: i64,
+    /// Device time in ms since boot when the host time was received
+    pub device_timestamp_ms: i64,
+    pub boot_id: i64,
+    pub host_epoch_ms: i64,
+}
+
+impl PedometerPersistenceHostEpoch {
+    pub fn from_common_event(common_event: PedometerEvent) -> anyhow::Result<Self> {
+        let PedometerEventType::HostEpochMs(host_epoch_ms) = common_event.event_type else {
+            return Err(PedometerGuiError::InvalidEventType(common_event.event_type).into());
+        };
+        Ok(Self {
+            event_id: common_event.index as i64,
+            device_timestamp_ms: common_event.timestamp_ms.try_into()?,
+            boot_id: common_event.boot_id as i64,
+            host_epoch_ms: host_epoch_ms.try_into()?,
+        })
+    }
+}
+
+/// Marker set by the user, see [`PedometerMarker`].
+#[derive(Debug, Copy, Clone, FromRow, Serialize, Deserialize)]
+pub(crate) struct PedometerPersistenceMarker {
+    /// Index of the marker event, unknown if the marker was set without connection
+    pub event_id: Option<i64>,
+    pub timestamp_ms: i64,
+    /// Boot of the marker event, unknown if the marker was set without connection
+    pub boot_id: Option<i64>,
+    pub kind: i64,
+}
+
+impl PedometerPersistenceMarker {
+    pub fn from_common_event(
+        common_event: PedometerEvent,
+        offset: Duration,
+    ) -> anyhow::Result<Self> {
+        let PedometerEventType::Marker(marker) = common_event.event_type else {
+            return Err(PedometerGuiError::InvalidEventType(common_event.event_type).into());
+        };
+        Ok(Self {
+            event_id: Some(common_event.index as i64),
+            timestamp_ms: (common_event.timestamp_ms + offset.as_millis() as u64).try_into()?,
+            boot_id: Some(common_event.boot_id as i64),
+            kind: marker as i64,
+        })
+    }
+
+    /// Marker that is set on the host because the device is not connected.
+    pub fn new_on_host(marker: PedometerMarker) -> Self {
+        Self {
+            event_id: None,
+            timestamp_ms: Utc::now().timestamp_millis(),
+            boot_id: None,
+            kind: marker as i64,
+        }
+    }
+
+    pub fn get_marker(&self) -> anyhow::Result<PedometerMarker> {
+        u8::try_from(self.kind)
+            .ok()
+            .and_then(PedometerMarker::from_u8)
+            .ok_or_else(|| anyhow!("Invalid marker kind: {}", self.kind))
+    }
+}
+
+/// Result of the power-on self test of a boot, see [`PedometerSelfTest`].
+#[derive(Debug, Copy, Clone, FromRow, Serialize, Deserialize)]
+pub(crate) struct PedometerPersistenceSelfTest {
+    pub event_id: i64,
+    pub timestamp_ms: i64,
+    pub boot_id: i64,
+    pub result: i64,
+}
+
+impl PedometerPersistenceSelfTest {
+    pub fn from_common_event(
+        common_event: PedometerEvent,
+        offset: Duration,
+    ) -> anyhow::Result<Self> {
+        let PedometerEventType::SelfTest(self_test) = common_event.event_type else {
+            return Err(PedometerGuiError::InvalidEventType(common_event.event_type).into());
+        };
+        Ok(Self {
+            event_id: common_event.index as i64,
+            timestamp_ms: (common_event.timestamp_ms + offset.as_millis() as u64).try_into()?,
+            boot_id: common_event.boot_id as i64,
+            result: self_test.0 as i64,
+        })
+    }
+}
+
+/// Result of a maintenance of the event storage of the device, see
+/// [`pedomet_rs_common::PedometerMaintenance`].
+#[derive(Debug, Copy, Clone, FromRow, Serialize, Deserialize)]
+pub(crate) struct PedometerPersistenceMaintenance {
+    pub event_id: i64,
+    pub timestamp_ms: i64,
+    pub boot_id: i64,
+    pub events: i64,
+    pub removed_entries: i64,
+    pub out_of_order_events: i64,
+    pub fill_percent: i64,
+}
+
+impl PedometerPersistenceMaintenance {
+    pub fn from_common_event(
+        common_event: PedometerEvent,
+        offset: Duration,
+    ) -> anyhow::Result<Self> {
+        let PedometerEventType::Maintenance(maintenance) = common_event.event_type else {
+            return Err(PedometerGuiError::InvalidEventType(common_event.event_type).into());
+        };
+        Ok(Self {
+            event_id: common_event.index as i64,
+            timestamp_ms: (common_event.timestamp_ms + offset.as_millis() as u64).try_into()?,
+            boot_id: common_event.boot_id as i64,
+            events: maintenance.events as i64,
+            removed_entries: maintenance.removed_entries as i64,
+            out_of_order_events: maintenance.out_of_order_events as i64,
+            fill_percent: maintenance.fill_percent as i64,
+        })
+    }
+
+    pub fn get_date_time_local(&self) -> anyhow::Result<DateTime<Local>> {
+        timestamp_ms_to_local(self.timestamp_ms)
+    }
+}
+
+/// Reading of a sensor on an add-on board, see [`pedomet_rs_common::PedometerSensorReading`].
+#[derive(Debug, Copy, Clone, FromRow, Serialize, Deserialize)]
+pub(crate) struct PedometerPersistenceSensorReading {
+    pub event_id: i64,
+    pub timestamp_ms: i64,
+    pub boot_id: i64,
+    pub sensor: i64,
+    pub value: i64,
+}
+
+impl PedometerPersistenceSensorReading {
+    pub fn from_common_event(
+        common_event: PedometerEvent,
+        offset: Duration,
+    ) -> anyhow::Result<Self> {
+        let PedometerEventType::SensorReading(reading) = common_event.event_type else {
+            return Err(PedometerGuiError::InvalidEventType(common_event.event_type).into());
+        };
+        Ok(Self {
+            event_id: common_event.index as i64,
+            timestamp_ms: (common_event.timestamp_ms + offset.as_millis() as u64).try_into()?,
+            boot_id: common_event.boot_id as i64,
+            sensor: reading.sensor as i64,
+            value: reading.value as i64,
+        })
+    }
+}
+
+/// Change of the activity that the device classified, see [`PedometerActivity`].
+#[derive(Debug, Copy, Clone, FromRow, Serialize, Deserialize)]
+pub(crate) struct PedometerPersistenceActivity {
+    pub event_id: i64,
+    pub timestamp_ms: i64,
+    pub boot_id: i64,
+    pub activity: i64,
+}
+
+impl PedometerPersistenceActivity {
+    pub fn from_common_event(
+        common_event: PedometerEvent,
+        offset: Duration,
+    ) -> anyhow::Result<Self> {
+        let PedometerEventType::Activity(activity) = common_event.event_type else {
+            return Err(PedometerGuiError::InvalidEventType(common_event.event_type).into());
+        };
+        Ok(Self {
+            event_id: common_event.index as i64,
+            timestamp_ms: (common_event.timestamp_ms + offset.as_millis() as u64).try_into()?,
+            boot_id: common_event.boot_id as i64,
+            activity: activity as i64,
+        })
+    }
+
+    pub fn get_activity(&self) -> anyhow::Result<PedometerActivity> {
+        u8::try_from(self.activity)
+            .ok()
+            .and_then(PedometerActivity::from_u8)
+            .ok_or_else(|| anyhow!("Invalid activity: {}", self.activity))
+    }
+}
+
+/// Temperature of the IMU that the device measured periodically.
+#[derive(Debug, Copy, Clone, FromRow, Serialize, Deserialize)]
+pub(crate) struct PedometerPersistenceTemperature {
+    pub event_id: i64,
+    pub timestamp_ms: i64,
+    pub boot_id: i64,
+    /// 0.01 °C
+    pub temperature: i64,
+}
+
+impl PedometerPersistenceTemperature {
+    pub fn from_common_event(
+        common_event: PedometerEvent,
+        offset: Duration,
+    ) -> anyhow::Result<Self> {
+        let PedometerEventType::Temperature(temperature) = common_event.event_type else {
+            return Err(PedometerGuiError::InvalidEventType(common_event.event_type).into());
+        };
+        Ok(Self {
+            event_id: common_event.index as i64,
+            timestamp_ms: (common_event.timestamp_ms + offset.as_millis() as u64).try_into()?,
+            boot_id: common_event.boot_id as i64,
+            temperature: temperature as i64,
+        })
+    }
+}
+
+/// Step event with an improbable number of steps, e.g. because the device was shaken.
+#[derive(Debug, Copy, Clone, FromRow)]
+pub(crate) struct PedometerPersistenceOutlier {
+    pub event_id: i64,
+    pub boot_id: i64,
+    pub timestamp_ms: i64,
+    /// Steps since the previous event
+    pub steps: i64,
+    /// The user confirmed that the steps are real, so they are counted
+    pub accepted: bool,
+}
+
+impl PedometerPersistenceOutlier {
+    pub fn get_date_time_local(&self) -> anyhow::Result<DateTime<Local>> {
+        timestamp_ms_to_local(self.timestamp_ms)
+    }
+}
+
+/// Steps of a local day.
+#[derive(Debug, Copy, Clone, Serialize)]
+pub(crate) struct PedometerDailyTotal {
+    pub date: NaiveDate,
+    pub steps: i64,
+}
+
+/// Last event of a boot up to which all events were processed during a sync.
+#[derive(Debug, Copy, Clone, FromRow)]
+pub(crate) struct PedometerPersistenceSyncState {
+    pub boot_id: i64,
+    pub last_event_id: i64,
+}
+
+/// Index, boot and timestamp of a stored event of any type.
+#[derive(Debug, Copy, Clone, FromRow)]
+pub(crate) struct PedometerPersistenceEventId {
+    pub event_id: i64,
+    pub boot_id: i64,
+    pub timestamp_ms: i64,
+}
+
+/// Synced event of any type in the representation of its table.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub(crate) enum PedometerPersistenceRecord {
+    Event(PedometerPersistenceEvent),
+    /// Daily summaries share the representation of step events. `timestamp_ms` is the end of
+    /// the summarized day.
+    DailySummary(PedometerPersistenceEvent),
+    DeviceError(PedometerPersistenceError),
+    HostEpoch(PedometerPersistenceHostEpoch),
+    Boot(PedometerPersistenceBoot),
+    Marker(PedometerPersistenceMarker),
+    SelfTest(PedometerPersistenceSelfTest),
+    Maintenance(PedometerPersistenceMaintenance),
+    SensorReading(PedometerPersistenceSensorReading),
+    Activity(PedometerPersistenceActivity),
+    Temperature(PedometerPersistenceTemperature),
+}
+
+impl PedometerPersistenceRecord {
+    pub fn from_common_event(
+        common_event: PedometerEvent,
+        offset: Duration,
+    ) -> anyhow::Result<Self> {
+        Ok(match common_event.event_type {
+            PedometerEventType::DailySummary(_) => Self::DailySummary(
+                PedometerPersistenceEvent::from_common_event(common_event, offset)?,
+            ),
+            PedometerEventType::Error(_) => Self::DeviceError(
+                PedometerPersistenceError::from_common_event(common_event, offset)?,
+            ),
+            PedometerEventType::HostEpochMs(_) => Self::HostEpoch(
+                PedometerPersistenceHostEpoch::from_common_event(common_event)?,
+            ),
+            PedometerEventType::Boot | PedometerEventType::BootWithResetReason(_) => Self::Boot(
+                PedometerPersistenceBoot::from_common_event(common_event, offset)?,
+            ),
+            PedometerEventType::Marker(_) => Self::Marker(
+                PedometerPersistenceMarker::from_common_event(common_event, offset)?,
+            ),
+            PedometerEventType::SelfTest(_) => Self::SelfTest(
+                PedometerPersistenceSelfTest::from_common_event(common_event, offset)?,
+            ),
+            PedometerEventType::Maintenance(_) => Self::Maintenance(
+                PedometerPersistenceMaintenance::from_common_event(common_event, offset)?,
+            ),
+            PedometerEventType::SensorReading(_) => Self::SensorReading(
+                PedometerPersistenceSensorReading::from_common_event(common_event, offset)?,
+            ),
+            PedometerEventType::Activity(_) => Self::Activity(
+                PedometerPersistenceActivity::from_common_event(common_event, offset)?,
+            ),
+            PedometerEventType::Temperature(_) => Self::Temperature(
+                PedometerPersistenceTemperature::from_common_event(common_event, offset)?,
+            ),
+            _ => Self::Event(PedometerPersistenceEvent::from_common_event(
+                common_event,
+                offset,
+            )?),
+        })
+    }
+}
+
+/// Start of the local day if it starts at the given hour.
+///
+/// If the clock is set forward over the start, the day starts at the first time that exists
+/// afterwards. If the clock is set back over it, the day starts at its first occurrence.
+pub(crate) fn local_day_start<Tz: TimeZone>(
+    tz: &Tz,
+    day: NaiveDate,
+    hour: u8,
+) -> anyhow::Result<DateTime<Utc>> {
+    let start = day
+        .and_hms_opt(hour.into(), 0, 0)
+        .ok_or_else(|| anyhow!("Invalid start hour {hour}"))?;
+    // All UTC offsets are multiples of 15 minutes, and even skipped days have an end
+    (0..=25 * 4)
+        .map(|quarter| start + TimeDelta::minutes(15 * quarter))
+        .find_map(|local| tz.from_local_datetime(&local).earliest())
+        .map(|dt| dt.to_utc())
+        .ok_or_else(|| anyhow!("Invalid local start of {day}"))
+}
+
+/// Local day to which the time belongs if a day starts at the given hour. This is based on the
+/// wall clock, so it matches [`local_day_start`] on days on which the clock is changed.
+pub(crate) fn local_day<Tz: TimeZone>(dt: &DateTime<Tz>, hour: u8) -> NaiveDate {
+    let local = dt.naive_local();
+    if local.hour() < hour.into() {
+        local.date() - Days::new(1)
+    } else {
+        local.date()
+    }
+}
+
+fn timestamp_ms_to_local(timestamp_ms: i64) -> anyhow::Result<DateTime<Local>> {
+    Ok(DateTime::from(
+        DateTime::from_timestamp_millis(timestamp_ms).ok_or_else(|| anyhow!("Invalid epoch"))?,
+    ))
+}
+
+/// Migration that was applied to the database.
+#[derive(Debug, Clone, FromRow)]
+pub(crate) struct PedometerSchemaMigration {
+    pub version: i64,
+    pub description: String,
+    pub installed_on: String,
+}
+
+/// Version of the database schema, which is the version of the last applied migration.
+#[derive(Debug, Clone)]
+pub(crate) struct PedometerSchemaInfo {
+    pub migrations: Vec<PedometerSchemaMigration>,
+    /// Version of the last migration that this build of the app knows
+    pub app_version: i64,
+}
+
+impl PedometerSchemaInfo {
+    pub fn version(&self) -> Option<i64> {
+        self.migrations
+            .iter()
+            .map(|migration| migration.version)
+            .max()
+    }
+}
+
+fn database_file() -> anyhow::Result<PathBuf> {
+    let mut db_file = app_root(AppDataType::UserData, &APP_INFO)?;
+    db_file.push("events.db");
+    Ok(db_file)
+}
+
+fn app_schema_version() -> i64 {
+    MIGRATOR
+        .iter()
+        .map(|migration| migration.version)
+        .max()
+        .unwrap_or_default()
+}
+
+/// An older build of the app cannot use the schema of a newer one and might even damage the data
+/// with its own migrations, so the database is not opened then.
+fn check_schema_version(database_version: Option<i64>, app_version: i64) -> anyhow::Result<()> {
+    match database_version {
+        Some(database_version) if database_version > app_version => {
+            Err(PedometerGuiError::DatabaseTooNew {
+                database_version,
+                app_version,
+            }
+            .into())
+        }
+        _ => Ok(()),
+    }
+}
+
 pub(crate) struct PedometerDatabase {
     pool: SqlitePool,
+    /// Records that could not be stored, see [`retry_journal`]. The in-memory database has none.
+    retry_journal: Option<PathBuf>,
+    /// Opened after its migration failed, see [`Self::new_read_only`]
+    read_only: bool,
 }
 
 impl PedometerDatabase {
     pub(crate) async fn new() -> anyhow::Result<Self> {
-        let mut db_file = app_root(AppDataType::UserData, &APP_INFO)?;
-        db_file.push("events.db");
+        let db_file = database_file()?;
         info!("Database file: {:?}", db_file);
         let pool =
             SqlitePool::connect(&format!("sqlite:{}?mode=rwc", db_file.to_string_lossy())).await?;
-        sqlx::migrate!().run(&pool).await?;
-        Ok(Self { pool })
+        // The table of the migrations does not exist before the first one was applied
+        let database_version: Option<i64> =
+            sqlx::query_scalar("SELECT max(version) FROM _sqlx_migrations WHERE success")
+                .fetch_one(&pool)
+                .await
+                .ok()
+                .flatten();
+        check_schema_version(database_version, app_schema_version())?;
+        MIGRATOR
+            .run(&pool)
+            .await
+            .map_err(|e| PedometerGuiError::MigrationFailed(e.to_string()))?;
+        quarantine_invalid_rows(&mut *pool.acquire().await?).await?;
+        let db = Self {
+            pool,
+            retry_journal: Some(retry_journal::file_path(&db_file)),
+            read_only: false,
+        };
+        // Retry the records that could not be stored in the last session
+        if let Err(e) = db.add_records(Vec::new(), None).await {
+            warn!("Could not store records of retry journal: {e}");
+        }
+        Ok(db)
+    }
+
+    /// Open the database without changing it after its migration failed, so the stored steps
+    /// can still be shown and backed up. Nothing can be stored until the migration was retried
+    /// with [`PedometerDatabaseCommand::RetryMigration`].
+    pub(crate) async fn new_read_only() -> anyhow::Result<Self> {
+        let db_file = database_file()?;
+        info!("Open database file read-only: {:?}", db_file);
+        let pool =
+            SqlitePool::connect(&format!("sqlite:{}?mode=ro", db_file.to_string_lossy())).await?;
+        Ok(Self {
+            pool,
+            retry_journal: None,
+            read_only: true,
+        })
+    }
+
+    /// Database that only exists as long as its single connection, e.g. for the end-to-end test
+    /// of the simulation.
+    #[cfg(all(test, feature = "demo"))]
+    pub(crate) async fn new_in_memory() -> anyhow::Result<Self> {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect("sqlite::memory:")
+            .await?;
+        MIGRATOR.run(&pool).await?;
+        Ok(Self {
+            pool,
+            retry_journal: None,
+            read_only: false,
+        })
     }
+
     pub(crate) async fn spawn_message_handler(
-        self,
-        mut event_receiver: mpsc::Receiver<PedometerDatabaseCommand>,
+        mut self,
+        event_receiver: SharedReceiver<PedometerDatabaseCommand>,
     ) -> JoinHandle<()> {
         tokio::spawn(async move {
+            let mut event_receiver = event_receiver.lock().await;
             while let Some(cmd) = event_receiver.recv().await {
                 match cmd {
-                    PedometerDatabaseCommand::AddEvent { event, responder } => {
-                        info!("Got AddEvent command: {event:?}");
-                        if responder.send(self.add_event(event).await).is_err() {
+                    PedometerDatabaseCommand::AddRecords {
+                        records,
+                        sync_state,
+                        responder,
+                    } => {
+                        info!("Got AddRecords command with {} records", records.len());
+                        let result = self.add_records(records, sync_state).await;
+                        DB_GENERATION.fetch_add(1, Ordering::Relaxed);
+                        if responder.send(result).is_err() {
                             warn!("Could not send response");
                         }
                     }
@@ -88,31 +711,299 @@ impl PedometerDatabase {
                             warn!("Could not send response");
                         }
                     }
-                    PedometerDatabaseCommand::GetLastEvent { responder } => {
-                        if responder.send(self.get_last_row().await).is_err() {
+                    PedometerDatabaseCommand::GetDailySummariesInTimeRange {
+                        start,
+                        end,
+                        responder,
+                    } => {
+                        if responder
+                            .send(self.get_daily_summaries_in_time_range(start, end).await)
+                            .is_err()
+                        {
                             warn!("Could not send response");
                         }
                     }
-                    PedometerDatabaseCommand::Exit => break,
-                }
-            }
-        })
-    }
-    async fn add_event(&self, event: PedometerPersistenceEvent) -> anyhow::Result<()> {
-        let mut conn = self.pool.acquire().await?;
-        sqlx::query!(
-            "
-        INSERT INTO events ( event_id, timestamp_ms, boot_id, steps  )
-        VALUES ( ?, ?, ?, ? )
-        ",
-            event.event_id,
-            event.timestamp_ms,
-            event.boot_id,
-            event.steps,
-        )
-        .execute(&mut *conn)
-        .await?;
-        Ok(())
+                    PedometerDatabaseCommand::GetDailyTotals {
+                        first_day,
+                        last_day,
+                        responder,
+                    } => {
+                        if responder
+                            .send(self.get_daily_totals(first_day, last_day).await)
+                            .is_err()
+                        {
+                            warn!("Could not send response");
+                        }
+                    }
+                    PedometerDatabaseCommand::GetOutliers { responder } => {
+                        if responder.send(self.get_outliers().await).is_err() {
+                            warn!("Could not send response");
+                        }
+                    }
+                    PedometerDatabaseCommand::SetOutlierAccepted {
+                        event_id,
+                        boot_id,
+                        accepted,
+                        responder,
+                    } => {
+                        let result = self.set_outlier_accepted(event_id, boot_id, accepted).await;
+                        DB_GENERATION.fetch_add(1, Ordering::Relaxed);
+                        if responder.send(result).is_err() {
+                            warn!("Could not send response");
+                        }
+                    }
+                    PedometerDatabaseCommand::GetLastDisconnect { responder } => {
+                        if responder.send(self.get_last_disconnect().await).is_err() {
+                            warn!("Could not send response");
+                        }
+                    }
+                    PedometerDatabaseCommand::GetLastMaintenance { responder } => {
+                        if responder.send(self.get_last_maintenance().await).is_err() {
+                            warn!("Could not send response");
+                        }
+                    }
+                    PedometerDatabaseCommand::GetQuarantinedRowCount { responder } => {
+                        if responder
+                            .send(self.get_quarantined_row_count().await)
+                            .is_err()
+                        {
+                            warn!("Could not send response");
+                        }
+                    }
+                    PedometerDatabaseCommand::GetBoots { responder } => {
+                        if responder.send(self.get_boots().await).is_err() {
+                            warn!("Could not send response");
+                        }
+                    }
+                    PedometerDatabaseCommand::GetAuditReport { responder } => {
+                        if responder.send(self.get_audit_report().await).is_err() {
+                            warn!("Could not send response");
+                        }
+                    }
+                    PedometerDatabaseCommand::GetTimeSyncQuality { responder } => {
+                        if responder.send(self.get_time_sync_quality().await).is_err() {
+                            warn!("Could not send response");
+                        }
+                    }
+                    PedometerDatabaseCommand::GetSyncState { responder } => {
+                        if responder.send(self.get_sync_state().await).is_err() {
+                            warn!("Could not send response");
+                        }
+                    }
+                    PedometerDatabaseCommand::GetSessions { responder } => {
+                        if responder.send(self.get_sessions().await).is_err() {
+                            warn!("Could not send response");
+                        }
+                    }
+                    PedometerDatabaseCommand::ImportTrack { path, responder } => {
+                        let result = self.import_track(path).await;
+                        DB_GENERATION.fetch_add(1, Ordering::Relaxed);
+                        if responder.send(result).is_err() {
+                            warn!("Could not send response");
+                        }
+                    }
+                    PedometerDatabaseCommand::ArchiveEvents {
+                        before,
+                        keep_raw,
+                        responder,
+                    } => {
+                        let result = self.archive_events(before, keep_raw).await;
+                        DB_GENERATION.fetch_add(1, Ordering::Relaxed);
+                        if responder.send(result).is_err() {
+                            warn!("Could not send response");
+                        }
+                    }
+                    PedometerDatabaseCommand::GetActivitiesInTimeRange {
+                        start,
+                        end,
+                        responder,
+                    } => {
+                        if responder
+                            .send(self.get_activities_in_time_range(start, end).await)
+                            .is_err()
+                        {
+                            warn!("Could not send response");
+                        }
+                    }
+                    PedometerDatabaseCommand::GetDebugEvents {
+                        first_day,
+                        last_day,
+                        responder,
+                    } => {
+                        if responder
+                            .send(self.get_debug_events(first_day, last_day).await)
+                            .is_err()
+                        {
+                            warn!("Could not send response");
+                        }
+                    }
+                    PedometerDatabaseCommand::ExportResearchData {
+                        first_day,
+                        last_day,
+                        responder,
+                    } => {
+                        if responder
+                            .send(self.export_research_data(first_day, last_day).await)
+                            .is_err()
+                        {
+                            warn!("Could not send response");
+                        }
+                    }
+                    PedometerDatabaseCommand::GetLastEvent { responder } => {
+                        if responder.send(self.get_last_row().await).is_err() {
+                            warn!("Could not send response");
+                        }
+                    }
+                    PedometerDatabaseCommand::GetSchemaInfo { responder } => {
+                        if responder.send(self.get_schema_info().await).is_err() {
+                            warn!("Could not send response");
+                        }
+                    }
+                    PedometerDatabaseCommand::BackupDatabase { responder } => {
+                        if responder.send(self.backup().await).is_err() {
+                            warn!("Could not send response");
+                        }
+                    }
+                    PedometerDatabaseCommand::RetryMigration { responder } => {
+                        let result = match Self::new().await {
+                            Ok(db) => {
+                                info!("Database migrated, leave read-only mode");
+                                self = db;
+                                DB_GENERATION.fetch_add(1, Ordering::Relaxed);
+                                Ok(())
+                            }
+                            Err(e) => Err(e),
+                        };
+                        if responder.send(result).is_err() {
+                            warn!("Could not send response");
+                        }
+                    }
+                    PedometerDatabaseCommand::Exit => break,
+                }
+            }
+        })
+    }
+
+    /// Drop all commands while the database cannot be opened, so the GUI does not block on the
+    /// full channel. It treats the dropped responders like failed requests.
+    pub(crate) fn spawn_unavailable_handler(
+        event_receiver: SharedReceiver<PedometerDatabaseCommand>,
+    ) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut event_receiver = event_receiver.lock().await;
+            while let Some(cmd) = event_receiver.recv().await {
+                if let PedometerDatabaseCommand::Exit = cmd {
+                    break;
+                }
+            }
+        })
+    }
+    /// Store the records together with the ones of the retry journal.
+    ///
+    /// Records that cannot be stored, e.g. because the disk is full, are kept in the retry journal
+    /// and stored again with the next records, as the device may already have deleted them.
+    /// Records that were already synced are skipped.
+    async fn add_records(
+        &self,
+        mut records: Vec<PedometerPersistenceRecord>,
+        sync_state: Option<PedometerPersistenceSyncState>,
+    ) -> anyhow::Result<()> {
+        // Fail the sync, so the device keeps the events until they can be stored
+        if self.read_only {
+            return Err(anyhow!(
+                "The database is read-only until its migration succeeded"
+            ));
+        }
+        let Some(journal) = &self.retry_journal else {
+            self.insert_records(&records, sync_state).await?;
+            return Ok(());
+        };
+        let journaled = retry_journal::load(journal)?;
+        if !journaled.is_empty() {
+            info!("Retry {} records of retry journal", journaled.len());
+            records.splice(0..0, journaled);
+        }
+        if records.is_empty() && sync_state.is_none() {
+            return Ok(());
+        }
+        match self.insert_records(&records, sync_state).await {
+            Ok(failed) => retry_journal::store(journal, &failed),
+            Err(e) => {
+                retry_journal::store(journal, &records)?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Store the records in one transaction, which is much faster than one transaction per
+    /// record. The sync state is only updated if the records were stored.
+    ///
+    /// Returns the records that could not be stored for another reason than that they were
+    /// already synced.
+    async fn insert_records(
+        &self,
+        records: &[PedometerPersistenceRecord],
+        sync_state: Option<PedometerPersistenceSyncState>,
+    ) -> anyhow::Result<Vec<PedometerPersistenceRecord>> {
+        let mut failed = Vec::new();
+        let mut tx = self.pool.begin().await?;
+        // Range of the indexes of the new step events of every boot
+        let mut step_boot_ids: HashMap<i64, (i64, i64)> = HashMap::new();
+        for &record in records {
+            if let PedometerPersistenceRecord::Event(event) = record {
+                let range = step_boot_ids
+                    .entry(event.boot_id)
+                    .or_insert((event.event_id, event.event_id));
+                *range = (range.0.min(event.event_id), range.1.max(event.event_id));
+            }
+            let result = match record {
+                PedometerPersistenceRecord::Event(event) => add_event(&mut tx, event).await,
+                PedometerPersistenceRecord::DailySummary(summary) => {
+                    add_daily_summary(&mut tx, summary).await
+                }
+                PedometerPersistenceRecord::DeviceError(error) => {
+                    add_device_error(&mut tx, error).await
+                }
+                PedometerPersistenceRecord::HostEpoch(host_epoch) => {
+                    add_host_epoch(&mut tx, host_epoch).await
+                }
+                PedometerPersistenceRecord::Boot(boot) => add_boot(&mut tx, boot).await,
+                PedometerPersistenceRecord::Marker(marker) => add_marker(&mut tx, marker).await,
+                PedometerPersistenceRecord::SelfTest(self_test) => {
+                    add_self_test(&mut tx, self_test).await
+                }
+                PedometerPersistenceRecord::Maintenance(maintenance) => {
+                    add_maintenance(&mut tx, maintenance).await
+                }
+                PedometerPersistenceRecord::SensorReading(reading) => {
+                    add_sensor_reading(&mut tx, reading).await
+                }
+                PedometerPersistenceRecord::Activity(activity) => {
+                    add_activity(&mut tx, activity).await
+                }
+                PedometerPersistenceRecord::Temperature(temperature) => {
+                    add_temperature(&mut tx, temperature).await
+                }
+            };
+            if let Err(e) = result {
+                warn!("Could not add record to db: {record:?} -> {e}");
+                if !is_unique_violation(&e) {
+                    failed.push(record);
+                }
+            }
+        }
+        if let Some(sync_state) = sync_state {
+            set_sync_state(&mut tx, sync_state).await?;
+        }
+        quarantine_invalid_rows(&mut tx).await?;
+        let mut days = BTreeSet::new();
+        for (boot_id, (min_event_id, max_event_id)) in step_boot_ids {
+            flag_outliers(&mut tx, boot_id).await?;
+            days.extend(get_affected_days(&mut tx, boot_id, min_event_id, max_event_id).await?);
+        }
+        update_steps_daily(&mut tx, &days).await?;
+        tx.commit().await?;
+        Ok(failed)
     }
 
     async fn get_events_in_time_range(
@@ -126,7 +1017,7 @@ impl PedometerDatabase {
         Ok(sqlx::query_as!(
             PedometerPersistenceEvent,
             "
-        SELECT event_id, timestamp_ms, boot_id, steps
+        SELECT event_id, timestamp_ms, boot_id, steps, activity
         FROM events
         WHERE timestamp_ms BETWEEN ? AND ?
         ",
@@ -137,11 +1028,749 @@ impl PedometerDatabase {
         .await?)
     }
 
+    async fn get_daily_summaries_in_time_range(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<PedometerPersistenceEvent>> {
+        let start_ms: i64 = start.timestamp_millis();
+        let end_ms: i64 = end.timestamp_millis();
+        info!("Get daily summaries between {} and {}", start_ms, end_ms);
+        // Archived days are returned like daily summaries of the device, so that they are used
+        // for the days without step events
+        Ok(sqlx::query_as!(
+            PedometerPersistenceEvent,
+            r#"
+        SELECT event_id as "event_id!: i64", timestamp_ms as "timestamp_ms!: i64",
+            boot_id as "boot_id!: i64", steps as "steps!: i64", NULL as "activity?: i64"
+        FROM (
+            SELECT event_id, timestamp_ms, boot_id, steps
+            FROM daily_summaries
+            WHERE timestamp_ms BETWEEN ?1 AND ?2
+            UNION ALL
+            SELECT -1, timestamp_ms, -1, steps
+            FROM archived_days
+            WHERE timestamp_ms BETWEEN ?1 AND ?2
+        )
+        "#,
+            start_ms,
+            end_ms,
+        )
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    /// Steps per local day from `first_day` to `last_day` like in the overview, i.e. days
+    /// without step events fall back to the daily summaries of the device.
+    ///
+    /// A day starts at [`DAY_START_HOUR`], so steps before belong to the previous day. The
+    /// daily summaries of the device always end at midnight, though. The steps of the events are
+    /// read from `steps_daily`, which is rebuilt first if the start of the days changed.
+    async fn get_daily_totals(
+        &self,
+        first_day: NaiveDate,
+        last_day: NaiveDate,
+    ) -> anyhow::Result<Vec<PedometerDailyTotal>> {
+        let mut totals: HashMap<NaiveDate, i64> = first_day
+            .iter_days()
+            .take_while(|day| *day <= last_day)
+            .map(|day| (day, 0))
+            .collect();
+        let mut tx = self.pool.begin().await?;
+        if get_steps_daily_hour(&mut tx).await? != Some(DAY_START_HOUR.load(Ordering::Relaxed)) {
+            rebuild_steps_daily(&mut tx).await?;
+        }
+        let first_date = first_day.to_string();
+        let last_date = last_day.to_string();
+        for row in sqlx::query!(
+            "
+        SELECT date, steps
+        FROM steps_daily
+        WHERE date BETWEEN ? AND ?
+        ",
+            first_date,
+            last_date,
+        )
+        .fetch_all(&mut *tx)
+        .await?
+        {
+            if let Some(steps) = totals.get_mut(&row.date.parse()?) {
+                *steps = row.steps;
+            }
+        }
+        tx.commit().await?;
+
+        // Daily summaries are created at the end of a day and the device clock may drift a bit
+        let mut summaries: HashMap<NaiveDate, i64> = HashMap::new();
+        for summary in self
+            .get_daily_summaries_in_time_range(
+                local_day_start(&Local, first_day + Days::new(1), 0)? - TimeDelta::hours(1),
+                local_day_start(&Local, last_day + Days::new(1), 0)? + TimeDelta::hours(1),
+            )
+            .await?
+        {
+            if let Ok(summary_dt) = summary.get_date_time_local() {
+                let day = (summary_dt - TimeDelta::hours(1)).date_naive();
+                let steps = summaries.entry(day).or_default();
+                *steps = (*steps).max(summary.steps);
+            }
+        }
+
+        let mut totals: Vec<_> = totals
+            .into_iter()
+            .map(|(date, steps)| PedometerDailyTotal {
+                date,
+                steps: match summaries.get(&date) {
+                    Some(summary_steps) if steps == 0 => *summary_steps,
+                    _ => steps,
+                },
+            })
+            .collect();
+        totals.sort_by_key(|total| total.date);
+        Ok(totals)
+    }
+
+    async fn get_outliers(&self) -> anyhow::Result<Vec<PedometerPersistenceOutlier>> {
+        Ok(sqlx::query_as!(
+            PedometerPersistenceOutlier,
+            r#"
+        SELECT event_id, boot_id, timestamp_ms, steps, accepted as "accepted: bool"
+        FROM step_outliers
+        ORDER BY timestamp_ms
+        "#
+        )
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    /// Boot and index of the outliers that were not accepted by the user.
+    async fn get_excluded_outliers(&self) -> anyhow::Result<HashSet<(i64, i64)>> {
+        Ok(self
+            .get_outliers()
+            .await?
+            .into_iter()
+            .filter(|outlier| !outlier.accepted)
+            .map(|outlier| (outlier.boot_id, outlier.event_id))
+            .collect())
+    }
+
+    async fn set_outlier_accepted(
+        &self,
+        event_id: i64,
+        boot_id: i64,
+        accepted: bool,
+    ) -> anyhow::Result<()> {
+        let mut tx = self.pool.begin().await?;
+        let timestamp_ms = sqlx::query!(
+            "
+        UPDATE step_outliers
+        SET accepted = ?
+        WHERE event_id = ? AND boot_id = ?
+        RETURNING timestamp_ms
+        ",
+            accepted,
+            event_id,
+            boot_id,
+        )
+        .fetch_one(&mut *tx)
+        .await?
+        .timestamp_ms;
+        let day = local_day(
+            &timestamp_ms_to_local(timestamp_ms)?,
+            DAY_START_HOUR.load(Ordering::Relaxed),
+        );
+        update_steps_daily(&mut tx, &BTreeSet::from([day])).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Steps from the start of `first_day` to the end of `last_day` per hour without identifiers
+    /// and with moved timestamps. Excluded outliers and archived days are not exported.
+    async fn export_research_data(
+        &self,
+        first_day: NaiveDate,
+        last_day: NaiveDate,
+    ) -> anyhow::Result<PedometerResearchExport> {
+        let start = local_day_start(&Local, first_day, 0)?;
+        let end = local_day_start(&Local, last_day + Days::new(1), 0)? - TimeDelta::milliseconds(1);
+        let excluded = self.get_excluded_outliers().await?;
+        let mut events = self.get_events_in_time_range(start, end).await?;
+        events.sort_by_key(|event| (event.boot_id, event.event_id));
+        let events: Vec<_> = transform_events_to_relative_steps(events)
+            .into_iter()
+            .filter(|event| !excluded.contains(&(event.boot_id, event.event_id)))
+            .collect();
+        let bins = research::hourly_bins(&events);
+        let file = research::file_path()?;
+        tokio::fs::write(&file, research::to_csv(&bins, research::random_jitter())).await?;
+        info!("Exported {} hours to {}", bins.len(), file.display());
+        Ok(PedometerResearchExport {
+            hours: bins.len(),
+            file,
+        })
+    }
+
+    async fn get_activities_in_time_range(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<PedometerPersistenceActivity>> {
+        let start_ms: i64 = start.timestamp_millis();
+        let end_ms: i64 = end.timestamp_millis();
+        let mut activities: Vec<_> = sqlx::query_as!(
+            PedometerPersistenceActivity,
+            "
+        SELECT event_id, timestamp_ms, boot_id, activity
+        FROM activities
+        WHERE timestamp_ms < ?
+        ORDER BY timestamp_ms DESC
+        LIMIT 1
+        ",
+            start_ms,
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .into_iter()
+        .collect();
+        activities.extend(
+            sqlx::query_as!(
+                PedometerPersistenceActivity,
+                "
+        SELECT event_id, timestamp_ms, boot_id, activity
+        FROM activities
+        WHERE timestamp_ms BETWEEN ? AND ?
+        ORDER BY timestamp_ms
+        ",
+                start_ms,
+                end_ms,
+            )
+            .fetch_all(&self.pool)
+            .await?,
+        );
+        Ok(activities)
+    }
+
+    /// Records of all types from the start of `first_day` to the end of `last_day` ordered by
+    /// their timestamp. Host epochs are placed at the time of the host.
+    async fn get_debug_events(
+        &self,
+        first_day: NaiveDate,
+        last_day: NaiveDate,
+    ) -> anyhow::Result<Vec<PedometerDebugEvent>> {
+        let start_ms = local_day_start(&Local, first_day, 0)?.timestamp_millis();
+        let end_ms = local_day_start(&Local, last_day + Days::new(1), 0)?.timestamp_millis() - 1;
+        info!("Get debug events between {} and {}", start_ms, end_ms);
+        let rows = sqlx::query!(
+            r#"
+        SELECT event_type as "event_type!: i64", event_id as "event_id?: i64",
+            boot_id as "boot_id?: i64", timestamp_ms as "timestamp_ms!: i64",
+            details as "details!: String"
+        FROM (
+            SELECT 0 AS event_type, event_id, boot_id, timestamp_ms, 'steps=' || steps AS details
+            FROM events
+            WHERE timestamp_ms BETWEEN ?1 AND ?2
+            UNION ALL
+            SELECT 1, event_id, boot_id, timestamp_ms, 'steps=' || steps
+            FROM daily_summaries
+            WHERE timestamp_ms BETWEEN ?1 AND ?2
+            UNION ALL
+            SELECT 2, event_id, boot_id, timestamp_ms, 'kind=' || kind || ' code=' || code
+            FROM device_errors
+            WHERE timestamp_ms BETWEEN ?1 AND ?2
+            UNION ALL
+            SELECT 3, event_id, boot_id, host_epoch_ms,
+                'device_timestamp_ms=' || device_timestamp_ms
+            FROM host_epochs
+            WHERE host_epoch_ms BETWEEN ?1 AND ?2
+            UNION ALL
+            SELECT 4, event_id, boot_id, timestamp_ms, 'kind=' || kind
+            FROM markers
+            WHERE timestamp_ms BETWEEN ?1 AND ?2
+            UNION ALL
+            SELECT 5, event_id, boot_id, timestamp_ms, 'result=' || result
+            FROM self_tests
+            WHERE timestamp_ms BETWEEN ?1 AND ?2
+            UNION ALL
+            SELECT 6, event_id, boot_id, timestamp_ms,
+                'events=' || events || ' removed_entries=' || removed_entries
+                || ' out_of_order_events=' || out_of_order_events
+                || ' fill_percent=' || fill_percent
+            FROM maintenances
+            WHERE timestamp_ms BETWEEN ?1 AND ?2
+            UNION ALL
+            SELECT 7, event_id, boot_id, timestamp_ms, 'sensor=' || sensor || ' value=' || value
+            FROM sensor_readings
+            WHERE timestamp_ms BETWEEN ?1 AND ?2
+            UNION ALL
+            SELECT 8, event_id, boot_id, timestamp_ms, 'activity=' || activity
+            FROM activities
+            WHERE timestamp_ms BETWEEN ?1 AND ?2
+            UNION ALL
+            SELECT 9, event_id, boot_id, timestamp_ms, 'temperature=' || temperature
+            FROM temperatures
+            WHERE timestamp_ms BETWEEN ?1 AND ?2
+        )
+        ORDER BY timestamp_ms, event_type
+        "#,
+            start_ms,
+            end_ms,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        rows.into_iter()
+            .map(|row| {
+                Ok(PedometerDebugEvent {
+                    event_type: PedometerDebugEventType::from_discriminant(row.event_type)
+                        .ok_or_else(|| anyhow!("Invalid debug event type {}", row.event_type))?,
+                    // Only markers that were set without connection have no index
+                    source: if row.event_id.is_some() {
+                        PedometerDebugEventSource::Device
+                    } else {
+                        PedometerDebugEventSource::Host
+                    },
+                    event_id: row.event_id,
+                    boot_id: row.boot_id,
+                    timestamp_ms: row.timestamp_ms,
+                    details: row.details,
+                })
+            })
+            .collect()
+    }
+
+    async fn get_last_disconnect(&self) -> anyhow::Result<Option<PedometerPersistenceError>> {
+        Ok(sqlx::query_as!(
+            PedometerPersistenceError,
+            "
+        SELECT event_id, timestamp_ms, boot_id, kind, code
+        FROM device_errors
+        WHERE kind = ?
+        ORDER BY timestamp_ms DESC
+        LIMIT 1
+        ",
+            PedometerPersistenceError::KIND_DISCONNECTED,
+        )
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    async fn get_last_maintenance(
+        &self,
+    ) -> anyhow::Result<Option<PedometerPersistenceMaintenance>> {
+        Ok(sqlx::query_as!(
+            PedometerPersistenceMaintenance,
+            "
+        SELECT event_id, timestamp_ms, boot_id, events, removed_entries, out_of_order_events,
+            fill_percent
+        FROM maintenances
+        ORDER BY timestamp_ms DESC
+        LIMIT 1
+        ",
+        )
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    async fn get_schema_info(&self) -> anyhow::Result<PedometerSchemaInfo> {
+        // The table is created by sqlx itself, so it cannot be checked at compile time
+        let migrations = sqlx::query_as::<_, PedometerSchemaMigration>(
+            "
+        SELECT version, description, CAST(installed_on AS TEXT) AS installed_on
+        FROM _sqlx_migrations
+        WHERE success
+        ORDER BY version
+        ",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(PedometerSchemaInfo {
+            migrations,
+            app_version: app_schema_version(),
+        })
+    }
+
+    /// Consistent copy of the database, which also works while it is opened read-only. The backup
+    /// directory is next to the database.
+    async fn backup(&self) -> anyhow::Result<PathBuf> {
+        let mut path = app_dir(AppDataType::UserData, &APP_INFO, "backup")?;
+        path.push(format!(
+            "events-{}.db",
+            Local::now().format("%Y%m%d-%H%M%S")
+        ));
+        // The statement cannot be prepared at compile time because of the bound file name
+        sqlx::query("VACUUM INTO ?")
+            .bind(path.to_string_lossy().into_owned())
+            .execute(&self.pool)
+            .await?;
+        info!("Database backed up to {}", path.display());
+        Ok(path)
+    }
+
+    async fn get_quarantined_row_count(&self) -> anyhow::Result<i64> {
+        Ok(sqlx::query_scalar!(
+            r#"
+        SELECT count(*) as "count!: i64"
+        FROM quarantined_rows
+        "#
+        )
+        .fetch_one(&self.pool)
+        .await?)
+    }
+
+    async fn get_audit_report(&self) -> anyhow::Result<PedometerAuditReport> {
+        let event_ids = sqlx::query_as!(
+            PedometerPersistenceEventId,
+            r#"
+        SELECT event_id as "event_id!", boot_id as "boot_id!", timestamp_ms as "timestamp_ms!"
+        FROM (
+            SELECT event_id, boot_id, timestamp_ms FROM events
+            UNION ALL
+            SELECT event_id, boot_id, timestamp_ms FROM daily_summaries
+            UNION ALL
+            SELECT event_id, boot_id, timestamp_ms FROM device_errors
+            UNION ALL
+            SELECT event_id, boot_id, host_epoch_ms FROM host_epochs
+            UNION ALL
+            SELECT event_id, boot_id, first_timestamp_ms FROM boots WHERE event_id IS NOT NULL
+            UNION ALL
+            SELECT event_id, boot_id, timestamp_ms FROM markers WHERE event_id IS NOT NULL
+            UNION ALL
+            SELECT event_id, boot_id, timestamp_ms FROM self_tests
+            UNION ALL
+            SELECT event_id, boot_id, timestamp_ms FROM maintenances
+            UNION ALL
+            SELECT event_id, boot_id, timestamp_ms FROM sensor_readings
+            UNION ALL
+            SELECT event_id, boot_id, timestamp_ms FROM activities
+            UNION ALL
+            SELECT event_id, boot_id, timestamp_ms FROM temperatures
+        )
+        ORDER BY event_id
+        "#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        let step_events = sqlx::query_as!(
+            PedometerPersistenceEvent,
+            "
+        SELECT event_id, timestamp_ms, boot_id, steps, activity
+        FROM events
+        ORDER BY boot_id, event_id
+        "
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        let archived_event_ids = sqlx::query!(
+            "
+        SELECT first_event_id, last_event_id
+        FROM archives
+        "
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|archive| archive.first_event_id..=archive.last_event_id)
+        .collect::<Vec<_>>();
+        Ok(PedometerAuditReport::new(
+            &event_ids,
+            &step_events,
+            &archived_event_ids,
+            Utc::now().timestamp_millis(),
+        ))
+    }
+
+    async fn get_boots(&self) -> anyhow::Result<Vec<PedometerPersistenceBoot>> {
+        Ok(sqlx::query_as!(
+            PedometerPersistenceBoot,
+            r#"
+        SELECT boots.boot_id, first_timestamp_ms, last_timestamp_ms, reset_reason,
+            boots.event_id, self_tests.result as "self_test?"
+        FROM boots
+        LEFT JOIN self_tests ON self_tests.boot_id = boots.boot_id
+        ORDER BY boots.boot_id
+        "#
+        )
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    async fn get_time_sync_quality(&self) -> anyhow::Result<Vec<PedometerTimeSyncQuality>> {
+        let host_epochs = sqlx::query_as!(
+            PedometerPersistenceHostEpoch,
+            "
+        SELECT event_id, device_timestamp_ms, boot_id, host_epoch_ms
+        FROM host_epochs
+        ORDER BY boot_id, event_id
+        "
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(PedometerTimeSyncQuality::from_host_epochs(&host_epochs))
+    }
+
+    async fn get_sync_state(&self) -> anyhow::Result<Option<PedometerPersistenceSyncState>> {
+        Ok(sqlx::query_as!(
+            PedometerPersistenceSyncState,
+            "
+        SELECT boot_id, last_event_id
+        FROM sync_state
+        ORDER BY last_event_id DESC
+        LIMIT 1
+        "
+        )
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    async fn get_sessions(&self) -> anyhow::Result<Vec<PedometerSession>> {
+        let markers = sqlx::query_as!(
+            PedometerPersistenceMarker,
+            "
+        SELECT event_id, timestamp_ms, boot_id, kind
+        FROM markers
+        ORDER BY timestamp_ms
+        "
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        let Some(first_marker) = markers.first() else {
+            return Ok(Vec::new());
+        };
+        let step_events = sqlx::query_as!(
+            PedometerPersistenceEvent,
+            "
+        SELECT event_id, timestamp_ms, boot_id, steps, activity
+        FROM events
+        WHERE timestamp_ms >= ?
+        ORDER BY boot_id, event_id
+        ",
+            first_marker.timestamp_ms,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        let distances: HashMap<i64, f64> = sqlx::query!(
+            "
+        SELECT session_start_ms, distance_m
+        FROM session_tracks
+        "
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|track| (track.session_start_ms, track.distance_m))
+        .collect();
+        let mut sessions = PedometerSession::from_markers(&markers, step_events);
+        for session in &mut sessions {
+            session.distance_m = distances.get(&session.start_ms).copied();
+        }
+        Ok(sessions)
+    }
+
+    /// Import the GPX file and store the distance of the track for every finished session it
+    /// overlaps. Returns the number of annotated sessions.
+    async fn import_track(&self, path: PathBuf) -> anyhow::Result<usize> {
+        info!("Import track {path:?}");
+        let points = gpx::parse(&tokio::fs::read_to_string(path).await?)?;
+        let mut annotated = 0;
+        let mut tx = self.pool.begin().await?;
+        for session in self.get_sessions().await? {
+            let Some(end_ms) = session.end_ms else {
+                continue;
+            };
+            let Some(segment) = gpx::segment(&points, session.start_ms, end_ms) else {
+                continue;
+            };
+            let points = segment.points as i64;
+            sqlx::query!(
+                "
+            INSERT INTO session_tracks ( session_start_ms, distance_m, points )
+            VALUES ( ?, ?, ? )
+            ON CONFLICT ( session_start_ms ) DO UPDATE SET
+                distance_m = excluded.distance_m,
+                points = excluded.points
+            ",
+                session.start_ms,
+                segment.distance_m,
+                points,
+            )
+            .execute(&mut *tx)
+            .await?;
+            annotated += 1;
+        }
+        tx.commit().await?;
+        Ok(annotated)
+    }
+
+    /// Roll the step events before the given time into daily totals, which are returned like
+    /// daily summaries afterwards, and remove them to keep the database small.
+    ///
+    /// Like the daily summaries of the device, archived days always end at midnight.
+    async fn archive_events(
+        &self,
+        before: DateTime<Utc>,
+        keep_raw: bool,
+    ) -> anyhow::Result<PedometerArchiveSummary> {
+        let before_ms = before.timestamp_millis();
+        info!("Archive events before {before_ms}");
+        let excluded = self.get_excluded_outliers().await?;
+        let mut tx = self.pool.begin().await?;
+        let archived = sqlx::query_as!(
+            PedometerPersistenceEvent,
+            "
+        SELECT event_id, timestamp_ms, boot_id, steps, activity
+        FROM events
+        WHERE timestamp_ms < ?
+        ORDER BY boot_id, event_id
+        ",
+            before_ms,
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        let (Some(first_event_id), Some(last_event_id)) = (
+            archived.iter().map(|event| event.event_id).min(),
+            archived.iter().map(|event| event.event_id).max(),
+        ) else {
+            info!("No events to archive");
+            return Ok(Default::default());
+        };
+
+        // The steps of the first event of a boot are relative to the last archived one
+        let anchors = sqlx::query_as!(
+            PedometerPersistenceEvent,
+            r#"
+        SELECT event_id, timestamp_ms, boot_id, steps, NULL as "activity?: i64"
+        FROM archive_anchors
+        "#
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        let anchor_ids: HashSet<_> = anchors
+            .iter()
+            .map(|anchor| (anchor.boot_id, anchor.event_id))
+            .collect();
+        let mut events: Vec<_> = anchors
+            .into_iter()
+            .chain(archived.iter().copied())
+            .collect();
+        events.sort_by_key(|event| (event.boot_id, event.event_id));
+        let mut days: BTreeMap<NaiveDate, i64> = BTreeMap::new();
+        for event in transform_events_to_relative_steps(events)
+            .into_iter()
+            .filter(|event| !anchor_ids.contains(&(event.boot_id, event.event_id)))
+            .filter(|event| !excluded.contains(&(event.boot_id, event.event_id)))
+        {
+            *days
+                .entry(local_day(&event.get_date_time_local()?, 0))
+                .or_default() += event.steps;
+        }
+        days.retain(|_, steps| *steps > 0);
+        for (day, steps) in &days {
+            let timestamp_ms = local_day_start(&Local, *day + Days::new(1), 0)?.timestamp_millis();
+            sqlx::query!(
+                "
+            INSERT INTO archived_days ( timestamp_ms, steps )
+            VALUES ( ?, ? )
+            ON CONFLICT ( timestamp_ms ) DO UPDATE SET
+                steps = steps + excluded.steps
+            ",
+                timestamp_ms,
+                steps,
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        // The events are sorted by boot and index, so the last one of a boot is its anchor
+        for anchor in archived
+            .chunk_by(|a, b| a.boot_id == b.boot_id)
+            .filter_map(|boot| boot.last())
+        {
+            sqlx::query!(
+                "
+            INSERT INTO archive_anchors ( boot_id, event_id, timestamp_ms, steps )
+            VALUES ( ?, ?, ?, ? )
+            ON CONFLICT ( boot_id ) DO UPDATE SET
+                event_id = excluded.event_id,
+                timestamp_ms = excluded.timestamp_ms,
+                steps = excluded.steps
+            ",
+                anchor.boot_id,
+                anchor.event_id,
+                anchor.timestamp_ms,
+                anchor.steps,
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        let removed = sqlx::query!(
+            "
+        DELETE FROM events
+        WHERE timestamp_ms < ?
+        ",
+            before_ms,
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        sqlx::query!(
+            "
+        DELETE FROM step_outliers
+        WHERE timestamp_ms < ?
+        ",
+            before_ms,
+        )
+        .execute(&mut *tx)
+        .await?;
+        // The archived days are rebuilt from the daily summaries on the next read
+        sqlx::query!(
+            "
+        DELETE FROM steps_daily_state
+        "
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        // The file is written before the commit, so that no raw events get lost
+        let raw_file = if keep_raw {
+            let path = archive::raw_file_path(before)?;
+            tokio::fs::write(&path, archive::compress_raw_events(&archived)?).await?;
+            Some(path)
+        } else {
+            None
+        };
+        let archived_at_ms = Utc::now().timestamp_millis();
+        let events = archived.len() as i64;
+        let raw_file_str = raw_file.as_ref().map(|path| path.to_string_lossy());
+        sqlx::query!(
+            "
+        INSERT INTO archives ( archived_at_ms, before_ms, first_event_id, last_event_id, events,
+            raw_file )
+        VALUES ( ?, ?, ?, ?, ?, ? )
+        ",
+            archived_at_ms,
+            before_ms,
+            first_event_id,
+            last_event_id,
+            events,
+            raw_file_str,
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        // Deleted rows are only given back to the file system by a vacuum
+        sqlx::query!("VACUUM").execute(&self.pool).await?;
+        info!("Archived {removed} events of {} days", days.len());
+        Ok(PedometerArchiveSummary {
+            events: removed,
+            days: days.len(),
+            raw_file,
+        })
+    }
+
     async fn get_last_row(&self) -> anyhow::Result<Option<PedometerPersistenceEvent>> {
         Ok(sqlx::query_as!(
             PedometerPersistenceEvent,
             "
-        SELECT event_id, timestamp_ms, boot_id, steps
+        SELECT event_id, timestamp_ms, boot_id, steps, activity
         FROM events
         ORDER BY rowid desc
         LIMIT 1
@@ -152,10 +1781,594 @@ impl PedometerDatabase {
     }
 }
 
+/// Whether the row already exists, i.e. the record was already stored.
+fn is_unique_violation(e: &anyhow::Error) -> bool {
+    matches!(
+        e.downcast_ref::<sqlx::Error>(),
+        Some(sqlx::Error::Database(e)) if e.is_unique_violation()
+    )
+}
+
+async fn add_event(
+    conn: &mut SqliteConnection,
+    event: PedometerPersistenceEvent,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        "
+    INSERT INTO events ( event_id, timestamp_ms, boot_id, steps, activity )
+    VALUES ( ?1, ?2, ?3, ?4, coalesce(?5, (
+        SELECT activity
+        FROM activities
+        WHERE boot_id = ?3 AND event_id < ?1
+        ORDER BY event_id DESC
+        LIMIT 1
+    )) )
+    ",
+        event.event_id,
+        event.timestamp_ms,
+        event.boot_id,
+        event.steps,
+        event.activity,
+    )
+    .execute(&mut *conn)
+    .await?;
+    update_boot(conn, event.boot_id, event.timestamp_ms, None, None).await
+}
+
+async fn add_daily_summary(
+    conn: &mut SqliteConnection,
+    summary: PedometerPersistenceEvent,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        "
+    INSERT INTO daily_summaries ( event_id, timestamp_ms, boot_id, steps  )
+    VALUES ( ?, ?, ?, ? )
+    ",
+        summary.event_id,
+        summary.timestamp_ms,
+        summary.boot_id,
+        summary.steps,
+    )
+    .execute(&mut *conn)
+    .await?;
+    update_boot(conn, summary.boot_id, summary.timestamp_ms, None, None).await
+}
+
+async fn add_device_error(
+    conn: &mut SqliteConnection,
+    error: PedometerPersistenceError,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        "
+    INSERT INTO device_errors ( event_id, timestamp_ms, boot_id, kind, code  )
+    VALUES ( ?, ?, ?, ?, ? )
+    ",
+        error.event_id,
+        error.timestamp_ms,
+        error.boot_id,
+        error.kind,
+        error.code,
+    )
+    .execute(&mut *conn)
+    .await?;
+    update_boot(conn, error.boot_id, error.timestamp_ms, None, None).await
+}
+
+async fn add_boot(
+    conn: &mut SqliteConnection,
+    boot: PedometerPersistenceBoot,
+) -> anyhow::Result<()> {
+    update_boot(
+        conn,
+        boot.boot_id,
+        boot.first_timestamp_ms,
+        boot.reset_reason,
+        boot.event_id,
+    )
+    .await
+}
+
+async fn add_host_epoch(
+    conn: &mut SqliteConnection,
+    host_epoch: PedometerPersistenceHostEpoch,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        "
+    INSERT INTO host_epochs ( event_id, device_timestamp_ms, boot_id, host_epoch_ms  )
+    VALUES ( ?, ?, ?, ? )
+    ",
+        host_epoch.event_id,
+        host_epoch.device_timestamp_ms,
+        host_epoch.boot_id,
+        host_epoch.host_epoch_ms,
+    )
+    .execute(&mut *conn)
+    .await?;
+    update_boot(
+        conn,
+        host_epoch.boot_id,
+        host_epoch.host_epoch_ms,
+        None,
+        None,
+    )
+    .await
+}
+
+async fn add_marker(
+    conn: &mut SqliteConnection,
+    marker: PedometerPersistenceMarker,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        "
+    INSERT INTO markers ( event_id, timestamp_ms, boot_id, kind  )
+    VALUES ( ?, ?, ?, ? )
+    ",
+        marker.event_id,
+        marker.timestamp_ms,
+        marker.boot_id,
+        marker.kind,
+    )
+    .execute(&mut *conn)
+    .await?;
+    if let Some(boot_id) = marker.boot_id {
+        update_boot(conn, boot_id, marker.timestamp_ms, None, None).await?;
+    }
+    Ok(())
+}
+
+async fn add_self_test(
+    conn: &mut SqliteConnection,
+    self_test: PedometerPersistenceSelfTest,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        "
+    INSERT INTO self_tests ( event_id, timestamp_ms, boot_id, result  )
+    VALUES ( ?, ?, ?, ? )
+    ",
+        self_test.event_id,
+        self_test.timestamp_ms,
+        self_test.boot_id,
+        self_test.result,
+    )
+    .execute(&mut *conn)
+    .await?;
+    update_boot(conn, self_test.boot_id, self_test.timestamp_ms, None, None).await
+}
+
+async fn add_maintenance(
+    conn: &mut SqliteConnection,
+    maintenance: PedometerPersistenceMaintenance,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        "
+    INSERT INTO maintenances ( event_id, timestamp_ms, boot_id, events, removed_entries,
+        out_of_order_events, fill_percent )
+    VALUES ( ?, ?, ?, ?, ?, ?, ? )
+    ",
+        maintenance.event_id,
+        maintenance.timestamp_ms,
+        maintenance.boot_id,
+        maintenance.events,
+        maintenance.removed_entries,
+        maintenance.out_of_order_events,
+        maintenance.fill_percent,
+    )
+    .execute(&mut *conn)
+    .await?;
+    update_boot(
+        conn,
+        maintenance.boot_id,
+        maintenance.timestamp_ms,
+        None,
+        None,
+    )
+    .await
+}
+
+async fn add_sensor_reading(
+    conn: &mut SqliteConnection,
+    reading: PedometerPersistenceSensorReading,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        "
+    INSERT INTO sensor_readings ( event_id, timestamp_ms, boot_id, sensor, value )
+    VALUES ( ?, ?, ?, ?, ? )
+    ",
+        reading.event_id,
+        reading.timestamp_ms,
+        reading.boot_id,
+        reading.sensor,
+        reading.value,
+    )
+    .execute(&mut *conn)
+    .await?;
+    update_boot(conn, reading.boot_id, reading.timestamp_ms, None, None).await
+}
+
+async fn add_activity(
+    conn: &mut SqliteConnection,
+    activity: PedometerPersistenceActivity,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        "
+    INSERT INTO activities ( event_id, timestamp_ms, boot_id, activity )
+    VALUES ( ?, ?, ?, ? )
+    ",
+        activity.event_id,
+        activity.timestamp_ms,
+        activity.boot_id,
+        activity.activity,
+    )
+    .execute(&mut *conn)
+    .await?;
+    update_boot(conn, activity.boot_id, activity.timestamp_ms, None, None).await
+}
+
+async fn add_temperature(
+    conn: &mut SqliteConnection,
+    temperature: PedometerPersistenceTemperature,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        "
+    INSERT INTO temperatures ( event_id, timestamp_ms, boot_id, temperature )
+    VALUES ( ?, ?, ?, ? )
+    ",
+        temperature.event_id,
+        temperature.timestamp_ms,
+        temperature.boot_id,
+        temperature.temperature,
+    )
+    .execute(&mut *conn)
+    .await?;
+    update_boot(
+        conn,
+        temperature.boot_id,
+        temperature.timestamp_ms,
+        None,
+        None,
+    )
+    .await
+}
+
+/// Move step events and daily summaries whose timestamp cannot be converted to a date into
+/// the quarantine, so that a single corrupt row does not break the plots.
+async fn quarantine_invalid_rows(conn: &mut SqliteConnection) -> anyhow::Result<()> {
+    let min_ms = DateTime::<Utc>::MIN_UTC.timestamp_millis();
+    let max_ms = DateTime::<Utc>::MAX_UTC.timestamp_millis();
+    let now_ms = Utc::now().timestamp_millis();
+    sqlx::query!(
+        "
+    INSERT INTO quarantined_rows ( source, event_id, timestamp_ms, boot_id, steps,
+        quarantined_at_ms )
+    SELECT 'events', event_id, timestamp_ms, boot_id, steps, ?
+    FROM events
+    WHERE typeof(timestamp_ms) != 'integer' OR timestamp_ms NOT BETWEEN ? AND ?
+    ",
+        now_ms,
+        min_ms,
+        max_ms,
+    )
+    .execute(&mut *conn)
+    .await?;
+    let events = sqlx::query!(
+        "
+    DELETE FROM events
+    WHERE typeof(timestamp_ms) != 'integer' OR timestamp_ms NOT BETWEEN ? AND ?
+    ",
+        min_ms,
+        max_ms,
+    )
+    .execute(&mut *conn)
+    .await?
+    .rows_affected();
+    sqlx::query!(
+        "
+    INSERT INTO quarantined_rows ( source, event_id, timestamp_ms, boot_id, steps,
+        quarantined_at_ms )
+    SELECT 'daily_summaries', event_id, timestamp_ms, boot_id, steps, ?
+    FROM daily_summaries
+    WHERE typeof(timestamp_ms) != 'integer' OR timestamp_ms NOT BETWEEN ? AND ?
+    ",
+        now_ms,
+        min_ms,
+        max_ms,
+    )
+    .execute(&mut *conn)
+    .await?;
+    let daily_summaries = sqlx::query!(
+        "
+    DELETE FROM daily_summaries
+    WHERE typeof(timestamp_ms) != 'integer' OR timestamp_ms NOT BETWEEN ? AND ?
+    ",
+        min_ms,
+        max_ms,
+    )
+    .execute(&mut *conn)
+    .await?
+    .rows_affected();
+    if events + daily_summaries > 0 {
+        warn!(
+            "Quarantined {events} events and {daily_summaries} daily summaries with invalid timestamps"
+        );
+    }
+    Ok(())
+}
+
+/// Flag the step events of the boot whose steps since the previous event exceed
+/// [`MAX_PLAUSIBLE_STEPS_PER_MINUTE`]. Short intervals are treated as one minute, so that a few
+/// steps in quick succession are not flagged.
+async fn flag_outliers(conn: &mut SqliteConnection, boot_id: i64) -> anyhow::Result<()> {
+    let flagged = sqlx::query!(
+        "
+    INSERT OR IGNORE INTO step_outliers ( event_id, boot_id, timestamp_ms, steps )
+    SELECT event_id, boot_id, timestamp_ms, steps
+    FROM (
+        SELECT event_id, boot_id, timestamp_ms,
+            (steps - lag(steps) OVER w) & 65535 AS steps,
+            timestamp_ms - lag(timestamp_ms) OVER w AS duration_ms
+        FROM events
+        WHERE boot_id = ?
+        WINDOW w AS (ORDER BY event_id)
+    )
+    WHERE steps * 60000 > ? * max(duration_ms, 60000)
+    ",
+        boot_id,
+        MAX_PLAUSIBLE_STEPS_PER_MINUTE,
+    )
+    .execute(&mut *conn)
+    .await?
+    .rows_affected();
+    if flagged > 0 {
+        warn!("Flagged {flagged} step events of boot {boot_id} as outliers");
+    }
+    Ok(())
+}
+
+/// Local days of the step events of the boot from the first new event up to the first event
+/// after the new ones, whose steps are relative to the last new event.
+async fn get_affected_days(
+    conn: &mut SqliteConnection,
+    boot_id: i64,
+    min_event_id: i64,
+    max_event_id: i64,
+) -> anyhow::Result<BTreeSet<NaiveDate>> {
+    let day_start_hour = DAY_START_HOUR.load(Ordering::Relaxed);
+    let mut days = BTreeSet::new();
+    for row in sqlx::query!(
+        "
+    SELECT timestamp_ms
+    FROM events
+    WHERE boot_id = ?1 AND event_id >= ?2 AND event_id <= coalesce((
+        SELECT min(event_id)
+        FROM events
+        WHERE boot_id = ?1 AND event_id > ?3
+    ), ?3)
+    ",
+        boot_id,
+        min_event_id,
+        max_event_id,
+    )
+    .fetch_all(&mut *conn)
+    .await?
+    {
+        days.insert(local_day(
+            &timestamp_ms_to_local(row.timestamp_ms)?,
+            day_start_hour,
+        ));
+    }
+    Ok(days)
+}
+
+async fn get_steps_daily_hour(conn: &mut SqliteConnection) -> anyhow::Result<Option<u8>> {
+    Ok(sqlx::query!(
+        "
+    SELECT day_start_hour
+    FROM steps_daily_state
+    "
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    .map(|state| u8::try_from(state.day_start_hour))
+    .transpose()?)
+}
+
+/// Recompute the steps of the given days in `steps_daily`. The whole table is rebuilt instead
+/// if the start of the days changed since it was computed.
+async fn update_steps_daily(
+    conn: &mut SqliteConnection,
+    days: &BTreeSet<NaiveDate>,
+) -> anyhow::Result<()> {
+    let day_start_hour = DAY_START_HOUR.load(Ordering::Relaxed);
+    if get_steps_daily_hour(&mut *conn).await? != Some(day_start_hour) {
+        return rebuild_steps_daily(conn).await;
+    }
+    for day in days {
+        let start_ms = local_day_start(&Local, *day, day_start_hour)?.timestamp_millis();
+        let end_ms =
+            local_day_start(&Local, *day + Days::new(1), day_start_hour)?.timestamp_millis();
+        // The steps of the first event of the day are relative to the last event before it
+        let previous = sqlx::query_as!(
+            PedometerPersistenceEvent,
+            "
+        SELECT event_id, timestamp_ms, boot_id, steps, activity
+        FROM events
+        WHERE timestamp_ms < ?
+        ORDER BY timestamp_ms DESC, event_id DESC
+        LIMIT 1
+        ",
+            start_ms,
+        )
+        .fetch_optional(&mut *conn)
+        .await?;
+        let events = sqlx::query_as!(
+            PedometerPersistenceEvent,
+            "
+        SELECT event_id, timestamp_ms, boot_id, steps, activity
+        FROM events
+        WHERE timestamp_ms >= ? AND timestamp_ms < ?
+        ORDER BY timestamp_ms, event_id
+        ",
+            start_ms,
+            end_ms,
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+        let steps = sum_steps_per_day(&mut *conn, previous.into_iter().chain(events).collect())
+            .await?
+            .remove(day)
+            .unwrap_or_default();
+        let date = day.to_string();
+        sqlx::query!(
+            "
+        INSERT INTO steps_daily ( date, steps )
+        VALUES ( ?, ? )
+        ON CONFLICT ( date ) DO UPDATE SET
+            steps = excluded.steps
+        ",
+            date,
+            steps,
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(())
+}
+
+/// Compute `steps_daily` from all step events for the current [`DAY_START_HOUR`].
+async fn rebuild_steps_daily(conn: &mut SqliteConnection) -> anyhow::Result<()> {
+    let day_start_hour = DAY_START_HOUR.load(Ordering::Relaxed);
+    info!("Rebuild daily steps for days starting at {day_start_hour}:00");
+    let events = sqlx::query_as!(
+        PedometerPersistenceEvent,
+        "
+    SELECT event_id, timestamp_ms, boot_id, steps, activity
+    FROM events
+    ORDER BY timestamp_ms, event_id
+    "
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    let days = sum_steps_per_day(&mut *conn, events).await?;
+    sqlx::query!(
+        "
+    DELETE FROM steps_daily
+    "
+    )
+    .execute(&mut *conn)
+    .await?;
+    for (day, steps) in days {
+        let date = day.to_string();
+        sqlx::query!(
+            "
+        INSERT INTO steps_daily ( date, steps )
+        VALUES ( ?, ? )
+        ",
+            date,
+            steps,
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+    sqlx::query!(
+        "
+    INSERT INTO steps_daily_state ( id, day_start_hour )
+    VALUES ( 0, ? )
+    ON CONFLICT ( id ) DO UPDATE SET
+        day_start_hour = excluded.day_start_hour
+    ",
+        day_start_hour,
+    )
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+/// Relative steps of the sorted events per local day without the outliers that were not
+/// accepted. The first event only serves as reference for the second one.
+async fn sum_steps_per_day(
+    conn: &mut SqliteConnection,
+    events: Vec<PedometerPersistenceEvent>,
+) -> anyhow::Result<BTreeMap<NaiveDate, i64>> {
+    let day_start_hour = DAY_START_HOUR.load(Ordering::Relaxed);
+    let excluded: HashSet<_> = sqlx::query!(
+        "
+    SELECT event_id, boot_id
+    FROM step_outliers
+    WHERE accepted = 0
+    "
+    )
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .map(|outlier| (outlier.boot_id, outlier.event_id))
+    .collect();
+    let mut days = BTreeMap::new();
+    for event in transform_events_to_relative_steps(events)
+        .into_iter()
+        .filter(|event| !excluded.contains(&(event.boot_id, event.event_id)))
+    {
+        *days
+            .entry(local_day(&event.get_date_time_local()?, day_start_hour))
+            .or_default() += event.steps;
+    }
+    Ok(days)
+}
+
+async fn set_sync_state(
+    conn: &mut SqliteConnection,
+    sync_state: PedometerPersistenceSyncState,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        "
+    INSERT INTO sync_state ( boot_id, last_event_id )
+    VALUES ( ?, ? )
+    ON CONFLICT ( boot_id ) DO UPDATE SET
+        last_event_id = max(last_event_id, excluded.last_event_id)
+    ",
+        sync_state.boot_id,
+        sync_state.last_event_id,
+    )
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+/// Extend the time range of the boot by the given timestamp. The reset reason and the index of
+/// the boot event are only updated if they are known.
+async fn update_boot(
+    conn: &mut SqliteConnection,
+    boot_id: i64,
+    timestamp_ms: i64,
+    reset_reason: Option<i64>,
+    event_id: Option<i64>,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        "
+    INSERT INTO boots ( boot_id, first_timestamp_ms, last_timestamp_ms, reset_reason, event_id )
+    VALUES ( ?, ?, ?, ?, ? )
+    ON CONFLICT ( boot_id ) DO UPDATE SET
+        first_timestamp_ms = min(first_timestamp_ms, excluded.first_timestamp_ms),
+        last_timestamp_ms = max(last_timestamp_ms, excluded.last_timestamp_ms),
+        reset_reason = coalesce(excluded.reset_reason, reset_reason),
+        event_id = coalesce(excluded.event_id, event_id)
+    ",
+        boot_id,
+        timestamp_ms,
+        timestamp_ms,
+        reset_reason,
+        event_id,
+    )
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
 #[allow(unused)]
 pub(crate) enum PedometerDatabaseCommand {
-    AddEvent {
-        event: PedometerPersistenceEvent,
+    /// Add synced records of any type at once and update the sync state afterwards
+    AddRecords {
+        records: Vec<PedometerPersistenceRecord>,
+        sync_state: Option<PedometerPersistenceSyncState>,
         responder: oneshot::Sender<anyhow::Result<()>>,
     },
     GetEventsInTimeRange {
@@ -163,11 +2376,248 @@ pub(crate) enum PedometerDatabaseCommand {
         end: DateTime<Utc>,
         responder: oneshot::Sender<anyhow::Result<Vec<PedometerPersistenceEvent>>>,
     },
+    GetDailySummariesInTimeRange {
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        responder: oneshot::Sender<anyhow::Result<Vec<PedometerPersistenceEvent>>>,
+    },
+    /// Steps per local day, see [`PedometerDailyTotal`]
+    GetDailyTotals {
+        first_day: NaiveDate,
+        last_day: NaiveDate,
+        responder: oneshot::Sender<anyhow::Result<Vec<PedometerDailyTotal>>>,
+    },
+    GetOutliers {
+        responder: oneshot::Sender<anyhow::Result<Vec<PedometerPersistenceOutlier>>>,
+    },
+    SetOutlierAccepted {
+        event_id: i64,
+        boot_id: i64,
+        accepted: bool,
+        responder: oneshot::Sender<anyhow::Result<()>>,
+    },
+    GetLastDisconnect {
+        responder: oneshot::Sender<anyhow::Result<Option<PedometerPersistenceError>>>,
+    },
+    GetLastMaintenance {
+        responder: oneshot::Sender<anyhow::Result<Option<PedometerPersistenceMaintenance>>>,
+    },
+    GetQuarantinedRowCount {
+        responder: oneshot::Sender<anyhow::Result<i64>>,
+    },
+    GetBoots {
+        responder: oneshot::Sender<anyhow::Result<Vec<PedometerPersistenceBoot>>>,
+    },
+    GetAuditReport {
+        responder: oneshot::Sender<anyhow::Result<PedometerAuditReport>>,
+    },
+    GetTimeSyncQuality {
+        responder: oneshot::Sender<anyhow::Result<Vec<PedometerTimeSyncQuality>>>,
+    },
+    /// Get the sync state of the latest synced event
+    GetSyncState {
+        responder: oneshot::Sender<anyhow::Result<Option<PedometerPersistenceSyncState>>>,
+    },
+    /// Get all sessions, see [`PedometerSession`]
+    GetSessions {
+        responder: oneshot::Sender<anyhow::Result<Vec<PedometerSession>>>,
+    },
+    ImportTrack {
+        path: PathBuf,
+        responder: oneshot::Sender<anyhow::Result<usize>>,
+    },
+    /// Roll the step events before the given time into daily totals
+    ArchiveEvents {
+        before: DateTime<Utc>,
+        keep_raw: bool,
+        responder: oneshot::Sender<anyhow::Result<PedometerArchiveSummary>>,
+    },
     GetLastEvent {
         responder: oneshot::Sender<anyhow::Result<Option<PedometerPersistenceEvent>>>,
     },
+    /// Activity changes in the time range and the last one before, which lasts until the first
+    GetActivitiesInTimeRange {
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        responder: oneshot::Sender<anyhow::Result<Vec<PedometerPersistenceActivity>>>,
+    },
+    /// Get the records of all types of the given local days for the debug view
+    GetDebugEvents {
+        first_day: NaiveDate,
+        last_day: NaiveDate,
+        responder: oneshot::Sender<anyhow::Result<Vec<PedometerDebugEvent>>>,
+    },
+    /// Write the steps of the given local days to an anonymized file, see [`research`]
+    ExportResearchData {
+        first_day: NaiveDate,
+        last_day: NaiveDate,
+        responder: oneshot::Sender<anyhow::Result<PedometerResearchExport>>,
+    },
+    /// Version of the database schema and the applied migrations for the debug view
+    GetSchemaInfo {
+        responder: oneshot::Sender<anyhow::Result<PedometerSchemaInfo>>,
+    },
+    /// Copy the database to a new file in the backup directory and return its path
+    BackupDatabase {
+        responder: oneshot::Sender<anyhow::Result<PathBuf>>,
+    },
+    /// Open the database again and migrate it after it was opened read-only
+    RetryMigration {
+        responder: oneshot::Sender<anyhow::Result<()>>,
+    },
     Exit,
 }
 
 pub(crate) type PedometerDatabaseGetEventsInTimeRangeReceiver =
     anyhow::Result<Vec<PedometerPersistenceEvent>>;
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDateTime, TimeDelta};
+    use chrono_tz::{America::Sao_Paulo, Europe::Berlin};
+
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        s.parse().unwrap()
+    }
+
+    fn berlin(s: &str) -> DateTime<chrono_tz::Tz> {
+        Berlin
+            .from_local_datetime(&s.parse::<NaiveDateTime>().unwrap())
+            .earliest()
+            .unwrap()
+    }
+
+    fn day_length<Tz: TimeZone>(tz: &Tz, day: &str, hour: u8) -> TimeDelta {
+        let day = date(day);
+        local_day_start(tz, day + Days::new(1), hour).unwrap()
+            - local_day_start(tz, day, hour).unwrap()
+    }
+
+    #[test]
+    fn day_start_at_midnight() {
+        assert_eq!(
+            local_day_start(&Berlin, date("2024-06-01"), 0).unwrap(),
+            utc("2024-05-31T22:00:00Z")
+        );
+        assert_eq!(day_length(&Berlin, "2024-06-01", 0), TimeDelta::hours(24));
+    }
+
+    #[test]
+    fn spring_forward_day_is_shorter() {
+        assert_eq!(day_length(&Berlin, "2024-03-31", 0), TimeDelta::hours(23));
+        // The clock changes at 02:00, so with a later start the previous day is shorter
+        assert_eq!(day_length(&Berlin, "2024-03-30", 3), TimeDelta::hours(23));
+        assert_eq!(day_length(&Berlin, "2024-03-31", 3), TimeDelta::hours(24));
+    }
+
+    #[test]
+    fn fall_back_day_is_longer() {
+        assert_eq!(day_length(&Berlin, "2024-10-27", 0), TimeDelta::hours(25));
+        assert_eq!(day_length(&Berlin, "2024-10-26", 3), TimeDelta::hours(25));
+        assert_eq!(day_length(&Berlin, "2024-10-27", 3), TimeDelta::hours(24));
+    }
+
+    #[test]
+    fn skipped_start_hour_starts_after_the_gap() {
+        // 02:00 does not exist, the clock jumps to 03:00 CEST
+        assert_eq!(
+            local_day_start(&Berlin, date("2024-03-31"), 2).unwrap(),
+            utc("2024-03-31T01:00:00Z")
+        );
+        // Midnight did not exist in Brazil when DST started
+        assert_eq!(
+            local_day_start(&Sao_Paulo, date("2018-11-04"), 0).unwrap(),
+            utc("2018-11-04T03:00:00Z")
+        );
+    }
+
+    #[test]
+    fn repeated_start_hour_starts_at_first_occurrence() {
+        // 02:00 exists in CEST and in CET
+        assert_eq!(
+            local_day_start(&Berlin, date("2024-10-27"), 2).unwrap(),
+            utc("2024-10-27T00:00:00Z")
+        );
+    }
+
+    #[test]
+    fn local_day_uses_the_wall_clock() {
+        // 03:30 CEST is only 2.5 hours after midnight CET
+        assert_eq!(
+            local_day(&berlin("2024-03-31T03:30:00"), 3),
+            date("2024-03-31")
+        );
+        assert_eq!(
+            local_day(&berlin("2024-03-31T01:59:00"), 3),
+            date("2024-03-30")
+        );
+        // The second 02:30 is 3.5 hours after midnight, but still before the day starts
+        let second = Berlin
+            .from_local_datetime(&"2024-10-27T02:30:00".parse().unwrap())
+            .latest()
+            .unwrap();
+        assert_eq!(local_day(&second, 3), date("2024-10-26"));
+        assert_eq!(
+            local_day(&berlin("2024-10-27T03:00:00"), 3),
+            date("2024-10-27")
+        );
+    }
+
+    #[test]
+    fn local_day_matches_day_start() {
+        for day in ["2024-03-31", "2024-10-27", "2024-02-29"] {
+            for hour in 0..=6 {
+                let start = local_day_start(&Berlin, date(day), hour)
+                    .unwrap()
+                    .with_timezone(&Berlin);
+                assert_eq!(local_day(&start, hour), date(day), "{day} {hour}");
+                assert_eq!(
+                    local_day(&(start - TimeDelta::minutes(1)), hour),
+                    date(day) - Days::new(1),
+                    "{day} {hour}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn leap_day() {
+        assert_eq!(day_length(&Berlin, "2024-02-29", 0), TimeDelta::hours(24));
+        assert_eq!(
+            local_day_start(&Berlin, date("2024-02-28") + Days::new(1), 0).unwrap(),
+            utc("2024-02-28T23:00:00Z")
+        );
+        assert_eq!(
+            local_day(&berlin("2024-02-29T23:59:00"), 0),
+            date("2024-02-29")
+        );
+        assert_eq!(
+            local_day(&berlin("2024-03-01T00:30:00"), 1),
+            date("2024-02-29")
+        );
+        assert_eq!(
+            local_day(&berlin("2023-03-01T00:30:00"), 1),
+            date("2023-02-28")
+        );
+    }
+
+    #[test]
+    fn newer_schema_is_refused() {
+        let app_version = app_schema_version();
+        assert!(check_schema_version(None, app_version).is_ok());
+        assert!(check_schema_version(Some(app_version), app_version).is_ok());
+        assert!(check_schema_version(Some(app_version - 1), app_version).is_ok());
+        let error = check_schema_version(Some(app_version + 1), app_version).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref(),
+            Some(PedometerGuiError::DatabaseTooNew { database_version, .. })
+                if *database_version == app_version + 1
+        ));
+    }
+}
@@ -0,0 +1,120 @@
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use app_dirs2::{app_dir, AppDataType};
+use log::info;
+use pedomet_rs_common::{PedometerConfig, PedometerConfigValue};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    settings::{BatteryPolicy, IdleAlertPolicy, PedometerSettings},
+    APP_INFO,
+};
+
+const PROFILES_DIR: &str = "profiles";
+const PROFILE_EXTENSION: &str = "json";
+const PROFILE_VERSION: u64 = 1;
+
+/// Complete configuration of a device that can be saved as JSON and applied to another device,
+/// e.g. when the hardware is replaced.
+///
+/// The UTC offset is not part of a profile because it belongs to the host.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct DeviceProfile {
+    pub version: u64,
+    pub daily_target: u32,
+    pub idle_alert: IdleAlertPolicy,
+    pub battery: BatteryPolicy,
+    pub step_coalescing_window_secs: u16,
+    pub storage_warning_percent: u8,
+    pub storage_critical_percent: u8,
+}
+
+impl DeviceProfile {
+    /// Create a profile from the settings and the config values that have no setting.
+    pub(crate) fn new(settings: &PedometerSettings, config: &PedometerConfig) -> Self {
+        Self {
+            version: PROFILE_VERSION,
+            daily_target: settings.daily_target,
+            idle_alert: settings.idle_alert,
+            battery: settings.battery,
+            step_coalescing_window_secs: config.step_coalescing_window_secs,
+            storage_warning_percent: config.storage_warning_percent,
+            storage_critical_percent: config.storage_critical_percent,
+        }
+    }
+
+    /// Take over the profile into the settings. The config values have to be written to the
+    /// device separately.
+    pub(crate) fn apply(&self, settings: &mut PedometerSettings) {
+        settings.daily_target = self.daily_target;
+        settings.idle_alert = self.idle_alert;
+        settings.battery = self.battery;
+    }
+
+    pub(crate) fn config_values(&self) -> Vec<PedometerConfigValue> {
+        [
+            vec![
+                PedometerConfigValue::StepCoalescingWindowSecs(self.step_coalescing_window_secs),
+                PedometerConfigValue::StorageWarningPercent(self.storage_warning_percent),
+                PedometerConfigValue::StorageCriticalPercent(self.storage_critical_percent),
+            ],
+            self.idle_alert.config_values(),
+            self.battery.config_values(),
+        ]
+        .concat()
+    }
+
+    pub(crate) fn save(&self, name: &str) -> anyhow::Result<()> {
+        let path = profile_path(name)?;
+        info!("Save profile to {path:?}");
+        std::fs::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub(crate) fn load(name: &str) -> anyhow::Result<Self> {
+        let path = profile_path(name)?;
+        info!("Load profile from {path:?}");
+        let profile: Self = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        if profile.version != PROFILE_VERSION {
+            return Err(anyhow!(
+                "Unsupported profile version {} (current: {PROFILE_VERSION})",
+                profile.version
+            ));
+        }
+        Ok(profile)
+    }
+
+    /// Names of the saved profiles in alphabetical order.
+    pub(crate) fn list() -> anyhow::Result<Vec<String>> {
+        let mut names: Vec<_> = std::fs::read_dir(profiles_dir()?)?
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                if path.extension()? != PROFILE_EXTENSION {
+                    return None;
+                }
+                Some(path.file_stem()?.to_str()?.to_string())
+            })
+            .collect();
+        names.sort();
+        Ok(names)
+    }
+}
+
+fn profiles_dir() -> anyhow::Result<PathBuf> {
+    Ok(app_dir(AppDataType::UserConfig, &APP_INFO, PROFILES_DIR)?)
+}
+
+fn profile_path(name: &str) -> anyhow::Result<PathBuf> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, ' ' | '-' | '_'))
+    {
+        return Err(anyhow!("Invalid profile name: {name:?}"));
+    }
+    let mut path = profiles_dir()?;
+    path.push(name);
+    path.set_extension(PROFILE_EXTENSION);
+    Ok(path)
+}
@@ -0,0 +1,139 @@
+//! Anonymized export of the steps, e.g. to share them with a study.
+//!
+//! Only the steps per hour are exported. The identifiers of the device, boots and events are
+//! dropped and the start of every hour is moved by a random offset, so that the dataset cannot
+//! be matched with the raw events.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    hash::{BuildHasher, RandomState},
+    path::PathBuf,
+};
+
+use app_dirs2::{app_dir, AppDataType};
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+
+use crate::{persistence::PedometerPersistenceEvent, APP_INFO};
+
+/// Maximum offset by which the start of an hour is moved in either direction
+pub(crate) const RESEARCH_JITTER_MAX: Duration = Duration::minutes(15);
+
+/// Result of an anonymized export.
+#[derive(Debug, Clone)]
+pub(crate) struct PedometerResearchExport {
+    /// Hours with steps in the export
+    pub hours: usize,
+    pub file: PathBuf,
+}
+
+/// Sum of the relative steps per UTC hour by the start of the hour. Hours without steps are
+/// omitted.
+pub(crate) fn hourly_bins(events: &[PedometerPersistenceEvent]) -> BTreeMap<i64, i64> {
+    let hour_ms = Duration::hours(1).num_milliseconds();
+    let mut bins = BTreeMap::new();
+    for event in events {
+        *bins
+            .entry(event.timestamp_ms.div_euclid(hour_ms) * hour_ms)
+            .or_default() += event.steps;
+    }
+    bins.retain(|_, steps| *steps > 0);
+    bins
+}
+
+/// Random offsets in milliseconds of at most [`RESEARCH_JITTER_MAX`] in either direction.
+pub(crate) fn random_jitter() -> impl FnMut() -> i64 {
+    let state = RandomState::new();
+    let max_ms = RESEARCH_JITTER_MAX.num_milliseconds();
+    let mut counter = 0_u64;
+    move || {
+        counter += 1;
+        (state.hash_one(counter) % (2 * max_ms + 1) as u64) as i64 - max_ms
+    }
+}
+
+/// CSV with the moved start of every hour in UTC and its steps.
+pub(crate) fn to_csv(bins: &BTreeMap<i64, i64>, mut jitter_ms: impl FnMut() -> i64) -> String {
+    let mut csv = "hour_start_utc,steps\n".to_string();
+    for (hour_start_ms, steps) in bins {
+        let Some(hour_start) = DateTime::from_timestamp_millis(hour_start_ms + jitter_ms()) else {
+            continue;
+        };
+        let _ = writeln!(
+            csv,
+            "{},{steps}",
+            hour_start.to_rfc3339_opts(SecondsFormat::Secs, true)
+        );
+    }
+    csv
+}
+
+/// Path of a new export file. The export directory is next to the database.
+pub(crate) fn file_path() -> anyhow::Result<PathBuf> {
+    let mut path = app_dir(AppDataType::UserData, &APP_INFO, "export")?;
+    path.push(format!(
+        "steps-research-{}.csv",
+        Utc::now().format("%Y%m%d%H%M%S")
+    ));
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(timestamp: &str, steps: i64) -> PedometerPersistenceEvent {
+        PedometerPersistenceEvent {
+            event_id: 1,
+            timestamp_ms: timestamp
+                .parse::<DateTime<Utc>>()
+                .unwrap()
+                .timestamp_millis(),
+            boot_id: 1,
+            steps,
+            activity: None,
+        }
+    }
+
+    fn hour(timestamp: &str) -> i64 {
+        timestamp
+            .parse::<DateTime<Utc>>()
+            .unwrap()
+            .timestamp_millis()
+    }
+
+    #[test]
+    fn bins_per_hour() {
+        let bins = hourly_bins(&[
+            event("2024-11-20T08:00:00Z", 10),
+            event("2024-11-20T08:59:59Z", 20),
+            event("2024-11-20T09:00:00Z", 30),
+            event("2024-11-20T10:30:00Z", 0),
+        ]);
+        assert_eq!(
+            bins,
+            BTreeMap::from([
+                (hour("2024-11-20T08:00:00Z"), 30),
+                (hour("2024-11-20T09:00:00Z"), 30),
+            ])
+        );
+    }
+
+    #[test]
+    fn csv_without_identifiers() {
+        let bins = BTreeMap::from([(hour("2024-11-20T08:00:00Z"), 30)]);
+        assert_eq!(
+            to_csv(&bins, || 90_000),
+            "hour_start_utc,steps\n2024-11-20T08:01:30Z,30\n"
+        );
+    }
+
+    #[test]
+    fn jitter_within_bounds() {
+        let mut jitter = random_jitter();
+        let max_ms = RESEARCH_JITTER_MAX.num_milliseconds();
+        let offsets: Vec<_> = (0..1000).map(|_| jitter()).collect();
+        assert!(offsets.iter().all(|offset| offset.abs() <= max_ms));
+        assert!(offsets.iter().any(|offset| *offset != offsets[0]));
+    }
+}
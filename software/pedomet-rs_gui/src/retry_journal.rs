@@ -0,0 +1,115 @@
+//! Journal of the records that could not be stored in the database, e.g. because the disk was
+//! full or the database was locked.
+//!
+//! The device may already have deleted the events after the sync, so the records are kept in a
+//! file next to the database and stored again with the next records. Every line contains one
+//! record as JSON.
+
+use std::{
+    fs,
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+use log::{info, warn};
+
+use crate::persistence::PedometerPersistenceRecord;
+
+/// Maximum number of records in the journal. If more records fail, the oldest ones are dropped.
+pub(crate) const RETRY_JOURNAL_MAX_RECORDS: usize = 100_000;
+
+/// Path of the journal in the directory of the database.
+pub(crate) fn file_path(db_file: &Path) -> PathBuf {
+    db_file.with_file_name("retry_journal.jsonl")
+}
+
+/// Records in the journal in the order in which they failed. Lines that cannot be parsed are
+/// skipped.
+pub(crate) fn load(path: &Path) -> anyhow::Result<Vec<PedometerPersistenceRecord>> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| {
+            serde_json::from_str(line)
+                .inspect_err(|e| warn!("Skip invalid line of retry journal: {line} -> {e}"))
+                .ok()
+        })
+        .collect())
+}
+
+/// Replace the journal with the given records. The journal is removed if there are none.
+///
+/// The records are written to a temporary file first, so a crash does not leave a truncated
+/// journal behind.
+pub(crate) fn store(path: &Path, records: &[PedometerPersistenceRecord]) -> anyhow::Result<()> {
+    if records.is_empty() {
+        return match fs::remove_file(path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        };
+    }
+    let dropped = records.len().saturating_sub(RETRY_JOURNAL_MAX_RECORDS);
+    if dropped > 0 {
+        warn!("Retry journal is full, drop the {dropped} oldest records");
+    }
+    let tmp_path = path.with_extension("jsonl.tmp");
+    let mut file = fs::File::create(&tmp_path)?;
+    for record in &records[dropped..] {
+        serde_json::to_writer(&mut file, record)?;
+        file.write_all(b"\n")?;
+    }
+    file.sync_all()?;
+    fs::rename(&tmp_path, path)?;
+    info!(
+        "Kept {} records in retry journal {:?}",
+        records.len() - dropped,
+        path
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::persistence::{PedometerPersistenceEvent, PedometerPersistenceMarker};
+
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let path = std::env::temp_dir().join(format!(
+            "pedomet-rs-retry-journal-{}.jsonl",
+            std::process::id()
+        ));
+        let records = vec![
+            PedometerPersistenceRecord::Event(PedometerPersistenceEvent {
+                event_id: 3,
+                timestamp_ms: 1_700_000_000_000,
+                boot_id: 2,
+                steps: 42,
+                activity: Some(1),
+            }),
+            PedometerPersistenceRecord::Marker(PedometerPersistenceMarker {
+                event_id: None,
+                timestamp_ms: 1_700_000_001_000,
+                boot_id: None,
+                kind: 0,
+            }),
+        ];
+        assert!(load(&path).unwrap().is_empty());
+        store(&path, &records).unwrap();
+        let mut content = fs::read_to_string(&path).unwrap();
+        content.push_str("not a record\n");
+        fs::write(&path, content).unwrap();
+        assert_eq!(
+            format!("{:?}", load(&path).unwrap()),
+            format!("{records:?}")
+        );
+        store(&path, &[]).unwrap();
+        assert!(!path.exists());
+    }
+}
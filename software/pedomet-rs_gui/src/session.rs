@@ -0,0 +1,83 @@
+use anyhow::anyhow;
+use chrono::{DateTime, Local};
+use log::warn;
+use pedomet_rs_common::PedometerMarker;
+
+use crate::{
+    persistence::{PedometerPersistenceEvent, PedometerPersistenceMarker},
+    steps::transform_events_to_relative_steps,
+};
+
+/// Session between a start and an end marker of the user.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct PedometerSession {
+    pub start_ms: i64,
+    /// End of the session, unknown while it is running
+    pub end_ms: Option<i64>,
+    pub steps: i64,
+    /// Distance of the GPS track that was imported for the session
+    pub distance_m: Option<f64>,
+}
+
+impl PedometerSession {
+    /// `markers` have to be sorted by timestamp, `step_events` by boot and index.
+    ///
+    /// A start marker during a running session and an end marker without running session are
+    /// ignored.
+    pub(crate) fn from_markers(
+        markers: &[PedometerPersistenceMarker],
+        step_events: Vec<PedometerPersistenceEvent>,
+    ) -> Vec<Self> {
+        let mut sessions: Vec<Self> = Vec::new();
+        for marker in markers {
+            let running = sessions
+                .last()
+                .is_some_and(|session| session.end_ms.is_none());
+            match marker.get_marker() {
+                Ok(PedometerMarker::SessionStart) if !running => sessions.push(Self {
+                    start_ms: marker.timestamp_ms,
+                    end_ms: None,
+                    steps: 0,
+                    distance_m: None,
+                }),
+                Ok(PedometerMarker::SessionEnd) if running => {
+                    if let Some(session) = sessions.last_mut() {
+                        session.end_ms = Some(marker.timestamp_ms);
+                    }
+                }
+                Ok(marker) => warn!("Ignored unexpected marker: {marker:?}"),
+                Err(e) => warn!("Ignored marker: {e}"),
+            }
+        }
+
+        for event in transform_events_to_relative_steps(step_events) {
+            if let Some(session) = sessions.iter_mut().find(|session| {
+                event.timestamp_ms >= session.start_ms
+                    && session
+                        .end_ms
+                        .is_none_or(|end_ms| event.timestamp_ms <= end_ms)
+            }) {
+                session.steps += event.steps;
+            }
+        }
+        sessions
+    }
+
+    pub(crate) fn get_start_date_time_local(&self) -> anyhow::Result<DateTime<Local>> {
+        Ok(DateTime::from(
+            DateTime::from_timestamp_millis(self.start_ms)
+                .ok_or_else(|| anyhow!("Invalid epoch"))?,
+        ))
+    }
+
+    /// Duration of the session, which is still growing while it is running.
+    pub(crate) fn duration_ms(&self, now_ms: i64) -> i64 {
+        self.end_ms.unwrap_or(now_ms) - self.start_ms
+    }
+
+    /// Average steps per minute of the session.
+    pub(crate) fn steps_per_minute(&self, now_ms: i64) -> Option<f64> {
+        let duration_ms = self.duration_ms(now_ms);
+        (duration_ms > 0).then(|| self.steps as f64 * 60_000.0 / duration_ms as f64)
+    }
+}
@@ -0,0 +1,499 @@
+use std::{
+    hash::{BuildHasher, RandomState},
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::anyhow;
+use app_dirs2::{app_root, AppDataType};
+use chrono::{Months, NaiveDate};
+use log::{info, warn};
+use pedomet_rs_common::{PedometerConfig, PedometerConfigValue};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use strum::EnumIter;
+
+use crate::APP_INFO;
+
+const SETTINGS_FILE_NAME: &str = "settings.json";
+const SETTINGS_VERSION_KEY: &str = "version";
+
+/// Migrations from version `i + 1` to version `i + 2` of the settings file.
+///
+/// Version 1 is the first version of the settings file, so new migrations have to be appended
+/// here whenever a field is renamed or its meaning changes.
+const MIGRATIONS: &[fn(&mut Value)] = &[];
+const SETTINGS_VERSION: u64 = MIGRATIONS.len() as u64 + 1;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct PedometerSettings {
+    pub daily_target: u32,
+    /// Local hour at which a day starts. Steps before it are attributed to the previous day,
+    /// e.g. for night shifts.
+    pub day_start_hour: u8,
+    pub unit_system: UnitSystem,
+    pub language: Language,
+    /// Address of the last connected device
+    pub device_address: Option<String>,
+    /// Advertised name of the last connected device to tell it apart from others
+    pub device_name: Option<String>,
+    pub sync_policy: SyncPolicy,
+    pub scan_policy: ScanPolicy,
+    /// Receive the live data from the advertisements of the device without connecting
+    pub listen_for_live_data: bool,
+    pub idle_alert: IdleAlertPolicy,
+    pub battery: BatteryPolicy,
+    pub step_detection: StepDetectionPolicy,
+    pub api: ApiPolicy,
+    pub notifications: NotificationPolicy,
+    pub archive: ArchivePolicy,
+    /// Show confetti when the daily target is reached while the app is open
+    pub celebrate_goal: bool,
+    /// Identifies the app towards the device, which keeps the sync cursor of every host
+    pub host_id: u32,
+}
+
+impl Default for PedometerSettings {
+    fn default() -> Self {
+        Self {
+            daily_target: 10_000,
+            day_start_hour: 0,
+            unit_system: Default::default(),
+            language: Default::default(),
+            device_address: None,
+            device_name: None,
+            sync_policy: Default::default(),
+            scan_policy: Default::default(),
+            listen_for_live_data: false,
+            idle_alert: Default::default(),
+            battery: Default::default(),
+            step_detection: Default::default(),
+            api: Default::default(),
+            notifications: Default::default(),
+            archive: Default::default(),
+            celebrate_goal: true,
+            host_id: random_host_id(),
+        }
+    }
+}
+
+/// New settings and settings files from before the host ID get a random one.
+fn random_host_id() -> u32 {
+    RandomState::new().hash_one(SystemTime::now()) as u32
+}
+
+#[derive(
+    Debug, Copy, Clone, Default, PartialEq, EnumIter, strum::Display, Serialize, Deserialize,
+)]
+pub(crate) enum UnitSystem {
+    #[default]
+    #[strum(to_string = "Metrisch")]
+    Metric,
+    #[strum(to_string = "Imperial")]
+    Imperial,
+}
+
+#[derive(
+    Debug,
+    Copy,
+    Clone,
+    Default,
+    PartialEq,
+    Eq,
+    Hash,
+    EnumIter,
+    strum::Display,
+    Serialize,
+    Deserialize,
+)]
+pub(crate) enum Language {
+    #[default]
+    #[strum(to_string = "Deutsch")]
+    German,
+    #[strum(to_string = "English")]
+    English,
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct SyncPolicy {
+    /// Request new events right after a connection was established
+    pub sync_on_connect: bool,
+    /// Interval in which the app connects and syncs on its own, 0 disables it
+    pub auto_sync_interval_mins: u16,
+    /// Connect and sync when the advertising data of the device says that many events wait for
+    /// a sync. It is only received while listening for the live data.
+    pub sync_when_data_waiting: bool,
+    /// Delete the synced events on the device to free its storage
+    pub delete_after_sync: bool,
+    /// Only sync automatically while the phone is connected to a Wi-Fi (Android only)
+    pub wifi_only: bool,
+    /// Only sync automatically while the phone is charging (Android only)
+    pub charging_only: bool,
+}
+
+impl SyncPolicy {
+    pub(crate) fn auto_sync_interval(&self) -> Option<Duration> {
+        (self.auto_sync_interval_mins > 0)
+            .then(|| Duration::from_secs(u64::from(self.auto_sync_interval_mins) * 60))
+    }
+
+    /// The constraints for the automatic sync are met. They only apply on Android.
+    pub(crate) fn allows_auto_sync(&self) -> bool {
+        #[cfg(target_os = "android")]
+        {
+            let met =
+                |constraint: bool, check: fn() -> Result<bool, crate::android::AndroidError>| {
+                    !constraint
+                        || check().unwrap_or_else(|e| {
+                            warn!("Could not check the auto sync constraint: {e}");
+                            false
+                        })
+                };
+            met(self.wifi_only, crate::android::is_on_wifi)
+                && met(self.charging_only, crate::android::is_charging)
+        }
+        #[cfg(not(target_os = "android"))]
+        true
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct ScanPolicy {
+    /// Duration of the first scan for the device
+    pub timeout_ms: u64,
+    /// Interval in which the scan results are searched for the device
+    pub poll_interval_ms: u64,
+    /// Number of scans after the first one failed. The first retry is as long as the first scan,
+    /// every further retry takes twice as long as the previous one.
+    pub retries: u8,
+    /// Duration of the long scan that can be started after the device was not found, e.g.
+    /// because it advertises slowly to save power
+    pub long_scan_ms: u64,
+}
+
+impl Default for ScanPolicy {
+    fn default() -> Self {
+        Self {
+            timeout_ms: 5000,
+            poll_interval_ms: 200,
+            retries: 2,
+            long_scan_ms: 45_000,
+        }
+    }
+}
+
+impl ScanPolicy {
+    /// Policy of a single long scan without retries.
+    pub(crate) fn long_scan(&self) -> Self {
+        Self {
+            timeout_ms: self.long_scan_ms,
+            retries: 0,
+            ..*self
+        }
+    }
+
+    pub(crate) fn attempts(&self) -> u8 {
+        self.retries.saturating_add(1)
+    }
+
+    /// Duration of the scan with the given zero based attempt.
+    pub(crate) fn scan_duration(&self, attempt: u8) -> Duration {
+        Duration::from_millis(self.timeout_ms)
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1).into()))
+    }
+
+    pub(crate) fn poll_interval(&self) -> Duration {
+        Duration::from_millis(self.poll_interval_ms)
+    }
+}
+
+/// Idle alert of the device. It is taken over from the device on every connect.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct IdleAlertPolicy {
+    /// Duration without steps after which the device alerts the user. 0 disables the alert.
+    pub minutes: u16,
+    /// First local hour in which the alert is active
+    pub active_hours_start: u8,
+    /// Local hour at which the alert becomes inactive
+    pub active_hours_end: u8,
+}
+
+impl Default for IdleAlertPolicy {
+    fn default() -> Self {
+        Self {
+            minutes: 0,
+            active_hours_start: 8,
+            active_hours_end: 20,
+        }
+    }
+}
+
+impl IdleAlertPolicy {
+    pub(crate) fn from_config(config: &PedometerConfig) -> Self {
+        Self {
+            minutes: config.idle_alert_minutes,
+            active_hours_start: config.active_hours_start,
+            active_hours_end: config.active_hours_end,
+        }
+    }
+
+    pub(crate) fn config_values(&self) -> Vec<PedometerConfigValue> {
+        vec![
+            PedometerConfigValue::IdleAlertMinutes(self.minutes),
+            PedometerConfigValue::ActiveHoursStart(self.active_hours_start),
+            PedometerConfigValue::ActiveHoursEnd(self.active_hours_end),
+        ]
+    }
+}
+
+/// Battery monitoring of the device. It is taken over from the device on every connect.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct BatteryPolicy {
+    /// Interval in which the device measures the battery voltage
+    pub sample_interval_secs: u16,
+    /// Interval in which the device measures the battery voltage while it is low
+    pub low_sample_interval_secs: u16,
+    /// Battery voltage below which the LED of the device blinks
+    pub low_mv: u16,
+}
+
+impl Default for BatteryPolicy {
+    fn default() -> Self {
+        Self {
+            sample_interval_secs: 5 * 60,
+            low_sample_interval_secs: 30,
+            low_mv: 3550,
+        }
+    }
+}
+
+impl BatteryPolicy {
+    pub(crate) fn from_config(config: &PedometerConfig) -> Self {
+        Self {
+            sample_interval_secs: config.battery_sample_interval_secs,
+            low_sample_interval_secs: config.battery_low_sample_interval_secs,
+            low_mv: config.battery_low_mv,
+        }
+    }
+
+    pub(crate) fn config_values(&self) -> Vec<PedometerConfigValue> {
+        vec![
+            PedometerConfigValue::BatterySampleIntervalSecs(self.sample_interval_secs),
+            PedometerConfigValue::BatteryLowSampleIntervalSecs(self.low_sample_interval_secs),
+            PedometerConfigValue::BatteryLowMv(self.low_mv),
+        ]
+    }
+}
+
+/// Sensitivity of the step detection of the device. It is taken over from the device on every
+/// connect.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct StepDetectionPolicy {
+    /// Minimum acceleration of a step in 16 mg
+    pub threshold: u8,
+    /// Steps that have to be detected in a row before they are counted
+    pub debounce_steps: u8,
+    /// The accelerometer sleeps after this duration without steps, 0 keeps it awake
+    pub motion_sleep_minutes: u16,
+}
+
+impl Default for StepDetectionPolicy {
+    fn default() -> Self {
+        Self {
+            threshold: 16,
+            debounce_steps: 6,
+            motion_sleep_minutes: 15,
+        }
+    }
+}
+
+impl StepDetectionPolicy {
+    pub(crate) fn from_config(config: &PedometerConfig) -> Self {
+        Self {
+            threshold: config.step_threshold,
+            debounce_steps: config.step_debounce_steps,
+            motion_sleep_minutes: config.motion_sleep_minutes,
+        }
+    }
+
+    pub(crate) fn config_values(&self) -> Vec<PedometerConfigValue> {
+        vec![
+            PedometerConfigValue::StepThreshold(self.threshold),
+            PedometerConfigValue::StepDebounceSteps(self.debounce_steps),
+            PedometerConfigValue::MotionSleepMinutes(self.motion_sleep_minutes),
+        ]
+    }
+}
+
+/// Local HTTP/JSON API for other apps, see [`crate::api`].
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct ApiPolicy {
+    /// The API is only reachable from localhost
+    pub enabled: bool,
+    /// Serve metrics in the Prometheus format under `/metrics`
+    pub metrics: bool,
+    pub port: u16,
+}
+
+impl Default for ApiPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            metrics: false,
+            port: 8737,
+        }
+    }
+}
+
+impl ApiPolicy {
+    /// The server is needed for the data as well as for the metrics.
+    pub(crate) fn is_serving(&self) -> bool {
+        self.enabled || self.metrics
+    }
+}
+
+/// Events for which a notification of the operating system is shown, see
+/// [`crate::notifications`].
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct NotificationPolicy {
+    pub goal_reached: bool,
+    pub low_battery: bool,
+    pub sync_failed: bool,
+    /// Ongoing notification with the steps of today while the automatic sync is active
+    pub sync_progress: bool,
+}
+
+impl Default for NotificationPolicy {
+    fn default() -> Self {
+        Self {
+            goal_reached: true,
+            low_battery: true,
+            sync_failed: true,
+            sync_progress: true,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct ArchivePolicy {
+    /// Step events older than this are rolled into daily totals at startup, 0 disables it
+    pub months: u32,
+    /// Keep the raw step events in a compressed file next to the database
+    pub keep_raw: bool,
+}
+
+impl Default for ArchivePolicy {
+    fn default() -> Self {
+        Self {
+            months: 0,
+            keep_raw: true,
+        }
+    }
+}
+
+impl ArchivePolicy {
+    /// First day whose step events are kept.
+    pub(crate) fn first_kept_day(&self, today: NaiveDate) -> Option<NaiveDate> {
+        (self.months > 0).then(|| today - Months::new(self.months))
+    }
+}
+
+/// Settings that were stored inside the eframe app state before the settings file existed.
+#[derive(Debug, Deserialize)]
+#[serde(rename = "PedometerAppState")]
+pub(crate) struct LegacySettings {
+    daily_target: u32,
+}
+
+impl PedometerSettings {
+    /// Quick choices for the daily target
+    pub(crate) const DAILY_TARGET_PRESETS: [u32; 3] = [7_500, 10_000, 12_500];
+    /// Daily targets that can be entered
+    pub(crate) const DAILY_TARGET_RANGE: RangeInclusive<u32> = 1_000..=100_000;
+
+    /// Load the settings file. If it does not exist yet, the legacy settings are taken over.
+    pub(crate) fn load(legacy: Option<LegacySettings>) -> Self {
+        let path = match settings_path() {
+            Ok(path) => path,
+            Err(e) => {
+                warn!("Could not determine settings path: {e}");
+                return Default::default();
+            }
+        };
+        if !path.exists() {
+            info!("No settings file found, use legacy settings: {legacy:?}");
+            let mut settings = Self::default();
+            if let Some(legacy) = legacy {
+                settings.daily_target = legacy.daily_target;
+            }
+            return settings;
+        }
+        match Self::load_from_file(&path) {
+            Ok(settings) => settings,
+            Err(e) => {
+                warn!("Could not load settings, use defaults: {e}");
+                let mut invalid_path = path.clone();
+                invalid_path.set_extension("json.invalid");
+                if let Err(e) = std::fs::rename(&path, &invalid_path) {
+                    warn!("Could not move invalid settings file: {e}");
+                }
+                Default::default()
+            }
+        }
+    }
+
+    fn load_from_file(path: &Path) -> anyhow::Result<Self> {
+        let mut value: Value = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        let version = value
+            .get(SETTINGS_VERSION_KEY)
+            .and_then(Value::as_u64)
+            .ok_or_else(|| anyhow!("Settings file has no version"))?;
+        if version == 0 || version > SETTINGS_VERSION {
+            return Err(anyhow!(
+                "Unsupported settings file version {version} (current: {SETTINGS_VERSION})"
+            ));
+        }
+        for (from_version, migration) in MIGRATIONS.iter().enumerate().skip(version as usize - 1) {
+            info!(
+                "Migrate settings from version {} to {}",
+                from_version + 1,
+                from_version + 2
+            );
+            migration(&mut value);
+        }
+        Ok(serde_json::from_value(value)?)
+    }
+
+    pub(crate) fn save(&self) -> anyhow::Result<()> {
+        let mut value = serde_json::to_value(self)?;
+        value
+            .as_object_mut()
+            .ok_or_else(|| anyhow!("Settings are not serialized as object"))?
+            .insert(SETTINGS_VERSION_KEY.to_string(), SETTINGS_VERSION.into());
+
+        let path = settings_path()?;
+        info!("Save settings to {path:?}");
+        // Write to a temporary file first so that a crash cannot leave a truncated file behind
+        let mut tmp_path = path.clone();
+        tmp_path.set_extension("json.tmp");
+        std::fs::write(&tmp_path, serde_json::to_string_pretty(&value)?)?;
+        std::fs::rename(&tmp_path, &path)?;
+        Ok(())
+    }
+}
+
+fn settings_path() -> anyhow::Result<PathBuf> {
+    let mut path = app_root(AppDataType::UserConfig, &APP_INFO)?;
+    path.push(SETTINGS_FILE_NAME);
+    Ok(path)
+}
@@ -0,0 +1,934 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use anyhow::anyhow;
+use btleplug::api::ValueNotification;
+use chrono::{DateTime, Datelike, Days, Local, NaiveDate, NaiveTime, Timelike};
+use log::{info, warn};
+use pedomet_rs_common::{
+    PedometerEvent, PedometerEventChunkHeader, PedometerEventFilter, PedometerEventQuery,
+    PedometerEventRequest, PedometerEventType, PedometerMarker, PedometerSyncCursor,
+    MAX_SYNC_HOSTS,
+};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+use crate::ble::{
+    event_query, get_last_synced_event, get_max_deletable_event_id, get_sync_cursor,
+    next_event_request, report_sync_error, set_connection_state, PedometerConnectionState,
+    PedometerDeviceHandler, PedometerDeviceHandlerCommand, PedometerEventResponses,
+    SharedSyncPhase, CHARACTERISTIC_BOOT_ID, CHARACTERISTIC_MAX_EVENT_ID,
+    CHARACTERISTIC_UUID_ACK_EVENTS, CHARACTERISTIC_UUID_DELETE_BOOT,
+    CHARACTERISTIC_UUID_DELETE_EVENTS, CHARACTERISTIC_UUID_MARKER,
+    CHARACTERISTIC_UUID_REQUEST_EVENTS_QUERY, CHARACTERISTIC_UUID_REQUEST_EVENTS_SINCE,
+    CHARACTERISTIC_UUID_REQUEST_EVENTS_TRANSFER, CHARACTERISTIC_UUID_RESPONSE_EVENTS,
+    CHARACTERISTIC_UUID_SOC, SYNC_WRITE_BUFFER_SIZE, TRANSFER_TIMEOUT,
+};
+use crate::gui::{PedometerGuiEvent, GUI_EVENT_TX};
+use crate::supervisor::SharedReceiver;
+
+/// Size of the event responses of the firmware
+const EVENT_RESPONSE_SIZE: usize = 250;
+/// Days before today at whose start the simulated device booted
+const HISTORY_DAYS: u64 = 2;
+/// The simulated device counts the steps once per minute
+const SAMPLE_INTERVAL_MS: u64 = 60_000;
+const SIMULATED_SOC: u8 = 87;
+const SIMULATED_ADDRESS: &str = "00:00:00:00:00:00";
+/// Lower half of the FICR device address the name suffix of the simulated device is made from
+const SIMULATED_DEVICE_ADDRESS: u32 = 0x5133;
+
+/// Event storage and step counting of the firmware. Steps are only counted when the host
+/// accesses the device, so the events only depend on the boot and the time of the access.
+#[derive(Debug)]
+pub(crate) struct SimulatedPedometer {
+    boot_id: u32,
+    boot_epoch_ms: u64,
+    uptime_ms: u64,
+    next_index: u32,
+    /// Step counter as stored in the steps events
+    steps: u16,
+    daily_steps: u32,
+    day: NaiveDate,
+    random_state: u64,
+    events: VecDeque<PedometerEvent>,
+    sync_cursors: Vec<PedometerSyncCursor>,
+    /// The next transfer with more chunks ends before the given chunk without a last one, like a
+    /// transfer whose last chunk the firmware could not notify
+    abort_transfer_at: Option<usize>,
+    /// Number of the next transfers with more chunks whose second chunk is garbled, like a
+    /// notification that was corrupted on the air
+    corrupt_transfers: u8,
+}
+
+impl SimulatedPedometer {
+    pub(crate) fn new(boot_id: u32, boot_epoch_ms: u64) -> Self {
+        let mut pedometer = Self {
+            boot_id,
+            boot_epoch_ms,
+            uptime_ms: 0,
+            next_index: 0,
+            steps: 0,
+            daily_steps: 0,
+            day: local_date(boot_epoch_ms),
+            // The state of xorshift must not be zero
+            random_state: boot_id as u64 | 1 << 32,
+            events: VecDeque::new(),
+            sync_cursors: Vec::new(),
+            abort_transfer_at: None,
+            corrupt_transfers: 0,
+        };
+        pedometer.push(PedometerEventType::BootWithResetReason(0));
+        // The clock of the simulated device does not drift, so it only needs the host time once
+        pedometer.push(PedometerEventType::HostEpochMs(boot_epoch_ms));
+        pedometer
+    }
+
+    /// Device that booted at the start of the day [`HISTORY_DAYS`] ago. Restarting the demo on
+    /// the same day results in the same device, so the events are not stored twice.
+    fn for_demo() -> anyhow::Result<Self> {
+        let boot_day = Local::now().date_naive() - Days::new(HISTORY_DAYS);
+        let boot = boot_day
+            .and_time(NaiveTime::MIN)
+            .and_local_timezone(Local)
+            .earliest()
+            .ok_or_else(|| anyhow!("Invalid boot time"))?;
+        Ok(Self::new(
+            boot_day.num_days_from_ce().try_into()?,
+            boot.timestamp_millis().try_into()?,
+        ))
+    }
+
+    pub(crate) fn max_event_id(&self) -> u32 {
+        self.next_index.saturating_sub(1)
+    }
+
+    pub(crate) fn events(&self) -> impl Iterator<Item = &PedometerEvent> {
+        self.events.iter()
+    }
+
+    /// Count the steps of every minute up to the given time. Like the firmware, a steps event is
+    /// only stored if there were steps and the daily summary is stored at local midnight.
+    pub(crate) fn advance(&mut self, epoch_ms: u64) {
+        while self.boot_epoch_ms + self.uptime_ms + SAMPLE_INTERVAL_MS <= epoch_ms {
+            self.uptime_ms += SAMPLE_INTERVAL_MS;
+            let now = local_time(self.boot_epoch_ms + self.uptime_ms);
+            if now.date_naive() != self.day {
+                self.push(PedometerEventType::DailySummary(self.daily_steps));
+                self.daily_steps = 0;
+                self.day = now.date_naive();
+            }
+            let steps = self.walk(now.hour());
+            if steps > 0 {
+                self.steps = self.steps.wrapping_add(steps);
+                self.daily_steps += steps as u32;
+                self.push(PedometerEventType::Steps(self.steps));
+            }
+        }
+    }
+
+    /// Answer the query with as many events as fit into one response. The remaining bytes are
+    /// zero, so an empty response marks the end of the events.
+    pub(crate) fn respond(&self, query: PedometerEventQuery) -> [u8; EVENT_RESPONSE_SIZE] {
+        let mut buf = [0; EVENT_RESPONSE_SIZE];
+        serialize_events(
+            self.events.iter().filter(|event| query.matches(event)),
+            &mut buf,
+        );
+        buf
+    }
+
+    /// Answer the query with all matching events in chunks like a transfer of the firmware.
+    pub(crate) fn transfer(&self, query: PedometerEventQuery) -> Vec<[u8; EVENT_RESPONSE_SIZE]> {
+        let events: Vec<_> = self
+            .events
+            .iter()
+            .filter(|event| query.matches(event))
+            .collect();
+        let mut chunks = Vec::new();
+        let mut sent = 0;
+        loop {
+            let mut chunk = [0; EVENT_RESPONSE_SIZE];
+            let (header, buf) = chunk.split_at_mut(PedometerEventChunkHeader::SIZE);
+            sent += serialize_events(events[sent..].iter().copied(), buf);
+            let last = sent == events.len();
+            header.copy_from_slice(
+                &PedometerEventChunkHeader {
+                    sequence: chunks.len() as u16,
+                    last,
+                    incomplete: false,
+                }
+                .to_bytes(),
+            );
+            chunks.push(chunk);
+            if last {
+                return chunks;
+            }
+        }
+    }
+
+    /// Delete the events up to the first one with the given index like the firmware does.
+    pub(crate) fn delete_events(&mut self, min_event_index: u32) {
+        self.delete_events_while(|event| event.index < min_event_index);
+    }
+
+    /// Update the cursor of the host and delete the events that all hosts acknowledged.
+    pub(crate) fn ack_events(&mut self, cursor: PedometerSyncCursor) {
+        if let Some(existing) = self
+            .sync_cursors
+            .iter_mut()
+            .find(|existing| existing.host_id == cursor.host_id)
+        {
+            *existing = cursor;
+        } else if self.sync_cursors.len() < MAX_SYNC_HOSTS {
+            self.sync_cursors.push(cursor);
+        } else if let Some(oldest) = self
+            .sync_cursors
+            .iter_mut()
+            .min_by_key(|existing| (existing.boot_id, existing.min_event_index))
+        {
+            *oldest = cursor;
+        }
+        self.delete_events_while(|_| true);
+    }
+
+    /// Pop the events from the front as long as they fulfill the predicate and all hosts
+    /// acknowledged them.
+    fn delete_events_while(&mut self, predicate: impl Fn(&PedometerEvent) -> bool) {
+        while self.events.front().is_some_and(|event| {
+            predicate(event)
+                && self
+                    .sync_cursors
+                    .iter()
+                    .all(|cursor| cursor.is_synced(event))
+        }) {
+            self.events.pop_front();
+        }
+    }
+
+    /// Delete the events of the boot that all hosts acknowledged like the firmware does.
+    fn delete_boot(&mut self, boot_id: u32) {
+        let sync_cursors = &self.sync_cursors;
+        self.events.retain(|event| {
+            event.boot_id != boot_id || !sync_cursors.iter().all(|cursor| cursor.is_synced(event))
+        });
+    }
+
+    fn push(&mut self, event_type: PedometerEventType) {
+        self.events.push_back(PedometerEvent {
+            index: self.next_index,
+            timestamp_ms: self.uptime_ms,
+            boot_id: self.boot_id,
+            event_type,
+        });
+        self.next_index += 1;
+    }
+
+    /// Steps of one minute: Walks of one to two steps per second on a quarter of the minutes
+    /// during the day.
+    fn walk(&mut self, hour: u32) -> u16 {
+        let random = self.next_random();
+        if !(7..22).contains(&hour) || !random.is_multiple_of(4) {
+            return 0;
+        }
+        60 + (random >> 8) as u16 % 60
+    }
+
+    /// xorshift64
+    fn next_random(&mut self) -> u64 {
+        self.random_state ^= self.random_state << 13;
+        self.random_state ^= self.random_state >> 7;
+        self.random_state ^= self.random_state << 17;
+        self.random_state
+    }
+}
+
+/// Serialize as many of the events as fit into the buffer and return how many. The remaining
+/// bytes are zero.
+fn serialize_events<'a>(events: impl Iterator<Item = &'a PedometerEvent>, buf: &mut [u8]) -> usize {
+    let mut offset = 0;
+    let mut serialized_events = 0;
+    for event in events {
+        match event.serialize_for_transport(&mut buf[offset..]) {
+            Ok(serialized) => offset += serialized.len(),
+            Err(_) => {
+                buf[offset..].fill(0);
+                break;
+            }
+        }
+        serialized_events += 1;
+        if offset >= buf.len() {
+            break;
+        }
+    }
+    serialized_events
+}
+
+fn local_time(epoch_ms: u64) -> DateTime<Local> {
+    DateTime::from_timestamp_millis(epoch_ms as i64)
+        .unwrap_or_default()
+        .with_timezone(&Local)
+}
+
+fn local_date(epoch_ms: u64) -> NaiveDate {
+    local_time(epoch_ms).date_naive()
+}
+
+fn now_ms() -> u64 {
+    Local::now().timestamp_millis() as u64
+}
+
+/// In-process replacement of the BLE connection. The reads and writes of the characteristics are
+/// handled by the simulated firmware, which sends its notifications like btleplug does.
+#[derive(Debug, Clone)]
+struct VirtualPeripheral {
+    pedometer: Arc<Mutex<SimulatedPedometer>>,
+    notification_tx: mpsc::UnboundedSender<ValueNotification>,
+}
+
+impl VirtualPeripheral {
+    fn pedometer(&self) -> MutexGuard<'_, SimulatedPedometer> {
+        self.pedometer.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn read(&self, uuid: Uuid) -> anyhow::Result<Vec<u8>> {
+        let pedometer = self.pedometer();
+        Ok(match uuid {
+            CHARACTERISTIC_BOOT_ID => pedometer.boot_id.to_le_bytes().to_vec(),
+            CHARACTERISTIC_MAX_EVENT_ID => pedometer.max_event_id().to_le_bytes().to_vec(),
+            CHARACTERISTIC_UUID_SOC => vec![SIMULATED_SOC],
+            uuid => return Err(anyhow!("Characteristic {uuid} is not simulated")),
+        })
+    }
+
+    fn write(&self, uuid: Uuid, value: &[u8]) -> anyhow::Result<()> {
+        let mut pedometer = self.pedometer();
+        match uuid {
+            CHARACTERISTIC_UUID_REQUEST_EVENTS_SINCE | CHARACTERISTIC_UUID_REQUEST_EVENTS_QUERY => {
+                let query = if uuid == CHARACTERISTIC_UUID_REQUEST_EVENTS_QUERY {
+                    PedometerEventQuery::from_bytes(value.try_into()?)
+                        .ok_or_else(|| anyhow!("Invalid event query: {value:?}"))?
+                } else {
+                    PedometerEventQuery {
+                        since: PedometerEventRequest::from_bytes(value.try_into()?),
+                        filter: PedometerEventFilter::All,
+                    }
+                };
+                info!("Simulated device got query: {query:?}");
+                self.notification_tx.send(ValueNotification {
+                    uuid: CHARACTERISTIC_UUID_RESPONSE_EVENTS,
+                    value: pedometer.respond(query).to_vec(),
+                })?;
+            }
+            CHARACTERISTIC_UUID_REQUEST_EVENTS_TRANSFER => {
+                let query = PedometerEventQuery::from_bytes(value.try_into()?)
+                    .ok_or_else(|| anyhow!("Invalid event query: {value:?}"))?;
+                info!("Simulated device got transfer: {query:?}");
+                let mut chunks = pedometer.transfer(query);
+                if let Some(abort_at) = pedometer
+                    .abort_transfer_at
+                    .filter(|abort_at| *abort_at < chunks.len())
+                {
+                    info!("Simulated device aborts transfer after {abort_at} chunks");
+                    chunks.truncate(abort_at);
+                    pedometer.abort_transfer_at = None;
+                }
+                if pedometer.corrupt_transfers > 0 && chunks.len() > 1 {
+                    info!("Simulated device corrupts transfer");
+                    chunks[1][PedometerEventChunkHeader::SIZE + 1] ^= 0x01;
+                    pedometer.corrupt_transfers -= 1;
+                }
+                for chunk in chunks {
+                    self.notification_tx.send(ValueNotification {
+                        uuid: CHARACTERISTIC_UUID_RESPONSE_EVENTS,
+                        value: chunk.to_vec(),
+                    })?;
+                }
+            }
+            CHARACTERISTIC_UUID_DELETE_EVENTS => {
+                pedometer.delete_events(u32::from_le_bytes(value.try_into()?))
+            }
+            CHARACTERISTIC_UUID_ACK_EVENTS => {
+                pedometer.ack_events(PedometerSyncCursor::from_bytes(value.try_into()?))
+            }
+            CHARACTERISTIC_UUID_DELETE_BOOT => {
+                pedometer.delete_boot(u32::from_le_bytes(value.try_into()?))
+            }
+            CHARACTERISTIC_UUID_MARKER => {
+                let marker = value
+                    .first()
+                    .and_then(|value| PedometerMarker::from_u8(*value))
+                    .ok_or_else(|| anyhow!("Invalid marker: {value:?}"))?;
+                pedometer.push(PedometerEventType::Marker(marker));
+            }
+            uuid => return Err(anyhow!("Characteristic {uuid} is not simulated")),
+        }
+        Ok(())
+    }
+}
+
+/// Replaces [`PedometerDeviceHandler`] in the demo, so that the sync protocol runs against the
+/// simulated firmware instead of a real device.
+#[derive(Debug)]
+pub(crate) struct SimulatedDeviceHandler {
+    pedometer: Arc<Mutex<SimulatedPedometer>>,
+    device: Option<VirtualPeripheral>,
+    sync_phase: SharedSyncPhase,
+    transfer_timeout: Duration,
+}
+
+impl SimulatedDeviceHandler {
+    pub(crate) async fn new() -> anyhow::Result<Self> {
+        Ok(Self::with_pedometer(Arc::new(Mutex::new(
+            SimulatedPedometer::for_demo()?,
+        ))))
+    }
+
+    pub(crate) fn with_pedometer(pedometer: Arc<Mutex<SimulatedPedometer>>) -> Self {
+        Self {
+            pedometer,
+            device: None,
+            sync_phase: Default::default(),
+            transfer_timeout: TRANSFER_TIMEOUT,
+        }
+    }
+
+    pub(crate) async fn spawn_message_handler(
+        mut self,
+        event_receiver: SharedReceiver<PedometerDeviceHandlerCommand>,
+    ) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut event_receiver = event_receiver.lock().await;
+            while let Some(cmd) = event_receiver.recv().await {
+                match cmd {
+                    PedometerDeviceHandlerCommand::TryConnect { responder, .. } => {
+                        let res = self.try_connect();
+                        if res.is_err() {
+                            set_connection_state(PedometerConnectionState::Disconnected);
+                        }
+                        let _ = responder.send(res);
+                    }
+                    PedometerDeviceHandlerCommand::IsConnected { responder } => {
+                        let _ = responder.send(Ok(self.device.is_some()));
+                    }
+                    PedometerDeviceHandlerCommand::RequestEvents { since, responder } => {
+                        let res = self.request_events(since).await;
+                        match &res {
+                            Ok(()) => set_connection_state(PedometerConnectionState::Syncing),
+                            Err(e) => report_sync_error(e),
+                        }
+                        let _ = responder.send(res);
+                    }
+                    PedometerDeviceHandlerCommand::DeleteEvents {
+                        max_event_id,
+                        responder,
+                    } => {
+                        let res = self.delete_events(max_event_id).await;
+                        if let Err(e) = &res {
+                            warn!("Could not delete events: {e}");
+                        }
+                        let _ = responder.send(res);
+                    }
+                    PedometerDeviceHandlerCommand::DeleteBoot { boot_id, responder } => {
+                        let _ = responder.send(self.device().and_then(|device| {
+                            device.write(CHARACTERISTIC_UUID_DELETE_BOOT, &boot_id.to_le_bytes())
+                        }));
+                    }
+                    PedometerDeviceHandlerCommand::SetMarker { marker, responder } => {
+                        let res = match self.device().and_then(|device| {
+                            device.write(CHARACTERISTIC_UUID_MARKER, &[marker as u8])
+                        }) {
+                            // The device stores the marker before it handles the request
+                            Ok(()) => self.request_events(None).await,
+                            Err(e) => Err(e),
+                        };
+                        let _ = responder.send(res);
+                    }
+                    PedometerDeviceHandlerCommand::Disconnect { responder } => {
+                        set_connection_state(PedometerConnectionState::Disconnecting);
+                        self.disconnect();
+                        let _ = responder.send(Ok(()));
+                    }
+                    // There are no advertisements without a real device
+                    PedometerDeviceHandlerCommand::StartListening { responder }
+                    | PedometerDeviceHandlerCommand::StopListening { responder } => {
+                        let _ = responder.send(Ok(()));
+                    }
+                    PedometerDeviceHandlerCommand::WriteConfig { responder, .. } => {
+                        let _ = responder.send(Err(anyhow!("Not supported by the simulation")));
+                    }
+                    PedometerDeviceHandlerCommand::ReadDiagnostics { responder } => {
+                        let _ = responder.send(Err(anyhow!("Not supported by the simulation")));
+                    }
+                    PedometerDeviceHandlerCommand::ReadDeviceClock { responder } => {
+                        let _ = responder.send(Err(anyhow!("Not supported by the simulation")));
+                    }
+                    PedometerDeviceHandlerCommand::ReadBootSummaries { responder } => {
+                        let _ = responder.send(Err(anyhow!("Not supported by the simulation")));
+                    }
+                    PedometerDeviceHandlerCommand::StreamAcceleration { responder, .. } => {
+                        let _ = responder.send(Err(anyhow!("Not supported by the simulation")));
+                    }
+                    // The clock of the simulated device does not drift
+                    PedometerDeviceHandlerCommand::ResyncTime { responder } => {
+                        let _ = responder.send(Ok(()));
+                    }
+                    PedometerDeviceHandlerCommand::AdapterRemoved => {
+                        self.device = None;
+                        set_connection_state(PedometerConnectionState::Disconnected);
+                    }
+                    PedometerDeviceHandlerCommand::Exit => break,
+                }
+            }
+        })
+    }
+
+    fn device(&self) -> anyhow::Result<&VirtualPeripheral> {
+        self.device
+            .as_ref()
+            .ok_or_else(|| anyhow!("Device not connected"))
+    }
+
+    fn try_connect(&mut self) -> anyhow::Result<()> {
+        if self.device.is_some() {
+            return Ok(());
+        }
+        set_connection_state(PedometerConnectionState::Connecting);
+        let (notification_tx, mut notification_rx) = mpsc::unbounded_channel();
+        let device = VirtualPeripheral {
+            pedometer: self.pedometer.clone(),
+            notification_tx,
+        };
+        device.pedometer().advance(now_ms());
+        let max_event_id =
+            u32::from_le_bytes(device.read(CHARACTERISTIC_MAX_EVENT_ID)?[..].try_into()?);
+        let soc = device.read(CHARACTERISTIC_UUID_SOC)?[0];
+        info!("Connected to simulated device: max_event_id: {max_event_id}, soc: {soc}");
+
+        let (batch_tx, batch_rx) = mpsc::channel(SYNC_WRITE_BUFFER_SIZE);
+        tokio::spawn(PedometerDeviceHandler::write_sync_batches(batch_rx));
+        let sync_phase = self.sync_phase.clone();
+        let transfer_timeout = self.transfer_timeout;
+        tokio::spawn(async move {
+            // Like the current firmware, the simulated device sends all events in transfers
+            let mut responses = PedometerEventResponses::new(max_event_id, true);
+            responses.transfer_timeout = transfer_timeout;
+            loop {
+                let Some(notification) = responses.receive(notification_rx.recv()).await else {
+                    PedometerDeviceHandler::process_transfer_timeout(&mut responses).await;
+                    continue;
+                };
+                let Some(notification) = notification else {
+                    break;
+                };
+                PedometerDeviceHandler::process_event_response(
+                    notification,
+                    &mut responses,
+                    &batch_tx,
+                    &sync_phase,
+                )
+                .await;
+            }
+        });
+
+        GUI_EVENT_TX
+            .get()
+            .unwrap()
+            .send(PedometerGuiEvent::Soc(soc));
+        GUI_EVENT_TX
+            .get()
+            .unwrap()
+            .send(PedometerGuiEvent::Connected {
+                address: SIMULATED_ADDRESS.to_string(),
+                name: Some(
+                    String::from_utf8_lossy(&pedomet_rs_common::device_name(
+                        SIMULATED_DEVICE_ADDRESS,
+                    ))
+                    .into_owned(),
+                ),
+            });
+        self.device = Some(device);
+        set_connection_state(PedometerConnectionState::Connected);
+        Ok(())
+    }
+
+    fn disconnect(&mut self) {
+        // Dropping the device ends the notification task
+        if self.device.take().is_some() {
+            GUI_EVENT_TX
+                .get()
+                .unwrap()
+                .send(PedometerGuiEvent::Disconnected);
+        }
+        set_connection_state(PedometerConnectionState::Disconnected);
+    }
+
+    async fn request_events(&self, since: Option<PedometerEventRequest>) -> anyhow::Result<()> {
+        let device = self.device()?;
+        let new_sync = since.is_none();
+        let since = match since {
+            Some(since) => since,
+            None => {
+                // A new sync catches up with the steps since the last one
+                device.pedometer().advance(now_ms());
+                match get_last_synced_event().await? {
+                    Some(last_synced) => {
+                        let current_boot_id = u32::from_le_bytes(
+                            device.read(CHARACTERISTIC_BOOT_ID)?[..].try_into()?,
+                        );
+                        let current_max_event_id = u32::from_le_bytes(
+                            device.read(CHARACTERISTIC_MAX_EVENT_ID)?[..].try_into()?,
+                        );
+                        next_event_request(last_synced, current_boot_id, current_max_event_id)?
+                    }
+                    None => PedometerEventRequest::default(),
+                }
+            }
+        };
+        let query = event_query(&self.sync_phase, since, new_sync);
+        info!("Query events: {query:?}");
+        device.write(
+            CHARACTERISTIC_UUID_REQUEST_EVENTS_TRANSFER,
+            &query.to_bytes(),
+        )
+    }
+
+    async fn delete_events(&self, max_event_id: Option<u32>) -> anyhow::Result<()> {
+        let device = self.device()?;
+        if max_event_id.is_none() {
+            let Some(cursor) = get_sync_cursor().await? else {
+                info!("Nothing synced that could be deleted");
+                return Ok(());
+            };
+            info!("Acknowledge events before {cursor:?} on simulated device");
+            return device.write(CHARACTERISTIC_UUID_ACK_EVENTS, &cursor.to_bytes());
+        }
+        let Some(max_event_id) = get_max_deletable_event_id(max_event_id).await? else {
+            info!("Nothing synced that could be deleted");
+            return Ok(());
+        };
+        info!("Delete events up to {max_event_id} on simulated device");
+        device.write(
+            CHARACTERISTIC_UUID_DELETE_EVENTS,
+            &(max_event_id + 1).to_le_bytes(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use tokio::sync::oneshot;
+
+    use super::*;
+    use crate::ble::{PedometerSyncBatch, BLE_CMD_TX};
+    use crate::gui::gui_event_channel;
+    use crate::persistence::{PedometerDatabase, PedometerDatabaseCommand, DB_CMD_TX};
+    use crate::supervisor::{supervise, PedometerBackend};
+
+    const BOOT_ID: u32 = 3;
+
+    fn pedometer_since(hours: u64) -> SimulatedPedometer {
+        SimulatedPedometer::new(BOOT_ID, now_ms() - hours * 60 * 60 * 1000)
+    }
+
+    fn count_steps_events<'a>(events: impl Iterator<Item = &'a PedometerEvent>) -> usize {
+        events
+            .filter(|event| matches!(event.event_type, PedometerEventType::Steps(_)))
+            .count()
+    }
+
+    #[test]
+    fn simulation_is_deterministic() {
+        let mut first = pedometer_since(48);
+        let mut second = pedometer_since(48);
+        let now = now_ms();
+        first.advance(now);
+        second.advance(now - 6 * 60 * 60 * 1000);
+        second.advance(now);
+        assert_eq!(first.max_event_id(), second.max_event_id());
+        assert!(count_steps_events(first.events()) > 0);
+    }
+
+    /// Query the events like the host until the response is empty
+    fn query_all(
+        pedometer: &SimulatedPedometer,
+        filter: PedometerEventFilter,
+    ) -> Vec<PedometerEvent> {
+        let mut query = PedometerEventQuery {
+            since: PedometerEventRequest::default(),
+            filter,
+        };
+        let mut received = Vec::new();
+        loop {
+            let mut response = pedometer.respond(query);
+            let mut buf = &mut response[..];
+            let received_before = received.len();
+            while let Ok((event, rest)) = PedometerEvent::deserialize_from_transport(buf) {
+                buf = rest;
+                received.push(event);
+            }
+            let Some(last) = received[received_before..].last() else {
+                return received;
+            };
+            query.since = PedometerEventRequest {
+                boot_id: last.boot_id,
+                min_event_index: last.index + 1,
+            };
+        }
+    }
+
+    #[test]
+    fn response_contains_complete_events() {
+        let mut pedometer = pedometer_since(48);
+        pedometer.advance(now_ms());
+        let received = query_all(&pedometer, PedometerEventFilter::All);
+        assert!(received
+            .iter()
+            .enumerate()
+            .all(|(i, event)| event.index == i as u32));
+        assert_eq!(received.len() as u32, pedometer.max_event_id() + 1);
+    }
+
+    #[test]
+    fn transfer_contains_all_events() {
+        let mut pedometer = pedometer_since(48);
+        pedometer.advance(now_ms());
+        let query = PedometerEventQuery::default();
+        let chunks = pedometer.transfer(query);
+        assert!(chunks.len() > 1);
+        let mut received = Vec::new();
+        for (i, mut chunk) in chunks.into_iter().enumerate() {
+            let (header, mut buf) = chunk
+                .split_first_chunk_mut::<{ PedometerEventChunkHeader::SIZE }>()
+                .unwrap();
+            let header = PedometerEventChunkHeader::from_bytes(header);
+            assert_eq!(header.sequence, i as u16);
+            assert!(!header.incomplete);
+            while let Ok((event, rest)) = PedometerEvent::deserialize_from_transport(buf) {
+                buf = rest;
+                received.push(event);
+            }
+            assert_eq!(
+                header.last,
+                received.len() as u32 == pedometer.max_event_id() + 1
+            );
+        }
+        let queried = query_all(&pedometer, PedometerEventFilter::All);
+        assert!(received
+            .iter()
+            .map(|event| event.index)
+            .eq(queried.iter().map(|event| event.index)));
+    }
+
+    #[test]
+    fn query_time_references() {
+        let mut pedometer = pedometer_since(48);
+        pedometer.advance(now_ms());
+        let received = query_all(&pedometer, PedometerEventFilter::TimeReferences);
+        assert_eq!(
+            received.iter().map(|event| event.index).collect::<Vec<_>>(),
+            [0, 1]
+        );
+    }
+
+    #[test]
+    fn delete_events_keeps_unsynced() {
+        let mut pedometer = pedometer_since(48);
+        pedometer.advance(now_ms());
+        pedometer.delete_events(10);
+        assert_eq!(pedometer.events().next().map(|event| event.index), Some(10));
+    }
+
+    fn cursor(host_id: u32, min_event_index: u32) -> PedometerSyncCursor {
+        PedometerSyncCursor {
+            host_id,
+            boot_id: BOOT_ID,
+            min_event_index,
+        }
+    }
+
+    fn first_index(pedometer: &SimulatedPedometer) -> Option<u32> {
+        pedometer.events().next().map(|event| event.index)
+    }
+
+    #[test]
+    fn ack_events_keeps_events_of_other_hosts() {
+        let mut pedometer = pedometer_since(48);
+        pedometer.advance(now_ms());
+        pedometer.ack_events(cursor(1, 10));
+        assert_eq!(first_index(&pedometer), Some(10));
+        pedometer.ack_events(cursor(2, 5));
+        pedometer.ack_events(cursor(1, 20));
+        assert_eq!(first_index(&pedometer), Some(10));
+        pedometer.delete_events(30);
+        assert_eq!(first_index(&pedometer), Some(10));
+        pedometer.ack_events(cursor(2, 15));
+        assert_eq!(first_index(&pedometer), Some(15));
+    }
+
+    /// Process the chunks like the notification task of a connection
+    async fn process_chunks(
+        chunks: &[[u8; EVENT_RESPONSE_SIZE]],
+        responses: &mut PedometerEventResponses,
+        batch_tx: &mpsc::Sender<PedometerSyncBatch>,
+        sync_phase: &SharedSyncPhase,
+    ) {
+        for chunk in chunks {
+            PedometerDeviceHandler::process_event_response(
+                ValueNotification {
+                    uuid: CHARACTERISTIC_UUID_RESPONSE_EVENTS,
+                    value: chunk.to_vec(),
+                },
+                responses,
+                batch_tx,
+                sync_phase,
+            )
+            .await;
+        }
+    }
+
+    #[tokio::test]
+    async fn lost_first_chunk_after_single_chunk_transfer() {
+        GUI_EVENT_TX.get_or_init(|| gui_event_channel().0);
+        let mut pedometer = pedometer_since(48);
+        pedometer.advance(now_ms());
+        let (batch_tx, mut batch_rx) = mpsc::channel(SYNC_WRITE_BUFFER_SIZE);
+        let sync_phase = SharedSyncPhase::default();
+        let mut responses = PedometerEventResponses::new(pedometer.max_event_id(), true);
+
+        let time_references = pedometer.transfer(PedometerEventQuery {
+            since: PedometerEventRequest::default(),
+            filter: PedometerEventFilter::TimeReferences,
+        });
+        assert_eq!(time_references.len(), 1);
+        process_chunks(&time_references, &mut responses, &batch_tx, &sync_phase).await;
+        assert!(batch_rx.try_recv().is_ok_and(|batch| batch.complete));
+
+        // The second chunk of the next transfer must not be taken for its continuation
+        let chunks = pedometer.transfer(PedometerEventQuery::default());
+        assert!(chunks.len() > 2);
+        process_chunks(
+            &chunks[1..chunks.len() - 1],
+            &mut responses,
+            &batch_tx,
+            &sync_phase,
+        )
+        .await;
+        assert!(batch_rx.try_recv().is_err());
+    }
+
+    async fn send_db_command<T>(
+        command: impl FnOnce(oneshot::Sender<anyhow::Result<T>>) -> PedometerDatabaseCommand,
+    ) -> T {
+        let (responder_tx, responder_rx) = oneshot::channel();
+        DB_CMD_TX
+            .get()
+            .unwrap()
+            .send(command(responder_tx))
+            .await
+            .unwrap();
+        responder_rx.await.unwrap().unwrap()
+    }
+
+    /// Sync from the simulated firmware over the virtual transport into the database
+    #[tokio::test]
+    async fn sync_end_to_end() {
+        let (database_cmd_tx, database_cmd_rx) = mpsc::channel(1000);
+        let (device_cmd_tx, device_cmd_rx) = mpsc::channel(1000);
+        let (gui_events_tx, _gui_events_rx) = gui_event_channel();
+        BLE_CMD_TX.get_or_init(|| device_cmd_tx);
+        DB_CMD_TX.get_or_init(|| database_cmd_tx);
+        GUI_EVENT_TX.get_or_init(|| gui_events_tx);
+
+        let mut pedometer = pedometer_since(48);
+        // The sync only finishes if the host notices the aborted transfer and the corrupted
+        // events and requests the rest
+        pedometer.abort_transfer_at = Some(2);
+        pedometer.corrupt_transfers = 2;
+        let pedometer = Arc::new(Mutex::new(pedometer));
+        tokio::spawn(supervise(
+            PedometerBackend::Database,
+            database_cmd_rx,
+            |rx| async move {
+                Ok(PedometerDatabase::new_in_memory()
+                    .await?
+                    .spawn_message_handler(rx)
+                    .await)
+            },
+        ));
+        let handler_pedometer = pedometer.clone();
+        tokio::spawn(supervise(
+            PedometerBackend::Device,
+            device_cmd_rx,
+            move |rx| {
+                let pedometer = handler_pedometer.clone();
+                async move {
+                    let mut handler = SimulatedDeviceHandler::with_pedometer(pedometer);
+                    handler.transfer_timeout = Duration::from_millis(200);
+                    Ok(handler.spawn_message_handler(rx).await)
+                }
+            },
+        ));
+
+        let (responder_tx, responder_rx) = oneshot::channel();
+        BLE_CMD_TX
+            .get()
+            .unwrap()
+            .send(PedometerDeviceHandlerCommand::TryConnect {
+                address: None,
+                scan_policy: Default::default(),
+                responder: responder_tx,
+            })
+            .await
+            .unwrap();
+        responder_rx.await.unwrap().unwrap();
+        let (responder_tx, responder_rx) = oneshot::channel();
+        BLE_CMD_TX
+            .get()
+            .unwrap()
+            .send(PedometerDeviceHandlerCommand::RequestEvents {
+                since: None,
+                responder: responder_tx,
+            })
+            .await
+            .unwrap();
+        responder_rx.await.unwrap().unwrap();
+
+        // The new events are only counted when the next sync starts
+        let (max_event_id, steps_events) = {
+            let pedometer = pedometer.lock().unwrap();
+            (
+                pedometer.max_event_id(),
+                count_steps_events(pedometer.events()),
+            )
+        };
+        tokio::time::timeout(Duration::from_secs(30), async {
+            loop {
+                let sync_state = send_db_command(|responder| {
+                    PedometerDatabaseCommand::GetSyncState { responder }
+                })
+                .await;
+                if sync_state.is_some_and(|sync_state| {
+                    sync_state.boot_id == BOOT_ID as i64
+                        && sync_state.last_event_id == max_event_id as i64
+                }) {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .expect("Sync did not finish");
+
+        let events = send_db_command(|responder| PedometerDatabaseCommand::GetEventsInTimeRange {
+            start: Utc::now() - chrono::Duration::days(3),
+            end: Utc::now(),
+            responder,
+        })
+        .await;
+        assert_eq!(events.len(), steps_events);
+    }
+}
@@ -0,0 +1,513 @@
+use std::collections::HashSet;
+
+use chrono::{DateTime, Duration, NaiveDate, TimeZone, Timelike};
+use log::debug;
+use pedomet_rs_common::PedometerActivity;
+
+use crate::persistence::{
+    local_day, PedometerDailyTotal, PedometerPersistenceActivity, PedometerPersistenceEvent,
+};
+
+/// Number of days in the week view, which ends at the selected day.
+pub(crate) const WEEK_DAYS: i64 = 7;
+/// Number of previous weeks in the average of the week comparison.
+pub(crate) const COMPARISON_WEEKS: i64 = 4;
+
+/// Convert the step counters of the events into the steps since the previous event.
+///
+/// The counter of the device is 16 bit and wraps around. It starts from zero with every boot, so
+/// the first event of a boot keeps its counter. The first event overall has no predecessor and
+/// gets zero steps.
+pub(crate) fn transform_events_to_relative_steps(
+    mut events: Vec<PedometerPersistenceEvent>,
+) -> Vec<PedometerPersistenceEvent> {
+    if events.is_empty() {
+        return events;
+    }
+    let first_steps = events.first().unwrap().steps;
+    let first_boot_id = events.first().unwrap().boot_id;
+    debug!("Db events: {events:?}");
+    events = events
+        .into_iter()
+        .scan(
+            (first_steps, first_boot_id),
+            |(last_steps, last_boot_id), mut event| {
+                let event_steps = event.steps as u16;
+                if *last_boot_id == event.boot_id {
+                    event.steps = (event_steps).overflowing_sub(*last_steps as u16).0 as i64;
+                }
+                *last_steps = event_steps as i64;
+                *last_boot_id = event.boot_id;
+                Some(event)
+            },
+        )
+        .collect();
+    debug!("Mapped events: {events:?}");
+    events
+}
+
+/// Relative steps of one local day per hour of the wall clock.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct PedometerDaySteps {
+    pub hourly: [i64; 24],
+    /// Steps per hour of the events with a classified activity, indexed by [`PedometerActivity`].
+    /// The rest of the hourly steps were taken before the device classified any activity.
+    pub hourly_by_activity: [[i64; 24]; 3],
+    pub total: i64,
+    /// Boots of the counted events, e.g. to warn about their time sync quality
+    pub boot_ids: HashSet<i64>,
+}
+
+impl PedometerDaySteps {
+    /// Sum up the relative steps of the events that belong to the given day in the time zone.
+    /// Events with an invalid timestamp are skipped.
+    pub(crate) fn new<'a, Tz: TimeZone>(
+        tz: &Tz,
+        day: NaiveDate,
+        events: impl IntoIterator<Item = &'a PedometerPersistenceEvent>,
+    ) -> Self {
+        let mut day_steps = Self::default();
+        for event in events {
+            let Some(event_dt) = DateTime::from_timestamp_millis(event.timestamp_ms) else {
+                continue;
+            };
+            let event_dt = event_dt.with_timezone(tz);
+            if local_day(&event_dt, 0) != day {
+                continue;
+            }
+            day_steps.hourly[event_dt.hour() as usize] += event.steps;
+            if let Some(activity) = event.get_activity() {
+                day_steps.hourly_by_activity[activity as usize][event_dt.hour() as usize] +=
+                    event.steps;
+            }
+            day_steps.total += event.steps;
+            day_steps.boot_ids.insert(event.boot_id);
+        }
+        day_steps
+    }
+}
+
+/// Time and steps per activity that the device classified, indexed by [`PedometerActivity`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct PedometerActivityBreakdown {
+    pub duration_ms: [i64; 3],
+    pub steps: [i64; 3],
+}
+
+impl PedometerActivityBreakdown {
+    /// Split the time from `start_ms` to `end_ms` and the relative steps of the events in it by
+    /// the activity that was classified last before. Events that were tagged with their activity
+    /// when they were stored keep it. The time before the first known activity counts as idle.
+    /// The activities have to be ordered by their timestamp.
+    pub(crate) fn new<'a>(
+        start_ms: i64,
+        end_ms: i64,
+        activities: &[PedometerPersistenceActivity],
+        events: impl IntoIterator<Item = &'a PedometerPersistenceEvent>,
+    ) -> Self {
+        let changes: Vec<_> = activities
+            .iter()
+            .filter_map(|activity| Some((activity.timestamp_ms, activity.get_activity().ok()?)))
+            .collect();
+        let activity_at = |timestamp_ms: i64| {
+            let index = changes.partition_point(|(change_ms, _)| *change_ms <= timestamp_ms);
+            index
+                .checked_sub(1)
+                .map(|index| changes[index].1)
+                .unwrap_or_default()
+        };
+
+        let mut breakdown = Self::default();
+        let mut period_start_ms = start_ms;
+        for &(change_ms, _) in changes
+            .iter()
+            .filter(|(change_ms, _)| (start_ms..end_ms).contains(change_ms))
+        {
+            breakdown.duration_ms[activity_at(period_start_ms) as usize] +=
+                change_ms - period_start_ms;
+            period_start_ms = change_ms;
+        }
+        if period_start_ms < end_ms {
+            breakdown.duration_ms[activity_at(period_start_ms) as usize] +=
+                end_ms - period_start_ms;
+        }
+        for event in events {
+            if (start_ms..end_ms).contains(&event.timestamp_ms) {
+                let activity = event
+                    .get_activity()
+                    .unwrap_or_else(|| activity_at(event.timestamp_ms));
+                breakdown.steps[activity as usize] += event.steps;
+            }
+        }
+        breakdown
+    }
+
+    /// Whether the device classified any activity in the time range
+    pub(crate) fn is_active(&self) -> bool {
+        self.duration_ms[PedometerActivity::Walking as usize] > 0
+            || self.duration_ms[PedometerActivity::Running as usize] > 0
+    }
+}
+
+/// Hours selected in the day view by dragging over the bars.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) struct PedometerHourRange {
+    pub first_hour: usize,
+    pub last_hour: usize,
+}
+
+impl PedometerHourRange {
+    /// Hours between two positions of the day view in either order. The bar of an hour is
+    /// centered on its position.
+    pub(crate) fn new(start: f64, end: f64) -> Self {
+        let hour = |position: f64| position.round().clamp(0.0, 23.0) as usize;
+        Self {
+            first_hour: hour(start.min(end)),
+            last_hour: hour(start.max(end)),
+        }
+    }
+
+    pub(crate) fn contains(&self, hour: usize) -> bool {
+        (self.first_hour..=self.last_hour).contains(&hour)
+    }
+
+    pub(crate) fn steps(&self, hourly: &[i64; 24]) -> i64 {
+        hourly[self.first_hour..=self.last_hour].iter().sum()
+    }
+
+    pub(crate) fn duration(&self) -> Duration {
+        Duration::hours((self.last_hour - self.first_hour + 1) as i64)
+    }
+}
+
+/// First day of the week view that ends at the given day.
+pub(crate) fn week_first_day(last_day: NaiveDate) -> NaiveDate {
+    last_day - Duration::days(WEEK_DAYS - 1)
+}
+
+/// Position of the bar of the day in the week view, the last day is at zero.
+pub(crate) fn week_bar_position(last_day: NaiveDate, day: NaiveDate) -> f64 {
+    -(last_day - day).num_days() as f64
+}
+
+/// First day of the daily totals needed for the week comparison that ends at the given day.
+pub(crate) fn comparison_first_day(last_day: NaiveDate) -> NaiveDate {
+    week_first_day(last_day) - Duration::days(COMPARISON_WEEKS * WEEK_DAYS)
+}
+
+/// Daily totals of the week view next to the same days of the previous week and their average
+/// over the previous [`COMPARISON_WEEKS`] weeks. The days are in the order of the week view.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct PedometerWeekComparison {
+    pub this_week: [i64; WEEK_DAYS as usize],
+    pub last_week: [i64; WEEK_DAYS as usize],
+    pub average: [f64; WEEK_DAYS as usize],
+}
+
+impl PedometerWeekComparison {
+    /// Compare the week that ends at the given day. Days without totals count as zero steps.
+    pub(crate) fn new<'a>(
+        last_day: NaiveDate,
+        totals: impl IntoIterator<Item = &'a PedometerDailyTotal>,
+    ) -> Self {
+        let mut comparison = Self::default();
+        let mut sums = [0_i64; WEEK_DAYS as usize];
+        for total in totals {
+            let offset = (total.date - week_first_day(last_day)).num_days();
+            let day = offset.rem_euclid(WEEK_DAYS) as usize;
+            let week = offset.div_euclid(WEEK_DAYS);
+            if week == 0 {
+                comparison.this_week[day] = total.steps;
+            } else if (-COMPARISON_WEEKS..0).contains(&week) {
+                if week == -1 {
+                    comparison.last_week[day] = total.steps;
+                }
+                sums[day] += total.steps;
+            }
+        }
+        for (average, sum) in comparison.average.iter_mut().zip(sums) {
+            *average = sum as f64 / COMPARISON_WEEKS as f64;
+        }
+        comparison
+    }
+}
+
+/// Day that a daily summary of the device belongs to. The summary is created at the start of the
+/// next day and the device clock may be a bit early.
+pub(crate) fn daily_summary_day<Tz: TimeZone>(summary_dt: &DateTime<Tz>) -> NaiveDate {
+    (summary_dt.clone() - Duration::hours(1)).date_naive()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use chrono_tz::Europe::Berlin;
+
+    use super::*;
+
+    fn event(
+        event_id: i64,
+        boot_id: i64,
+        timestamp: &str,
+        steps: i64,
+    ) -> PedometerPersistenceEvent {
+        PedometerPersistenceEvent {
+            event_id,
+            timestamp_ms: timestamp
+                .parse::<DateTime<Utc>>()
+                .unwrap()
+                .timestamp_millis(),
+            boot_id,
+            steps,
+            activity: None,
+        }
+    }
+
+    fn date(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    fn steps(events: &[PedometerPersistenceEvent]) -> Vec<i64> {
+        events.iter().map(|event| event.steps).collect()
+    }
+
+    #[test]
+    fn relative_steps_of_empty_range() {
+        assert!(transform_events_to_relative_steps(vec![]).is_empty());
+    }
+
+    #[test]
+    fn relative_steps_start_at_zero() {
+        let events = transform_events_to_relative_steps(vec![
+            event(1, 1, "2024-11-20T08:00:00Z", 100),
+            event(2, 1, "2024-11-20T08:01:00Z", 150),
+            event(3, 1, "2024-11-20T08:02:00Z", 150),
+            event(4, 1, "2024-11-20T08:03:00Z", 420),
+        ]);
+        assert_eq!(steps(&events), [0, 50, 0, 270]);
+    }
+
+    #[test]
+    fn relative_steps_across_counter_wrap() {
+        let events = transform_events_to_relative_steps(vec![
+            event(1, 1, "2024-11-20T08:00:00Z", 65_530),
+            event(2, 1, "2024-11-20T08:01:00Z", 65_535),
+            event(3, 1, "2024-11-20T08:02:00Z", 4),
+        ]);
+        assert_eq!(steps(&events), [0, 5, 5]);
+    }
+
+    #[test]
+    fn relative_steps_restart_with_boot() {
+        let events = transform_events_to_relative_steps(vec![
+            event(1, 1, "2024-11-20T08:00:00Z", 1_000),
+            event(2, 1, "2024-11-20T08:01:00Z", 1_200),
+            event(3, 2, "2024-11-20T09:00:00Z", 30),
+            event(4, 2, "2024-11-20T09:01:00Z", 80),
+        ]);
+        assert_eq!(steps(&events), [0, 200, 30, 50]);
+    }
+
+    #[test]
+    fn day_steps_of_empty_range() {
+        let day_steps = PedometerDaySteps::new(&Berlin, date("2024-11-20"), &[]);
+        assert_eq!(day_steps, PedometerDaySteps::default());
+    }
+
+    #[test]
+    fn day_steps_per_local_hour() {
+        let events = [
+            // Previous local day
+            event(1, 1, "2024-11-19T22:59:00Z", 10),
+            event(2, 1, "2024-11-19T23:00:00Z", 20),
+            event(3, 1, "2024-11-20T07:30:00Z", 30),
+            event(4, 1, "2024-11-20T07:45:00Z", 40),
+            event(5, 1, "2024-11-20T22:59:00Z", 50),
+            // Next local day
+            event(6, 1, "2024-11-20T23:00:00Z", 60),
+        ];
+        let day_steps = PedometerDaySteps::new(&Berlin, date("2024-11-20"), &events);
+        assert_eq!(day_steps.total, 140);
+        assert_eq!(day_steps.hourly[0], 20);
+        assert_eq!(day_steps.hourly[8], 70);
+        assert_eq!(day_steps.hourly[23], 50);
+        assert_eq!(day_steps.hourly.iter().sum::<i64>(), day_steps.total);
+    }
+
+    #[test]
+    fn day_steps_of_multiple_boots() {
+        let events = transform_events_to_relative_steps(vec![
+            event(1, 1, "2024-11-20T07:00:00Z", 500),
+            event(2, 1, "2024-11-20T07:10:00Z", 700),
+            event(1, 2, "2024-11-20T12:00:00Z", 40),
+            event(2, 2, "2024-11-20T12:10:00Z", 90),
+        ]);
+        let day_steps = PedometerDaySteps::new(&Berlin, date("2024-11-20"), &events);
+        assert_eq!(day_steps.total, 290);
+        assert_eq!(day_steps.hourly[8], 200);
+        assert_eq!(day_steps.hourly[13], 90);
+        assert_eq!(day_steps.boot_ids, HashSet::from([1, 2]));
+    }
+
+    #[test]
+    fn day_steps_on_fall_back_day() {
+        // 02:30 occurs twice, once in CEST and once in CET
+        let events = [
+            event(1, 1, "2024-10-27T00:30:00Z", 10),
+            event(2, 1, "2024-10-27T01:30:00Z", 20),
+        ];
+        let day_steps = PedometerDaySteps::new(&Berlin, date("2024-10-27"), &events);
+        assert_eq!(day_steps.hourly[2], 30);
+        assert_eq!(day_steps.total, 30);
+    }
+
+    #[test]
+    fn day_steps_per_activity() {
+        let tagged =
+            |event_id, timestamp, steps, activity: PedometerActivity| PedometerPersistenceEvent {
+                activity: Some(activity as i64),
+                ..event(event_id, 1, timestamp, steps)
+            };
+        let events = [
+            // Before the first classification
+            event(1, 1, "2024-11-20T07:10:00Z", 10),
+            tagged(2, "2024-11-20T07:20:00Z", 20, PedometerActivity::Walking),
+            tagged(3, "2024-11-20T07:30:00Z", 30, PedometerActivity::Running),
+            tagged(4, "2024-11-20T09:00:00Z", 40, PedometerActivity::Walking),
+        ];
+        let day_steps = PedometerDaySteps::new(&Berlin, date("2024-11-20"), &events);
+        assert_eq!(day_steps.hourly[8], 60);
+        let walking = day_steps.hourly_by_activity[PedometerActivity::Walking as usize];
+        let running = day_steps.hourly_by_activity[PedometerActivity::Running as usize];
+        assert_eq!((walking[8], walking[10]), (20, 40));
+        assert_eq!(running[8], 30);
+        assert_eq!(
+            day_steps.hourly_by_activity[PedometerActivity::Idle as usize],
+            [0; 24]
+        );
+    }
+
+    #[test]
+    fn hour_range_from_drag() {
+        let mut hourly = [0; 24];
+        hourly[7] = 100;
+        hourly[8] = 250;
+        hourly[9] = 50;
+        let range = PedometerHourRange::new(9.4, 7.6);
+        assert_eq!(
+            range,
+            PedometerHourRange {
+                first_hour: 8,
+                last_hour: 9,
+            }
+        );
+        assert_eq!(range.steps(&hourly), 300);
+        assert_eq!(range.duration(), Duration::hours(2));
+        // Dragged beyond the bars
+        let range = PedometerHourRange::new(-3.0, 30.0);
+        assert_eq!(range.steps(&hourly), 400);
+        assert_eq!(range.duration(), Duration::hours(24));
+    }
+
+    #[test]
+    fn activity_breakdown_of_day() {
+        let ms = |timestamp: &str| {
+            timestamp
+                .parse::<DateTime<Utc>>()
+                .unwrap()
+                .timestamp_millis()
+        };
+        let activity =
+            |timestamp: &str, activity: PedometerActivity| PedometerPersistenceActivity {
+                event_id: 0,
+                timestamp_ms: ms(timestamp),
+                boot_id: 1,
+                activity: activity as i64,
+            };
+        let activities = [
+            // Still walking at the start of the day
+            activity("2024-11-19T23:30:00Z", PedometerActivity::Walking),
+            activity("2024-11-20T00:30:00Z", PedometerActivity::Idle),
+            activity("2024-11-20T08:00:00Z", PedometerActivity::Running),
+            activity("2024-11-20T08:20:00Z", PedometerActivity::Idle),
+        ];
+        let events = [
+            event(1, 1, "2024-11-20T00:10:00Z", 300),
+            event(2, 1, "2024-11-20T08:10:00Z", 1_500),
+            event(3, 1, "2024-11-20T12:00:00Z", 20),
+            // After the end of the range
+            event(4, 1, "2024-11-21T00:10:00Z", 1_000),
+        ];
+        let breakdown = PedometerActivityBreakdown::new(
+            ms("2024-11-20T00:00:00Z"),
+            ms("2024-11-21T00:00:00Z"),
+            &activities,
+            &events,
+        );
+        assert_eq!(
+            breakdown.duration_ms,
+            [(23 * 60 + 10) * 60_000, 30 * 60_000, 20 * 60_000]
+        );
+        assert_eq!(breakdown.steps, [20, 300, 1_500]);
+        assert!(breakdown.is_active());
+
+        let breakdown = PedometerActivityBreakdown::new(
+            ms("2024-11-20T00:00:00Z"),
+            ms("2024-11-21T00:00:00Z"),
+            &[],
+            &events,
+        );
+        assert_eq!(breakdown.duration_ms, [24 * 60 * 60_000, 0, 0]);
+        assert!(!breakdown.is_active());
+    }
+
+    #[test]
+    fn week_ends_at_selected_day() {
+        assert_eq!(week_first_day(date("2024-03-03")), date("2024-02-26"));
+        assert_eq!(
+            week_bar_position(date("2024-03-03"), date("2024-03-03")),
+            0.0
+        );
+        assert_eq!(
+            week_bar_position(date("2024-03-03"), date("2024-02-26")),
+            -6.0
+        );
+    }
+
+    #[test]
+    fn week_comparison_by_day_of_week_view() {
+        let last_day = date("2024-03-03");
+        assert_eq!(comparison_first_day(last_day), date("2024-01-29"));
+        let totals: Vec<_> = comparison_first_day(last_day)
+            .iter_days()
+            .take_while(|day| *day <= last_day)
+            .enumerate()
+            .map(|(i, date)| PedometerDailyTotal {
+                date,
+                steps: i as i64 * 100,
+            })
+            .collect();
+        let comparison = PedometerWeekComparison::new(last_day, &totals);
+        assert_eq!(comparison.this_week[0], 2_800);
+        assert_eq!(comparison.this_week[6], 3_400);
+        assert_eq!(comparison.last_week[0], 2_100);
+        assert_eq!(comparison.last_week[6], 2_700);
+        // Weeks from 2024-01-29 to 2024-02-25
+        assert_eq!(comparison.average[0], 1_050.0);
+        assert_eq!(comparison.average[6], 1_650.0);
+    }
+
+    #[test]
+    fn daily_summary_belongs_to_previous_day() {
+        let summary_dt = |s: &str| s.parse::<DateTime<Utc>>().unwrap().with_timezone(&Berlin);
+        assert_eq!(
+            daily_summary_day(&summary_dt("2024-11-20T23:00:00Z")),
+            date("2024-11-20")
+        );
+        // Device clock slightly early
+        assert_eq!(
+            daily_summary_day(&summary_dt("2024-11-20T22:58:00Z")),
+            date("2024-11-20")
+        );
+    }
+}
@@ -0,0 +1,65 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use log::{error, info, warn};
+use tokio::sync::{mpsc, Mutex};
+use tokio::task::JoinHandle;
+
+use crate::gui::{PedometerGuiEvent, GUI_EVENT_TX};
+
+/// Delay before an actor is restarted. It is multiplied by the number of consecutive restarts.
+const RESTART_DELAY: Duration = Duration::from_millis(500);
+const MAX_RESTART_DELAY_FACTOR: u32 = 20;
+/// An actor that ran at least this long is considered healthy again.
+const HEALTHY_RUNTIME: Duration = Duration::from_secs(60);
+
+/// The command receiver is shared between actor incarnations so that the senders held by the GUI
+/// stay valid when an actor has to be restarted.
+pub(crate) type SharedReceiver<C> = Arc<Mutex<mpsc::Receiver<C>>>;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, strum::Display)]
+pub(crate) enum PedometerBackend {
+    #[strum(to_string = "Datenbank")]
+    Database,
+    #[strum(to_string = "Schrittzähler")]
+    Device,
+}
+
+/// Run the actor created by `spawn_actor` and restart it whenever it panics or could not be
+/// created. Returns when the actor exits regularly, i.e. on `Exit` or when all senders are gone.
+pub(crate) async fn supervise<C, F, Fut>(
+    backend: PedometerBackend,
+    receiver: mpsc::Receiver<C>,
+    mut spawn_actor: F,
+) where
+    F: FnMut(SharedReceiver<C>) -> Fut,
+    Fut: Future<Output = anyhow::Result<JoinHandle<()>>>,
+{
+    let receiver = Arc::new(Mutex::new(receiver));
+    let mut restarts = 0;
+    loop {
+        let started = Instant::now();
+        match spawn_actor(receiver.clone()).await {
+            Ok(handle) => match handle.await {
+                Ok(()) => {
+                    info!("{backend:?} actor exited");
+                    return;
+                }
+                Err(e) => error!("{backend:?} actor died: {e}"),
+            },
+            Err(e) => error!("Could not start {backend:?} actor: {e}"),
+        }
+
+        if started.elapsed() >= HEALTHY_RUNTIME {
+            restarts = 0;
+        }
+        restarts = (restarts + 1).min(MAX_RESTART_DELAY_FACTOR);
+        tokio::time::sleep(RESTART_DELAY * restarts).await;
+        warn!("Restart {backend:?} actor");
+        GUI_EVENT_TX
+            .get()
+            .unwrap()
+            .send(PedometerGuiEvent::BackendRestarted(backend));
+    }
+}
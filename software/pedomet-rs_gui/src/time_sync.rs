@@ -0,0 +1,77 @@
+use crate::persistence::PedometerPersistenceHostEpoch;
+
+/// Deviations above this shift events into the wrong hour noticeably.
+pub(crate) const TIME_SYNC_WARNING_THRESHOLD_MS: i64 = 3 * 60 * 1000;
+
+/// Quality of the time synchronization of one boot, estimated from its host time samples.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct PedometerTimeSyncQuality {
+    pub boot_id: i64,
+    pub samples: usize,
+    /// Offset between host time and device time of the latest sample
+    pub offset_ms: i64,
+    /// Drift of the device clock in ppm, positive if the device clock is too slow
+    pub drift_ppm: Option<f64>,
+    /// Largest difference between the offset of any sample and the latest one. Events are stored
+    /// with the offset that was known when they were synced, so they may be shifted by this much.
+    pub max_deviation_ms: i64,
+}
+
+impl PedometerTimeSyncQuality {
+    /// `host_epochs` have to be sorted by boot and index.
+    pub(crate) fn from_host_epochs(host_epochs: &[PedometerPersistenceHostEpoch]) -> Vec<Self> {
+        host_epochs
+            .chunk_by(|a, b| a.boot_id == b.boot_id)
+            .map(Self::from_boot_host_epochs)
+            .collect()
+    }
+
+    fn from_boot_host_epochs(host_epochs: &[PedometerPersistenceHostEpoch]) -> Self {
+        let offset = |host_epoch: &PedometerPersistenceHostEpoch| {
+            host_epoch.host_epoch_ms - host_epoch.device_timestamp_ms
+        };
+        let latest = host_epochs.last().expect("Chunks are never empty");
+        let offset_ms = offset(latest);
+        let max_deviation_ms = host_epochs
+            .iter()
+            .map(|host_epoch| (offset(host_epoch) - offset_ms).abs())
+            .max()
+            .unwrap_or_default();
+
+        // Least squares fit of the offset over the device time
+        let n = host_epochs.len() as f64;
+        let mean_time = host_epochs
+            .iter()
+            .map(|host_epoch| host_epoch.device_timestamp_ms as f64)
+            .sum::<f64>()
+            / n;
+        let mean_offset = host_epochs
+            .iter()
+            .map(|host_epoch| offset(host_epoch) as f64)
+            .sum::<f64>()
+            / n;
+        let (covariance, variance) =
+            host_epochs
+                .iter()
+                .fold((0.0, 0.0), |(covariance, variance), host_epoch| {
+                    let time = host_epoch.device_timestamp_ms as f64 - mean_time;
+                    (
+                        covariance + time * (offset(host_epoch) as f64 - mean_offset),
+                        variance + time * time,
+                    )
+                });
+        let drift_ppm = (variance > 0.0).then(|| covariance / variance * 1e6);
+
+        Self {
+            boot_id: latest.boot_id,
+            samples: host_epochs.len(),
+            offset_ms,
+            drift_ppm,
+            max_deviation_ms,
+        }
+    }
+
+    pub(crate) fn is_accurate(&self) -> bool {
+        self.max_deviation_ms <= TIME_SYNC_WARNING_THRESHOLD_MS
+    }
+}
@@ -0,0 +1,811 @@
+//! Async driver for the embedded pedometer of the ST LSM6DS3 IMU.
+//!
+//! The configuration steps are encoded as typestates, so that e.g. steps can only be read from the
+//! FIFO after it was configured for the pedometer.
+#![cfg_attr(not(test), no_std)]
+
+mod fmt;
+
+use core::marker::PhantomData;
+use core::time::Duration;
+
+use embedded_hal_async::i2c::I2c;
+
+use crate::fmt::debug;
+
+const ADDRESS: u8 = 0b1101010;
+pub const NUM_REGS: u8 = 0x76;
+/// The FIFO threshold register is 11 bits wide
+const MAX_FIFO_THRESHOLD: u16 = 2_u16.pow(11) - 1;
+/// Minimum acceleration of a tap in 62.5 mg at ±2 g
+const DOUBLE_TAP_THRESHOLD: u8 = 12;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<E> {
+    I2c(E),
+    InvalidFifoThreshold,
+    InvalidPedometerSensitivity,
+}
+
+pub type Result<T, E> = core::result::Result<T, Error<E>>;
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Steps {
+    pub steps: u16,
+    pub timestamp: Timestamp,
+}
+
+impl Steps {
+    fn from_step_registers(buf: [u8; 4]) -> Self {
+        Self {
+            steps: u16::from_le_bytes(buf[2..4].try_into().unwrap()),
+            timestamp: Timestamp::from_step_registers(buf),
+        }
+    }
+
+    fn from_fifo(buf: [u8; 6]) -> Self {
+        Self {
+            steps: u16::from_le_bytes(buf[4..6].try_into().unwrap()),
+            timestamp: Timestamp::from_fifo(buf),
+        }
+    }
+}
+
+/// 24 bit timestamp of the IMU
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Timestamp(u32);
+
+impl Timestamp {
+    const MASK: u32 = 0xFF_FFFF;
+
+    fn from_step_registers(buf: [u8; 4]) -> Self {
+        Self((u16::from_le_bytes(buf[0..2].try_into().unwrap()) as u32) << 8)
+    }
+
+    fn from_time_registers(buf: [u8; 3]) -> Self {
+        Self(u16::from_le_bytes(buf[..2].try_into().unwrap()) as u32 | (buf[2] as u32) << 16)
+    }
+
+    fn from_fifo(buf: [u8; 6]) -> Self {
+        Self((u16::from_le_bytes(buf[0..2].try_into().unwrap()) as u32) << 8 | buf[3] as u32)
+    }
+
+    pub fn as_duration(self) -> Duration {
+        Duration::from_micros(self.0 as u64 * 6400)
+    }
+
+    /// Time from self until `later`.
+    ///
+    /// It is always assumed that self is before `later` and there was at most one timer overflow
+    /// between.
+    pub fn elapsed_until(self, later: Self) -> Duration {
+        Self(later.0.wrapping_sub(self.0) & Self::MASK).as_duration()
+    }
+}
+
+/// Raw output of the accelerometer. At ±2 g one digit is 0.061 mg.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Acceleration {
+    pub x: i16,
+    pub y: i16,
+    pub z: i16,
+}
+
+impl Acceleration {
+    fn from_output_registers(buf: [u8; 6]) -> Self {
+        Self {
+            x: i16::from_le_bytes([buf[0], buf[1]]),
+            y: i16::from_le_bytes([buf[2], buf[3]]),
+            z: i16::from_le_bytes([buf[4], buf[5]]),
+        }
+    }
+}
+
+/// Output of the temperature sensor of the LSM6DS3TR-C. One digit is 1/256 °C and 0 is 25 °C.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Temperature(i16);
+
+impl Temperature {
+    fn from_output_registers(buf: [u8; 2]) -> Self {
+        Self(i16::from_le_bytes(buf))
+    }
+
+    /// Temperature in 0.01 °C
+    pub fn as_centi_celsius(self) -> i16 {
+        (2500 + i32::from(self.0) * 100 / 256) as i16
+    }
+}
+
+/// How readily the pedometer algorithm counts steps.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PedometerSensitivity {
+    /// Minimum acceleration of a step in 16 mg at ±2 g, at most [`Self::MAX_THRESHOLD`]
+    pub threshold: u8,
+    /// Steps that have to be detected in a row before they are counted, at most
+    /// [`Self::MAX_DEBOUNCE_STEPS`]
+    pub debounce_steps: u8,
+}
+
+impl PedometerSensitivity {
+    /// The threshold is 5 bits wide
+    pub const MAX_THRESHOLD: u8 = 0x1F;
+    /// The debounce steps are 3 bits wide
+    pub const MAX_DEBOUNCE_STEPS: u8 = 0x07;
+    /// Default debounce time of 1040 ms in 80 ms, which is kept
+    const DEBOUNCE_TIME: u8 = 0x0D;
+
+    fn is_valid(&self) -> bool {
+        self.threshold <= Self::MAX_THRESHOLD && self.debounce_steps <= Self::MAX_DEBOUNCE_STEPS
+    }
+}
+
+impl Default for PedometerSensitivity {
+    /// Reset values of the registers
+    fn default() -> Self {
+        Self {
+            threshold: 0x10,
+            debounce_steps: 0x06,
+        }
+    }
+}
+
+/// WHO_AM_I values of the LSM6DS3 and the LSM6DS3TR-C
+const WHO_AM_I_VALUES: [u8; 2] = [0x69, 0x6A];
+
+#[repr(u8)]
+enum Register {
+    FuncCfgAccess = 0x01,
+    FifoCtrl1 = 0x06,
+    FifoCtrl2 = 0x07,
+    FifoCtrl4 = 0x09,
+    FifoCtrl5 = 0x0A,
+    Int1Ctrl = 0x0D,
+    Int2Ctrl = 0x0E,
+    WhoAmI = 0x0F,
+    Ctrl1Xl = 0x10,
+    Ctrl3C = 0x12,
+    Ctrl6C = 0x15,
+    Ctrl10C = 0x19,
+    TapSrc = 0x1C,
+    OutTempL = 0x20,
+    FifoStatus1 = 0x3A,
+    FifoStatus2 = 0x3B,
+    OutxLXl = 0x28,
+    FifoDataOutL = 0x3E,
+    Timestamp0Reg = 0x40,
+    StepTimestampL = 0x49,
+    TapCfg = 0x58,
+    TapThs6d = 0x59,
+    WakeUpThs = 0x5B,
+    Md1Cfg = 0x5E,
+}
+
+/// Registers of the embedded functions, which are only accessible while FUNC_CFG_EN is set
+#[repr(u8)]
+enum EmbeddedRegister {
+    ConfigPedoThsMin = 0x0F,
+    PedoDebReg = 0x14,
+}
+
+/// Typestate: The registers were not touched, yet.
+pub struct Unconfigured;
+/// Typestate: Block data update is enabled.
+pub struct Initialized;
+/// Typestate: The pedometer algorithm and the timestamp are running.
+pub struct PedometerEnabled;
+/// Typestate: The pedometer is running and its steps are stored in the FIFO.
+pub struct FifoEnabled;
+/// Typestate: The FIFO is off, the accelerometer runs in low-power mode and INT1 is raised by a
+/// significant motion. The pedometer keeps counting.
+pub struct MotionWake;
+
+mod sealed {
+    pub trait Sealed {}
+    impl Sealed for super::PedometerEnabled {}
+    impl Sealed for super::FifoEnabled {}
+    impl Sealed for super::MotionWake {}
+}
+
+/// States in which the step counter and the timestamp are running
+pub trait PedometerRunning: sealed::Sealed {}
+impl PedometerRunning for PedometerEnabled {}
+impl PedometerRunning for FifoEnabled {}
+impl PedometerRunning for MotionWake {}
+
+pub struct Lsm6ds3<I, S> {
+    i2c: I,
+    _state: PhantomData<S>,
+}
+
+impl<I: I2c> Lsm6ds3<I, Unconfigured> {
+    pub fn new(i2c: I) -> Self {
+        Self {
+            i2c,
+            _state: PhantomData,
+        }
+    }
+
+    /// Returns `true` if the IMU answers with the expected WHO_AM_I value.
+    pub async fn check_who_am_i(&mut self) -> Result<bool, I::Error> {
+        let who_am_i = self.read_register(Register::WhoAmI as u8).await?;
+        debug!("WHO_AM_I: 0x{:02x}", who_am_i);
+        Ok(WHO_AM_I_VALUES.contains(&who_am_i))
+    }
+
+    pub async fn init(mut self) -> Result<Lsm6ds3<I, Initialized>, I::Error> {
+        // Enable Block Data Update
+        self.write_register(Register::Ctrl3C as u8, 0x44).await?;
+        Ok(self.into_state())
+    }
+}
+
+impl<I: I2c> Lsm6ds3<I, Initialized> {
+    /// Tune the pedometer algorithm, e.g. to count less false steps while cycling. Has to be done
+    /// before the pedometer is enabled.
+    pub async fn set_pedometer_sensitivity(
+        &mut self,
+        sensitivity: PedometerSensitivity,
+    ) -> Result<(), I::Error> {
+        if !sensitivity.is_valid() {
+            return Err(Error::InvalidPedometerSensitivity);
+        }
+        // Enable access to the embedded functions registers
+        self.write_register(Register::FuncCfgAccess as u8, 0x80)
+            .await?;
+        // PEDO_4G is not set, as the accelerometer runs at ±2 g
+        self.write_register(
+            EmbeddedRegister::ConfigPedoThsMin as u8,
+            sensitivity.threshold,
+        )
+        .await?;
+        self.write_register(
+            EmbeddedRegister::PedoDebReg as u8,
+            PedometerSensitivity::DEBOUNCE_TIME << 3 | sensitivity.debounce_steps,
+        )
+        .await?;
+        self.write_register(Register::FuncCfgAccess as u8, 0x00)
+            .await
+    }
+
+    /// Raise INT1 on a double tap, see [`Lsm6ds3::read_double_tap`]. The default time windows
+    /// are kept, which already span several samples at the 26 Hz of the pedometer.
+    pub async fn enable_double_tap(&mut self) -> Result<(), I::Error> {
+        // Enable the embedded interrupts and the tap detection on all axes, not latched
+        self.write_register(Register::TapCfg as u8, 0x8E).await?;
+        self.write_register(Register::TapThs6d as u8, DOUBLE_TAP_THRESHOLD)
+            .await?;
+        // Detect double taps in addition to single taps
+        self.write_register(Register::WakeUpThs as u8, 0x80).await?;
+        // Double tap interrupt driven to INT1 pin
+        self.write_register(Register::Md1Cfg as u8, 0x08).await
+    }
+
+    pub async fn enable_pedometer(
+        mut self,
+        enable_interrupt: bool,
+    ) -> Result<Lsm6ds3<I, PedometerEnabled>, I::Error> {
+        // 1. Write 20h to CTRL1_XL // Turn on the accelerometer: ODR_XL = 26 Hz, FS_XL = ±2 g
+        self.write_register(Register::Ctrl1Xl as u8, 0x20).await?;
+        // 2. Write 34h to CTRL10_C // Enable embedded functions, pedometer algorithm and timestamp
+        self.write_register(Register::Ctrl10C as u8, 0x34).await?;
+        if enable_interrupt {
+            // 3. Write 80h to INT1_CTRL // Step detector interrupt driven to INT1 pin
+            self.write_register(Register::Int1Ctrl as u8, 0x80).await?;
+        }
+        // 4. Write 40h to INT2_CTRL // Enable step count overflow interrupt which is not
+        //    connected but resets the counter automatically on overflow
+        self.write_register(Register::Int2Ctrl as u8, 0x40).await?;
+        Ok(self.into_state())
+    }
+}
+
+impl<I: I2c> Lsm6ds3<I, PedometerEnabled> {
+    /// Store every step in the FIFO. If a threshold (in words) is given, INT1 is raised when it is
+    /// reached.
+    pub async fn enable_fifo_for_pedometer(
+        mut self,
+        interrupt_threshold: Option<u16>,
+    ) -> Result<Lsm6ds3<I, FifoEnabled>, I::Error> {
+        if let Some(interrupt_threshold) = interrupt_threshold {
+            if interrupt_threshold > MAX_FIFO_THRESHOLD {
+                return Err(Error::InvalidFifoThreshold);
+            }
+        }
+
+        // Choose the decimation factor for the 4th FIFO data set through the DEC_DS4_FIFO[2:0] bits of the FIFO_CTRL4 register => 0b101 (8)
+        self.write_register(Register::FifoCtrl4 as u8, 0x28).await?;
+
+        // Set to 1 the TIMER_PEDO_FIFO_EN bit in the FIFO_CTRL2 register
+        // Configure the bit TIMER_PEDO_FIFO_DRDY in the FIFO_CTRL2 register in order to choose the method of storing data in the FIFO (internal trigger or every step detected)
+        let mut fifo_ctrl2 = 0xC0;
+        if let Some(interrupt_threshold) = interrupt_threshold {
+            // set threshold registers/values
+            fifo_ctrl2 |= (interrupt_threshold >> 8) as u8;
+            self.write_register(
+                Register::FifoCtrl1 as u8,
+                (interrupt_threshold & 0xFF) as u8,
+            )
+            .await?;
+            // Enable threshold interrupt
+            self.write_register(Register::Int1Ctrl as u8, 0x08).await?;
+        }
+        self.write_register(Register::FifoCtrl2 as u8, fifo_ctrl2)
+            .await?;
+
+        // Configure the FIFO operating mode through the FIFO_MODE_[2:0] field of the FIFO_CTRL5 register.
+        //
+        // IMPORTANT: Apparently this is not enough and ODR_FIFO_[3:0] has to be set as well contrary to what is
+        // written in AN5130
+        self.write_register(Register::FifoCtrl5 as u8, 0b10110)
+            .await?;
+        Ok(self.into_state())
+    }
+}
+
+impl<I: I2c, S: PedometerRunning> Lsm6ds3<I, S> {
+    pub async fn read_steps_from_registers(&mut self) -> Result<Steps, I::Error> {
+        let mut buf = [0; 4];
+        self.read_register_range(Register::StepTimestampL as u8, &mut buf)
+            .await?;
+        Ok(Steps::from_step_registers(buf))
+    }
+
+    /// Latest sample of the accelerometer, which runs at 26 Hz for the pedometer.
+    pub async fn read_acceleration(&mut self) -> Result<Acceleration, I::Error> {
+        let mut buf = [0; 6];
+        self.read_register_range(Register::OutxLXl as u8, &mut buf)
+            .await?;
+        Ok(Acceleration::from_output_registers(buf))
+    }
+
+    /// The temperature sensor runs whenever the accelerometer is on.
+    pub async fn read_temperature(&mut self) -> Result<Temperature, I::Error> {
+        let mut buf = [0; 2];
+        self.read_register_range(Register::OutTempL as u8, &mut buf)
+            .await?;
+        Ok(Temperature::from_output_registers(buf))
+    }
+
+    /// Returns `true` if a double tap was detected since the last read.
+    pub async fn read_double_tap(&mut self) -> Result<bool, I::Error> {
+        let tap_src = self.read_register(Register::TapSrc as u8).await?;
+        debug!("TAP_SRC: 0b{:08b}", tap_src);
+        Ok(tap_src & 0x10 != 0)
+    }
+
+    pub async fn read_timestamp(&mut self) -> Result<Timestamp, I::Error> {
+        let mut buf = [0; 3];
+        self.read_register_range(Register::Timestamp0Reg as u8, &mut buf)
+            .await?;
+        debug!("Timestamp registers: {:?}", buf);
+        Ok(Timestamp::from_time_registers(buf))
+    }
+}
+
+impl<I: I2c> Lsm6ds3<I, FifoEnabled> {
+    pub async fn read_steps_from_fifo(&mut self) -> Result<Option<Steps>, I::Error> {
+        let unread_words = self.read_register(Register::FifoStatus1 as u8).await?;
+        debug!("Unread fifo words: {}", unread_words);
+        if unread_words < 3 {
+            return Ok(None);
+        }
+
+        let mut buf = [0; 6];
+        for i in 0..3 {
+            self.read_register_range(Register::FifoDataOutL as u8, &mut buf[i * 2..i * 2 + 2])
+                .await?;
+        }
+        debug!("Step buf: {:?}", buf);
+        Ok(Some(Steps::from_fifo(buf)))
+    }
+
+    /// Returns `true` if the FIFO was completely filled and old samples were overwritten.
+    pub async fn read_fifo_overrun(&mut self) -> Result<bool, I::Error> {
+        let fifo_status2 = self.read_register(Register::FifoStatus2 as u8).await?;
+        debug!("FIFO status 2: 0b{:08b}", fifo_status2);
+        Ok(fifo_status2 & 0x40 != 0)
+    }
+
+    /// Stop the FIFO and wait for a significant motion with as little power as possible. The
+    /// steps in the FIFO are discarded, so they have to be read before.
+    pub async fn enter_motion_wake(mut self) -> Result<Lsm6ds3<I, MotionWake>, I::Error> {
+        // Bypass mode, the FIFO is off
+        self.write_register(Register::FifoCtrl5 as u8, 0x00).await?;
+        // Disable the high-performance mode, so the accelerometer runs in low-power mode at 26 Hz
+        self.write_register(Register::Ctrl6C as u8, 0x10).await?;
+        // Additionally enable the significant motion detection
+        self.write_register(Register::Ctrl10C as u8, 0x35).await?;
+        // Significant motion interrupt driven to INT1 pin instead of the FIFO threshold
+        self.write_register(Register::Int1Ctrl as u8, 0x40).await?;
+        Ok(self.into_state())
+    }
+}
+
+impl<I: I2c> Lsm6ds3<I, MotionWake> {
+    /// Leave the low-power mode after a significant motion. The FIFO has to be enabled again, the
+    /// step counter was not reset.
+    pub async fn wake(mut self) -> Result<Lsm6ds3<I, PedometerEnabled>, I::Error> {
+        self.write_register(Register::Int1Ctrl as u8, 0x00).await?;
+        self.write_register(Register::Ctrl10C as u8, 0x34).await?;
+        self.write_register(Register::Ctrl6C as u8, 0x00).await?;
+        Ok(self.into_state())
+    }
+}
+
+impl<I: I2c, S> Lsm6ds3<I, S> {
+    fn into_state<T>(self) -> Lsm6ds3<I, T> {
+        Lsm6ds3 {
+            i2c: self.i2c,
+            _state: PhantomData,
+        }
+    }
+
+    /// Give back the bus, e.g. to put the IMU into another configuration from scratch.
+    pub fn release(self) -> I {
+        self.i2c
+    }
+
+    pub async fn read_register(&mut self, register_addr: u8) -> Result<u8, I::Error> {
+        let mut buf = [0; 1];
+        self.read_register_range(register_addr, &mut buf).await?;
+        Ok(buf[0])
+    }
+
+    pub async fn read_register_range(
+        &mut self,
+        start_addr: u8,
+        buf: &mut [u8],
+    ) -> Result<(), I::Error> {
+        self.i2c
+            .write_read(ADDRESS, &[start_addr], buf)
+            .await
+            .map_err(Error::I2c)
+    }
+
+    pub async fn read_all_registers(&mut self) -> Result<[u8; NUM_REGS as usize], I::Error> {
+        let mut buf = [0; NUM_REGS as usize];
+        self.read_register_range(0, &mut buf).await?;
+        Ok(buf)
+    }
+
+    pub async fn dump_all_registers(&mut self) -> Result<(), I::Error> {
+        let buf = self.read_all_registers().await?;
+        debug!("IMU registers:");
+        for (i, b) in buf.iter().enumerate() {
+            debug!("0x{0:02x}: 0x{1:02x} (0b{1:08b})", i, *b);
+        }
+        Ok(())
+    }
+
+    pub async fn write_register(&mut self, register_addr: u8, value: u8) -> Result<(), I::Error> {
+        self.i2c
+            .write(ADDRESS, &[register_addr, value])
+            .await
+            .map_err(Error::I2c)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embassy_futures::block_on;
+    use embedded_hal_mock::eh1::i2c::{Mock as I2cMock, Transaction as I2cTransaction};
+
+    fn enable_pedometer_transactions() -> Vec<I2cTransaction> {
+        vec![
+            I2cTransaction::write(ADDRESS, vec![Register::Ctrl3C as u8, 0x44]),
+            I2cTransaction::write(ADDRESS, vec![Register::Ctrl1Xl as u8, 0x20]),
+            I2cTransaction::write(ADDRESS, vec![Register::Ctrl10C as u8, 0x34]),
+            I2cTransaction::write(ADDRESS, vec![Register::Int2Ctrl as u8, 0x40]),
+        ]
+    }
+
+    async fn pedometer(i2c: I2cMock) -> Lsm6ds3<I2cMock, PedometerEnabled> {
+        Lsm6ds3::new(i2c)
+            .init()
+            .await
+            .unwrap()
+            .enable_pedometer(false)
+            .await
+            .unwrap()
+    }
+
+    #[test]
+    fn enable_fifo_with_threshold() {
+        let mut transactions = enable_pedometer_transactions();
+        transactions.extend([
+            I2cTransaction::write(ADDRESS, vec![Register::FifoCtrl4 as u8, 0x28]),
+            I2cTransaction::write(ADDRESS, vec![Register::FifoCtrl1 as u8, 0x34]),
+            I2cTransaction::write(ADDRESS, vec![Register::Int1Ctrl as u8, 0x08]),
+            I2cTransaction::write(ADDRESS, vec![Register::FifoCtrl2 as u8, 0xC1]),
+            I2cTransaction::write(ADDRESS, vec![Register::FifoCtrl5 as u8, 0b10110]),
+        ]);
+        let i2c = I2cMock::new(&transactions);
+
+        block_on(async {
+            let imu = pedometer(i2c)
+                .await
+                .enable_fifo_for_pedometer(Some(0x134))
+                .await
+                .unwrap();
+            imu.release().done();
+        });
+    }
+
+    #[test]
+    fn enable_fifo_rejects_invalid_threshold() {
+        let i2c = I2cMock::new(&enable_pedometer_transactions());
+        let mut i2c_handle = i2c.clone();
+
+        block_on(async {
+            let res = pedometer(i2c)
+                .await
+                .enable_fifo_for_pedometer(Some(MAX_FIFO_THRESHOLD + 1))
+                .await;
+            assert!(matches!(res, Err(Error::InvalidFifoThreshold)));
+        });
+        i2c_handle.done();
+    }
+
+    #[test]
+    fn set_pedometer_sensitivity() {
+        let transactions = [
+            I2cTransaction::write(ADDRESS, vec![Register::Ctrl3C as u8, 0x44]),
+            I2cTransaction::write(ADDRESS, vec![Register::FuncCfgAccess as u8, 0x80]),
+            I2cTransaction::write(
+                ADDRESS,
+                vec![EmbeddedRegister::ConfigPedoThsMin as u8, 0x14],
+            ),
+            I2cTransaction::write(ADDRESS, vec![EmbeddedRegister::PedoDebReg as u8, 0x6F]),
+            I2cTransaction::write(ADDRESS, vec![Register::FuncCfgAccess as u8, 0x00]),
+        ];
+        let i2c = I2cMock::new(&transactions);
+
+        block_on(async {
+            let mut imu = Lsm6ds3::new(i2c).init().await.unwrap();
+            imu.set_pedometer_sensitivity(PedometerSensitivity {
+                threshold: 0x14,
+                debounce_steps: 7,
+            })
+            .await
+            .unwrap();
+            let res = imu
+                .set_pedometer_sensitivity(PedometerSensitivity {
+                    threshold: PedometerSensitivity::MAX_THRESHOLD + 1,
+                    debounce_steps: 0,
+                })
+                .await;
+            assert!(matches!(res, Err(Error::InvalidPedometerSensitivity)));
+            imu.release().done();
+        });
+    }
+
+    #[test]
+    fn read_steps_from_fifo() {
+        let mut transactions = enable_pedometer_transactions();
+        transactions.extend([
+            I2cTransaction::write(ADDRESS, vec![Register::FifoCtrl4 as u8, 0x28]),
+            I2cTransaction::write(ADDRESS, vec![Register::FifoCtrl2 as u8, 0xC0]),
+            I2cTransaction::write(ADDRESS, vec![Register::FifoCtrl5 as u8, 0b10110]),
+            I2cTransaction::write_read(ADDRESS, vec![Register::FifoStatus1 as u8], vec![3]),
+            I2cTransaction::write_read(
+                ADDRESS,
+                vec![Register::FifoDataOutL as u8],
+                vec![0x34, 0x12],
+            ),
+            I2cTransaction::write_read(
+                ADDRESS,
+                vec![Register::FifoDataOutL as u8],
+                vec![0x00, 0x56],
+            ),
+            I2cTransaction::write_read(
+                ADDRESS,
+                vec![Register::FifoDataOutL as u8],
+                vec![0x2A, 0x01],
+            ),
+            I2cTransaction::write_read(ADDRESS, vec![Register::FifoStatus1 as u8], vec![2]),
+        ]);
+        let i2c = I2cMock::new(&transactions);
+
+        block_on(async {
+            let mut imu = pedometer(i2c)
+                .await
+                .enable_fifo_for_pedometer(None)
+                .await
+                .unwrap();
+            assert_eq!(
+                imu.read_steps_from_fifo().await.unwrap(),
+                Some(Steps {
+                    steps: 0x012A,
+                    timestamp: Timestamp(0x123456),
+                })
+            );
+            assert_eq!(imu.read_steps_from_fifo().await.unwrap(), None);
+            imu.release().done();
+        });
+    }
+
+    #[test]
+    fn read_fifo_overrun() {
+        let mut transactions = enable_pedometer_transactions();
+        transactions.extend([
+            I2cTransaction::write(ADDRESS, vec![Register::FifoCtrl4 as u8, 0x28]),
+            I2cTransaction::write(ADDRESS, vec![Register::FifoCtrl2 as u8, 0xC0]),
+            I2cTransaction::write(ADDRESS, vec![Register::FifoCtrl5 as u8, 0b10110]),
+            I2cTransaction::write_read(ADDRESS, vec![Register::FifoStatus2 as u8], vec![0x40]),
+            I2cTransaction::write_read(ADDRESS, vec![Register::FifoStatus2 as u8], vec![0x90]),
+        ]);
+        let i2c = I2cMock::new(&transactions);
+
+        block_on(async {
+            let mut imu = pedometer(i2c)
+                .await
+                .enable_fifo_for_pedometer(None)
+                .await
+                .unwrap();
+            assert!(imu.read_fifo_overrun().await.unwrap());
+            assert!(!imu.read_fifo_overrun().await.unwrap());
+            imu.release().done();
+        });
+    }
+
+    #[test]
+    fn read_timestamp() {
+        let mut transactions = enable_pedometer_transactions();
+        transactions.push(I2cTransaction::write_read(
+            ADDRESS,
+            vec![Register::Timestamp0Reg as u8],
+            vec![0x56, 0x34, 0x12],
+        ));
+        let i2c = I2cMock::new(&transactions);
+
+        block_on(async {
+            let mut imu = pedometer(i2c).await;
+            assert_eq!(imu.read_timestamp().await.unwrap(), Timestamp(0x123456));
+            imu.release().done();
+        });
+    }
+
+    #[test]
+    fn read_temperature() {
+        let mut transactions = enable_pedometer_transactions();
+        transactions.extend([
+            I2cTransaction::write_read(ADDRESS, vec![Register::OutTempL as u8], vec![0x80, 0x01]),
+            I2cTransaction::write_read(ADDRESS, vec![Register::OutTempL as u8], vec![0x00, 0xFE]),
+        ]);
+        let i2c = I2cMock::new(&transactions);
+
+        block_on(async {
+            let mut imu = pedometer(i2c).await;
+            assert_eq!(
+                imu.read_temperature().await.unwrap().as_centi_celsius(),
+                2650
+            );
+            assert_eq!(
+                imu.read_temperature().await.unwrap().as_centi_celsius(),
+                2300
+            );
+            imu.release().done();
+        });
+    }
+
+    #[test]
+    fn read_acceleration() {
+        let mut transactions = enable_pedometer_transactions();
+        transactions.push(I2cTransaction::write_read(
+            ADDRESS,
+            vec![Register::OutxLXl as u8],
+            vec![0x10, 0x00, 0xF0, 0xFF, 0x64, 0x40],
+        ));
+        let i2c = I2cMock::new(&transactions);
+
+        block_on(async {
+            let mut imu = pedometer(i2c).await;
+            assert_eq!(
+                imu.read_acceleration().await.unwrap(),
+                Acceleration {
+                    x: 16,
+                    y: -16,
+                    z: 0x4064,
+                }
+            );
+            imu.release().done();
+        });
+    }
+
+    #[test]
+    fn double_tap() {
+        let mut transactions = vec![
+            I2cTransaction::write(ADDRESS, vec![Register::Ctrl3C as u8, 0x44]),
+            I2cTransaction::write(ADDRESS, vec![Register::TapCfg as u8, 0x8E]),
+            I2cTransaction::write(ADDRESS, vec![Register::TapThs6d as u8, 12]),
+            I2cTransaction::write(ADDRESS, vec![Register::WakeUpThs as u8, 0x80]),
+            I2cTransaction::write(ADDRESS, vec![Register::Md1Cfg as u8, 0x08]),
+        ];
+        transactions.extend(enable_pedometer_transactions().into_iter().skip(1));
+        transactions.extend([
+            I2cTransaction::write_read(ADDRESS, vec![Register::TapSrc as u8], vec![0x5C]),
+            I2cTransaction::write_read(ADDRESS, vec![Register::TapSrc as u8], vec![0x00]),
+        ]);
+        let i2c = I2cMock::new(&transactions);
+
+        block_on(async {
+            let mut imu = Lsm6ds3::new(i2c).init().await.unwrap();
+            imu.enable_double_tap().await.unwrap();
+            let mut imu = imu.enable_pedometer(false).await.unwrap();
+            assert!(imu.read_double_tap().await.unwrap());
+            assert!(!imu.read_double_tap().await.unwrap());
+            imu.release().done();
+        });
+    }
+
+    #[test]
+    fn motion_wake() {
+        let mut transactions = enable_pedometer_transactions();
+        transactions.extend([
+            I2cTransaction::write(ADDRESS, vec![Register::FifoCtrl4 as u8, 0x28]),
+            I2cTransaction::write(ADDRESS, vec![Register::FifoCtrl2 as u8, 0xC0]),
+            I2cTransaction::write(ADDRESS, vec![Register::FifoCtrl5 as u8, 0b10110]),
+            I2cTransaction::write(ADDRESS, vec![Register::FifoCtrl5 as u8, 0x00]),
+            I2cTransaction::write(ADDRESS, vec![Register::Ctrl6C as u8, 0x10]),
+            I2cTransaction::write(ADDRESS, vec![Register::Ctrl10C as u8, 0x35]),
+            I2cTransaction::write(ADDRESS, vec![Register::Int1Ctrl as u8, 0x40]),
+            I2cTransaction::write(ADDRESS, vec![Register::Int1Ctrl as u8, 0x00]),
+            I2cTransaction::write(ADDRESS, vec![Register::Ctrl10C as u8, 0x34]),
+            I2cTransaction::write(ADDRESS, vec![Register::Ctrl6C as u8, 0x00]),
+        ]);
+        let i2c = I2cMock::new(&transactions);
+
+        block_on(async {
+            let imu = pedometer(i2c)
+                .await
+                .enable_fifo_for_pedometer(None)
+                .await
+                .unwrap()
+                .enter_motion_wake()
+                .await
+                .unwrap()
+                .wake()
+                .await
+                .unwrap();
+            imu.release().done();
+        });
+    }
+
+    #[test]
+    fn check_who_am_i() {
+        let transactions = [
+            I2cTransaction::write_read(ADDRESS, vec![Register::WhoAmI as u8], vec![0x69]),
+            I2cTransaction::write_read(ADDRESS, vec![Register::WhoAmI as u8], vec![0x6A]),
+            I2cTransaction::write_read(ADDRESS, vec![Register::WhoAmI as u8], vec![0xFF]),
+        ];
+        let i2c = I2cMock::new(&transactions);
+
+        block_on(async {
+            let mut imu = Lsm6ds3::new(i2c);
+            assert!(imu.check_who_am_i().await.unwrap());
+            assert!(imu.check_who_am_i().await.unwrap());
+            assert!(!imu.check_who_am_i().await.unwrap());
+            imu.release().done();
+        });
+    }
+
+    #[test]
+    fn timestamp_elapsed_until() {
+        assert_eq!(
+            Timestamp(10).elapsed_until(Timestamp(15)),
+            Duration::from_micros(5 * 6400)
+        );
+        // Timer overflow between both timestamps
+        assert_eq!(
+            Timestamp(0xFF_FFFE).elapsed_until(Timestamp(1)),
+            Duration::from_micros(3 * 6400)
+        );
+    }
+}